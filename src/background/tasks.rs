@@ -6,6 +6,8 @@ use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
 /// Priority levels for background tasks
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum TaskPriority {
+    /// Bulk work such as offline downloads - runs when nothing else is waiting
+    Background = 0,
     /// Low priority - can be delayed significantly
     Low = 1,
     /// Normal priority - standard background processing
@@ -369,6 +371,9 @@ pub struct AsyncExecutor;
 impl AsyncExecutor {
     /// Execute a CPU-intensive task using the appropriate runtime
     /// This consolidates the #[cfg(feature = "tokio-runtime")] patterns
+    ///
+    /// Outside a tokio runtime the task runs inline, so callers can also
+    /// drive it with a plain `block_on`.
     pub async fn execute_blocking<F, R>(task: F) -> Result<R>
     where
         F: FnOnce() -> Result<R> + Send + 'static,
@@ -376,15 +381,14 @@ impl AsyncExecutor {
     {
         #[cfg(feature = "tokio-runtime")]
         {
-            tokio::task::spawn_blocking(task)
-                .await
-                .map_err(|e| crate::Error::Plugin(format!("Task execution failed: {}", e)))?
+            if tokio::runtime::Handle::try_current().is_ok() {
+                return tokio::task::spawn_blocking(task)
+                    .await
+                    .map_err(|e| crate::Error::Plugin(format!("Task execution failed: {}", e)))?;
+            }
         }
 
-        #[cfg(not(feature = "tokio-runtime"))]
-        {
-            task()
-        }
+        task()
     }
 
     /// Execute a CPU-intensive task that returns a boxed result
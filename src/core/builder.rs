@@ -13,7 +13,7 @@ use crate::{
         map::{Map, MapOptions},
         viewport::Viewport,
     },
-    layers::tile::TileSource,
+    layers::tile::{DiskCacheConfig, DiskTileCache, TileSource},
     traits::ConfigPreset,
     Result,
 };
@@ -85,10 +85,19 @@ impl MapBuilder {
 
     /// Apply a unified configuration preset that affects all subsystems
     pub fn with_unified_config(mut self, config: UnifiedMapConfig) -> Self {
+        // Keep a disk cache configured earlier unless the preset brings its own
+        let disk_cache = config
+            .performance
+            .tile_loader
+            .disk_cache
+            .clone()
+            .or_else(|| self.tile_config.as_ref().and_then(|c| c.disk_cache.clone()));
+        let memory_budget = config.performance.tile_loader.memory_budget;
         self.performance = MapPerformanceProfile::Custom(config.performance);
         self.task_config = Some(config.task_manager);
         self.tile_config = Some(TileLoadingConfig {
             cache_size: 1024, // Use performance config values
+            memory_budget,
             fetch_batch_size: 6,
             lazy_eviction: true,
             prefetch_buffer: 2,
@@ -98,6 +107,7 @@ impl MapBuilder {
             error_tile_url: None,
             show_parent_tiles: true,
             preload_zoom_tiles: true,
+            disk_cache,
         });
         // Note: UI controls would be applied when creating the UI widget
         self
@@ -189,6 +199,14 @@ impl MapBuilder {
         self
     }
 
+    /// Keep downloaded tiles in a persistent on-disk cache
+    pub fn with_disk_cache(mut self, config: DiskCacheConfig) -> Self {
+        let mut tile_config = self.tile_config.unwrap_or_default();
+        tile_config.disk_cache = Some(config);
+        self.tile_config = Some(tile_config);
+        self
+    }
+
     /// Enable advanced tile prefetching
     pub fn with_tile_prefetching(mut self, buffer_size: u32, preload_zoom_tiles: bool) -> Self {
         let mut config = self.tile_config.unwrap_or_default();
@@ -230,6 +248,12 @@ impl MapBuilder {
 
         // Resolve performance configuration
         let performance_options = self.performance.resolve();
+        let disk_cache_config = self
+            .tile_config
+            .as_ref()
+            .unwrap_or(&performance_options.tile_loader)
+            .disk_cache
+            .clone();
 
         // Create task manager config based on performance options
         let task_config = self.task_config.unwrap_or_else(|| TaskManagerConfig {
@@ -250,7 +274,7 @@ impl MapBuilder {
         )?;
 
         // Add tile source if provided
-        if let Some(_tile_source) = self.tile_source {
+        if let Some(tile_source) = self.tile_source {
             // Create a high-performance tile layer with background task manager
             let bg_task_manager =
                 std::sync::Arc::new(crate::background::BackgroundTaskManager::new(task_config));
@@ -258,7 +282,12 @@ impl MapBuilder {
                 "base_tiles".to_string(),
                 "Base Map Tiles".to_string(),
                 bg_task_manager,
-            );
+            )
+            .with_tile_source(tile_source);
+            let tile_layer = match disk_cache_config {
+                Some(config) => tile_layer.with_disk_cache(DiskTileCache::shared(config)?),
+                None => tile_layer,
+            };
             map.add_layer(Box::new(tile_layer))?;
         }
 
@@ -456,6 +485,7 @@ mod tests {
             },
             tile_loader: TileLoadingConfig {
                 cache_size: 512,
+                memory_budget: TileMemoryBudget::default(),
                 fetch_batch_size: 4,
                 lazy_eviction: true,
                 prefetch_buffer: 2,
@@ -465,6 +495,7 @@ mod tests {
                 error_tile_url: None,
                 show_parent_tiles: true,
                 preload_zoom_tiles: true,
+                disk_cache: None,
             },
             rendering: GpuRenderingConfig {
                 msaa_samples: 2,
@@ -23,6 +23,11 @@ impl MapPerformanceProfile {
                 },
                 tile_loader: TileLoadingConfig {
                     cache_size: 1024,
+                    memory_budget: TileMemoryBudget {
+                        compressed_bytes: 64 * MIB,
+                        decoded_bytes: 128 * MIB,
+                        texture_bytes: 128 * MIB,
+                    },
                     fetch_batch_size: 6,
                     lazy_eviction: true,
                     prefetch_buffer: 2,
@@ -32,6 +37,7 @@ impl MapPerformanceProfile {
                     error_tile_url: None,
                     show_parent_tiles: true,
                     preload_zoom_tiles: true,
+                    disk_cache: None,
                 },
 
                 rendering: GpuRenderingConfig {
@@ -49,6 +55,11 @@ impl MapPerformanceProfile {
                 },
                 tile_loader: TileLoadingConfig {
                     cache_size: 256,
+                    memory_budget: TileMemoryBudget {
+                        compressed_bytes: 16 * MIB,
+                        decoded_bytes: 32 * MIB,
+                        texture_bytes: 32 * MIB,
+                    },
                     fetch_batch_size: 2,
                     lazy_eviction: true,
                     prefetch_buffer: 1,
@@ -58,6 +69,7 @@ impl MapPerformanceProfile {
                     error_tile_url: None,
                     show_parent_tiles: false,
                     preload_zoom_tiles: false,
+                    disk_cache: None,
                 },
 
                 rendering: GpuRenderingConfig {
@@ -75,6 +87,11 @@ impl MapPerformanceProfile {
                 },
                 tile_loader: TileLoadingConfig {
                     cache_size: 4096,
+                    memory_budget: TileMemoryBudget {
+                        compressed_bytes: 256 * MIB,
+                        decoded_bytes: 512 * MIB,
+                        texture_bytes: 512 * MIB,
+                    },
                     fetch_batch_size: 12,
                     lazy_eviction: false,
                     prefetch_buffer: 3,
@@ -84,6 +101,7 @@ impl MapPerformanceProfile {
                     error_tile_url: None,
                     show_parent_tiles: true,
                     preload_zoom_tiles: true,
+                    disk_cache: None,
                 },
                 rendering: GpuRenderingConfig {
                     msaa_samples: 8,
@@ -188,9 +206,45 @@ impl FrameTimingConfig {
     // Removed should_render() and should_update() - timing is now controlled by UpdateOrchestrator
 }
 
+const MIB: usize = 1024 * 1024;
+
+/// Memory tile caches may hold, in bytes
+///
+/// Each tier is bounded separately, since tiles differ widely in size: a
+/// 512px WebP tile is a fraction of a 256px PNG's size downloaded but four
+/// times its size decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileMemoryBudget {
+    /// Tiles as downloaded, e.g. PNG or WebP
+    pub compressed_bytes: usize,
+    /// Tiles decoded to RGBA pixels
+    pub decoded_bytes: usize,
+    /// Tile textures uploaded to the GPU
+    pub texture_bytes: usize,
+}
+
+impl TileMemoryBudget {
+    pub fn total_bytes(&self) -> usize {
+        self.compressed_bytes + self.decoded_bytes + self.texture_bytes
+    }
+}
+
+impl Default for TileMemoryBudget {
+    fn default() -> Self {
+        Self {
+            compressed_bytes: crate::layers::tile::cache::DEFAULT_COMPRESSED_BUDGET,
+            decoded_bytes: crate::layers::tile::cache::DEFAULT_DECODED_BUDGET,
+            texture_bytes: 128 * MIB,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct TileLoadingConfig {
+    /// Most tiles kept in memory, whatever their size
     pub cache_size: usize,
+    /// Most memory the tile caches may take
+    pub memory_budget: TileMemoryBudget,
     pub fetch_batch_size: usize,
     pub lazy_eviction: bool,
     pub prefetch_buffer: u32,
@@ -200,11 +254,14 @@ pub struct TileLoadingConfig {
     pub error_tile_url: Option<String>,
     pub show_parent_tiles: bool,
     pub preload_zoom_tiles: bool,
+    /// Persistent on-disk tile cache; `None` keeps tiles in memory only
+    pub disk_cache: Option<crate::layers::tile::disk_cache::DiskCacheConfig>,
 }
 
 impl TileLoadingConfig {
+    /// Upper bound of the memory taken by cached tiles
     pub fn estimated_memory_usage(&self) -> usize {
-        self.cache_size * 15_000
+        self.memory_budget.total_bytes()
     }
 
     pub fn recommended_concurrent_tasks(&self) -> usize {
@@ -216,6 +273,7 @@ impl Default for TileLoadingConfig {
     fn default() -> Self {
         Self {
             cache_size: 1024,
+            memory_budget: TileMemoryBudget::default(),
             fetch_batch_size: 8,
             lazy_eviction: true,
             prefetch_buffer: 2,
@@ -225,6 +283,7 @@ impl Default for TileLoadingConfig {
             error_tile_url: None,
             show_parent_tiles: true,
             preload_zoom_tiles: true,
+            disk_cache: None,
         }
     }
 }
@@ -334,6 +393,7 @@ mod tests {
     fn test_tile_loading_config() {
         let config = TileLoadingConfig {
             cache_size: 1000,
+            memory_budget: TileMemoryBudget::default(),
             fetch_batch_size: 8,
             lazy_eviction: true,
             prefetch_buffer: 2,
@@ -343,6 +403,7 @@ mod tests {
             error_tile_url: None,
             show_parent_tiles: true,
             preload_zoom_tiles: true,
+            disk_cache: None,
         };
 
         assert!(config.estimated_memory_usage() > 0);
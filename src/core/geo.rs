@@ -284,6 +284,34 @@ impl TileCoord {
         }
     }
 
+    /// Gets the tile at the lower zoom level `zoom` that contains this one
+    pub fn ancestor(&self, zoom: u8) -> Option<TileCoord> {
+        let depth = self.z.checked_sub(zoom)? as u32;
+        Some(TileCoord::new(
+            self.x.checked_shr(depth).unwrap_or(0),
+            self.y.checked_shr(depth).unwrap_or(0),
+            zoom,
+        ))
+    }
+
+    /// Gets the tiles at the higher zoom level `zoom` that make up this one,
+    /// row by row
+    pub fn descendants(&self, zoom: u8) -> Vec<TileCoord> {
+        let Some(depth) = zoom.checked_sub(self.z) else {
+            return Vec::new();
+        };
+        let Some(n) = 1_u32.checked_shl(depth as u32) else {
+            return Vec::new();
+        };
+        (0..n)
+            .flat_map(|dy| {
+                (0..n).map(move |dx| {
+                    TileCoord::new(self.x * n + dx, self.y * n + dy, zoom)
+                })
+            })
+            .collect()
+    }
+
     /// Gets the child tiles at a higher zoom level
     pub fn children(&self) -> Vec<TileCoord> {
         if self.z >= 18 {
@@ -303,6 +331,45 @@ impl TileCoord {
         let max_coord = 2_u32.pow(self.z as u32);
         self.x < max_coord && self.y < max_coord
     }
+
+    /// The same tile with rows numbered bottom-up, as in TMS, or back
+    pub fn flip_y(&self) -> TileCoord {
+        let rows = 1_u32.checked_shl(self.z as u32).unwrap_or(0);
+        TileCoord::new(self.x, rows.saturating_sub(1).saturating_sub(self.y), self.z)
+    }
+
+    /// Bing-style quadkey: one base-4 digit per zoom level, interleaving the
+    /// bits of `x` and `y`. The zoom 0 tile has an empty quadkey.
+    pub fn to_quadkey(&self) -> String {
+        (1..=self.z)
+            .rev()
+            .map(|level| {
+                let mask = 1_u32 << (level - 1);
+                let digit = (self.x & mask != 0) as u8 + 2 * (self.y & mask != 0) as u8;
+                char::from(b'0' + digit)
+            })
+            .collect()
+    }
+
+    /// Parses a quadkey, the zoom being its length
+    ///
+    /// Returns `None` for keys with characters other than `0`-`3` or more
+    /// than 31 digits.
+    pub fn from_quadkey(quadkey: &str) -> Option<TileCoord> {
+        if quadkey.len() > 31 {
+            return None;
+        }
+        let (mut x, mut y) = (0_u32, 0_u32);
+        for digit in quadkey.bytes() {
+            let digit = match digit {
+                b'0'..=b'3' => (digit - b'0') as u32,
+                _ => return None,
+            };
+            x = (x << 1) | (digit & 1);
+            y = (y << 1) | (digit >> 1);
+        }
+        Some(TileCoord::new(x, y, quadkey.len() as u8))
+    }
 }
 
 #[cfg(test)]
@@ -338,6 +405,43 @@ mod tests {
         assert!((back_to_lat_lng.lng - lat_lng.lng).abs() < 1.0);
     }
 
+    #[test]
+    fn test_tile_ancestors_and_descendants() {
+        let tile = TileCoord::new(1234, 567, 20);
+        assert_eq!(tile.ancestor(17), Some(TileCoord::new(154, 70, 17)));
+        assert_eq!(tile.ancestor(20), Some(tile));
+        assert_eq!(tile.ancestor(21), None);
+
+        let parent = TileCoord::new(3, 1, 2);
+        let grandchildren = parent.descendants(4);
+        assert_eq!(grandchildren.len(), 16);
+        assert_eq!(grandchildren[0], TileCoord::new(12, 4, 4));
+        assert_eq!(grandchildren[15], TileCoord::new(15, 7, 4));
+        assert!(grandchildren.iter().all(|t| t.ancestor(2) == Some(parent)));
+        assert!(parent.descendants(1).is_empty());
+        // Too deep to count in tile coordinates
+        assert!(TileCoord::new(0, 0, 0).descendants(40).is_empty());
+    }
+
+    #[test]
+    fn test_quadkeys_and_tms_rows() {
+        // The example from Bing's tile system documentation
+        let tile = TileCoord::new(3, 5, 3);
+        assert_eq!(tile.to_quadkey(), "213");
+        assert_eq!(TileCoord::from_quadkey("213"), Some(tile));
+        assert_eq!(TileCoord::new(0, 0, 0).to_quadkey(), "");
+        assert_eq!(TileCoord::from_quadkey(""), Some(TileCoord::new(0, 0, 0)));
+        assert_eq!(TileCoord::from_quadkey("0124"), None);
+        assert_eq!(TileCoord::from_quadkey(&"3".repeat(32)), None);
+
+        let deep = TileCoord::new(1234567, 7654321, 23);
+        assert_eq!(TileCoord::from_quadkey(&deep.to_quadkey()), Some(deep));
+
+        assert_eq!(tile.flip_y(), TileCoord::new(3, 2, 3));
+        assert_eq!(tile.flip_y().flip_y(), tile);
+        assert_eq!(TileCoord::new(0, 0, 0).flip_y(), TileCoord::new(0, 0, 0));
+    }
+
     #[test]
     fn test_bounds_contains() {
         let bounds = LatLngBounds::from_coords(40.0, -75.0, 41.0, -73.0);
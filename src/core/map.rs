@@ -1,8 +1,15 @@
 use crate::{
     background::{tasks::TaskManagerConfig, BackgroundTaskManager},
-    core::{config::MapPerformanceOptions, geo::LatLng, viewport::Viewport},
+    core::{
+        config::{MapPerformanceOptions, TileMemoryBudget},
+        geo::LatLng,
+        viewport::Viewport,
+    },
     input::{Action, EventManager, InputEvent, InputHandler, MapEvent, MapOperations},
-    layers::{animation::AnimationManager, base::LayerTrait, manager::LayerManager},
+    layers::{
+        animation::AnimationManager, base::LayerTrait, manager::LayerManager,
+        tile::TimeDimensionLayer,
+    },
     plugins::base::PluginTrait,
     prelude::HashMap,
     traits::PointMath,
@@ -443,7 +450,10 @@ impl Map {
         MapOperations::fit_bounds(&mut self.viewport, bounds, padding)
     }
 
-    pub fn add_layer(&mut self, layer: Box<dyn LayerTrait>) -> Result<()> {
+    /// Add a layer; tile layers get the memory budget of the performance
+    /// options
+    pub fn add_layer(&mut self, mut layer: Box<dyn LayerTrait>) -> Result<()> {
+        apply_memory_budget(layer.as_mut(), &self.performance.tile_loader.memory_budget);
         self.layer_manager.add_layer(layer)
     }
 
@@ -708,18 +718,29 @@ impl Map {
         }
 
         let mut content_changed = false;
+        let mut tile_errors = Vec::new();
         self.layer_manager.for_each_layer_mut(|layer| {
             let _ = layer.update(0.016); // Fixed delta time since timing is controlled centrally
 
             if let Some(tile_layer) = layer
-                .as_any()
-                .downcast_ref::<crate::layers::tile::TileLayer>()
+                .as_any_mut()
+                .downcast_mut::<crate::layers::tile::TileLayer>()
             {
                 if tile_layer.needs_repaint() {
                     content_changed = true;
                 }
+                tile_errors.extend(tile_layer.take_tile_errors());
+            } else if let Some(time_layer) = layer.as_any_mut().downcast_mut::<TimeDimensionLayer>()
+            {
+                if time_layer.needs_repaint() {
+                    content_changed = true;
+                }
+                tile_errors.extend(time_layer.take_tile_errors());
             }
         });
+        for event in tile_errors {
+            self.event_manager.emit(event);
+        }
 
         if content_changed {
             self.update_orchestrator.mark_content_ready();
@@ -783,6 +804,10 @@ impl Map {
             self.update_orchestrator = UpdateOrchestrator::new(target_fps);
         }
 
+        let budget = performance.tile_loader.memory_budget;
+        self.layer_manager
+            .for_each_layer_mut(|layer| apply_memory_budget(layer, &budget));
+
         self.performance = performance;
     }
 
@@ -800,6 +825,24 @@ impl Map {
         self.animation_manager.stop_zoom_animation();
     }
 
+    /// Whether a layer keeps changing without input, such as tiles fading
+    /// in or a time-dimension layer playing, so the host should keep
+    /// requesting frames
+    pub fn needs_repaint(&self) -> bool {
+        let mut needs_repaint = false;
+        self.layer_manager.for_each_layer(|layer| {
+            if let Some(tile_layer) = layer
+                .as_any()
+                .downcast_ref::<crate::layers::tile::TileLayer>()
+            {
+                needs_repaint |= tile_layer.needs_repaint();
+            } else if let Some(time_layer) = layer.as_any().downcast_ref::<TimeDimensionLayer>() {
+                needs_repaint |= time_layer.needs_repaint();
+            }
+        });
+        needs_repaint
+    }
+
     /// Get the update orchestrator for advanced configuration
     pub fn update_orchestrator(&self) -> &UpdateOrchestrator {
         &self.update_orchestrator
@@ -820,6 +863,18 @@ impl Map {
     }
 }
 
+/// Bound the tile caches of `layer` by `budget`, if it has any
+fn apply_memory_budget(layer: &mut dyn LayerTrait, budget: &TileMemoryBudget) {
+    if let Some(tile_layer) = layer
+        .as_any_mut()
+        .downcast_mut::<crate::layers::tile::TileLayer>()
+    {
+        tile_layer.set_memory_budget(budget);
+    } else if let Some(time_layer) = layer.as_any_mut().downcast_mut::<TimeDimensionLayer>() {
+        time_layer.set_memory_budget(budget);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -919,6 +974,41 @@ mod tests {
         assert_eq!(map.performance().framerate.target_fps, Some(30));
     }
 
+    #[tokio::test]
+    async fn test_performance_profile_sets_tile_memory_budget() {
+        let mut map = Map::new(
+            LatLng::new(37.7749, -122.4194),
+            12.0,
+            crate::core::geo::Point::new(800.0, 600.0),
+        );
+        let tile_layer = TileLayer::openstreetmap("osm".to_string(), "OpenStreetMap".to_string());
+        map.add_layer(Box::new(tile_layer)).unwrap();
+
+        let budget = |map: &Map| {
+            let layer = map.get_layer("osm").unwrap();
+            let cache = layer
+                .as_any()
+                .downcast_ref::<TileLayer>()
+                .unwrap()
+                .tile_cache();
+            (cache.compressed_budget(), cache.decoded_budget())
+        };
+        let balanced = map.performance().tile_loader.memory_budget;
+        assert_eq!(
+            budget(&map),
+            (balanced.compressed_bytes, balanced.decoded_bytes)
+        );
+
+        let low = crate::core::config::MapPerformanceProfile::LowQuality.resolve();
+        let low_budget = low.tile_loader.memory_budget;
+        map.set_performance(low);
+        assert_eq!(
+            budget(&map),
+            (low_budget.compressed_bytes, low_budget.decoded_bytes)
+        );
+        assert!(low_budget.total_bytes() < balanced.total_bytes());
+    }
+
     #[tokio::test]
     async fn test_animation_controls() {
         let mut map = Map::new(
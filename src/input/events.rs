@@ -1,4 +1,4 @@
-use crate::core::geo::{LatLng, Point};
+use crate::core::geo::{LatLng, Point, TileCoord};
 use serde::{Deserialize, Serialize};
 
 /// Input events that can be handled by the map and layers
@@ -125,6 +125,12 @@ pub enum MapEvent {
     OverlayAdd { layer_id: String },
     /// Overlay layer was removed
     OverlayRemove { layer_id: String },
+    /// A tile failed to load
+    TileError {
+        coord: TileCoord,
+        layer_id: String,
+        error: String,
+    },
 }
 
 /// Mouse button types
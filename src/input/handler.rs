@@ -153,6 +153,7 @@ impl EventManager {
                 MapEvent::BaseLayerChange { .. } => "baselayerchange",
                 MapEvent::OverlayAdd { .. } => "overlayadd",
                 MapEvent::OverlayRemove { .. } => "overlayremove",
+                MapEvent::TileError { .. } => "tileerror",
             };
 
             if let Some(callbacks) = self.listeners.get(event_type) {
@@ -0,0 +1,215 @@
+//! Tile addressing schemes
+//!
+//! Tile servers name the same tile differently: XYZ paths, TMS paths with
+//! rows numbered bottom-up, ArcGIS REST paths with the row before the
+//! column, or Bing-style quadkeys. A [`TileScheme`] writes a tile's address
+//! in one of these forms and [`AddressedTileSource`] puts it into the
+//! `{tile}` placeholder of a URL template. Schemes not covered here can be
+//! plugged in by implementing [`TileAddressing`].
+
+use super::request::RequestOptions;
+use super::source::{TemplateTileSource, TileSource};
+use super::types::TileLayerOptions;
+use crate::core::geo::TileCoord;
+use crate::prelude::Arc;
+
+/// Writes the address of a tile as it appears in a request URL
+pub trait TileAddressing: Send + Sync {
+    fn address(&self, coord: TileCoord) -> String;
+}
+
+impl<F> TileAddressing for F
+where
+    F: Fn(TileCoord) -> String + Send + Sync,
+{
+    fn address(&self, coord: TileCoord) -> String {
+        self(coord)
+    }
+}
+
+/// Common tile addressing schemes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TileScheme {
+    /// `{z}/{x}/{y}` with rows numbered from the north
+    #[default]
+    Xyz,
+    /// `{z}/{x}/{y}` with rows numbered from the south
+    Tms,
+    /// `{z}/{y}/{x}`, as served by ArcGIS `MapServer/tile` endpoints
+    ArcGis,
+    /// Bing-style quadkey
+    Quadkey,
+}
+
+impl TileScheme {
+    /// The tile at `address`, the inverse of [`TileAddressing::address`]
+    pub fn parse(&self, address: &str) -> Option<TileCoord> {
+        if *self == TileScheme::Quadkey {
+            return TileCoord::from_quadkey(address);
+        }
+        let mut parts = address.trim_matches('/').split('/');
+        let z = parts.next()?.parse::<u8>().ok()?;
+        let first = parts.next()?.parse::<u32>().ok()?;
+        let second = parts.next()?.parse::<u32>().ok()?;
+        if parts.next().is_some() {
+            return None;
+        }
+        let coord = match self {
+            TileScheme::Xyz => TileCoord::new(first, second, z),
+            TileScheme::Tms => TileCoord::new(first, second, z).flip_y(),
+            TileScheme::ArcGis => TileCoord::new(second, first, z),
+            TileScheme::Quadkey => unreachable!(),
+        };
+        coord.is_valid().then_some(coord)
+    }
+}
+
+impl TileAddressing for TileScheme {
+    fn address(&self, coord: TileCoord) -> String {
+        match self {
+            TileScheme::Xyz => format!("{}/{}/{}", coord.z, coord.x, coord.y),
+            TileScheme::Tms => format!("{}/{}/{}", coord.z, coord.x, coord.flip_y().y),
+            TileScheme::ArcGis => format!("{}/{}/{}", coord.z, coord.y, coord.x),
+            TileScheme::Quadkey => coord.to_quadkey(),
+        }
+    }
+}
+
+/// Tile source whose URL template has a `{tile}` placeholder filled in by a
+/// [`TileAddressing`] scheme
+///
+/// All other placeholders of [`TemplateTileSource`] work as usual, so a Bing
+/// imagery URL such as
+/// `https://ecn.{subdomain}.tiles.virtualearth.net/tiles/a{tile}.jpeg?g=1`
+/// with [`TileScheme::Quadkey`] and subdomains `t0`-`t3` requests its tiles
+/// by quadkey.
+pub struct AddressedTileSource {
+    template: TemplateTileSource,
+    addressing: Arc<dyn TileAddressing>,
+}
+
+impl AddressedTileSource {
+    /// Create a source using the default tile layer options
+    pub fn new(template: impl Into<String>, addressing: impl TileAddressing + 'static) -> Self {
+        Self::from_options(template, addressing, &TileLayerOptions::default())
+    }
+
+    /// Create a source that honours the subdomains, zoom range and bounds
+    /// of `options`
+    pub fn from_options(
+        template: impl Into<String>,
+        addressing: impl TileAddressing + 'static,
+        options: &TileLayerOptions,
+    ) -> Self {
+        Self {
+            template: TemplateTileSource::from_options(template, options),
+            addressing: Arc::new(addressing),
+        }
+    }
+
+    /// Override the subdomains used for `{s}` and `{subdomain}`
+    pub fn with_subdomains(mut self, subdomains: Vec<String>) -> Self {
+        self.template = self.template.with_subdomains(subdomains);
+        self
+    }
+
+    /// Register a value for a custom `{key}` placeholder
+    pub fn with_param(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.template = self.template.with_param(key, value);
+        self
+    }
+
+    /// Send these headers, User-Agent and credentials with tile requests
+    pub fn with_request_options(mut self, request: RequestOptions) -> Self {
+        self.template = self.template.with_request_options(request);
+        self
+    }
+
+    pub fn template(&self) -> &str {
+        self.template.template()
+    }
+
+    fn expand(&self, coord: TileCoord, retina: bool) -> String {
+        self.template.expand_with(coord, retina, &|key| {
+            (key == "tile").then(|| self.addressing.address(coord))
+        })
+    }
+}
+
+impl TileSource for AddressedTileSource {
+    fn url(&self, coord: TileCoord) -> String {
+        self.expand(coord, false)
+    }
+
+    fn has_tile(&self, coord: TileCoord) -> bool {
+        self.template.has_tile(coord)
+    }
+
+    fn request_options(&self) -> Option<&RequestOptions> {
+        self.template.request_options()
+    }
+
+    /// Available when the template has an `{r}` placeholder
+    fn retina_url(&self, coord: TileCoord) -> Option<String> {
+        self.template
+            .template()
+            .contains("{r}")
+            .then(|| self.expand(coord, true))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schemes_round_trip() {
+        let coord = TileCoord::new(3, 5, 3);
+        let cases = [
+            (TileScheme::Xyz, "3/3/5"),
+            (TileScheme::Tms, "3/3/2"),
+            (TileScheme::ArcGis, "3/5/3"),
+            (TileScheme::Quadkey, "213"),
+        ];
+        for (scheme, address) in cases {
+            assert_eq!(scheme.address(coord), address);
+            assert_eq!(scheme.parse(address), Some(coord), "{:?}", scheme);
+        }
+        assert_eq!(TileScheme::Xyz.parse("3/9/0"), None);
+        assert_eq!(TileScheme::ArcGis.parse("3/1"), None);
+        assert_eq!(TileScheme::Xyz.parse("3/1/2/4"), None);
+    }
+
+    #[test]
+    fn test_addressed_source_urls() {
+        let bing = AddressedTileSource::new(
+            "https://ecn.{subdomain}.tiles.virtualearth.net/tiles/a{tile}.jpeg?g={g}",
+            TileScheme::Quadkey,
+        )
+        .with_subdomains(vec!["t0".into(), "t1".into()])
+        .with_param("g", "14624");
+        assert_eq!(
+            bing.url(TileCoord::new(3, 5, 3)),
+            "https://ecn.t0.tiles.virtualearth.net/tiles/a213.jpeg?g=14624"
+        );
+
+        let arcgis = AddressedTileSource::new(
+            "https://server.example.com/MapServer/tile/{tile}",
+            TileScheme::ArcGis,
+        );
+        assert_eq!(
+            arcgis.url(TileCoord::new(1, 2, 3)),
+            "https://server.example.com/MapServer/tile/3/2/1"
+        );
+        assert_eq!(arcgis.retina_url(TileCoord::new(1, 2, 3)), None);
+
+        // Any function of the tile works as a scheme
+        let custom = AddressedTileSource::new("/tiles/{tile}{r}.png", |coord: TileCoord| {
+            format!("L{:02}/R{:08x}/C{:08x}", coord.z, coord.y, coord.x)
+        });
+        assert_eq!(
+            custom.retina_url(TileCoord::new(10, 255, 9)).as_deref(),
+            Some("/tiles/L09/R000000ff/C0000000a@2x.png")
+        );
+    }
+}
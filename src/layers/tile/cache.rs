@@ -1,13 +1,147 @@
+use super::decode::DecodedTile;
+use super::http_cache::HttpCachePolicy;
+use crate::core::config::TileMemoryBudget;
 use crate::core::geo::{LatLng, TileCoord};
 use crate::core::viewport::Viewport;
-use crate::prelude::{Arc, HashSet, Mutex};
+use crate::prelude::{Arc, Duration, HashSet, Instant, Mutex};
 use lru::LruCache;
-use std::num::NonZeroUsize;
+use std::time::SystemTime;
+
+/// Memory for downloaded tiles by default, a few thousand typical tiles
+pub const DEFAULT_COMPRESSED_BUDGET: usize = 64 * 1024 * 1024;
+
+/// Memory for decoded tiles by default, about 500 tiles of 256x256 pixels
+pub const DEFAULT_DECODED_BUDGET: usize = 128 * 1024 * 1024;
+
+/// Memory taken by a cached value
+trait ByteSize {
+    fn byte_size(&self) -> usize;
+}
+
+impl ByteSize for CompressedTile {
+    fn byte_size(&self) -> usize {
+        self.data.len()
+    }
+}
+
+impl ByteSize for Arc<DecodedTile> {
+    fn byte_size(&self) -> usize {
+        DecodedTile::byte_size(self)
+    }
+}
+
+/// HTTP caching state of a downloaded tile kept in memory
+#[derive(Debug, Clone)]
+pub struct TileFreshness {
+    /// Caching headers of the last response for the tile
+    pub policy: HttpCachePolicy,
+    /// When the tile was last downloaded, or last asked to be revalidated
+    pub checked_at: Instant,
+}
+
+impl TileFreshness {
+    /// Whether the tile should be requested again: it is stale or was not
+    /// to be stored at all, and has not been checked within `interval`
+    pub fn is_due(&self, interval: Duration) -> bool {
+        (self.policy.no_store || self.policy.info.is_stale(SystemTime::now()))
+            && self.checked_at.elapsed() >= interval
+    }
+}
+
+/// A tile as downloaded
+#[derive(Debug, Clone)]
+struct CompressedTile {
+    data: Arc<Vec<u8>>,
+    /// `None` for tiles of local sources, which never go stale
+    freshness: Option<TileFreshness>,
+}
+
+/// Recently used tiles, limited by their count and total size
+#[derive(Debug)]
+struct BudgetedTiles<V> {
+    tiles: LruCache<TileCoord, V>,
+    bytes: usize,
+    budget: usize,
+    capacity: usize,
+}
+
+impl<V: ByteSize> BudgetedTiles<V> {
+    fn new(budget: usize, capacity: usize) -> Self {
+        Self {
+            tiles: LruCache::unbounded(),
+            bytes: 0,
+            budget,
+            capacity,
+        }
+    }
+
+    fn is_over(&self) -> bool {
+        self.bytes > self.budget || self.tiles.len() > self.capacity
+    }
+
+    /// Keep `value` unless it alone exceeds the budget, then make room
+    fn put(&mut self, coord: TileCoord, value: V, retained: &HashSet<u8>) {
+        self.remove(&coord);
+        if value.byte_size() > self.budget {
+            return;
+        }
+        self.bytes += value.byte_size();
+        self.tiles.put(coord, value);
+        self.shrink(retained);
+    }
+
+    fn remove(&mut self, coord: &TileCoord) -> Option<V> {
+        let value = self.tiles.pop(coord)?;
+        self.bytes -= value.byte_size();
+        Some(value)
+    }
+
+    /// Drop the least recently used tiles until the rest fit, starting with
+    /// tiles outside the `retained` zoom levels
+    fn shrink(&mut self, retained: &HashSet<u8>) {
+        if !self.is_over() {
+            return;
+        }
+        let expendable: Vec<TileCoord> = self
+            .tiles
+            .iter()
+            .rev()
+            .map(|(coord, _)| *coord)
+            .filter(|coord| !retained.contains(&coord.z))
+            .collect();
+        for coord in expendable {
+            if !self.is_over() {
+                return;
+            }
+            self.remove(&coord);
+        }
+        while self.is_over() {
+            match self.tiles.pop_lru() {
+                Some((_, value)) => self.bytes -= value.byte_size(),
+                None => break,
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.tiles.clear();
+        self.bytes = 0;
+    }
+}
 
 /// Intelligent tile cache with multi-level prefetching strategy
+///
+/// Tiles are kept as downloaded, and recently used ones also as decoded
+/// pixels, each within its own memory budget. A tile whose pixels were
+/// dropped is still drawn, from its compressed form. When over budget the
+/// least recently used tiles go first, but tiles of the zoom levels set by
+/// [`TileCache::set_retained_zooms`] only once no others are left.
 #[derive(Debug)]
 pub struct TileCache {
-    cache: Arc<Mutex<LruCache<TileCoord, Arc<Vec<u8>>>>>,
+    cache: Arc<Mutex<BudgetedTiles<CompressedTile>>>,
+    decoded: Arc<Mutex<BudgetedTiles<Arc<DecodedTile>>>>,
+    /// Zoom levels on screen or kept for a zoom transition
+    retained_zooms: Arc<Mutex<HashSet<u8>>>,
     /// Current viewport for smart prefetching
     current_viewport: Arc<Mutex<Option<Viewport>>>,
     /// Movement direction for predictive prefetching
@@ -17,11 +151,20 @@ pub struct TileCache {
 }
 
 impl TileCache {
-    /// Create a new tile cache with the given capacity
+    /// Create a new tile cache holding at most `capacity` tiles, within the
+    /// default memory budgets
     pub fn new(capacity: usize) -> Self {
-        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::new(2048).unwrap()); // Increased default
+        let capacity = if capacity == 0 { 2048 } else { capacity }; // Increased default
         Self {
-            cache: Arc::new(Mutex::new(LruCache::new(capacity))),
+            cache: Arc::new(Mutex::new(BudgetedTiles::new(
+                DEFAULT_COMPRESSED_BUDGET,
+                capacity,
+            ))),
+            decoded: Arc::new(Mutex::new(BudgetedTiles::new(
+                DEFAULT_DECODED_BUDGET,
+                usize::MAX,
+            ))),
+            retained_zooms: Arc::new(Mutex::new(HashSet::default())),
             current_viewport: Arc::new(Mutex::new(None)),
             movement_direction: Arc::new(Mutex::new(None)),
             last_center: Arc::new(Mutex::new(None)),
@@ -33,6 +176,77 @@ impl TileCache {
         Self::new(2048)
     }
 
+    /// Keep at most `bytes` of decoded pixels
+    pub fn with_decoded_budget(self, bytes: usize) -> Self {
+        self.set_decoded_budget(bytes);
+        self
+    }
+
+    /// Keep at most `bytes` of downloaded tile data
+    pub fn with_compressed_budget(self, bytes: usize) -> Self {
+        self.set_compressed_budget(bytes);
+        self
+    }
+
+    /// Change the memory for decoded pixels, dropping pixels over the new
+    /// budget
+    pub fn set_decoded_budget(&self, bytes: usize) {
+        let retained = self.retained_zooms();
+        if let Ok(mut decoded) = self.decoded.lock() {
+            decoded.budget = bytes;
+            decoded.shrink(&retained);
+        }
+    }
+
+    /// Change the memory for downloaded tile data, dropping tiles over the
+    /// new budget
+    pub fn set_compressed_budget(&self, bytes: usize) {
+        let retained = self.retained_zooms();
+        if let Ok(mut cache) = self.cache.lock() {
+            cache.budget = bytes;
+            cache.shrink(&retained);
+        }
+    }
+
+    /// Apply the compressed and decoded budgets of `budget`
+    pub fn set_memory_budget(&self, budget: &TileMemoryBudget) {
+        self.set_compressed_budget(budget.compressed_bytes);
+        self.set_decoded_budget(budget.decoded_bytes);
+    }
+
+    pub fn compressed_budget(&self) -> usize {
+        self.cache.lock().map(|c| c.budget).unwrap_or(0)
+    }
+
+    /// Memory taken by downloaded tile data
+    pub fn compressed_bytes(&self) -> usize {
+        self.cache.lock().map(|c| c.bytes).unwrap_or(0)
+    }
+
+    /// Evict tiles of these zoom levels only once no others are left
+    pub fn set_retained_zooms(&self, zooms: impl IntoIterator<Item = u8>) {
+        if let Ok(mut retained) = self.retained_zooms.lock() {
+            retained.clear();
+            retained.extend(zooms);
+        }
+    }
+
+    fn retained_zooms(&self) -> HashSet<u8> {
+        self.retained_zooms
+            .lock()
+            .map(|retained| retained.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn decoded_budget(&self) -> usize {
+        self.decoded.lock().map(|d| d.budget).unwrap_or(0)
+    }
+
+    /// Memory taken by decoded pixels
+    pub fn decoded_bytes(&self) -> usize {
+        self.decoded.lock().map(|d| d.bytes).unwrap_or(0)
+    }
+
     /// Update viewport and calculate movement direction for smart prefetching
     pub fn update_viewport(&self, viewport: &Viewport) {
         // Calculate movement direction
@@ -277,27 +491,105 @@ impl TileCache {
 
     /// Get a tile from the cache
     pub fn get(&self, coord: &TileCoord) -> Option<Arc<Vec<u8>>> {
-        self.cache.lock().ok()?.get(coord).cloned()
+        let mut cache = self.cache.lock().ok()?;
+        cache.tiles.get(coord).map(|tile| tile.data.clone())
     }
 
     /// Insert a tile into the cache
     pub fn insert(&self, coord: TileCoord, data: Vec<u8>) {
-        if let Ok(mut cache) = self.cache.lock() {
-            cache.put(coord, Arc::new(data));
-        }
+        self.put(coord, Arc::new(data));
     }
 
     /// Insert a tile into the cache (using Arc directly)
+    ///
+    /// Pixels decoded from an earlier version of the tile are dropped.
     pub fn put(&self, coord: TileCoord, data: Arc<Vec<u8>>) {
+        self.put_tile(
+            coord,
+            CompressedTile {
+                data,
+                freshness: None,
+            },
+        );
+    }
+
+    /// Insert a downloaded tile along with the caching headers it was
+    /// served with
+    pub fn put_downloaded(&self, coord: TileCoord, data: Arc<Vec<u8>>, policy: HttpCachePolicy) {
+        let freshness = TileFreshness {
+            policy,
+            checked_at: Instant::now(),
+        };
+        self.put_tile(
+            coord,
+            CompressedTile {
+                data,
+                freshness: Some(freshness),
+            },
+        );
+    }
+
+    fn put_tile(&self, coord: TileCoord, tile: CompressedTile) {
+        let retained = self.retained_zooms();
         if let Ok(mut cache) = self.cache.lock() {
-            cache.put(coord, data);
+            cache.put(coord, tile, &retained);
+        }
+        if let Ok(mut decoded) = self.decoded.lock() {
+            decoded.remove(&coord);
+        }
+    }
+
+    /// HTTP caching state of a cached tile, `None` for tiles that never go
+    /// stale or are not cached
+    pub fn freshness(&self, coord: &TileCoord) -> Option<TileFreshness> {
+        let cache = self.cache.lock().ok()?;
+        cache.tiles.peek(coord)?.freshness.clone()
+    }
+
+    /// Take the caching headers of a response confirming a cached tile
+    /// unchanged, keeping its data and pixels
+    pub fn revalidated(&self, coord: &TileCoord, policy: HttpCachePolicy) {
+        self.update_freshness(coord, |freshness| freshness.policy = policy);
+    }
+
+    /// Note that a cached tile was asked to be revalidated, so it is not
+    /// asked again within the interval passed to [`TileFreshness::is_due`]
+    pub fn mark_checked(&self, coord: &TileCoord) {
+        self.update_freshness(coord, |_| {});
+    }
+
+    fn update_freshness(&self, coord: &TileCoord, update: impl FnOnce(&mut TileFreshness)) {
+        let Ok(mut cache) = self.cache.lock() else {
+            return;
+        };
+        if let Some(freshness) = cache
+            .tiles
+            .peek_mut(coord)
+            .and_then(|tile| tile.freshness.as_mut())
+        {
+            update(freshness);
+            freshness.checked_at = Instant::now();
+        }
+    }
+
+    /// Decoded pixels of a tile, if they are still within the budget
+    pub fn get_decoded(&self, coord: &TileCoord) -> Option<Arc<DecodedTile>> {
+        self.decoded.lock().ok()?.tiles.get(coord).cloned()
+    }
+
+    /// Keep the decoded pixels of a tile, making room by dropping the least
+    /// recently used pixels
+    pub fn put_decoded(&self, coord: TileCoord, image: Arc<DecodedTile>) {
+        let retained = self.retained_zooms();
+        if let Ok(mut decoded) = self.decoded.lock() {
+            decoded.put(coord, image, &retained);
         }
     }
 
     /// Check if a tile is in the cache without retrieving it
     pub fn contains(&self, coord: &TileCoord) -> bool {
         if let Ok(cache) = self.cache.lock() {
-            cache.contains(coord)
+            cache.tiles.contains(coord)
         } else {
             false
         }
@@ -305,7 +597,10 @@ impl TileCache {
 
     /// Remove a tile from the cache
     pub fn remove(&self, coord: &TileCoord) -> Option<Arc<Vec<u8>>> {
-        self.cache.lock().ok()?.pop(coord)
+        if let Ok(mut decoded) = self.decoded.lock() {
+            decoded.remove(coord);
+        }
+        self.cache.lock().ok()?.remove(coord).map(|tile| tile.data)
     }
 
     /// Clear all tiles from the cache
@@ -313,11 +608,18 @@ impl TileCache {
         if let Ok(mut cache) = self.cache.lock() {
             cache.clear();
         }
+        if let Ok(mut decoded) = self.decoded.lock() {
+            decoded.clear();
+        }
     }
 
     /// Get the current number of cached tiles
     pub fn len(&self) -> usize {
-        self.cache.lock().ok().map(|cache| cache.len()).unwrap_or(0)
+        self.cache
+            .lock()
+            .ok()
+            .map(|cache| cache.tiles.len())
+            .unwrap_or(0)
     }
 
     /// Check if the cache is empty
@@ -325,12 +627,12 @@ impl TileCache {
         self.len() == 0
     }
 
-    /// Get cache capacity
+    /// Get cache capacity, in tiles
     pub fn capacity(&self) -> usize {
         self.cache
             .lock()
             .ok()
-            .map(|cache| cache.cap().get())
+            .map(|cache| cache.capacity)
             .unwrap_or(0)
     }
 }
@@ -339,6 +641,8 @@ impl Clone for TileCache {
     fn clone(&self) -> Self {
         Self {
             cache: Arc::clone(&self.cache),
+            decoded: Arc::clone(&self.decoded),
+            retained_zooms: Arc::clone(&self.retained_zooms),
             current_viewport: Arc::clone(&self.current_viewport),
             movement_direction: Arc::clone(&self.movement_direction),
             last_center: Arc::clone(&self.last_center),
@@ -434,4 +738,119 @@ mod tests {
         assert!(cache.contains(&coord2));
         assert!(cache.contains(&coord3));
     }
+
+    #[test]
+    fn test_decoded_tiles_stay_within_budget() {
+        let image = |size: u32| {
+            Arc::new(DecodedTile {
+                width: size,
+                height: size,
+                format: crate::layers::tile::TileImageFormat::Png,
+                rgba: vec![0; (size * size * 4) as usize],
+            })
+        };
+        let cache = TileCache::new(16).with_decoded_budget(3 * 1024);
+        let coords: Vec<TileCoord> = (0..4).map(|x| TileCoord::new(x, 0, 2)).collect();
+        for coord in &coords[..3] {
+            cache.insert(*coord, vec![1]);
+            cache.put_decoded(*coord, image(16));
+        }
+        assert_eq!(cache.decoded_bytes(), 3 * 1024);
+
+        // The least recently used pixels make room; the tile stays cached
+        // in its compressed form
+        assert!(cache.get_decoded(&coords[0]).is_some());
+        cache.put_decoded(coords[3], image(16));
+        assert!(cache.get_decoded(&coords[1]).is_none());
+        assert!(cache.contains(&coords[1]));
+        assert!(cache.get_decoded(&coords[0]).is_some());
+
+        // Too large to keep at all
+        cache.put_decoded(coords[1], image(32));
+        assert!(cache.get_decoded(&coords[1]).is_none());
+
+        // New data for a tile invalidates its pixels
+        cache.insert(coords[0], vec![2]);
+        assert!(cache.get_decoded(&coords[0]).is_none());
+        assert_eq!(cache.decoded_bytes(), 2 * 1024);
+
+        cache.set_decoded_budget(1024);
+        assert_eq!(cache.decoded_bytes(), 1024);
+        assert!(cache.get_decoded(&coords[3]).is_some());
+        cache.clear();
+        assert_eq!((cache.decoded_bytes(), cache.decoded_budget()), (0, 1024));
+    }
+
+    #[test]
+    fn test_downloaded_tiles_keep_their_caching_headers() {
+        let cache = TileCache::new(16);
+        let coord = TileCoord::new(1, 1, 1);
+        let stale = HttpCachePolicy {
+            no_store: false,
+            info: crate::layers::tile::CachedTileInfo {
+                etag: Some("\"v1\"".to_string()),
+                last_modified: None,
+                expires_at: Some(SystemTime::now()),
+            },
+        };
+        cache.put_downloaded(coord, Arc::new(vec![1]), stale.clone());
+        let freshness = cache.freshness(&coord).unwrap();
+        assert_eq!(freshness.policy, stale);
+        assert!(freshness.is_due(Duration::ZERO));
+        assert!(!freshness.is_due(Duration::from_secs(3600)));
+
+        // Revalidating keeps the data and its pixels
+        cache.put_decoded(
+            coord,
+            Arc::new(DecodedTile {
+                width: 1,
+                height: 1,
+                format: crate::layers::tile::TileImageFormat::Png,
+                rgba: vec![0; 4],
+            }),
+        );
+        let mut fresh = stale.clone();
+        fresh.info.expires_at = Some(SystemTime::now() + Duration::from_secs(60));
+        cache.revalidated(&coord, fresh.clone());
+        assert_eq!(cache.freshness(&coord).unwrap().policy, fresh);
+        assert!(!cache.freshness(&coord).unwrap().is_due(Duration::ZERO));
+        assert!(cache.get_decoded(&coord).is_some());
+
+        // Tiles of local sources never go stale
+        cache.insert(coord, vec![2]);
+        assert!(cache.freshness(&coord).is_none());
+    }
+
+    #[test]
+    fn test_eviction_spares_retained_zoom_levels() {
+        let cache = TileCache::new(16).with_compressed_budget(3 * 100);
+        cache.set_retained_zooms([5]);
+        let shown = TileCoord::new(0, 0, 5);
+        let old = TileCoord::new(0, 0, 3);
+        cache.insert(shown, vec![0; 100]);
+        cache.insert(old, vec![0; 100]);
+        cache.insert(TileCoord::new(1, 0, 5), vec![0; 100]);
+        assert_eq!(cache.compressed_bytes(), 300);
+
+        // The least recently used tile is on screen, so the older zoom
+        // level goes first
+        cache.insert(TileCoord::new(2, 0, 5), vec![0; 100]);
+        assert!(!cache.contains(&old));
+        assert!(cache.contains(&shown));
+        assert_eq!(cache.compressed_bytes(), 300);
+
+        // With only retained tiles left, the least recently used goes
+        cache.insert(TileCoord::new(3, 0, 5), vec![0; 100]);
+        assert!(!cache.contains(&shown));
+
+        let budget = TileMemoryBudget {
+            compressed_bytes: 100,
+            decoded_bytes: 1024,
+            texture_bytes: 0,
+        };
+        cache.set_memory_budget(&budget);
+        assert_eq!(cache.len(), 1);
+        assert!(cache.contains(&TileCoord::new(3, 0, 5)));
+        assert_eq!(cache.decoded_budget(), 1024);
+    }
 }
@@ -0,0 +1,337 @@
+//! Procedurally generated tiles for offline development and tests
+//!
+//! [`DebugTileSource`] draws every tile locally: a checkerboard tinted by
+//! zoom level, a border marking the tile edges and a `z/x/y` label. Latency
+//! and failures can be injected, so the whole load, render and error path of
+//! a tile layer runs deterministically without a network.
+
+use super::source::{TileDataFuture, TileDataSource, TileSource};
+use crate::background::tasks::AsyncExecutor;
+use crate::core::geo::TileCoord;
+use crate::prelude::{Arc, Duration, HashSet, Mutex};
+use crate::runtime::async_utils::async_delay;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+/// Squares along each side of the checkerboard
+const CHECKER_SQUARES: u32 = 8;
+const LIGHT: [u8; 4] = [245, 245, 245, 255];
+const BORDER: [u8; 4] = [200, 40, 40, 255];
+const LABEL: [u8; 4] = [20, 20, 20, 255];
+/// Dark checkerboard colours, cycled through by zoom level so zoom
+/// transitions are easy to spot
+const ZOOM_TINTS: [[u8; 4]; 6] = [
+    [210, 220, 240, 255],
+    [215, 235, 210, 255],
+    [240, 225, 200, 255],
+    [230, 210, 235, 255],
+    [205, 230, 230, 255],
+    [235, 235, 200, 255],
+];
+
+/// 3x5 glyphs for the label, one row per byte using the low three bits
+const GLYPH_WIDTH: u32 = 3;
+const GLYPH_HEIGHT: u32 = 5;
+const DIGITS: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b001, 0b001, 0b001],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+const SLASH: [u8; 5] = [0b001, 0b001, 0b010, 0b100, 0b100];
+
+/// PNG image of the debug tile for `coord`
+pub fn debug_tile(coord: TileCoord, tile_size: u32) -> Vec<u8> {
+    let tile_size = tile_size.max(CHECKER_SQUARES);
+    let square = tile_size / CHECKER_SQUARES;
+    let tint = ZOOM_TINTS[coord.z as usize % ZOOM_TINTS.len()];
+    let mut image = image::RgbaImage::from_fn(tile_size, tile_size, |x, y| {
+        let dark = (x / square + y / square) % 2 == 1;
+        image::Rgba(if dark { tint } else { LIGHT })
+    });
+
+    let border = (tile_size / 128).max(1);
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        if x < border || y < border || x >= tile_size - border || y >= tile_size - border {
+            *pixel = image::Rgba(BORDER);
+        }
+    }
+
+    draw_label(&mut image, &format!("{}/{}/{}", coord.z, coord.x, coord.y));
+
+    let mut png = Vec::new();
+    // Encoding a fixed-size image into memory does not fail
+    let _ = image::DynamicImage::ImageRgba8(image)
+        .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png);
+    png
+}
+
+/// Draw `label` centred on a light background, as large as fits
+fn draw_label(image: &mut image::RgbaImage, label: &str) {
+    let size = image.width();
+    let columns = label.len() as u32 * (GLYPH_WIDTH + 1) - 1;
+    let scale = (size / 64).min(size * 3 / 4 / columns).max(1);
+    let (width, height) = (columns * scale, GLYPH_HEIGHT * scale);
+    let left = size.saturating_sub(width) / 2;
+    let top = size.saturating_sub(height) / 2;
+
+    let padding = scale * 2;
+    for y in top.saturating_sub(padding)..(top + height + padding).min(size) {
+        for x in left.saturating_sub(padding)..(left + width + padding).min(size) {
+            image.put_pixel(x, y, image::Rgba(LIGHT));
+        }
+    }
+
+    for (i, character) in label.chars().enumerate() {
+        let glyph = match character {
+            '0'..='9' => DIGITS[character as usize - '0' as usize],
+            _ => SLASH,
+        };
+        let glyph_left = left + i as u32 * (GLYPH_WIDTH + 1) * scale;
+        for (row, bits) in glyph.iter().enumerate() {
+            for column in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - column)) == 0 {
+                    continue;
+                }
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        let x = glyph_left + column * scale + dx;
+                        let y = top + row as u32 * scale + dy;
+                        if x < size && y < size {
+                            image.put_pixel(x, y, image::Rgba(LABEL));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Mix the bits of `coord` and `seed` into a number in `[0, 1)`
+fn tile_fraction(coord: TileCoord, seed: u64) -> f64 {
+    // splitmix64 finaliser
+    let mut value = seed ^ ((coord.z as u64) << 58) ^ ((coord.x as u64) << 29) ^ coord.y as u64;
+    value = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    value ^= value >> 31;
+    (value >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Shared state of a debug source and its clones
+struct Generator {
+    tile_size: u32,
+    latency: Duration,
+    failure_rate: f64,
+    seed: u64,
+    failing_tiles: Mutex<HashSet<TileCoord>>,
+    offline: AtomicBool,
+    generated: AtomicU64,
+    failed: AtomicU64,
+}
+
+impl Generator {
+    fn fails(&self, coord: TileCoord) -> bool {
+        self.offline.load(Ordering::Relaxed)
+            || self
+                .failing_tiles
+                .lock()
+                .map(|failing| failing.contains(&coord))
+                .unwrap_or(false)
+            || tile_fraction(coord, self.seed) < self.failure_rate
+    }
+}
+
+impl TileDataSource for Generator {
+    fn load_tile(&self, coord: TileCoord) -> TileDataFuture<'_> {
+        Box::pin(async move {
+            if !self.latency.is_zero() {
+                async_delay(self.latency).await;
+            }
+            if self.fails(coord) {
+                self.failed.fetch_add(1, Ordering::Relaxed);
+                return Err(format!("Simulated failure for tile {:?}", coord).into());
+            }
+            let tile_size = self.tile_size;
+            let png =
+                AsyncExecutor::execute_blocking(move || Ok(debug_tile(coord, tile_size))).await?;
+            self.generated.fetch_add(1, Ordering::Relaxed);
+            Ok(Some(png))
+        })
+    }
+}
+
+/// Tile source that draws labelled checkerboard tiles locally
+///
+/// Failures are deterministic: the same tiles fail for the same seed and
+/// failure rate on every run. Clones share their state, so a clone kept by
+/// a test can take the source offline or count the tiles it generated
+/// while a layer owns the original.
+#[derive(Clone)]
+pub struct DebugTileSource {
+    generator: Arc<Generator>,
+}
+
+impl DebugTileSource {
+    pub fn new() -> Self {
+        Self {
+            generator: Arc::new(Generator {
+                tile_size: 256,
+                latency: Duration::ZERO,
+                failure_rate: 0.0,
+                seed: 0,
+                failing_tiles: Mutex::new(HashSet::default()),
+                offline: AtomicBool::new(false),
+                generated: AtomicU64::new(0),
+                failed: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    fn configure(mut self, f: impl FnOnce(&mut Generator)) -> Self {
+        // Configuration happens before the source is shared
+        if let Some(generator) = Arc::get_mut(&mut self.generator) {
+            f(generator);
+        }
+        self
+    }
+
+    /// Size of the generated images, in pixels
+    pub fn with_tile_size(self, tile_size: u32) -> Self {
+        self.configure(|generator| generator.tile_size = tile_size)
+    }
+
+    /// Delay every tile by `latency`, as a slow server would
+    pub fn with_latency(self, latency: Duration) -> Self {
+        self.configure(|generator| generator.latency = latency)
+    }
+
+    /// Fail this fraction of tiles, chosen by `seed`
+    pub fn with_failure_rate(self, failure_rate: f64, seed: u64) -> Self {
+        self.configure(|generator| {
+            generator.failure_rate = failure_rate.clamp(0.0, 1.0);
+            generator.seed = seed;
+        })
+    }
+
+    /// Fail these tiles until [`DebugTileSource::set_failing_tiles`] changes
+    /// them
+    pub fn with_failing_tiles(self, tiles: impl IntoIterator<Item = TileCoord>) -> Self {
+        self.set_failing_tiles(tiles);
+        self
+    }
+
+    pub fn set_failing_tiles(&self, tiles: impl IntoIterator<Item = TileCoord>) {
+        if let Ok(mut failing) = self.generator.failing_tiles.lock() {
+            *failing = tiles.into_iter().collect();
+        }
+    }
+
+    /// Fail every tile while `offline`, as if the server were down
+    pub fn set_offline(&self, offline: bool) {
+        self.generator.offline.store(offline, Ordering::Relaxed);
+    }
+
+    /// Whether loading `coord` fails at the moment
+    pub fn fails(&self, coord: TileCoord) -> bool {
+        self.generator.fails(coord)
+    }
+
+    /// Number of tiles generated so far
+    pub fn generated_tiles(&self) -> u64 {
+        self.generator.generated.load(Ordering::Relaxed)
+    }
+
+    /// Number of simulated failures so far
+    pub fn failed_tiles(&self) -> u64 {
+        self.generator.failed.load(Ordering::Relaxed)
+    }
+
+    pub fn tile_size(&self) -> u32 {
+        self.generator.tile_size
+    }
+}
+
+impl Default for DebugTileSource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TileSource for DebugTileSource {
+    fn url(&self, coord: TileCoord) -> String {
+        format!("debug://{}/{}/{}", coord.z, coord.x, coord.y)
+    }
+
+    fn has_tile(&self, coord: TileCoord) -> bool {
+        coord.is_valid()
+    }
+
+    fn source_id(&self) -> String {
+        format!("debug:{}", self.generator.tile_size)
+    }
+
+    fn data_source(&self) -> Option<Arc<dyn TileDataSource>> {
+        Some(self.generator.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_debug_tile_has_border_checkerboard_and_label() {
+        let coord = TileCoord::new(5, 9, 4);
+        let image = image::load_from_memory(&debug_tile(coord, 256))
+            .unwrap()
+            .to_rgba8();
+        assert_eq!(image.dimensions(), (256, 256));
+        assert_eq!(image.get_pixel(0, 100).0, BORDER);
+        assert_eq!(image.get_pixel(255, 0).0, BORDER);
+        // Squares alternate between the light colour and the zoom's tint
+        assert_eq!(image.get_pixel(10, 10).0, LIGHT);
+        assert_eq!(image.get_pixel(40, 10).0, ZOOM_TINTS[4]);
+        // The label is drawn in the middle
+        assert!(image.pixels().any(|pixel| pixel.0 == LABEL));
+        assert_ne!(
+            debug_tile(coord, 256),
+            debug_tile(TileCoord::new(5, 9, 5), 256)
+        );
+
+        // Long labels still fit inside small tiles
+        let image = image::load_from_memory(&debug_tile(TileCoord::new(262143, 262143, 18), 64))
+            .unwrap()
+            .to_rgba8();
+        assert_eq!(image.width(), 64);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_injected_failures_are_deterministic() {
+        let source = DebugTileSource::new().with_failure_rate(0.25, 7);
+        let tiles = TileCoord::new(0, 0, 0).descendants(5);
+        let failing: Vec<bool> = tiles.iter().map(|coord| source.fails(*coord)).collect();
+        let rate = failing.iter().filter(|fails| **fails).count() as f64 / tiles.len() as f64;
+        assert!((0.15..0.35).contains(&rate), "{}", rate);
+        let again = DebugTileSource::new().with_failure_rate(0.25, 7);
+        assert!(tiles
+            .iter()
+            .zip(&failing)
+            .all(|(coord, fails)| again.fails(*coord) == *fails));
+
+        let data_source = source.data_source().unwrap();
+        let ok = tiles[failing.iter().position(|fails| !fails).unwrap()];
+        let bad = tiles[failing.iter().position(|fails| *fails).unwrap()];
+        assert!(data_source.load_tile(ok).await.unwrap().is_some());
+        assert!(data_source.load_tile(bad).await.is_err());
+
+        source.set_offline(true);
+        assert!(data_source.load_tile(ok).await.is_err());
+        assert_eq!((source.generated_tiles(), source.failed_tiles()), (1, 2));
+    }
+}
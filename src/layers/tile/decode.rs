@@ -0,0 +1,177 @@
+//! Tile image decoding
+//!
+//! Raster tiles arrive as PNG, JPEG or WebP files. Decoding one takes a few
+//! milliseconds, which adds up to a dropped frame when a pan brings in a
+//! screenful of tiles, so the tile loader decodes them on a blocking worker
+//! into [`DecodedTile`]s that the renderer uploads as they are.
+
+use crate::Result;
+
+/// Image formats served by raster tile servers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileImageFormat {
+    Png,
+    Jpeg,
+    WebP,
+    Gif,
+}
+
+impl TileImageFormat {
+    /// Format of `data` going by its magic bytes, whatever the URL or
+    /// `Content-Type` claimed
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(TileImageFormat::Png)
+        } else if data.starts_with(&[0xff, 0xd8, 0xff]) {
+            Some(TileImageFormat::Jpeg)
+        } else if data.len() >= 12 && data.starts_with(b"RIFF") && &data[8..12] == b"WEBP" {
+            Some(TileImageFormat::WebP)
+        } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+            Some(TileImageFormat::Gif)
+        } else {
+            None
+        }
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            TileImageFormat::Png => "image/png",
+            TileImageFormat::Jpeg => "image/jpeg",
+            TileImageFormat::WebP => "image/webp",
+            TileImageFormat::Gif => "image/gif",
+        }
+    }
+
+    fn image_format(&self) -> image::ImageFormat {
+        match self {
+            TileImageFormat::Png => image::ImageFormat::Png,
+            TileImageFormat::Jpeg => image::ImageFormat::Jpeg,
+            TileImageFormat::WebP => image::ImageFormat::WebP,
+            TileImageFormat::Gif => image::ImageFormat::Gif,
+        }
+    }
+}
+
+/// A tile image decoded to RGBA pixels, ready to upload as a texture
+#[derive(Clone, PartialEq)]
+pub struct DecodedTile {
+    pub width: u32,
+    pub height: u32,
+    /// Format the pixels were decoded from
+    pub format: TileImageFormat,
+    /// Unpremultiplied RGBA, row by row from the top
+    pub rgba: Vec<u8>,
+}
+
+impl std::fmt::Debug for DecodedTile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DecodedTile")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("format", &self.format)
+            .finish()
+    }
+}
+
+impl DecodedTile {
+    /// Decode a tile image
+    ///
+    /// Returns `None` for data that is not in a known image format, such as
+    /// vector tiles or a test server's placeholder bytes.
+    pub fn decode(data: &[u8]) -> Result<Option<Self>> {
+        let Some(format) = TileImageFormat::detect(data) else {
+            return Ok(None);
+        };
+        let image = image::load_from_memory_with_format(data, format.image_format())
+            .map_err(|e| format!("Failed to decode {} tile: {}", format.mime_type(), e))?
+            .into_rgba8();
+        Ok(Some(Self {
+            width: image.width(),
+            height: image.height(),
+            format,
+            rgba: image.into_raw(),
+        }))
+    }
+
+    /// Memory taken by the pixels
+    pub fn byte_size(&self) -> usize {
+        self.rgba.len()
+    }
+
+    /// `[width, height]`, as egui sizes images
+    pub fn size(&self) -> [usize; 2] {
+        [self.width as usize, self.height as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::geo::TileCoord;
+    use crate::layers::tile::debug_tiles::debug_tile;
+
+    /// 1x1 lossless and lossy WebP images
+    const WEBP_LOSSLESS: [u8; 34] = [
+        0x52, 0x49, 0x46, 0x46, 0x1a, 0x00, 0x00, 0x00, 0x57, 0x45, 0x42, 0x50, 0x56, 0x50, 0x38,
+        0x4c, 0x0d, 0x00, 0x00, 0x00, 0x2f, 0x00, 0x00, 0x00, 0x10, 0x07, 0x10, 0x11, 0x11, 0x88,
+        0x88, 0xfe, 0x07, 0x00,
+    ];
+    const WEBP_LOSSY: [u8; 42] = [
+        0x52, 0x49, 0x46, 0x46, 0x22, 0x00, 0x00, 0x00, 0x57, 0x45, 0x42, 0x50, 0x56, 0x50, 0x38,
+        0x20, 0x16, 0x00, 0x00, 0x00, 0x30, 0x01, 0x00, 0x9d, 0x01, 0x2a, 0x01, 0x00, 0x01, 0x00,
+        0x0e, 0xc0, 0xfe, 0x25, 0xa4, 0x00, 0x03, 0x70, 0x00, 0x00, 0x00, 0x00,
+    ];
+
+    fn encode(format: image::ImageOutputFormat) -> Vec<u8> {
+        let image = image::RgbImage::from_pixel(8, 4, image::Rgb([200, 40, 10]));
+        let mut data = std::io::Cursor::new(Vec::new());
+        image.write_to(&mut data, format).unwrap();
+        data.into_inner()
+    }
+
+    #[test]
+    fn test_formats_are_detected_by_magic_bytes() {
+        let png = debug_tile(TileCoord::new(0, 0, 0), 64);
+        let jpeg = encode(image::ImageOutputFormat::Jpeg(90));
+        let cases: [(&[u8], Option<TileImageFormat>); 7] = [
+            (&png, Some(TileImageFormat::Png)),
+            (&jpeg, Some(TileImageFormat::Jpeg)),
+            (&WEBP_LOSSLESS, Some(TileImageFormat::WebP)),
+            (&WEBP_LOSSY, Some(TileImageFormat::WebP)),
+            (b"GIF89a\x01\x00\x01\x00", Some(TileImageFormat::Gif)),
+            (b"RIFF\x00\x00\x00\x00WAVE", None),
+            (b"tile", None),
+        ];
+        for (data, format) in cases {
+            assert_eq!(TileImageFormat::detect(data), format);
+        }
+    }
+
+    #[test]
+    fn test_decoding_to_rgba() {
+        let png = DecodedTile::decode(&debug_tile(TileCoord::new(0, 0, 0), 64))
+            .unwrap()
+            .unwrap();
+        assert_eq!(png.size(), [64, 64]);
+        assert_eq!(png.byte_size(), 64 * 64 * 4);
+
+        let jpeg = DecodedTile::decode(&encode(image::ImageOutputFormat::Jpeg(90)))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            (jpeg.width, jpeg.height, jpeg.format),
+            (8, 4, TileImageFormat::Jpeg)
+        );
+        assert_eq!(jpeg.rgba[3], 255);
+
+        for webp in [&WEBP_LOSSLESS[..], &WEBP_LOSSY[..]] {
+            let decoded = DecodedTile::decode(webp).unwrap().unwrap();
+            assert_eq!(decoded.size(), [1, 1]);
+            assert_eq!(decoded.format, TileImageFormat::WebP);
+        }
+
+        // Unknown data is left alone, broken images are errors
+        assert_eq!(DecodedTile::decode(b"tile").unwrap(), None);
+        assert!(DecodedTile::decode(b"\x89PNG\r\n\x1a\ntruncated").is_err());
+    }
+}
@@ -0,0 +1,200 @@
+//! Raster elevation (DEM) tiles
+//!
+//! Decodes elevation tiles that pack heights into the RGB channels of an
+//! image, in either the Terrarium or the Mapbox Terrain-RGB encoding, and
+//! answers elevation queries from the tiles loaded so far.
+
+use crate::core::geo::{LatLng, TileCoord};
+use crate::prelude::{Arc, Mutex};
+use crate::Result;
+use lru::LruCache;
+use std::num::NonZeroUsize;
+
+/// How heights are packed into the red, green and blue channels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DemEncoding {
+    /// `(r * 256 + g + b / 256) - 32768` metres
+    Terrarium,
+    /// `-10000 + (r * 65536 + g * 256 + b) * 0.1` metres
+    MapboxRgb,
+}
+
+impl DemEncoding {
+    /// Height in metres encoded by one pixel
+    pub fn decode(&self, r: u8, g: u8, b: u8) -> f32 {
+        let (r, g, b) = (r as f32, g as f32, b as f32);
+        match self {
+            DemEncoding::Terrarium => r * 256.0 + g + b / 256.0 - 32768.0,
+            DemEncoding::MapboxRgb => -10000.0 + (r * 65536.0 + g * 256.0 + b) * 0.1,
+        }
+    }
+}
+
+/// Heights of one tile, row by row from the north-west corner
+#[derive(Debug, Clone, PartialEq)]
+pub struct DemTile {
+    pub width: u32,
+    pub height: u32,
+    pub heights: Vec<f32>,
+}
+
+impl DemTile {
+    /// Decode an encoded elevation image (PNG, WebP, ...)
+    pub fn decode(data: &[u8], encoding: DemEncoding) -> Result<Self> {
+        let image = image::load_from_memory(data)
+            .map_err(|e| format!("Failed to decode elevation tile: {}", e))?
+            .to_rgb8();
+        let (width, height) = image.dimensions();
+        let heights = image
+            .pixels()
+            .map(|pixel| encoding.decode(pixel[0], pixel[1], pixel[2]))
+            .collect();
+        Ok(Self {
+            width,
+            height,
+            heights,
+        })
+    }
+
+    /// Height of the pixel at `(x, y)`, clamped to the tile edges
+    pub fn height_at(&self, x: i64, y: i64) -> f32 {
+        let x = x.clamp(0, self.width as i64 - 1) as usize;
+        let y = y.clamp(0, self.height as i64 - 1) as usize;
+        self.heights[y * self.width as usize + x]
+    }
+
+    /// Bilinearly interpolated height at a fractional position within the
+    /// tile, where `(0, 0)` is the north-west corner and `(1, 1)` the
+    /// south-east corner
+    pub fn sample(&self, fx: f64, fy: f64) -> f32 {
+        // Pixel centres sit half a pixel in from the edges
+        let x = fx * self.width as f64 - 0.5;
+        let y = fy * self.height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = ((x - x0) as f32, (y - y0) as f32);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let top = self.height_at(x0, y0) * (1.0 - tx) + self.height_at(x0 + 1, y0) * tx;
+        let bottom = self.height_at(x0, y0 + 1) * (1.0 - tx) + self.height_at(x0 + 1, y0 + 1) * tx;
+        top * (1.0 - ty) + bottom * ty
+    }
+}
+
+/// Loaded elevation tiles shared between a layer and elevation lookups
+pub(crate) type DemStore = Arc<Mutex<LruCache<TileCoord, Arc<DemTile>>>>;
+
+pub(crate) fn new_dem_store(capacity: usize) -> DemStore {
+    Arc::new(Mutex::new(LruCache::new(
+        NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::new(1).unwrap()),
+    )))
+}
+
+/// Elevation queries against the tiles a terrain layer has loaded
+///
+/// Cheap to clone and usable from any thread. Lookups use the most detailed
+/// loaded tile covering the position and return `None` where nothing has
+/// loaded yet.
+#[derive(Clone)]
+pub struct ElevationLookup {
+    tiles: DemStore,
+    min_zoom: u8,
+    max_zoom: u8,
+}
+
+impl ElevationLookup {
+    pub(crate) fn new(tiles: DemStore, min_zoom: u8, max_zoom: u8) -> Self {
+        Self {
+            tiles,
+            min_zoom,
+            max_zoom,
+        }
+    }
+
+    /// Elevation in metres at `lat_lng`
+    pub fn elevation_at(&self, lat_lng: &LatLng) -> Option<f64> {
+        let lat = LatLng::clamp_lat(lat_lng.lat).to_radians();
+        let x = (lat_lng.lng + 180.0) / 360.0;
+        let y = (1.0 - lat.tan().asinh() / std::f64::consts::PI) / 2.0;
+        if !(0.0..=1.0).contains(&x) || !(0.0..=1.0).contains(&y) {
+            return None;
+        }
+
+        let tiles = self.tiles.lock().ok()?;
+        (self.min_zoom..=self.max_zoom).rev().find_map(|zoom| {
+            let n = 2_f64.powi(zoom as i32);
+            let last = n - 1.0;
+            let (tx, ty) = ((x * n).floor().min(last), (y * n).floor().min(last));
+            let tile = tiles.peek(&TileCoord::new(tx as u32, ty as u32, zoom))?;
+            Some(tile.sample(x * n - tx, y * n - ty) as f64)
+        })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Encode `heights` (row-major) as a Terrarium PNG
+    pub(crate) fn terrarium_png(width: u32, height: u32, heights: &[f32]) -> Vec<u8> {
+        let image = image::RgbImage::from_fn(width, height, |x, y| {
+            let value = heights[(y * width + x) as usize] + 32768.0;
+            let r = (value / 256.0).floor();
+            let g = (value - r * 256.0).floor();
+            let b = ((value - r * 256.0 - g) * 256.0).round();
+            image::Rgb([r as u8, g as u8, b as u8])
+        });
+        let mut data = Vec::new();
+        image::DynamicImage::ImageRgb8(image)
+            .write_to(
+                &mut std::io::Cursor::new(&mut data),
+                image::ImageFormat::Png,
+            )
+            .unwrap();
+        data
+    }
+
+    #[test]
+    fn test_encodings() {
+        assert_eq!(DemEncoding::Terrarium.decode(128, 0, 0), 0.0);
+        assert_eq!(DemEncoding::Terrarium.decode(129, 2, 128), 258.5);
+        assert_eq!(DemEncoding::MapboxRgb.decode(1, 134, 160), 0.0);
+        assert!((DemEncoding::MapboxRgb.decode(1, 138, 136) - 100.0).abs() < 1e-3);
+
+        let tile = DemTile::decode(
+            &terrarium_png(2, 1, &[-12.5, 8848.0]),
+            DemEncoding::Terrarium,
+        )
+        .unwrap();
+        assert_eq!((tile.width, tile.height), (2, 1));
+        assert_eq!(tile.heights, vec![-12.5, 8848.0]);
+        assert!(DemTile::decode(b"not an image", DemEncoding::Terrarium).is_err());
+    }
+
+    #[test]
+    fn test_lookup_prefers_detailed_tiles() {
+        let store = new_dem_store(8);
+        let lookup = ElevationLookup::new(store.clone(), 0, 2);
+        assert_eq!(lookup.elevation_at(&LatLng::new(10.0, 10.0)), None);
+
+        let flat = |height: f32| {
+            Arc::new(DemTile {
+                width: 2,
+                height: 2,
+                heights: vec![height; 4],
+            })
+        };
+        store
+            .lock()
+            .unwrap()
+            .put(TileCoord::new(0, 0, 0), flat(100.0));
+        assert_eq!(lookup.elevation_at(&LatLng::new(10.0, 10.0)), Some(100.0));
+
+        // North-east quarter at z1 wins over the world tile
+        store
+            .lock()
+            .unwrap()
+            .put(TileCoord::new(1, 0, 1), flat(250.0));
+        assert_eq!(lookup.elevation_at(&LatLng::new(10.0, 10.0)), Some(250.0));
+        assert_eq!(lookup.elevation_at(&LatLng::new(-10.0, 10.0)), Some(100.0));
+    }
+}
@@ -0,0 +1,267 @@
+//! Tiles stored as files in a local directory tree
+//!
+//! Reads `{z}/{x}/{y}.{ext}` trees such as those written by `gdal2tiles` or
+//! exported from a tile server's cache, without any archive format. The
+//! image extension and zoom range are detected from the files present when
+//! the directory is opened.
+
+use super::source::{covers_tile, TileDataFuture, TileDataSource, TileSource};
+use super::types::TileLayerOptions;
+use crate::background::tasks::AsyncExecutor;
+use crate::core::geo::TileCoord;
+use crate::prelude::Arc;
+use crate::Result;
+use std::path::{Path, PathBuf};
+
+/// Image extensions recognised when detecting a directory's format, in order
+/// of preference
+const IMAGE_EXTENSIONS: [&str; 5] = ["png", "jpg", "jpeg", "webp", "gif"];
+
+/// Reads tile files for the tile loader
+#[derive(Clone)]
+struct DirectoryReader {
+    root: PathBuf,
+    extension: String,
+    tms: bool,
+}
+
+impl DirectoryReader {
+    fn tile_path(&self, coord: TileCoord) -> PathBuf {
+        // TMS rows are numbered bottom-up
+        let y = if self.tms { coord.flip_y().y } else { coord.y };
+        self.root
+            .join(coord.z.to_string())
+            .join(coord.x.to_string())
+            .join(format!("{}.{}", y, self.extension))
+    }
+
+    fn read_tile(path: &Path) -> Result<Option<Vec<u8>>> {
+        match std::fs::read(path) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(format!("Failed to read tile {}: {}", path.display(), e).into()),
+        }
+    }
+}
+
+impl TileDataSource for DirectoryReader {
+    fn load_tile(&self, coord: TileCoord) -> TileDataFuture<'_> {
+        let path = self.tile_path(coord);
+        Box::pin(AsyncExecutor::execute_blocking(move || {
+            Self::read_tile(&path)
+        }))
+    }
+}
+
+/// Tile source backed by a `{z}/{x}/{y}.{ext}` directory tree
+pub struct DirectoryTileSource {
+    reader: Arc<DirectoryReader>,
+    min_zoom: Option<u8>,
+    max_zoom: Option<u8>,
+}
+
+impl DirectoryTileSource {
+    /// Open an XYZ directory, detecting the image extension and zoom range
+    /// from the files it contains
+    pub fn open(root: impl AsRef<Path>) -> Result<Self> {
+        let root = root.as_ref().to_path_buf();
+        let zooms = numbered_entries(&root)
+            .map_err(|e| format!("Failed to open tile directory {}: {}", root.display(), e))?;
+        let extension = zooms
+            .iter()
+            .find_map(|(_, zoom_dir)| detect_extension(zoom_dir))
+            .ok_or_else(|| {
+                format!(
+                    "{} contains no {{z}}/{{x}}/{{y}} image tiles",
+                    root.display()
+                )
+            })?;
+
+        Ok(Self {
+            reader: Arc::new(DirectoryReader {
+                root,
+                extension,
+                tms: false,
+            }),
+            min_zoom: zooms.iter().map(|(zoom, _)| *zoom).min(),
+            max_zoom: zooms.iter().map(|(zoom, _)| *zoom).max(),
+        })
+    }
+
+    /// Number rows bottom-up, as in `gdal2tiles`' default TMS layout
+    pub fn with_tms(mut self, tms: bool) -> Self {
+        Arc::make_mut(&mut self.reader).tms = tms;
+        self
+    }
+
+    /// Read files with `extension` instead of the detected one
+    pub fn with_extension(mut self, extension: impl Into<String>) -> Self {
+        let extension = extension.into();
+        Arc::make_mut(&mut self.reader).extension = extension.trim_start_matches('.').to_string();
+        self
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.reader.root
+    }
+
+    /// Extension of the tile files, without the dot
+    pub fn extension(&self) -> &str {
+        &self.reader.extension
+    }
+
+    pub fn is_tms(&self) -> bool {
+        self.reader.tms
+    }
+
+    /// Lowest and highest zoom with a directory in the tree
+    pub fn zoom_range(&self) -> Option<(u8, u8)> {
+        self.min_zoom.zip(self.max_zoom)
+    }
+
+    /// Limit `options` to the zooms the directory has tiles for
+    ///
+    /// The layer is hidden when zoomed out past the shallowest level, and
+    /// zooming in past the deepest level shows its tiles scaled up rather
+    /// than leaving the map blank.
+    pub fn apply_to_options(&self, options: &mut TileLayerOptions) {
+        if let Some((min_zoom, max_zoom)) = self.zoom_range() {
+            options.min_zoom = min_zoom;
+            options.max_native_zoom = Some(max_zoom);
+        }
+    }
+
+    /// Read a tile; `Ok(None)` when the directory has no file for `coord`
+    pub async fn read_tile(&self, coord: TileCoord) -> Result<Option<Vec<u8>>> {
+        self.reader.load_tile(coord).await
+    }
+}
+
+impl TileSource for DirectoryTileSource {
+    fn url(&self, coord: TileCoord) -> String {
+        format!("file://{}", self.reader.tile_path(coord).display())
+    }
+
+    fn has_tile(&self, coord: TileCoord) -> bool {
+        covers_tile(coord, self.min_zoom, self.max_zoom, None)
+    }
+
+    fn source_id(&self) -> String {
+        format!("dir:{}", self.reader.root.display())
+    }
+
+    fn data_source(&self) -> Option<Arc<dyn TileDataSource>> {
+        Some(self.reader.clone())
+    }
+}
+
+/// Subdirectories of `dir` named by a number, as `(number, path)`
+fn numbered_entries(dir: &Path) -> std::io::Result<Vec<(u8, PathBuf)>> {
+    let mut entries = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let number = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.parse::<u8>().ok());
+        if let (Some(number), true) = (number, path.is_dir()) {
+            entries.push((number, path));
+        }
+    }
+    entries.sort();
+    Ok(entries)
+}
+
+/// Extension of the first image tile found under a zoom directory
+fn detect_extension(zoom_dir: &Path) -> Option<String> {
+    std::fs::read_dir(zoom_dir)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_dir())
+        .find_map(|column_dir| {
+            let found: Vec<String> = std::fs::read_dir(column_dir)
+                .ok()?
+                .filter_map(|entry| {
+                    let path = entry.ok()?.path();
+                    path.extension()?.to_str().map(str::to_string)
+                })
+                .collect();
+            // Keep the extension as written, since file names are case sensitive
+            IMAGE_EXTENSIONS.iter().find_map(|extension| {
+                found
+                    .iter()
+                    .find(|found| found.eq_ignore_ascii_case(extension))
+                    .cloned()
+            })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Write `tiles` as `{z}/{x}/{y}.{ext}` files under a fresh directory
+    fn create_tree(name: &str, tiles: &[(u8, u32, u32, &str)]) -> PathBuf {
+        let root =
+            std::env::temp_dir().join(format!("maplet_directory_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        for (z, x, y, extension) in tiles {
+            let column = root.join(z.to_string()).join(x.to_string());
+            std::fs::create_dir_all(&column).unwrap();
+            std::fs::write(
+                column.join(format!("{}.{}", y, extension)),
+                format!("{}/{}/{}", z, x, y),
+            )
+            .unwrap();
+        }
+        // Files alongside the tiles are ignored
+        std::fs::write(root.join("tilemapresource.xml"), "<TileMap/>").unwrap();
+        root
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_detects_layout_and_reads_tiles() {
+        let root = create_tree(
+            "xyz",
+            &[(2, 1, 1, "jpg"), (3, 2, 3, "jpg"), (4, 5, 6, "jpg")],
+        );
+        let source = DirectoryTileSource::open(&root).unwrap();
+        assert_eq!(source.extension(), "jpg");
+        assert_eq!(source.zoom_range(), Some((2, 4)));
+        assert!(source.has_tile(TileCoord::new(0, 0, 3)));
+        assert!(!source.has_tile(TileCoord::new(0, 0, 5)));
+
+        let tile = source.read_tile(TileCoord::new(2, 3, 3)).await.unwrap();
+        assert_eq!(tile.as_deref(), Some(&b"3/2/3"[..]));
+        // A missing file is a miss, not an error
+        assert_eq!(
+            source.read_tile(TileCoord::new(0, 0, 3)).await.unwrap(),
+            None
+        );
+
+        let mut options = TileLayerOptions::default();
+        source.apply_to_options(&mut options);
+        assert_eq!((options.min_zoom, options.min_native_zoom), (2, None));
+        assert_eq!(options.max_native_zoom, Some(4));
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_tms_rows_and_extension_override() {
+        // TMS row 0 at zoom 1 is the southern row, y = 1 in XYZ terms
+        let root = create_tree("tms", &[(1, 0, 0, "png"), (1, 0, 1, "webp")]);
+        let source = DirectoryTileSource::open(&root).unwrap().with_tms(true);
+        assert_eq!(source.extension(), "png");
+        let tile = source.read_tile(TileCoord::new(0, 1, 1)).await.unwrap();
+        assert_eq!(tile.as_deref(), Some(&b"1/0/0"[..]));
+
+        let source = source.with_extension(".webp");
+        let tile = source.read_tile(TileCoord::new(0, 0, 1)).await.unwrap();
+        assert_eq!(tile.as_deref(), Some(&b"1/0/1"[..]));
+
+        assert!(DirectoryTileSource::open(root.join("missing")).is_err());
+        std::fs::create_dir_all(root.join("empty")).unwrap();
+        assert!(DirectoryTileSource::open(root.join("empty")).is_err());
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
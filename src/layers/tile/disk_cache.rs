@@ -0,0 +1,941 @@
+//! Persistent on-disk tier for tile data
+//!
+//! Tiles are stored as one file per tile under
+//! `<directory>/<source hash>/<z>/<x>/<y>.tile`, so the working set survives
+//! restarts without re-downloading. The cache is bounded by total size with
+//! least-recently-used eviction, and entries older than the configured max age
+//! are treated as misses. Writes go to a temporary file that is renamed into
+//! place, so a crash never leaves a half-written tile behind.
+//!
+//! Each file starts with a small header holding the HTTP validators and expiry
+//! of the tile ([`CachedTileInfo`]), so stale tiles can be revalidated instead
+//! of downloaded again.
+//!
+//! Tiles can be pinned, e.g. by an offline region download: pinned tiles count
+//! towards the size budget but are never evicted or expired until every pin
+//! holding them is released. Pins are kept in `<directory>/pins` so they last
+//! across restarts.
+
+use crate::core::geo::TileCoord;
+use crate::prelude::{Arc, Duration, HashMap, HashSet, Mutex};
+use crate::traits::{CacheStats, Cacheable};
+use crate::Result;
+use lru::LruCache;
+use once_cell::sync::Lazy;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Weak;
+use std::time::SystemTime;
+
+const TILE_EXTENSION: &str = "tile";
+const TEMP_EXTENSION: &str = "tmp";
+const PIN_EXTENSION: &str = "pins";
+const PIN_DIRECTORY: &str = "pins";
+/// Source hash, zoom, column and row of a pinned tile
+const PIN_RECORD_LEN: usize = 8 + 1 + 4 + 4;
+/// Marks files written with a [`CachedTileInfo`] header
+const ENTRY_MAGIC: &[u8; 4] = b"MTC\x01";
+
+/// Open caches by directory, so layers pointed at the same directory share one index
+static OPEN_CACHES: Lazy<Mutex<HashMap<PathBuf, Weak<DiskTileCache>>>> =
+    Lazy::new(|| Mutex::new(HashMap::default()));
+
+/// Configuration for the on-disk tile cache
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiskCacheConfig {
+    /// Directory the cache lives in; created if missing
+    pub directory: PathBuf,
+    /// Upper bound for the total size of cached tiles
+    pub max_size_bytes: u64,
+    /// Entries older than this are discarded; `None` keeps them forever
+    pub max_age: Option<Duration>,
+}
+
+impl DiskCacheConfig {
+    /// 256 MB cache in `directory` keeping tiles for 30 days
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+            max_size_bytes: 256 * 1024 * 1024,
+            max_age: Some(Duration::from_secs(30 * 24 * 60 * 60)),
+        }
+    }
+
+    pub fn with_max_size(mut self, max_size_bytes: u64) -> Self {
+        self.max_size_bytes = max_size_bytes;
+        self
+    }
+
+    pub fn with_max_age(mut self, max_age: Option<Duration>) -> Self {
+        self.max_age = max_age;
+        self
+    }
+}
+
+/// HTTP caching details stored with a tile
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CachedTileInfo {
+    /// `ETag` of the response, sent back as `If-None-Match`
+    pub etag: Option<String>,
+    /// `Last-Modified` of the response, sent back as `If-Modified-Since`
+    pub last_modified: Option<String>,
+    /// When the tile goes stale; `None` means it never does
+    pub expires_at: Option<SystemTime>,
+}
+
+impl CachedTileInfo {
+    /// Whether the tile needs revalidating before it can be trusted
+    pub fn is_stale(&self, now: SystemTime) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    /// Whether there is anything to revalidate against
+    pub fn has_validators(&self) -> bool {
+        self.etag.is_some() || self.last_modified.is_some()
+    }
+
+    fn encode(&self, data: &[u8]) -> Vec<u8> {
+        let expires = self
+            .expires_at
+            .and_then(|at| at.duration_since(SystemTime::UNIX_EPOCH).ok())
+            // 0 is reserved for "no expiry"
+            .map(|since| since.as_secs().max(1))
+            .unwrap_or(0);
+
+        let mut out = Vec::with_capacity(data.len() + 64);
+        out.extend_from_slice(ENTRY_MAGIC);
+        out.extend_from_slice(&expires.to_le_bytes());
+        for value in [&self.etag, &self.last_modified] {
+            let bytes = value.as_deref().unwrap_or("").as_bytes();
+            let len = bytes.len().min(u16::MAX as usize);
+            out.extend_from_slice(&(len as u16).to_le_bytes());
+            out.extend_from_slice(&bytes[..len]);
+        }
+        out.extend_from_slice(data);
+        out
+    }
+
+    /// Split a cache file into its info header and tile data. Files without a
+    /// header are treated as bare tile data.
+    fn decode(mut bytes: Vec<u8>) -> CachedTile {
+        fn take<'a>(rest: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+            let (head, tail) = rest.split_at_checked(len)?;
+            *rest = tail;
+            Some(head)
+        }
+        fn text(rest: &mut &[u8]) -> Option<Option<String>> {
+            let len = u16::from_le_bytes(take(rest, 2)?.try_into().ok()?) as usize;
+            let value = String::from_utf8(take(rest, len)?.to_vec()).ok()?;
+            Some((!value.is_empty()).then_some(value))
+        }
+
+        let parsed = bytes.starts_with(ENTRY_MAGIC).then(|| {
+            let mut rest = &bytes[ENTRY_MAGIC.len()..];
+            let expires = u64::from_le_bytes(take(&mut rest, 8)?.try_into().ok()?);
+            let info = CachedTileInfo {
+                etag: text(&mut rest)?,
+                last_modified: text(&mut rest)?,
+                expires_at: (expires > 0)
+                    .then(|| SystemTime::UNIX_EPOCH + Duration::from_secs(expires)),
+            };
+            Some((info, bytes.len() - rest.len()))
+        });
+
+        match parsed.flatten() {
+            Some((info, header_len)) => CachedTile {
+                data: bytes.split_off(header_len),
+                info,
+            },
+            None => CachedTile {
+                data: bytes,
+                info: CachedTileInfo::default(),
+            },
+        }
+    }
+}
+
+/// A tile read back from the disk cache
+#[derive(Debug, Clone, PartialEq)]
+pub struct CachedTile {
+    pub data: Vec<u8>,
+    pub info: CachedTileInfo,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct DiskKey {
+    source: u64,
+    coord: TileCoord,
+}
+
+/// A tile held by at least one pin
+#[derive(Debug, Clone, Copy)]
+struct PinnedTile {
+    /// Number of pins holding the tile
+    pins: usize,
+    /// Size on disk, `None` until the tile is stored
+    size: Option<u64>,
+}
+
+/// In-memory view of what is on disk
+#[derive(Debug)]
+struct DiskIndex {
+    /// Tiles that may be evicted, in least-recently-used order
+    entries: LruCache<DiskKey, u64>,
+    /// Tiles exempt from eviction and max age
+    pinned: HashMap<DiskKey, PinnedTile>,
+    /// Tiles of each pin, by hash of the pin name
+    pins: HashMap<u64, HashSet<DiskKey>>,
+    /// Size of every stored tile, pinned or not
+    total_bytes: u64,
+}
+
+impl DiskIndex {
+    fn size(&self, key: &DiskKey) -> Option<u64> {
+        match self.pinned.get(key) {
+            Some(tile) => tile.size,
+            None => self.entries.peek(key).copied(),
+        }
+    }
+
+    fn insert(&mut self, key: DiskKey, size: u64) {
+        self.remove(&key);
+        match self.pinned.get_mut(&key) {
+            Some(tile) => tile.size = Some(size),
+            None => {
+                self.entries.put(key, size);
+            }
+        }
+        self.total_bytes += size;
+    }
+
+    /// Forget a stored tile, keeping any pins on it for when it is stored
+    /// again. Returns its size.
+    fn remove(&mut self, key: &DiskKey) -> Option<u64> {
+        let size = match self.pinned.get_mut(key) {
+            Some(tile) => tile.size.take(),
+            None => self.entries.pop(key),
+        }?;
+        self.total_bytes -= size;
+        Some(size)
+    }
+
+    /// Hold `keys` under the pin `pin`, replacing what it held before
+    fn pin(&mut self, pin: u64, keys: HashSet<DiskKey>) {
+        self.unpin(pin);
+        for key in &keys {
+            let tile = self.pinned.entry(*key).or_insert_with(|| PinnedTile {
+                pins: 0,
+                size: None,
+            });
+            if tile.pins == 0 {
+                tile.size = self.entries.pop(key);
+            }
+            tile.pins += 1;
+        }
+        self.pins.insert(pin, keys);
+    }
+
+    /// Release the pin `pin`; tiles no other pin holds may be evicted again
+    fn unpin(&mut self, pin: u64) {
+        for key in self.pins.remove(&pin).unwrap_or_default() {
+            let Some(tile) = self.pinned.get_mut(&key) else {
+                continue;
+            };
+            tile.pins -= 1;
+            if tile.pins == 0 {
+                if let Some(size) = self.pinned.remove(&key).and_then(|tile| tile.size) {
+                    self.entries.put(key, size);
+                }
+            }
+        }
+    }
+
+    /// Size of the stored tiles held by pins other than `except`
+    fn pinned_bytes(&self, except: Option<u64>) -> u64 {
+        let own = except.and_then(|pin| self.pins.get(&pin));
+        self.pinned
+            .iter()
+            .filter(|(key, tile)| !(tile.pins == 1 && own.is_some_and(|own| own.contains(key))))
+            .filter_map(|(_, tile)| tile.size)
+            .sum()
+    }
+}
+
+/// Size-bounded, persistent tile cache
+#[derive(Debug)]
+pub struct DiskTileCache {
+    config: DiskCacheConfig,
+    index: Mutex<DiskIndex>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    temp_counter: AtomicU64,
+}
+
+/// Stable 64-bit FNV-1a hash, used to name per-source directories
+fn source_hash(source_id: &str) -> u64 {
+    source_id.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Subdirectories of `directory` whose names parse with `parse`
+fn numbered_entries<T>(directory: &Path, parse: impl Fn(&str) -> Option<T>) -> Vec<(T, PathBuf)> {
+    let Ok(entries) = std::fs::read_dir(directory) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| Some((parse(entry.file_name().to_str()?)?, entry.path())))
+        .collect()
+}
+
+impl DiskTileCache {
+    /// Open (or create) a cache and index the tiles already on disk
+    ///
+    /// Leftover temporary files from interrupted writes are removed, and the
+    /// cache is trimmed to the configured size.
+    pub fn open(config: DiskCacheConfig) -> Result<Self> {
+        std::fs::create_dir_all(&config.directory).map_err(|e| {
+            format!(
+                "Failed to create tile cache directory {}: {}",
+                config.directory.display(),
+                e
+            )
+        })?;
+
+        let mut found = Vec::new();
+        Self::scan(&config.directory, &mut found);
+        // Oldest first, so the most recently written tiles end up most recently used
+        found.sort_by_key(|(modified, _, _)| *modified);
+
+        let mut index = DiskIndex {
+            entries: LruCache::unbounded(),
+            pinned: HashMap::default(),
+            pins: HashMap::default(),
+            total_bytes: 0,
+        };
+        for (_, key, size) in found {
+            index.insert(key, size);
+        }
+        for (pin, keys) in Self::read_pins(&config.directory) {
+            index.pin(pin, keys);
+        }
+
+        let cache = Self {
+            config,
+            index: Mutex::new(index),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            temp_counter: AtomicU64::new(0),
+        };
+        if let Ok(mut index) = cache.index.lock() {
+            cache.evict_to_fit(&mut index);
+        }
+        Ok(cache)
+    }
+
+    /// Open a cache shared with every other user of the same directory
+    ///
+    /// Fails when the directory is already open with a different size or age
+    /// limit, since one directory can only be kept within one set of limits.
+    pub fn shared(config: DiskCacheConfig) -> Result<Arc<Self>> {
+        let mut open = OPEN_CACHES
+            .lock()
+            .map_err(|_| "Disk cache registry lock poisoned")?;
+        if let Some(cache) = open.get(&config.directory).and_then(Weak::upgrade) {
+            if cache.config != config {
+                return Err(format!(
+                    "Disk cache {} is already open with different limits",
+                    config.directory.display()
+                )
+                .into());
+            }
+            return Ok(cache);
+        }
+
+        let directory = config.directory.clone();
+        let cache = Arc::new(Self::open(config)?);
+        open.insert(directory, Arc::downgrade(&cache));
+        Ok(cache)
+    }
+
+    /// Collect `(modified, key, size)` for every tile under `directory`
+    fn scan(directory: &Path, found: &mut Vec<(SystemTime, DiskKey, u64)>) {
+        let sources = numbered_entries(directory, |name| u64::from_str_radix(name, 16).ok());
+        for (source, source_dir) in sources {
+            for (z, zoom_dir) in numbered_entries(&source_dir, |name| name.parse::<u8>().ok()) {
+                for (x, column_dir) in numbered_entries(&zoom_dir, |name| name.parse::<u32>().ok())
+                {
+                    let Ok(files) = std::fs::read_dir(column_dir) else {
+                        continue;
+                    };
+                    for file in files.flatten() {
+                        let path = file.path();
+                        match path.extension().and_then(|e| e.to_str()) {
+                            Some(TILE_EXTENSION) => {}
+                            Some(TEMP_EXTENSION) => {
+                                // Interrupted write from a previous run
+                                let _ = std::fs::remove_file(&path);
+                                continue;
+                            }
+                            _ => continue,
+                        }
+                        let y = path.file_stem().and_then(|s| s.to_str()?.parse().ok());
+                        let (Some(y), Ok(metadata)) = (y, file.metadata()) else {
+                            continue;
+                        };
+                        let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                        let key = DiskKey {
+                            source,
+                            coord: TileCoord::new(x, y, z),
+                        };
+                        found.push((modified, key, metadata.len()));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Pins stored under `directory`, by hash of the pin name. Unreadable
+    /// pin files are skipped.
+    fn read_pins(directory: &Path) -> Vec<(u64, HashSet<DiskKey>)> {
+        let Ok(files) = std::fs::read_dir(directory.join(PIN_DIRECTORY)) else {
+            return Vec::new();
+        };
+        files
+            .flatten()
+            .map(|file| file.path())
+            .filter(|path| path.extension().and_then(|e| e.to_str()) == Some(PIN_EXTENSION))
+            .filter_map(|path| {
+                let pin = u64::from_str_radix(path.file_stem()?.to_str()?, 16).ok()?;
+                let bytes = std::fs::read(&path).ok()?;
+                let keys = bytes
+                    .chunks_exact(PIN_RECORD_LEN)
+                    .map(|record| {
+                        let number = |range: std::ops::Range<usize>| {
+                            let mut bytes = [0; 8];
+                            bytes[..range.len()].copy_from_slice(&record[range]);
+                            u64::from_le_bytes(bytes)
+                        };
+                        DiskKey {
+                            source: number(0..8),
+                            coord: TileCoord::new(
+                                number(9..13) as u32,
+                                number(13..17) as u32,
+                                record[8],
+                            ),
+                        }
+                    })
+                    .collect();
+                Some((pin, keys))
+            })
+            .collect()
+    }
+
+    fn pin_path(&self, pin: u64) -> PathBuf {
+        self.config
+            .directory
+            .join(PIN_DIRECTORY)
+            .join(format!("{:016x}.{}", pin, PIN_EXTENSION))
+    }
+
+    fn key(source_id: &str, coord: TileCoord) -> DiskKey {
+        DiskKey {
+            source: source_hash(source_id),
+            coord,
+        }
+    }
+
+    fn tile_path(&self, key: &DiskKey) -> PathBuf {
+        self.config
+            .directory
+            .join(format!("{:016x}", key.source))
+            .join(key.coord.z.to_string())
+            .join(key.coord.x.to_string())
+            .join(format!("{}.{}", key.coord.y, TILE_EXTENSION))
+    }
+
+    fn is_expired(&self, path: &Path) -> bool {
+        let Some(max_age) = self.config.max_age else {
+            return false;
+        };
+        std::fs::metadata(path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age > max_age)
+    }
+
+    /// Read a cached tile. Missing, unreadable and expired entries are misses.
+    pub fn get(&self, source_id: &str, coord: TileCoord) -> Option<Vec<u8>> {
+        self.get_entry(source_id, coord).map(|entry| entry.data)
+    }
+
+    /// Read a cached tile together with its HTTP caching details
+    ///
+    /// Entries past the cache's max age are misses, unless they are pinned;
+    /// HTTP staleness is left to the caller, see [`CachedTileInfo::is_stale`].
+    pub fn get_entry(&self, source_id: &str, coord: TileCoord) -> Option<CachedTile> {
+        let key = Self::key(source_id, coord);
+        let (known, pinned) = self
+            .index
+            .lock()
+            .map(|index| (index.size(&key).is_some(), index.pinned.contains_key(&key)))
+            .unwrap_or((false, false));
+        if !known {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+
+        let path = self.tile_path(&key);
+        let bytes = if !pinned && self.is_expired(&path) {
+            None
+        } else {
+            std::fs::read(&path).ok()
+        };
+
+        match bytes {
+            Some(bytes) => {
+                if let Ok(mut index) = self.index.lock() {
+                    index.entries.promote(&key);
+                }
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Some(CachedTileInfo::decode(bytes))
+            }
+            None => {
+                self.remove_key(&key);
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    /// Store a tile without HTTP caching details
+    pub fn put(&self, source_id: &str, coord: TileCoord, data: &[u8]) -> Result<()> {
+        self.put_entry(source_id, coord, data, &CachedTileInfo::default())
+    }
+
+    /// Store a tile, evicting least recently used tiles to stay within budget
+    pub fn put_entry(
+        &self,
+        source_id: &str,
+        coord: TileCoord,
+        data: &[u8],
+        info: &CachedTileInfo,
+    ) -> Result<()> {
+        let contents = info.encode(data);
+        let size = contents.len() as u64;
+        if size > self.config.max_size_bytes {
+            return Ok(());
+        }
+
+        let key = Self::key(source_id, coord);
+        let path = self.tile_path(&key);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        // Write to a temporary file in the same directory, then rename over the
+        // final path so readers only ever see complete tiles
+        let temp_path = path.with_extension(format!(
+            "{}.{}.{}",
+            std::process::id(),
+            self.temp_counter.fetch_add(1, Ordering::Relaxed),
+            TEMP_EXTENSION
+        ));
+        let written = (|| -> std::io::Result<()> {
+            use std::io::Write;
+            let mut file = std::fs::File::create(&temp_path)?;
+            file.write_all(&contents)?;
+            file.sync_all()?;
+            std::fs::rename(&temp_path, &path)
+        })();
+        if let Err(e) = written {
+            let _ = std::fs::remove_file(&temp_path);
+            return Err(e.into());
+        }
+
+        let mut index = self
+            .index
+            .lock()
+            .map_err(|_| "Disk cache index lock poisoned")?;
+        index.insert(key, size);
+        self.evict_to_fit(&mut index);
+        Ok(())
+    }
+
+    /// Drop least recently used tiles until the cache is within its size
+    /// budget, or only pinned tiles are left
+    fn evict_to_fit(&self, index: &mut DiskIndex) {
+        while index.total_bytes > self.config.max_size_bytes {
+            let Some((key, size)) = index.entries.pop_lru() else {
+                break;
+            };
+            index.total_bytes -= size;
+            let _ = std::fs::remove_file(self.tile_path(&key));
+        }
+    }
+
+    fn remove_key(&self, key: &DiskKey) {
+        if let Ok(mut index) = self.index.lock() {
+            index.remove(key);
+        }
+        let _ = std::fs::remove_file(self.tile_path(key));
+    }
+
+    /// Keep `tiles` of `source_id` whatever the size budget and max age,
+    /// until [`DiskTileCache::unpin`] releases them, replacing the tiles
+    /// `pin` held before
+    ///
+    /// Tiles not stored yet are kept once they are. The pin is written to
+    /// disk, so it lasts across restarts.
+    pub fn pin(
+        &self,
+        pin: &str,
+        source_id: &str,
+        tiles: impl IntoIterator<Item = TileCoord>,
+    ) -> Result<()> {
+        let keys: HashSet<DiskKey> = tiles
+            .into_iter()
+            .map(|coord| Self::key(source_id, coord))
+            .collect();
+        let mut contents = Vec::with_capacity(keys.len() * PIN_RECORD_LEN);
+        for key in &keys {
+            contents.extend_from_slice(&key.source.to_le_bytes());
+            contents.push(key.coord.z);
+            contents.extend_from_slice(&key.coord.x.to_le_bytes());
+            contents.extend_from_slice(&key.coord.y.to_le_bytes());
+        }
+
+        let pin = source_hash(pin);
+        let path = self.pin_path(pin);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let temp_path = path.with_extension(TEMP_EXTENSION);
+        std::fs::write(&temp_path, &contents)
+            .and_then(|_| std::fs::rename(&temp_path, &path))
+            .map_err(|e| {
+                let _ = std::fs::remove_file(&temp_path);
+                format!("Failed to write tile pin {}: {}", path.display(), e)
+            })?;
+
+        let mut index = self
+            .index
+            .lock()
+            .map_err(|_| "Disk cache index lock poisoned")?;
+        index.pin(pin, keys);
+        Ok(())
+    }
+
+    /// Release the tiles held by `pin`, leaving them to the usual eviction
+    pub fn unpin(&self, pin: &str) {
+        let pin = source_hash(pin);
+        let _ = std::fs::remove_file(self.pin_path(pin));
+        if let Ok(mut index) = self.index.lock() {
+            index.unpin(pin);
+            self.evict_to_fit(&mut index);
+        }
+    }
+
+    /// Whether `pin` is in place
+    pub fn has_pin(&self, pin: &str) -> bool {
+        self.index
+            .lock()
+            .map(|index| index.pins.contains_key(&source_hash(pin)))
+            .unwrap_or(false)
+    }
+
+    /// Whether a tile is held by a pin
+    pub fn is_pinned(&self, source_id: &str, coord: TileCoord) -> bool {
+        self.index
+            .lock()
+            .map(|index| index.pinned.contains_key(&Self::key(source_id, coord)))
+            .unwrap_or(false)
+    }
+
+    /// Size of the stored tiles held by pins
+    pub fn pinned_bytes(&self) -> u64 {
+        self.index
+            .lock()
+            .map(|index| index.pinned_bytes(None))
+            .unwrap_or(0)
+    }
+
+    /// Size of the stored tiles held by pins other than `pin`
+    pub fn pinned_bytes_except(&self, pin: &str) -> u64 {
+        self.index
+            .lock()
+            .map(|index| index.pinned_bytes(Some(source_hash(pin))))
+            .unwrap_or(0)
+    }
+
+    /// Remove a single tile
+    pub fn remove(&self, source_id: &str, coord: TileCoord) {
+        self.remove_key(&Self::key(source_id, coord));
+    }
+
+    /// Remove every cached tile and pin
+    pub fn clear(&self) {
+        if let Ok(mut index) = self.index.lock() {
+            while let Some((key, _)) = index.entries.pop_lru() {
+                let _ = std::fs::remove_file(self.tile_path(&key));
+            }
+            for (key, _) in index.pinned.drain() {
+                let _ = std::fs::remove_file(self.tile_path(&key));
+            }
+            index.pins.clear();
+            index.total_bytes = 0;
+        }
+        let _ = std::fs::remove_dir_all(self.config.directory.join(PIN_DIRECTORY));
+    }
+
+    /// Whether a tile is cached (without checking its age)
+    pub fn contains(&self, source_id: &str, coord: TileCoord) -> bool {
+        self.index
+            .lock()
+            .map(|index| index.size(&Self::key(source_id, coord)).is_some())
+            .unwrap_or(false)
+    }
+
+    /// Number of cached tiles
+    pub fn len(&self) -> usize {
+        self.index
+            .lock()
+            .map(|i| i.entries.len() + i.pinned.values().filter(|t| t.size.is_some()).count())
+            .unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Total size of cached tiles in bytes
+    pub fn size_bytes(&self) -> u64 {
+        self.index.lock().map(|i| i.total_bytes).unwrap_or(0)
+    }
+
+    pub fn config(&self) -> &DiskCacheConfig {
+        &self.config
+    }
+}
+
+impl Cacheable for DiskTileCache {
+    type Key = (String, TileCoord);
+    type Value = Vec<u8>;
+
+    fn get_cached(&self, key: &Self::Key) -> Option<Self::Value> {
+        self.get(&key.0, key.1)
+    }
+
+    fn cache(&mut self, key: Self::Key, value: Self::Value) {
+        if let Err(_e) = self.put(&key.0, key.1, &value) {
+            #[cfg(feature = "debug")]
+            log::warn!("Failed to write tile {:?} to disk cache: {}", key.1, _e);
+        }
+    }
+
+    fn invalidate(&mut self, key: &Self::Key) {
+        self.remove(&key.0, key.1);
+    }
+
+    fn clear_cache(&mut self) {
+        self.clear();
+    }
+
+    fn cache_stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            size: self.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers::tile::TileSource;
+
+    fn temp_config(name: &str) -> DiskCacheConfig {
+        let directory =
+            std::env::temp_dir().join(format!("maplet_disk_cache_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        DiskCacheConfig::new(directory)
+    }
+
+    #[test]
+    fn test_disk_cache_round_trip_and_persistence() {
+        let config = temp_config("round_trip");
+        let coord = TileCoord::new(3, 5, 4);
+        {
+            let cache = DiskTileCache::open(config.clone()).unwrap();
+            assert_eq!(cache.get("osm", coord), None);
+            cache.put("osm", coord, b"tile").unwrap();
+            assert_eq!(cache.get("osm", coord), Some(b"tile".to_vec()));
+            // Sources are kept apart
+            assert_eq!(cache.get("satellite", coord), None);
+
+            let stats = cache.cache_stats();
+            assert_eq!((stats.hits, stats.misses, stats.size), (1, 2, 1));
+        }
+
+        // A fresh instance picks up what is on disk and drops stale temp files
+        let stray = DiskTileCache::open(config.clone())
+            .unwrap()
+            .tile_path(&DiskTileCache::key("osm", coord))
+            .with_extension("1.2.tmp");
+        std::fs::write(&stray, b"partial").unwrap();
+
+        let cache = DiskTileCache::open(config.clone()).unwrap();
+        assert_eq!(cache.len(), 1);
+        // Four bytes of tile data plus the 16 byte header
+        assert_eq!(cache.size_bytes(), 20);
+        assert!(!stray.exists());
+        assert_eq!(cache.get("osm", coord), Some(b"tile".to_vec()));
+
+        let _ = std::fs::remove_dir_all(&config.directory);
+    }
+
+    #[test]
+    fn test_shared_cache_requires_matching_limits() {
+        let config = temp_config("shared");
+        let cache = DiskTileCache::shared(config.clone()).unwrap();
+        assert!(Arc::ptr_eq(
+            &cache,
+            &DiskTileCache::shared(config.clone()).unwrap()
+        ));
+        assert!(DiskTileCache::shared(config.clone().with_max_size(1024)).is_err());
+
+        // Once every user is gone the directory can be opened with new limits
+        drop(cache);
+        let cache = DiskTileCache::shared(config.clone().with_max_size(1024)).unwrap();
+        assert_eq!(cache.config().max_size_bytes, 1024);
+        let _ = std::fs::remove_dir_all(&config.directory);
+    }
+
+    #[test]
+    fn test_disk_cache_lru_eviction() {
+        // Room for two 4-byte tiles with their 16 byte headers
+        let config = temp_config("eviction").with_max_size(50);
+        let cache = DiskTileCache::open(config.clone()).unwrap();
+
+        cache.put("src", TileCoord::new(0, 0, 1), &[0; 4]).unwrap();
+        cache.put("src", TileCoord::new(1, 0, 1), &[0; 4]).unwrap();
+        // Touch the first tile so the second becomes least recently used
+        assert!(cache.get("src", TileCoord::new(0, 0, 1)).is_some());
+        cache.put("src", TileCoord::new(0, 1, 1), &[0; 4]).unwrap();
+
+        assert!(cache.contains("src", TileCoord::new(0, 0, 1)));
+        assert!(!cache.contains("src", TileCoord::new(1, 0, 1)));
+        assert!(cache.contains("src", TileCoord::new(0, 1, 1)));
+        assert_eq!(cache.size_bytes(), 40);
+
+        // Tiles larger than the whole budget are not stored
+        cache.put("src", TileCoord::new(1, 1, 1), &[0; 35]).unwrap();
+        assert!(!cache.contains("src", TileCoord::new(1, 1, 1)));
+
+        let _ = std::fs::remove_dir_all(&config.directory);
+    }
+
+    #[test]
+    fn test_disk_cache_keeps_http_validators() {
+        let config = temp_config("validators");
+        let cache = DiskTileCache::open(config.clone()).unwrap();
+        let coord = TileCoord::new(2, 1, 3);
+        let info = CachedTileInfo {
+            etag: Some("\"abc\"".to_string()),
+            last_modified: None,
+            expires_at: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+        };
+
+        cache.put_entry("src", coord, b"png", &info).unwrap();
+        let entry = cache.get_entry("src", coord).unwrap();
+        assert_eq!(entry.data, b"png".to_vec());
+        assert_eq!(entry.info, info);
+        assert!(entry.info.is_stale(SystemTime::now()));
+        assert!(entry.info.has_validators());
+
+        let _ = std::fs::remove_dir_all(&config.directory);
+    }
+
+    #[test]
+    fn test_disk_cache_max_age() {
+        let config = temp_config("max_age").with_max_age(Some(Duration::ZERO));
+        let cache = DiskTileCache::open(config.clone()).unwrap();
+        let coord = TileCoord::new(0, 0, 0);
+
+        cache.put("src", coord, b"old").unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(cache.get("src", coord), None);
+        assert!(cache.is_empty());
+
+        let _ = std::fs::remove_dir_all(&config.directory);
+    }
+
+    #[test]
+    fn test_pinned_tiles_are_not_evicted_or_expired() {
+        // Room for two tiles of four bytes each
+        let config = temp_config("pins")
+            .with_max_size(40)
+            .with_max_age(Some(Duration::ZERO));
+        let pinned = TileCoord::new(0, 0, 1);
+        {
+            let cache = DiskTileCache::open(config.clone()).unwrap();
+            cache.pin("region", "src", [pinned]).unwrap();
+            cache.put("src", pinned, b"keep").unwrap();
+            assert!(cache.is_pinned("src", pinned));
+            assert_eq!(cache.pinned_bytes(), 20);
+            assert_eq!(cache.pinned_bytes_except("region"), 0);
+
+            // Filling the cache evicts only unpinned tiles
+            for x in 0..4 {
+                cache.put("src", TileCoord::new(x, 1, 1), b"tile").unwrap();
+            }
+            assert_eq!(cache.len(), 2);
+            assert_eq!(cache.size_bytes(), 40);
+            std::thread::sleep(Duration::from_millis(20));
+            assert_eq!(cache.get("src", pinned), Some(b"keep".to_vec()));
+        }
+
+        // Pins last across restarts, and released tiles age out again
+        let cache = DiskTileCache::open(config.clone()).unwrap();
+        assert!(cache.has_pin("region"));
+        assert_eq!(cache.get("src", pinned), Some(b"keep".to_vec()));
+        cache.unpin("region");
+        assert!(!cache.has_pin("region"));
+        assert_eq!(cache.pinned_bytes(), 0);
+        assert_eq!(cache.get("src", pinned), None);
+
+        let _ = std::fs::remove_dir_all(&config.directory);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_loader_reads_disk_cache_before_downloading() {
+        use crate::layers::tile::{TemplateTileSource, TileLoader, TileLoaderConfig, TilePriority};
+
+        let config = temp_config("loader");
+        let cache = Arc::new(DiskTileCache::open(config.clone()).unwrap());
+        // Nothing listens on the discard port, so only the cache can answer
+        let source = TemplateTileSource::new("http://127.0.0.1:9/{z}/{x}/{y}.png");
+        let coord = TileCoord::new(1, 1, 1);
+        cache.put(&source.source_id(), coord, b"cached").unwrap();
+
+        let loader = TileLoader::new(TileLoaderConfig::for_testing()).with_disk_cache(cache);
+        loader
+            .queue_tile(&source, coord, TilePriority::Visible)
+            .unwrap();
+
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        let result = loop {
+            if let Some(result) = loader.try_recv_results().pop() {
+                break result;
+            }
+            assert!(std::time::Instant::now() < deadline, "no tile result");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+        assert_eq!(result.data.unwrap(), Some(b"cached".to_vec()));
+
+        let _ = std::fs::remove_dir_all(&config.directory);
+    }
+}
@@ -0,0 +1,116 @@
+//! Placeholder drawn in place of tiles that failed to load
+//!
+//! A layer with `error_tile_url` set shows that image once it has been
+//! downloaded. Until then, or when the download fails or no URL is set, a
+//! generated hatch pattern is shown instead, so a dead tile server is visibly
+//! different from tiles that are still loading.
+
+use super::loader::download_tile;
+use super::request::RequestOptions;
+use crate::core::geo::TileCoord;
+use crate::prelude::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Spacing of the diagonal hatch lines, in pixels
+const HATCH_SPACING: u32 = 16;
+const BACKGROUND: [u8; 4] = [238, 238, 238, 255];
+const HATCH: [u8; 4] = [204, 204, 204, 255];
+
+/// PNG-encoded hatch pattern the size of one tile
+pub fn error_pattern(tile_size: u32) -> Vec<u8> {
+    let tile_size = tile_size.max(1);
+    let image = image::RgbaImage::from_fn(tile_size, tile_size, |x, y| {
+        let on_line = (x + y) % HATCH_SPACING < 2;
+        let on_border = x == 0 || y == 0 || x + 1 == tile_size || y + 1 == tile_size;
+        image::Rgba(if on_line || on_border {
+            HATCH
+        } else {
+            BACKGROUND
+        })
+    });
+
+    let mut png = Vec::new();
+    // Encoding a fixed-size image into memory does not fail
+    let _ = image::DynamicImage::ImageRgba8(image)
+        .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png);
+    png
+}
+
+/// The image a tile layer draws for failed tiles
+pub(crate) struct ErrorTile {
+    url: Option<String>,
+    request: Option<RequestOptions>,
+    pattern: Arc<Vec<u8>>,
+    downloaded: Arc<Mutex<Option<Arc<Vec<u8>>>>>,
+    requested: AtomicBool,
+}
+
+impl ErrorTile {
+    pub(crate) fn new(url: Option<String>, tile_size: u32) -> Self {
+        Self {
+            url,
+            request: None,
+            pattern: Arc::new(error_pattern(tile_size)),
+            downloaded: Arc::new(Mutex::new(None)),
+            requested: AtomicBool::new(false),
+        }
+    }
+
+    /// Send the source's headers and credentials with the image request
+    pub(crate) fn with_request_options(mut self, request: Option<RequestOptions>) -> Self {
+        self.request = request;
+        self
+    }
+
+    /// Image to draw, starting the `error_tile_url` download on first use
+    pub(crate) fn image(&self) -> Arc<Vec<u8>> {
+        if let Some(url) = &self.url {
+            if !self.requested.swap(true, Ordering::Relaxed) {
+                self.download(url.clone());
+            }
+            if let Some(image) = self.downloaded.lock().ok().and_then(|d| d.clone()) {
+                return image;
+            }
+        }
+        self.pattern.clone()
+    }
+
+    fn download(&self, url: String) {
+        let downloaded = self.downloaded.clone();
+        let request = self.request.clone();
+        crate::runtime::spawn(async move {
+            match download_tile(&url, TileCoord::new(0, 0, 0), None, request.as_ref()).await {
+                Ok(download) => {
+                    if let (Some(data), Ok(mut slot)) = (download.data, downloaded.lock()) {
+                        *slot = Some(Arc::new(data));
+                    }
+                }
+                Err(_e) => {
+                    #[cfg(feature = "debug")]
+                    log::warn!("Failed to load error tile {}: {}", url, _e);
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_pattern_is_a_tile_sized_png() {
+        let png = error_pattern(256);
+        let image = image::load_from_memory(&png).unwrap();
+        assert_eq!((image.width(), image.height()), (256, 256));
+
+        // Without a URL the pattern is used and nothing is requested
+        let error_tile = ErrorTile::new(None, 128);
+        assert_eq!(
+            image::load_from_memory(&error_tile.image())
+                .unwrap()
+                .width(),
+            128
+        );
+    }
+}
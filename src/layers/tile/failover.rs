@@ -0,0 +1,324 @@
+//! Ordered chains of tile sources with per-source health tracking
+//!
+//! A [`FailoverTileSource`] holds a primary source and its mirrors. The tile
+//! loader requests each tile from the first healthy source and moves on to
+//! the next one when a request fails or times out. A source that fails
+//! several times in a row is skipped for a cooldown period, so a dead mirror
+//! does not slow down every tile.
+
+use super::request::RequestOptions;
+use super::source::TileSource;
+use crate::core::geo::TileCoord;
+use crate::prelude::{Arc, Duration, Instant, Mutex};
+use crate::Result;
+
+/// Consecutive failures after which a source is put on cooldown
+const DEFAULT_FAILURE_THRESHOLD: u32 = 3;
+const DEFAULT_COOLDOWN: Duration = Duration::from_secs(30);
+/// Shorter than the download timeout, so a stalled mirror is abandoned early
+const DEFAULT_ATTEMPT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Request counters of one source in a chain
+#[derive(Debug, Clone, Default)]
+struct SourceHealth {
+    successes: u64,
+    failures: u64,
+    consecutive_failures: u32,
+    unhealthy_until: Option<Instant>,
+    last_error: Option<String>,
+    total_response_time: Duration,
+}
+
+impl SourceHealth {
+    fn is_healthy(&self, now: Instant) -> bool {
+        self.unhealthy_until.is_none_or(|until| until <= now)
+    }
+}
+
+/// Snapshot of the health of one source in a [`FailoverTileSource`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceStats {
+    pub source_id: String,
+    /// Position in the chain, 0 being the primary source
+    pub index: usize,
+    pub successes: u64,
+    pub failures: u64,
+    /// Failures since the last success
+    pub consecutive_failures: u32,
+    /// False while the source is on cooldown
+    pub healthy: bool,
+    /// Time left until a source on cooldown is tried first again
+    pub cooldown_remaining: Option<Duration>,
+    pub last_error: Option<String>,
+    /// Mean time of successful requests
+    pub average_response_time: Option<Duration>,
+}
+
+/// Tile source trying a list of sources or mirrors in order
+///
+/// Clones share their health state, so a clone kept by the application
+/// reports on the requests of the layer it was given to.
+#[derive(Clone)]
+pub struct FailoverTileSource {
+    sources: Arc<Vec<Arc<dyn TileSource>>>,
+    health: Arc<Mutex<Vec<SourceHealth>>>,
+    failure_threshold: u32,
+    cooldown: Duration,
+    attempt_timeout: Duration,
+    /// Request `@2x` tiles from every source that has them
+    retina: bool,
+}
+
+impl FailoverTileSource {
+    /// Chain `sources`, the first being the primary
+    pub fn new(sources: Vec<Box<dyn TileSource>>) -> Result<Self> {
+        if sources.is_empty() {
+            return Err("A failover tile source needs at least one source".into());
+        }
+        let health = vec![SourceHealth::default(); sources.len()];
+        Ok(Self {
+            sources: Arc::new(sources.into_iter().map(Arc::from).collect()),
+            health: Arc::new(Mutex::new(health)),
+            failure_threshold: DEFAULT_FAILURE_THRESHOLD,
+            cooldown: DEFAULT_COOLDOWN,
+            attempt_timeout: DEFAULT_ATTEMPT_TIMEOUT,
+            retina: false,
+        })
+    }
+
+    /// Put a source on cooldown after this many consecutive failures
+    pub fn with_failure_threshold(mut self, failures: u32) -> Self {
+        self.failure_threshold = failures.max(1);
+        self
+    }
+
+    /// How long a failing source is tried only after the healthy ones
+    pub fn with_cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// Give up on a source and try the next after this long
+    pub fn with_attempt_timeout(mut self, timeout: Duration) -> Self {
+        self.attempt_timeout = timeout;
+        self
+    }
+
+    pub fn sources(&self) -> &[Arc<dyn TileSource>] {
+        &self.sources
+    }
+
+    pub fn attempt_timeout(&self) -> Duration {
+        self.attempt_timeout
+    }
+
+    /// Health of every source, in chain order
+    pub fn stats(&self) -> Vec<SourceStats> {
+        let now = Instant::now();
+        let health = self.health.lock().map(|h| h.clone()).unwrap_or_default();
+        self.sources
+            .iter()
+            .zip(health)
+            .enumerate()
+            .map(|(index, (source, health))| SourceStats {
+                source_id: source.source_id(),
+                index,
+                successes: health.successes,
+                failures: health.failures,
+                consecutive_failures: health.consecutive_failures,
+                healthy: health.is_healthy(now),
+                cooldown_remaining: health
+                    .unhealthy_until
+                    .filter(|until| *until > now)
+                    .map(|until| until - now),
+                last_error: health.last_error,
+                average_response_time: (health.successes > 0)
+                    .then(|| health.total_response_time / health.successes as u32),
+            })
+            .collect()
+    }
+
+    /// Whether the source at `index` is off cooldown
+    pub fn is_healthy(&self, index: usize) -> bool {
+        let now = Instant::now();
+        self.health
+            .lock()
+            .ok()
+            .and_then(|health| health.get(index).map(|h| h.is_healthy(now)))
+            .unwrap_or(false)
+    }
+
+    /// Forget all failures, making every source healthy again
+    pub fn reset_health(&self) {
+        if let Ok(mut health) = self.health.lock() {
+            health.fill(SourceHealth::default());
+        }
+    }
+
+    /// Indices of the sources to try, healthy ones in chain order followed
+    /// by those on cooldown, soonest to recover first
+    ///
+    /// Sources on cooldown stay in the list so a tile still loads when every
+    /// source has been failing.
+    pub(crate) fn attempt_order(&self) -> Vec<usize> {
+        let now = Instant::now();
+        let Ok(health) = self.health.lock() else {
+            return (0..self.sources.len()).collect();
+        };
+        let (mut order, mut cooling): (Vec<usize>, Vec<usize>) =
+            (0..self.sources.len()).partition(|&index| health[index].is_healthy(now));
+        cooling.sort_by_key(|&index| health[index].unhealthy_until);
+        order.extend(cooling);
+        order
+    }
+
+    /// URL of `coord` at the source at `index`
+    pub(crate) fn source_url(&self, index: usize, coord: TileCoord) -> String {
+        let source = &self.sources[index];
+        if self.retina {
+            if let Some(url) = source.retina_url(coord) {
+                return url;
+            }
+        }
+        source.url(coord)
+    }
+
+    pub(crate) fn record_success(&self, index: usize, response_time: Duration) {
+        if let Ok(mut health) = self.health.lock() {
+            let health = &mut health[index];
+            health.successes += 1;
+            health.consecutive_failures = 0;
+            health.unhealthy_until = None;
+            health.total_response_time += response_time;
+        }
+    }
+
+    pub(crate) fn record_failure(&self, index: usize, error: &str) {
+        if let Ok(mut health) = self.health.lock() {
+            let health = &mut health[index];
+            health.failures += 1;
+            health.consecutive_failures += 1;
+            health.last_error = Some(error.to_string());
+            if health.consecutive_failures >= self.failure_threshold {
+                health.unhealthy_until = Some(Instant::now() + self.cooldown);
+            }
+        }
+    }
+
+    /// The same chain requesting double-resolution tiles, sharing its health
+    pub(crate) fn with_retina(mut self) -> Self {
+        self.retina = true;
+        self
+    }
+}
+
+impl TileSource for FailoverTileSource {
+    /// URL at the source that would be tried first
+    fn url(&self, coord: TileCoord) -> String {
+        self.source_url(self.attempt_order()[0], coord)
+    }
+
+    fn has_tile(&self, coord: TileCoord) -> bool {
+        self.sources.iter().any(|source| source.has_tile(coord))
+    }
+
+    /// The primary's identifier, since mirrors serve the same tiles and can
+    /// share cache entries
+    fn source_id(&self) -> String {
+        self.sources[0].source_id()
+    }
+
+    fn request_options(&self) -> Option<&RequestOptions> {
+        self.sources[0].request_options()
+    }
+
+    fn retina_url(&self, coord: TileCoord) -> Option<String> {
+        self.sources[0].retina_url(coord)
+    }
+
+    fn failover(&self) -> Option<FailoverTileSource> {
+        Some(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers::tile::TemplateTileSource;
+
+    fn chain() -> FailoverTileSource {
+        FailoverTileSource::new(vec![
+            Box::new(TemplateTileSource::new(
+                "https://a.example.com/{z}/{x}/{y}.png",
+            )),
+            Box::new(TemplateTileSource::new(
+                "https://b.example.com/{z}/{x}/{y}.png",
+            )),
+            Box::new(TemplateTileSource::new(
+                "https://c.example.com/{z}/{x}/{y}{r}.png",
+            )),
+        ])
+        .unwrap()
+        .with_failure_threshold(2)
+        .with_cooldown(Duration::from_secs(60))
+    }
+
+    #[test]
+    fn test_repeated_failures_move_a_source_to_the_back() {
+        let source = chain();
+        let coord = TileCoord::new(1, 2, 3);
+        assert_eq!(source.attempt_order(), vec![0, 1, 2]);
+        assert_eq!(source.url(coord), "https://a.example.com/3/1/2.png");
+
+        // A single failure keeps the primary first
+        source.record_failure(0, "HTTP 503");
+        assert!(source.is_healthy(0));
+        source.record_failure(0, "HTTP 503");
+        assert!(!source.is_healthy(0));
+        assert_eq!(source.attempt_order(), vec![1, 2, 0]);
+        assert_eq!(source.url(coord), "https://b.example.com/3/1/2.png");
+
+        // When every source is failing, the one recovering soonest comes first
+        for index in [2, 2, 1, 1] {
+            source.record_failure(index, "timed out");
+        }
+        assert_eq!(source.attempt_order(), vec![0, 2, 1]);
+
+        source.record_success(0, Duration::from_millis(40));
+        let stats = source.clone().stats();
+        assert!(stats[0].healthy);
+        assert_eq!(stats[0].consecutive_failures, 0);
+        assert_eq!((stats[0].successes, stats[0].failures), (1, 2));
+        assert_eq!(
+            stats[0].average_response_time,
+            Some(Duration::from_millis(40))
+        );
+        assert!(!stats[1].healthy);
+        assert!(stats[1].cooldown_remaining.unwrap() > Duration::from_secs(59));
+        assert_eq!(stats[2].last_error.as_deref(), Some("timed out"));
+
+        source.reset_health();
+        assert_eq!(source.attempt_order(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_chain_identity_and_retina_urls() {
+        let source = chain();
+        let coord = TileCoord::new(0, 0, 1);
+        assert_eq!(source.source_id(), source.sources()[0].source_id());
+        assert!(FailoverTileSource::new(Vec::new()).is_err());
+
+        let retina = source.clone().with_retina();
+        assert_eq!(
+            retina.source_url(0, coord),
+            "https://a.example.com/1/0/0.png"
+        );
+        assert_eq!(
+            retina.source_url(2, coord),
+            "https://c.example.com/1/0/0@2x.png"
+        );
+        // Health is shared between clones
+        retina.record_failure(1, "HTTP 500");
+        assert_eq!(source.stats()[1].failures, 1);
+    }
+}
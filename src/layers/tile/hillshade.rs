@@ -0,0 +1,631 @@
+//! Hillshade layer
+//!
+//! Shades terrain from raster elevation tiles loaded through the regular
+//! tile loader. Each tile is decoded to heights once on a blocking worker;
+//! the shaded image is derived from those heights, also off the render
+//! thread, and recomputed only when the shading options change.
+
+use super::decode::{DecodedTile, TileImageFormat};
+use super::dem::{new_dem_store, DemEncoding, DemStore, DemTile, ElevationLookup};
+use super::prepared::{drawable_tiles, PreparedTiles, TileJobs};
+use super::{TemplateTileSource, TileLayerOptions, TileSource};
+use crate::{
+    core::{
+        geo::{LatLng, LatLngBounds, TileCoord},
+        viewport::Viewport,
+    },
+    layers::{
+        base::{LayerProperties, LayerTrait, LayerType},
+        vector::SerializableColor,
+    },
+    prelude::Arc,
+    rendering::context::{RenderContext, FULL_UV},
+    Result,
+};
+use lru::LruCache;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Elevation tiles, and shaded images, kept in memory
+const TILE_CACHE_SIZE: usize = 256;
+
+/// Earth's equatorial circumference in metres
+const EARTH_CIRCUMFERENCE: f64 = 40_075_016.686;
+
+/// Colours assigned to elevations, interpolated linearly between stops
+#[derive(Debug, Clone, PartialEq)]
+pub struct HypsometricTint {
+    stops: Vec<(f32, SerializableColor)>,
+}
+
+impl HypsometricTint {
+    /// Tint from `(elevation in metres, colour)` stops, in any order
+    pub fn new(mut stops: Vec<(f32, SerializableColor)>) -> Self {
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self { stops }
+    }
+
+    /// Blue below sea level, green lowlands through brown hills to white peaks
+    pub fn terrain() -> Self {
+        Self::new(vec![
+            (-500.0, SerializableColor::rgb(118, 164, 203)),
+            (0.0, SerializableColor::rgb(172, 208, 165)),
+            (300.0, SerializableColor::rgb(148, 191, 139)),
+            (1000.0, SerializableColor::rgb(209, 215, 171)),
+            (2000.0, SerializableColor::rgb(195, 167, 107)),
+            (3000.0, SerializableColor::rgb(170, 135, 83)),
+            (4500.0, SerializableColor::rgb(245, 244, 242)),
+        ])
+    }
+
+    pub fn color_at(&self, elevation: f32) -> SerializableColor {
+        let Some(index) = self.stops.iter().position(|(stop, _)| *stop > elevation) else {
+            return self
+                .stops
+                .last()
+                .map_or(SerializableColor::rgb(0, 0, 0), |(_, color)| *color);
+        };
+        if index == 0 {
+            return self.stops[0].1;
+        }
+
+        let (low, from) = self.stops[index - 1];
+        let (high, to) = self.stops[index];
+        let t = (elevation - low) / (high - low);
+        let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+        SerializableColor::new(
+            mix(from.r, to.r),
+            mix(from.g, to.g),
+            mix(from.b, to.b),
+            mix(from.a, to.a),
+        )
+    }
+}
+
+/// Lighting and colouring of a [`HillshadeLayer`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct HillshadeOptions {
+    /// Direction the light comes from, in degrees clockwise from north
+    pub azimuth: f32,
+    /// Height of the light above the horizon, in degrees
+    pub altitude: f32,
+    /// Multiplier applied to slopes; above 1 exaggerates relief
+    pub exaggeration: f32,
+    /// Colour of slopes facing away from the light, at full shadow
+    pub shadow_color: SerializableColor,
+    /// Colour of slopes facing the light, at full illumination
+    pub highlight_color: SerializableColor,
+    /// Colour by elevation beneath the shading; without one, flat ground is
+    /// transparent and only shadows and highlights are drawn
+    pub tint: Option<HypsometricTint>,
+}
+
+impl Default for HillshadeOptions {
+    fn default() -> Self {
+        Self {
+            azimuth: 315.0,
+            altitude: 45.0,
+            exaggeration: 1.0,
+            shadow_color: SerializableColor::new(0, 0, 0, 170),
+            highlight_color: SerializableColor::new(255, 255, 255, 90),
+            tint: None,
+        }
+    }
+}
+
+impl HillshadeOptions {
+    pub fn with_azimuth(mut self, azimuth: f32) -> Self {
+        self.azimuth = azimuth.rem_euclid(360.0);
+        self
+    }
+
+    pub fn with_altitude(mut self, altitude: f32) -> Self {
+        self.altitude = altitude.clamp(0.0, 90.0);
+        self
+    }
+
+    pub fn with_exaggeration(mut self, exaggeration: f32) -> Self {
+        self.exaggeration = exaggeration.max(0.0);
+        self
+    }
+
+    pub fn with_shadow_color(mut self, color: SerializableColor) -> Self {
+        self.shadow_color = color;
+        self
+    }
+
+    pub fn with_highlight_color(mut self, color: SerializableColor) -> Self {
+        self.highlight_color = color;
+        self
+    }
+
+    pub fn with_tint(mut self, tint: Option<HypsometricTint>) -> Self {
+        self.tint = tint;
+        self
+    }
+}
+
+/// Shade the elevations of the tile at `coord`
+///
+/// Slopes come from Horn's method over each pixel's neighbours, with the
+/// ground distance between pixels taken from the tile's latitude. Pixels on
+/// the tile edge reuse their own height for the missing neighbours.
+pub fn shade_tile(dem: &DemTile, coord: TileCoord, options: &HillshadeOptions) -> image::RgbaImage {
+    let n = 2_f64.powi(coord.z as i32);
+    let zenith = (90.0 - options.altitude as f64).to_radians();
+    let azimuth = (450.0 - options.azimuth as f64)
+        .rem_euclid(360.0)
+        .to_radians();
+    let flat = zenith.cos();
+    let exaggeration = options.exaggeration as f64;
+
+    image::RgbaImage::from_fn(dem.width, dem.height, |x, y| {
+        let world_y = (coord.y as f64 + (y as f64 + 0.5) / dem.height as f64) / n;
+        let latitude = (std::f64::consts::PI * (1.0 - 2.0 * world_y)).sinh().atan();
+        let cell = EARTH_CIRCUMFERENCE * latitude.cos() / (n * dem.width as f64);
+
+        let (x, y) = (x as i64, y as i64);
+        let h = |dx: i64, dy: i64| dem.height_at(x + dx, y + dy) as f64;
+        let dz_dx = ((h(1, -1) + 2.0 * h(1, 0) + h(1, 1))
+            - (h(-1, -1) + 2.0 * h(-1, 0) + h(-1, 1)))
+            / (8.0 * cell);
+        let dz_dy = ((h(-1, 1) + 2.0 * h(0, 1) + h(1, 1))
+            - (h(-1, -1) + 2.0 * h(0, -1) + h(1, -1)))
+            / (8.0 * cell);
+
+        let slope = (exaggeration * dz_dx.hypot(dz_dy)).atan();
+        let aspect = dz_dy.atan2(-dz_dx);
+        let light = (zenith.cos() * slope.cos()
+            + zenith.sin() * slope.sin() * (azimuth - aspect).cos())
+        .max(0.0);
+
+        // Shadow or highlight, relative to how lit flat ground is
+        let (overlay, strength) = if light < flat {
+            (
+                options.shadow_color,
+                (flat - light) / flat.max(f64::EPSILON),
+            )
+        } else {
+            (
+                options.highlight_color,
+                (light - flat) / (1.0 - flat).max(f64::EPSILON),
+            )
+        };
+        let alpha = strength.clamp(0.0, 1.0) as f32 * overlay.a as f32 / 255.0;
+
+        match &options.tint {
+            Some(tint) => {
+                let base = tint.color_at(h(0, 0) as f32);
+                let blend = |base: u8, top: u8| {
+                    (base as f32 * (1.0 - alpha) + top as f32 * alpha).round() as u8
+                };
+                image::Rgba([
+                    blend(base.r, overlay.r),
+                    blend(base.g, overlay.g),
+                    blend(base.b, overlay.b),
+                    base.a,
+                ])
+            }
+            None => image::Rgba([
+                overlay.r,
+                overlay.g,
+                overlay.b,
+                (alpha * 255.0).round() as u8,
+            ]),
+        }
+    })
+}
+
+/// A shaded tile ready to draw
+struct ShadedImage {
+    /// Texture key of the image, unique to each shading
+    label: Vec<u8>,
+    image: Arc<DecodedTile>,
+    /// Shading generation the image was made in; older images stay on
+    /// screen until their replacement is ready
+    generation: u64,
+}
+
+impl ShadedImage {
+    fn new(image: image::RgbaImage, generation: u64) -> Self {
+        static NEXT_LABEL: AtomicU64 = AtomicU64::new(0);
+        let label = format!(
+            "maplet-hillshade/{}",
+            NEXT_LABEL.fetch_add(1, Ordering::Relaxed)
+        );
+        let image = DecodedTile {
+            width: image.width(),
+            height: image.height(),
+            // Shaded pixels never went through a file; PNG is what they
+            // would be saved as
+            format: TileImageFormat::Png,
+            rgba: image.into_raw(),
+        };
+        Self {
+            label: label.into_bytes(),
+            image: Arc::new(image),
+            generation,
+        }
+    }
+}
+
+/// Layer drawing relief shading from Terrarium or Mapbox Terrain-RGB tiles
+///
+/// Tiles are decoded and shaded on blocking workers. Past the maximum zoom
+/// the deepest tiles are stretched over the view, and while a tile loads its
+/// nearest loaded ancestor is drawn beneath.
+pub struct HillshadeLayer {
+    properties: LayerProperties,
+    options: TileLayerOptions,
+    tiles: PreparedTiles<Arc<DemTile>>,
+    encoding: DemEncoding,
+    hillshade: HillshadeOptions,
+    dems: DemStore,
+    images: LruCache<TileCoord, ShadedImage>,
+    /// Shading of loaded tiles, restarted when the hillshade options change
+    shading: TileJobs<image::RgbaImage>,
+}
+
+impl HillshadeLayer {
+    pub fn new(
+        id: String,
+        tile_source: Box<dyn TileSource>,
+        encoding: DemEncoding,
+        options: TileLayerOptions,
+    ) -> Self {
+        let properties = LayerProperties {
+            id,
+            name: "Hillshade Layer".to_string(),
+            layer_type: LayerType::Tile,
+            visible: true,
+            opacity: options.opacity,
+            z_index: options.z_index,
+            interactive: false,
+            options: serde_json::Value::Null,
+        };
+
+        Self {
+            properties,
+            options,
+            tiles: PreparedTiles::new(Arc::from(tile_source)),
+            encoding,
+            hillshade: HillshadeOptions::default(),
+            dems: new_dem_store(TILE_CACHE_SIZE),
+            images: LruCache::new(NonZeroUsize::new(TILE_CACHE_SIZE).unwrap()),
+            shading: TileJobs::new(),
+        }
+    }
+
+    /// Create a layer from a URL template such as
+    /// `https://elevation.example.com/terrarium/{z}/{x}/{y}.png`
+    pub fn from_template(
+        id: String,
+        template: impl Into<String>,
+        encoding: DemEncoding,
+        options: TileLayerOptions,
+    ) -> Self {
+        let source = TemplateTileSource::from_options(template, &options);
+        Self::new(id, Box::new(source), encoding, options)
+    }
+
+    pub fn with_hillshade(mut self, hillshade: HillshadeOptions) -> Self {
+        self.set_hillshade(hillshade);
+        self
+    }
+
+    /// Change the lighting or colouring; loaded tiles are reshaded from
+    /// their heights without downloading them again, and keep their old
+    /// shading until the new one is ready
+    pub fn set_hillshade(&mut self, hillshade: HillshadeOptions) {
+        if hillshade != self.hillshade {
+            self.hillshade = hillshade;
+            self.shading.cancel();
+        }
+    }
+
+    pub fn hillshade(&self) -> &HillshadeOptions {
+        &self.hillshade
+    }
+
+    pub fn encoding(&self) -> DemEncoding {
+        self.encoding
+    }
+
+    /// Elevation in metres at `lat_lng` from the tiles loaded so far
+    pub fn elevation_at(&self, lat_lng: &LatLng) -> Option<f64> {
+        self.elevation_lookup().elevation_at(lat_lng)
+    }
+
+    /// Handle for elevation queries that stays valid as tiles load, for use
+    /// by code that does not own the layer
+    pub fn elevation_lookup(&self) -> ElevationLookup {
+        ElevationLookup::new(
+            self.dems.clone(),
+            self.options.min_zoom,
+            self.options.max_zoom,
+        )
+    }
+
+    /// Decode `data` as the elevation tile at `coord`
+    ///
+    /// An empty payload records that the source has no tile there. Tiles
+    /// the layer downloads itself are decoded on a blocking worker instead.
+    pub fn insert_tile(&mut self, coord: TileCoord, data: &[u8]) -> Result<()> {
+        if data.is_empty() {
+            self.tiles.mark_missing(coord);
+            return Ok(());
+        }
+        match DemTile::decode(data, self.encoding) {
+            Ok(dem) => {
+                self.tiles.mark_loaded(coord);
+                self.store_dem(coord, Arc::new(dem));
+                Ok(())
+            }
+            Err(e) => {
+                self.tiles.mark_missing(coord);
+                Err(e)
+            }
+        }
+    }
+
+    pub fn loaded_tile_count(&self) -> usize {
+        self.dems.lock().map(|dems| dems.len()).unwrap_or(0)
+    }
+
+    /// Tiles that failed to download, whether or not they will be retried
+    pub fn failed_tiles(&self) -> Vec<TileCoord> {
+        self.tiles.failed_tiles()
+    }
+
+    fn store_dem(&mut self, coord: TileCoord, dem: Arc<DemTile>) {
+        self.images.pop(&coord);
+        if let Ok(mut dems) = self.dems.lock() {
+            dems.put(coord, dem);
+        }
+    }
+
+    fn process_tile_results(&mut self) {
+        let encoding = self.encoding;
+        let decoded = self
+            .tiles
+            .receive(move |_, data| DemTile::decode(&data, encoding).map(Arc::new));
+        for (coord, dem) in decoded {
+            self.store_dem(coord, dem);
+        }
+
+        let generation = self.shading.generation();
+        for (coord, shaded) in self.shading.finished() {
+            match shaded {
+                Ok(image) => {
+                    self.images.put(coord, ShadedImage::new(image, generation));
+                }
+                Err(_e) => {
+                    #[cfg(feature = "debug")]
+                    log::warn!("Failed to shade elevation tile {:?}: {}", coord, _e);
+                }
+            }
+        }
+    }
+
+    /// Shade the loaded tile at `coord` on a blocking worker, unless its
+    /// image is up to date or already being shaded
+    fn shade(&mut self, coord: TileCoord) {
+        let generation = self.shading.generation();
+        if self
+            .images
+            .peek(&coord)
+            .is_some_and(|image| image.generation == generation)
+        {
+            return;
+        }
+        let Some(dem) = self
+            .dems
+            .lock()
+            .ok()
+            .and_then(|dems| dems.peek(&coord).cloned())
+        else {
+            return;
+        };
+        let hillshade = self.hillshade.clone();
+        self.shading
+            .spawn(coord, move || Ok(shade_tile(&dem, coord, &hillshade)));
+    }
+}
+
+impl LayerTrait for HillshadeLayer {
+    crate::impl_layer_trait!(HillshadeLayer, properties);
+
+    fn bounds(&self) -> Option<LatLngBounds> {
+        self.options.bounds.clone()
+    }
+
+    fn render(&mut self, context: &mut RenderContext, viewport: &Viewport) -> Result<()> {
+        if !self.is_visible() {
+            return Ok(());
+        }
+
+        self.process_tile_results();
+
+        let Some(zoom) = self.options.tile_zoom(viewport.zoom) else {
+            return Ok(());
+        };
+        let visible = self.options.covering_tiles(viewport, zoom);
+
+        let tiles = {
+            let dems = self
+                .dems
+                .lock()
+                .map_err(|_| "Elevation tile store poisoned")?;
+            let is_loaded = |coord: &TileCoord| dems.contains(coord);
+            self.tiles.request(&visible, is_loaded)?;
+            drawable_tiles(&visible, self.options.min_zoom, is_loaded)
+        };
+
+        let opacity = self.opacity().clamp(0.0, 1.0);
+        for coord in tiles {
+            self.shade(coord);
+            let Some(shaded) = self.images.get(&coord) else {
+                continue;
+            };
+            let bounds = coord.bounds();
+            let min = viewport
+                .lat_lng_to_pixel(&LatLng::new(bounds.north_east.lat, bounds.south_west.lng));
+            let max = viewport
+                .lat_lng_to_pixel(&LatLng::new(bounds.south_west.lat, bounds.north_east.lng));
+            context.render_decoded_tile_region(
+                &shaded.label,
+                Some(shaded.image.clone()),
+                FULL_UV,
+                (min, max),
+                opacity,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn options(&self) -> serde_json::Value {
+        serde_json::json!({
+            "azimuth": self.hillshade.azimuth,
+            "altitude": self.hillshade.altitude,
+            "exaggeration": self.hillshade.exaggeration,
+            "tinted": self.hillshade.tint.is_some(),
+            "loaded_tiles": self.loaded_tile_count()
+        })
+    }
+
+    crate::impl_todo_options_setting!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::geo::Point;
+    use crate::layers::tile::dem::tests::terrarium_png;
+    use crate::rendering::context::DrawCommand;
+
+    /// A 3x3 tile rising by `step` metres per pixel towards the east
+    fn east_rising(step: f32) -> DemTile {
+        DemTile {
+            width: 3,
+            height: 3,
+            heights: (0..9).map(|i| (i % 3) as f32 * step).collect(),
+        }
+    }
+
+    #[test]
+    fn test_slopes_facing_the_light_are_brighter() {
+        let coord = TileCoord::new(0, 0, 14);
+        let flat = shade_tile(&east_rising(0.0), coord, &HillshadeOptions::default());
+        assert!(flat.pixels().all(|pixel| pixel[3] == 0));
+
+        // A slope rising to the east faces west
+        let from_west = HillshadeOptions::default().with_azimuth(270.0);
+        let lit = shade_tile(&east_rising(50.0), coord, &from_west);
+        assert_eq!(lit.get_pixel(1, 1)[0], 255);
+        assert!(lit.get_pixel(1, 1)[3] > 0);
+
+        let shaded = shade_tile(
+            &east_rising(50.0),
+            coord,
+            &from_west.clone().with_azimuth(90.0),
+        );
+        assert_eq!(shaded.get_pixel(1, 1)[0], 0);
+
+        // Exaggeration deepens the shadow
+        let steeper = shade_tile(
+            &east_rising(50.0),
+            coord,
+            &from_west.with_azimuth(90.0).with_exaggeration(3.0),
+        );
+        assert!(steeper.get_pixel(1, 1)[3] > shaded.get_pixel(1, 1)[3]);
+    }
+
+    #[test]
+    fn test_hypsometric_tint() {
+        let tint = HypsometricTint::new(vec![
+            (1000.0, SerializableColor::rgb(200, 100, 0)),
+            (0.0, SerializableColor::rgb(0, 100, 200)),
+        ]);
+        assert_eq!(tint.color_at(-50.0), SerializableColor::rgb(0, 100, 200));
+        assert_eq!(tint.color_at(500.0), SerializableColor::rgb(100, 100, 100));
+        assert_eq!(tint.color_at(9000.0), SerializableColor::rgb(200, 100, 0));
+
+        // Flat tinted ground is opaque and untouched by shading
+        let options = HillshadeOptions::default().with_tint(Some(tint));
+        let image = shade_tile(&east_rising(0.0), TileCoord::new(0, 0, 10), &options);
+        assert_eq!(image.get_pixel(0, 0).0, [0, 100, 200, 255]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_layer_shades_loaded_tiles_and_answers_elevation() {
+        let mut layer = HillshadeLayer::from_template(
+            "relief".to_string(),
+            "http://127.0.0.1:9/{z}/{x}/{y}.png",
+            DemEncoding::Terrarium,
+            TileLayerOptions {
+                max_zoom: 1,
+                ..Default::default()
+            },
+        );
+        let lookup = layer.elevation_lookup();
+        let heights: Vec<f32> = (0..16).map(|i| (i % 4) as f32 * 100.0).collect();
+        layer
+            .insert_tile(TileCoord::new(0, 0, 1), &terrarium_png(4, 4, &heights))
+            .unwrap();
+        assert!(layer.insert_tile(TileCoord::new(1, 0, 1), b"junk").is_err());
+
+        // Centre of the north-west quarter, between the second and third columns
+        assert_eq!(
+            lookup.elevation_at(&LatLng::new(66.513, -90.0)),
+            Some(150.0)
+        );
+        assert_eq!(layer.elevation_at(&LatLng::new(-45.0, 10.0)), None);
+
+        // Zoomed past the maximum zoom: the z1 tile is stretched over the
+        // view once a worker has shaded it
+        let viewport = Viewport::new(LatLng::new(60.0, -100.0), 3.2, Point::new(256.0, 256.0));
+        let render = |layer: &mut HillshadeLayer| {
+            let mut context = RenderContext::new(256, 256).unwrap();
+            layer.render(&mut context, &viewport).unwrap();
+            context.get_drawing_queue().to_vec()
+        };
+        let mut queue = render(&mut layer);
+        for _ in 0..500 {
+            if !queue.is_empty() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            queue = render(&mut layer);
+        }
+        assert_eq!(queue.len(), 1);
+        let label = match &queue[0] {
+            DrawCommand::Tile {
+                data,
+                image: Some(image),
+                bounds,
+                ..
+            } => {
+                assert_eq!((image.width, image.height), (4, 4));
+                assert_eq!(image.rgba.len(), 4 * 4 * 4);
+                assert!(bounds.1.x - bounds.0.x > 1000.0);
+                data.clone()
+            }
+            other => panic!("expected a shaded tile, got {:?}", other),
+        };
+
+        // New lighting keeps the old shading on screen until it is redone
+        layer.set_hillshade(HillshadeOptions::default().with_azimuth(90.0));
+        let mut queue = render(&mut layer);
+        let reshaded = |queue: &[DrawCommand]| matches!(queue, [DrawCommand::Tile { data, .. }] if *data != label);
+        assert_eq!(queue.len(), 1);
+        for _ in 0..500 {
+            if reshaded(&queue) {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            queue = render(&mut layer);
+        }
+        assert!(reshaded(&queue), "{:?}", queue);
+    }
+}
@@ -0,0 +1,176 @@
+//! HTTP caching rules for tile responses
+//!
+//! Turns `Cache-Control`, `Expires`, `ETag` and `Last-Modified` response
+//! headers into the [`CachedTileInfo`] stored with each tile in the disk cache,
+//! and builds the conditional request headers used to revalidate stale tiles.
+
+use super::disk_cache::CachedTileInfo;
+use crate::prelude::Duration;
+use reqwest::header::{
+    HeaderMap, CACHE_CONTROL, ETAG, EXPIRES, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+};
+use std::time::SystemTime;
+
+/// Caching instructions taken from one tile response
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HttpCachePolicy {
+    /// `Cache-Control: no-store`; the tile must not be written to disk
+    pub no_store: bool,
+    /// Validators and expiry to store with the tile
+    pub info: CachedTileInfo,
+}
+
+impl HttpCachePolicy {
+    /// Read the caching headers of a response received at `now`
+    ///
+    /// `no-cache` makes the tile stale immediately, `max-age` takes precedence
+    /// over `Expires`, and an unparseable `Expires` counts as already expired.
+    /// Without any of them the tile never goes stale on its own.
+    pub fn from_headers(headers: &HeaderMap, now: SystemTime) -> Self {
+        let header = |name| {
+            headers
+                .get(name)
+                .and_then(|value: &reqwest::header::HeaderValue| value.to_str().ok())
+                .map(|value| value.trim().to_string())
+        };
+
+        let mut no_store = false;
+        let mut no_cache = false;
+        let mut max_age = None;
+        for directive in headers
+            .get_all(CACHE_CONTROL)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+        {
+            let directive = directive.trim().to_ascii_lowercase();
+            match directive.split_once('=') {
+                Some(("max-age", seconds)) => {
+                    max_age = seconds.trim_matches('"').parse::<u64>().ok();
+                }
+                _ if directive == "no-store" => no_store = true,
+                _ if directive == "no-cache" => no_cache = true,
+                _ => {}
+            }
+        }
+
+        let expires_at = if no_cache {
+            Some(now)
+        } else if let Some(seconds) = max_age {
+            Some(now + Duration::from_secs(seconds))
+        } else {
+            header(EXPIRES).map(|value| httpdate::parse_http_date(&value).unwrap_or(now))
+        };
+
+        Self {
+            no_store,
+            info: CachedTileInfo {
+                etag: header(ETAG),
+                last_modified: header(LAST_MODIFIED),
+                expires_at,
+            },
+        }
+    }
+
+    /// Combine a `304 Not Modified` response with the entry it revalidated.
+    /// Validators the server did not resend are kept.
+    pub fn merge_not_modified(mut self, previous: &CachedTileInfo) -> Self {
+        if self.info.etag.is_none() {
+            self.info.etag = previous.etag.clone();
+        }
+        if self.info.last_modified.is_none() {
+            self.info.last_modified = previous.last_modified.clone();
+        }
+        self
+    }
+}
+
+/// Conditional request headers for revalidating a stored tile
+pub fn conditional_headers(info: &CachedTileInfo) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    if let Some(value) = info.etag.as_deref().and_then(|v| v.parse().ok()) {
+        headers.insert(IF_NONE_MATCH, value);
+    }
+    if let Some(value) = info.last_modified.as_deref().and_then(|v| v.parse().ok()) {
+        headers.insert(IF_MODIFIED_SINCE, value);
+    }
+    headers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for (name, value) in pairs {
+            map.append(*name, value.parse().unwrap());
+        }
+        map
+    }
+
+    #[test]
+    fn test_policy_from_cache_headers() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+
+        let policy = HttpCachePolicy::from_headers(
+            &headers(&[
+                ("cache-control", "public, max-age=60"),
+                ("expires", "Thu, 01 Jan 1970 00:00:00 GMT"),
+                ("etag", "\"v1\""),
+                ("last-modified", "Wed, 21 Oct 2015 07:28:00 GMT"),
+            ]),
+            now,
+        );
+        assert!(!policy.no_store);
+        // max-age wins over Expires
+        assert_eq!(policy.info.expires_at, Some(now + Duration::from_secs(60)));
+        assert_eq!(policy.info.etag.as_deref(), Some("\"v1\""));
+
+        let policy = HttpCachePolicy::from_headers(
+            &headers(&[("expires", "Wed, 21 Oct 2015 07:28:00 GMT")]),
+            now,
+        );
+        assert_eq!(
+            policy.info.expires_at,
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_445_412_480))
+        );
+
+        let policy = HttpCachePolicy::from_headers(
+            &headers(&[("cache-control", "no-store"), ("cache-control", "no-cache")]),
+            now,
+        );
+        assert!(policy.no_store);
+        assert!(policy.info.is_stale(now));
+
+        // Invalid Expires values mean "already expired"
+        let policy = HttpCachePolicy::from_headers(&headers(&[("expires", "0")]), now);
+        assert!(policy.info.is_stale(now));
+
+        let policy = HttpCachePolicy::from_headers(&HeaderMap::new(), now);
+        assert_eq!(policy.info.expires_at, None);
+    }
+
+    #[test]
+    fn test_conditional_headers_and_not_modified_merge() {
+        let stored = CachedTileInfo {
+            etag: Some("\"v1\"".to_string()),
+            last_modified: Some("Wed, 21 Oct 2015 07:28:00 GMT".to_string()),
+            expires_at: None,
+        };
+        let conditional = conditional_headers(&stored);
+        assert_eq!(conditional.get(IF_NONE_MATCH).unwrap(), "\"v1\"");
+        assert_eq!(
+            conditional.get(IF_MODIFIED_SINCE).unwrap(),
+            "Wed, 21 Oct 2015 07:28:00 GMT"
+        );
+
+        let now = SystemTime::now();
+        let refreshed =
+            HttpCachePolicy::from_headers(&headers(&[("cache-control", "max-age=30")]), now)
+                .merge_not_modified(&stored);
+        assert_eq!(refreshed.info.etag, stored.etag);
+        assert_eq!(refreshed.info.last_modified, stored.last_modified);
+        assert!(!refreshed.info.is_stale(now));
+    }
+}
@@ -1,19 +1,28 @@
 //! Core TileLayer implementation
 
+use super::error_tile::ErrorTile;
+use super::source::RetinaTileSource;
+use super::types::{fade_progress, FilterTransition};
 use super::{
-    OpenStreetMapSource, TileCache, TileLayerOptions, TileLevel, TileLoader, TileLoaderConfig,
-    TilePriority, TileSource,
+    CachedTile, FailoverTileSource, OfflineDownload, OfflineRegion, OpenStreetMapSource, RetinaMode,
+    TemplateTileSource, TileCache, TileLayerOptions, TileLevel, TileLoader, TileLoaderConfig,
+    TilePriority, TileSource, TileState,
 };
 use crate::{
     core::{
+        config::TileMemoryBudget,
         geo::{Point, TileCoord},
         viewport::Viewport,
     },
+    input::MapEvent,
     layers::{
         animation::AnimationManager,
         base::{LayerProperties, LayerTrait, LayerType},
     },
-    rendering::context::RenderContext,
+    rendering::{
+        context::{RenderContext, FULL_UV},
+        filter::RasterFilter,
+    },
     traits::{GeometryOps, RetryLogic, PointMath},
     Result,
 };
@@ -21,7 +30,7 @@ use crate::{
 pub struct TileLayer {
     pub(crate) properties: LayerProperties,
     pub(crate) options: TileLayerOptions,
-    pub(crate) tile_source: Box<dyn TileSource>,
+    pub(crate) tile_source: Arc<dyn TileSource>,
     pub(crate) tile_loader: TileLoader,
     pub(crate) tile_cache: TileCache,
     pub(crate) levels: HashMap<u8, TileLevel>,
@@ -41,8 +50,30 @@ pub struct TileLayer {
 
     /// Track drag state for update orchestrator coordination
     pub(crate) is_dragging_last_frame: bool,
+
+    /// Display scale of the last render context
+    pub(crate) pixels_per_point: f32,
+    pub(crate) retina_mode: RetinaMode,
+    /// Source requesting `@2x` tiles while in [`RetinaMode::HighResolution`]
+    pub(crate) retina_source: Option<Arc<dyn TileSource>>,
+
+    /// Image drawn in place of failed tiles
+    pub(crate) error_tile: ErrorTile,
+    /// Tiles whose last load failed, with their error and retry state
+    pub(crate) failed_tiles: HashMap<TileCoord, TileState>,
+    /// Keep buffer around the view at its zoom, as of the last update;
+    /// requests and retries outside it are dropped
+    pub(crate) keep_range: Option<((Point, Point), u8)>,
+    /// Tiles the source has no data for, drawn from their fallbacks
+    pub(crate) missing_tiles: HashSet<TileCoord>,
+    /// Failures not yet reported through [`TileLayer::take_tile_errors`]
+    pub(crate) tile_errors: Vec<(TileCoord, String)>,
+    /// Newly arrived tiles that are fading in, with when they arrived
+    pub(crate) fading_tiles: HashMap<TileCoord, Instant>,
+    /// Colour filter animation started by [`TileLayer::animate_filter`]
+    pub(crate) filter_transition: Option<FilterTransition>,
 }
-use crate::prelude::{Arc, HashMap};
+use crate::prelude::{Arc, Duration, HashMap, HashSet, Instant};
 
 #[cfg(feature = "debug")]
 use log;
@@ -67,6 +98,18 @@ impl TileLayer {
         tile_source: Box<dyn TileSource>,
         options: TileLayerOptions,
         loader_config: TileLoaderConfig,
+    ) -> Result<Self> {
+        let tile_loader = TileLoader::new(loader_config).with_image_decoding(true);
+        Self::new_with_loader(id, tile_source, options, tile_loader)
+    }
+
+    /// Create a layer loading its tiles through `tile_loader`, e.g. one
+    /// [shared](TileLoader::share) with other layers
+    pub fn new_with_loader(
+        id: String,
+        tile_source: Box<dyn TileSource>,
+        options: TileLayerOptions,
+        tile_loader: TileLoader,
     ) -> Result<Self> {
         let properties = LayerProperties {
             id,
@@ -79,12 +122,13 @@ impl TileLayer {
             options: serde_json::Value::Null,
         };
 
-        let tile_loader = TileLoader::new(loader_config);
         let tile_cache = TileCache::new(2048);
+        let error_tile = ErrorTile::new(options.error_tile_url.clone(), options.tile_size)
+            .with_request_options(tile_source.request_options().cloned());
 
         Ok(Self {
             properties,
-            tile_source,
+            tile_source: Arc::from(tile_source),
             tile_loader,
             tile_cache,
             levels: HashMap::default(),
@@ -98,6 +142,16 @@ impl TileLayer {
             tiles_loading_count: 0,
             loading_state_changed: false,
             is_dragging_last_frame: false,
+            pixels_per_point: 1.0,
+            retina_mode: RetinaMode::Off,
+            retina_source: None,
+            error_tile,
+            failed_tiles: HashMap::default(),
+            keep_range: None,
+            missing_tiles: HashSet::default(),
+            tile_errors: Vec::new(),
+            fading_tiles: HashMap::default(),
+            filter_transition: None,
             options,
         })
     }
@@ -120,10 +174,273 @@ impl TileLayer {
         self
     }
 
+    /// Replace the tile source, dropping any tiles fetched from the previous one
+    pub fn with_tile_source(mut self, tile_source: Box<dyn TileSource>) -> Self {
+        self.set_tile_source(tile_source);
+        self
+    }
+
+    /// Persist downloaded tiles in `disk_cache` and read them back from it
+    /// before downloading
+    pub fn with_disk_cache(mut self, disk_cache: Arc<super::DiskTileCache>) -> Self {
+        self.tile_loader.set_disk_cache(Some(disk_cache));
+        self
+    }
+
+    /// The persistent disk cache used by this layer, if any
+    pub fn disk_cache(&self) -> Option<&Arc<super::DiskTileCache>> {
+        self.tile_loader.disk_cache()
+    }
+
+    /// Download `region` into the disk cache for offline use, limited to the
+    /// zoom levels this layer displays
+    ///
+    /// Requires a disk cache and a background task manager; see
+    /// [`TileLoader::download_region`].
+    pub fn download_region(&self, region: OfflineRegion) -> Result<OfflineDownload> {
+        self.tile_loader
+            .download_region(self.request_source().clone(), self.offline_zooms(region))
+    }
+
+    /// Release a region downloaded with [`TileLayer::download_region`], so
+    /// its tiles may be evicted from the disk cache again
+    pub fn release_region(&self, region: OfflineRegion) -> Result<()> {
+        self.tile_loader
+            .release_region(self.request_source().as_ref(), &self.offline_zooms(region))
+    }
+
+    fn offline_zooms(&self, mut region: OfflineRegion) -> OfflineRegion {
+        region.min_zoom = region.min_zoom.max(self.options.min_zoom);
+        region.max_zoom = region.max_zoom.min(self.options.max_zoom);
+        region
+    }
+
+    /// Swap the tile source at runtime
+    pub fn set_tile_source(&mut self, tile_source: Box<dyn TileSource>) {
+        self.tile_source = Arc::from(tile_source);
+        self.tile_cache.clear();
+        self.tile_loader.clear_pending();
+        self.levels.clear();
+        self.failed_tiles.clear();
+        self.missing_tiles.clear();
+        self.tile_zoom = None;
+        self.retina_mode = RetinaMode::Off;
+        self.update_retina_mode();
+        self.reset_error_tile();
+    }
+
+    /// Tell the layer how many physical pixels make up a screen point
+    ///
+    /// With `detect_retina` set, a scale above 1 switches the layer to
+    /// HiDPI tiles: the source's `@2x` tiles when it has them, otherwise tiles
+    /// from the next zoom level drawn at half size. Rendering picks the scale
+    /// up from the [`RenderContext`], so this is only needed to set it early.
+    pub fn set_pixels_per_point(&mut self, pixels_per_point: f32) {
+        self.pixels_per_point = pixels_per_point;
+        self.update_retina_mode();
+    }
+
+    /// How the layer currently serves HiDPI displays
+    pub fn retina_mode(&self) -> RetinaMode {
+        self.retina_mode
+    }
+
+    fn update_retina_mode(&mut self) {
+        let mode = if !self.options.detect_retina || self.pixels_per_point <= 1.0 {
+            RetinaMode::Off
+        } else if self
+            .tile_source
+            .retina_url(TileCoord::new(0, 0, 0))
+            .is_some()
+        {
+            RetinaMode::HighResolution
+        } else {
+            RetinaMode::ZoomOffset
+        };
+        if mode == self.retina_mode {
+            return;
+        }
+
+        // Tiles loaded for the previous mode have the wrong resolution or zoom
+        self.retina_mode = mode;
+        self.retina_source = (mode == RetinaMode::HighResolution).then(|| {
+            Arc::new(RetinaTileSource::new(self.tile_source.clone())) as Arc<dyn TileSource>
+        });
+        self.tile_cache.clear();
+        self.tile_loader.clear_pending();
+        self.levels.clear();
+        self.failed_tiles.clear();
+        self.missing_tiles.clear();
+        self.tile_zoom = None;
+    }
+
+    fn reset_error_tile(&mut self) {
+        self.error_tile =
+            ErrorTile::new(self.options.error_tile_url.clone(), self.options.tile_size)
+                .with_request_options(self.tile_source.request_options().cloned());
+    }
+
+    /// Source that tile requests go to, accounting for HiDPI tiles
+    pub(crate) fn request_source(&self) -> &Arc<dyn TileSource> {
+        self.retina_source.as_ref().unwrap_or(&self.tile_source)
+    }
+
+    /// Zoom of the tiles drawn at view zoom `zoom`: one level higher when
+    /// HiDPI tiles come from the next zoom level
+    fn data_zoom(&self, zoom: u8) -> u8 {
+        if self.retina_mode == RetinaMode::ZoomOffset && zoom < self.options.max_zoom {
+            zoom + 1
+        } else {
+            zoom
+        }
+    }
+
+    /// Screen size of a `zoom` tile relative to `tile_size`: one half when it
+    /// comes from the level above the viewport's
+    fn tile_display_scale(&self, zoom: u8, viewport: &Viewport) -> f64 {
+        if self.retina_mode == RetinaMode::ZoomOffset && zoom as f64 > viewport.zoom.floor() {
+            0.5
+        } else {
+            1.0
+        }
+    }
+
+    /// Create a tile layer from a Leaflet-style URL template such as
+    /// `https://{s}.tile.example.com/{z}/{x}/{y}{r}.png`
+    pub fn from_template(
+        id: String,
+        template: impl Into<String>,
+        options: TileLayerOptions,
+    ) -> Result<Self> {
+        let source = TemplateTileSource::from_options(template, &options);
+        Self::new(id, Box::new(source), options)
+    }
+
+    /// Create a tile layer backed by a WMS service, requesting `layers` in the
+    /// CRS named by `options.reference_system`
+    pub fn from_wms(
+        id: String,
+        base_url: impl Into<String>,
+        layers: Vec<String>,
+        options: TileLayerOptions,
+    ) -> Result<Self> {
+        let source = super::WmsTileSource::from_options(base_url, layers, &options)?;
+        Self::new(id, Box::new(source), options)
+    }
+
+    /// Create a tile layer for a WMTS layer and tile matrix set
+    ///
+    /// The matrix set's zoom range and tile size and the layer's extent
+    /// override the matching fields of `options`.
+    pub fn from_wmts(
+        id: String,
+        capabilities: &super::WmtsCapabilities,
+        layer: &str,
+        matrix_set: &str,
+        mut options: TileLayerOptions,
+    ) -> Result<Self> {
+        let source = super::WmtsTileSource::from_capabilities(capabilities, layer, matrix_set)?;
+        source.apply_to_options(&mut options);
+        Self::new(id, Box::new(source), options)
+    }
+
+    /// Create a tile layer that reads raster tiles from a local MBTiles archive
+    ///
+    /// The archive's zoom range, bounds and attribution override the matching
+    /// fields of `options`.
+    #[cfg(feature = "mbtiles")]
+    pub fn from_mbtiles(
+        id: String,
+        path: impl AsRef<std::path::Path>,
+        options: TileLayerOptions,
+    ) -> Result<Self> {
+        let source = super::MBTilesSource::open(path)?;
+        if source.metadata().is_vector() {
+            return Err(Box::new(crate::Error::Layer(format!(
+                "{} contains vector tiles and cannot be shown as a raster layer",
+                source.path().display()
+            ))));
+        }
+        let options = source.layer_options(options);
+        Self::new(id, Box::new(source), options)
+    }
+
+    /// Create a tile layer that reads raster tiles from a local PMTiles archive
+    ///
+    /// The archive's zoom range, bounds and attribution override the matching
+    /// fields of `options`.
+    pub fn from_pmtiles(
+        id: String,
+        path: impl AsRef<std::path::Path>,
+        mut options: TileLayerOptions,
+    ) -> Result<Self> {
+        let source = super::PMTilesSource::open_file(path)?;
+        if source.is_vector() {
+            return Err(Box::new(crate::Error::Layer(
+                "PMTiles archive contains vector tiles and cannot be shown as a raster layer"
+                    .to_string(),
+            )));
+        }
+        source.apply_to_options(&mut options);
+        Self::new(id, Box::new(source), options)
+    }
+
+    /// Create a tile layer that reads `{z}/{x}/{y}.{ext}` files from a local
+    /// directory, numbering rows bottom-up when `tms` is set
+    ///
+    /// Zooms beyond the deepest level in the directory show its tiles
+    /// scaled up.
+    pub fn from_directory(
+        id: String,
+        path: impl AsRef<std::path::Path>,
+        mut options: TileLayerOptions,
+    ) -> Result<Self> {
+        let source = super::DirectoryTileSource::open(path)?.with_tms(options.tms);
+        source.apply_to_options(&mut options);
+        Self::new(id, Box::new(source), options)
+    }
+
+    /// Create a tile layer that requests each tile from the first of
+    /// `sources` that delivers it, falling back to the next on errors
+    pub fn from_sources(
+        id: String,
+        sources: Vec<Box<dyn TileSource>>,
+        options: TileLayerOptions,
+    ) -> Result<Self> {
+        let source = FailoverTileSource::new(sources)?;
+        Self::new(id, Box::new(source), options)
+    }
+
+    /// Create a tile layer for a tile server and its mirrors, given as
+    /// URL templates in order of preference
+    pub fn from_mirrors(
+        id: String,
+        templates: Vec<String>,
+        options: TileLayerOptions,
+    ) -> Result<Self> {
+        let sources = templates
+            .into_iter()
+            .map(|template| {
+                Box::new(TemplateTileSource::from_options(template, &options))
+                    as Box<dyn TileSource>
+            })
+            .collect();
+        Self::from_sources(id, sources, options)
+    }
+
+    /// Health of each source when the layer's source is a failover chain,
+    /// in chain order; empty otherwise
+    pub fn source_stats(&self) -> Vec<super::SourceStats> {
+        self.tile_source
+            .failover()
+            .map(|chain| chain.stats())
+            .unwrap_or_default()
+    }
+
     /// Main rendering method that integrates all systems
     /// This consolidates the old duplicated render logic
     pub fn render_tiles(&self, ctx: &mut RenderContext, viewport: &Viewport) -> Result<()> {
-        let zoom = viewport.zoom.floor() as u8;
+        let zoom = self.data_zoom(viewport.zoom.floor() as u8);
 
         // Skip rendering if zoom is out of bounds
         if zoom < self.options.min_zoom || zoom > self.options.max_zoom {
@@ -139,6 +456,15 @@ impl TileLayer {
 
         let mut tiles_to_queue = Vec::new();
 
+        // Tiles of this layer carry its colour filter, or the theme's for
+        // a basemap without one
+        let previous_filter = ctx.raster_filter;
+        let filter = match self.options.filter {
+            None if self.options.basemap => ctx.basemap_filter,
+            filter => filter,
+        };
+        ctx.set_raster_filter(filter);
+
         // Render each visible tile with boundary checking and animation support
         for coord in &visible_tiles {
             // Enhanced boundary checking - skip tiles outside render bounds
@@ -157,103 +483,333 @@ impl TileLayer {
                 }
             }
 
-            let mut tile_rendered = false;
+            // Align tile edges with physical pixels so HiDPI tiles stay sharp
+            let bounds = snap_to_pixels(bounds, ctx.pixels_per_point as f64);
+
+            // Outside the source's native zooms the tile is drawn from
+            // tiles at the nearest native zoom
+            for piece in self.native_pieces(*coord, bounds) {
+                self.render_native_tile(
+                    ctx,
+                    piece.coord,
+                    piece.uv,
+                    piece.bounds,
+                    &mut tiles_to_queue,
+                );
+            }
+        }
+        ctx.set_raster_filter(previous_filter);
 
-            // Try to render from level tiles first
-            if let Some(level) = self.levels.get(&coord.z) {
-                if let Some(tile_state) = level.tiles.get(coord) {
-                    if let Some(tile_data) = tile_state.get_display_data() {
-                        if ctx.render_tile(tile_data, bounds, self.opacity()).is_ok() {
-                            tile_rendered = true;
-                        }
-                    }
-                }
+        // Queue tiles that need loading
+        if !tiles_to_queue.is_empty() {
+            let _ = self.tile_loader.queue_tiles_batch(
+                self.request_source().as_ref(),
+                tiles_to_queue,
+                TilePriority::Visible,
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Draw the `uv` part of source tile `coord` over `bounds`, falling back to
+    /// nearby zoom levels while it loads
+    fn render_native_tile(
+        &self,
+        ctx: &mut RenderContext,
+        coord: TileCoord,
+        uv: (Point, Point),
+        bounds: (Point, Point),
+        tiles_to_queue: &mut Vec<TileCoord>,
+    ) {
+        // Try to render from level tiles first, then from the cache
+        let tile_data = self
+            .levels
+            .get(&coord.z)
+            .and_then(|level| level.tiles.get(&coord))
+            .and_then(|tile_state| tile_state.get_display_data().cloned())
+            .or_else(|| self.tile_cache.get(&coord));
+
+        if let Some(tile_data) = tile_data {
+            // A tile fading in is drawn over its fallback, which fades out
+            let fade = self.fade_opacity(&coord);
+            if fade < 1.0 {
+                self.render_fallback(ctx, coord, uv, bounds, 1.0 - fade);
             }
+            let opacity = self.opacity() * fade;
+            if self.draw_tile(ctx, coord, &tile_data, uv, bounds, opacity) {
+                return;
+            }
+        }
 
-            // Fallback to cache if not in levels
-            if !tile_rendered {
-                if let Some(tile_data) = self.tile_cache.get(coord) {
-                    if ctx.render_tile(&tile_data, bounds, self.opacity()).is_ok() {
-                        tile_rendered = true;
-                    }
+        // Failed tiles show the error image and wait for a retry
+        if self.failed_tiles.contains_key(&coord) {
+            let _ = ctx.render_tile(&self.error_tile.image(), bounds, self.opacity());
+            return;
+        }
+
+        // Tiles the source lacks leave their fallback, or nothing, in place
+        if self.missing_tiles.contains(&coord) {
+            self.render_fallback(ctx, coord, uv, bounds, 1.0);
+            return;
+        }
+
+        // Queue for loading if not rendered
+        tiles_to_queue.push(coord);
+
+        // Only show grey placeholder if no fallback tiles available
+        if !self.render_fallback(ctx, coord, uv, bounds, 1.0) {
+            let _ = ctx.render_tile(&Vec::new(), bounds, self.opacity());
+        }
+    }
+
+    /// Draw cached tiles of nearby zoom levels in place of tile `coord`,
+    /// faded by `fade`, returning whether any were drawn
+    fn render_fallback(
+        &self,
+        ctx: &mut RenderContext,
+        coord: TileCoord,
+        uv: (Point, Point),
+        bounds: (Point, Point),
+        fade: f32,
+    ) -> bool {
+        // LEAFLET-STYLE FALLBACK: Show the matching part of parent tiles
+        // instead of grey placeholders
+        // 1. Try parent tiles (zoom-1), then grand-parent tiles (zoom-2)
+        for (depth, opacity) in [(1, 0.8), (2, 0.6)] {
+            let Some(ancestor) = coord.z.checked_sub(depth).and_then(|z| coord.ancestor(z)) else {
+                break;
+            };
+            if let Some(ancestor_data) = self.tile_cache.get(&ancestor) {
+                let region = sub_region(region_in_ancestor(coord, ancestor), uv);
+                let opacity = self.opacity() * opacity * fade;
+                if self.draw_tile(ctx, ancestor, &ancestor_data, region, bounds, opacity) {
+                    return true;
                 }
             }
+        }
 
-            // Queue for loading if not rendered
-            if !tile_rendered {
-                tiles_to_queue.push(*coord);
-                
-                // LEAFLET-STYLE FALLBACK: Show parent tiles instead of grey placeholders
-                let mut fallback_rendered = false;
-                
-                // 1. Try parent tiles (zoom-1) for immediate fallback
-                if coord.z > 0 {
-                    let parent_coord = TileCoord {
-                        x: coord.x / 2,
-                        y: coord.y / 2,
-                        z: coord.z - 1,
-                    };
-                    
-                    if let Some(parent_data) = self.tile_cache.get(&parent_coord) {
-                        if ctx.render_tile(&parent_data, bounds, self.opacity() * 0.8).is_ok() {
-                            fallback_rendered = true;
-                        }
-                    }
-                }
-                
-                // 2. Try grand-parent tiles (zoom-2) if parent not available
-                if !fallback_rendered && coord.z > 1 {
-                    let grandparent_coord = TileCoord {
-                        x: coord.x / 4,
-                        y: coord.y / 4,
-                        z: coord.z - 2,
-                    };
-                    
-                    if let Some(grandparent_data) = self.tile_cache.get(&grandparent_coord) {
-                        if ctx.render_tile(&grandparent_data, bounds, self.opacity() * 0.6).is_ok() {
-                            fallback_rendered = true;
-                        }
-                    }
-                }
-                
-                // 3. Try child tiles (zoom+1) for zoom-out scenarios
-                if !fallback_rendered && coord.z < 18 {
-                    let child_coords = [
-                        TileCoord { x: coord.x * 2, y: coord.y * 2, z: coord.z + 1 },
-                        TileCoord { x: coord.x * 2 + 1, y: coord.y * 2, z: coord.z + 1 },
-                        TileCoord { x: coord.x * 2, y: coord.y * 2 + 1, z: coord.z + 1 },
-                        TileCoord { x: coord.x * 2 + 1, y: coord.y * 2 + 1, z: coord.z + 1 },
-                    ];
-                    
-                    for child_coord in &child_coords {
-                        if let Some(child_data) = self.tile_cache.get(child_coord) {
-                            if ctx.render_tile(&child_data, bounds, self.opacity() * 0.7).is_ok() {
-                                fallback_rendered = true;
-                                break;
-                            }
-                        }
+        // 2. Try child tiles (zoom+1) for zoom-out scenarios
+        if coord.z < 18 {
+            for child_coord in &coord.children() {
+                if let Some(child_data) = self.tile_cache.get(child_coord) {
+                    let opacity = self.opacity() * 0.7 * fade;
+                    if self.draw_tile(ctx, *child_coord, &child_data, FULL_UV, bounds, opacity) {
+                        return true;
                     }
                 }
-                
-                // 4. Only show grey placeholder if no fallback tiles available
-                if !fallback_rendered {
-                    let _ = ctx.render_tile(&Vec::new(), bounds, self.opacity());
-                }
             }
         }
+        false
+    }
 
-        // Queue tiles that need loading
-        if !tiles_to_queue.is_empty() {
-            let _ = self.tile_loader.queue_tiles_batch(
-                self.tile_source.as_ref(),
-                tiles_to_queue,
-                TilePriority::Visible,
-            );
+    pub(crate) fn fade_duration(&self) -> Duration {
+        Duration::from_millis(self.options.fade_duration_ms as u64)
+    }
+
+    /// How far tile `coord` has faded in, 1.0 once it is fully shown
+    fn fade_opacity(&self, coord: &TileCoord) -> f32 {
+        self.fading_tiles
+            .get(coord)
+            .map_or(1.0, |arrived| fade_progress(*arrived, self.fade_duration()))
+    }
+
+    /// Whether newly loaded tiles are still fading in
+    pub fn is_fading(&self) -> bool {
+        let fade = self.fade_duration();
+        self.fading_tiles
+            .values()
+            .any(|arrived| arrived.elapsed() < fade)
+    }
+
+    /// Colour filter the tiles are drawn with, part way there while
+    /// [`TileLayer::animate_filter`] runs
+    pub fn filter(&self) -> Option<RasterFilter> {
+        match &self.filter_transition {
+            Some(transition) => transition.current(),
+            None => self.options.filter,
         }
+    }
 
+    /// Change the colour filter; tiles are redrawn from the pixels already
+    /// loaded
+    pub fn set_filter(&mut self, filter: Option<RasterFilter>) {
+        self.filter_transition = None;
+        self.options.filter = filter;
+    }
+
+    /// Move the colour filter to `filter` over `duration`, starting from
+    /// the filter drawn now; no filter counts as [`RasterFilter::default`]
+    pub fn animate_filter(&mut self, filter: Option<RasterFilter>, duration: Duration) {
+        let from = self.filter().unwrap_or_default();
+        self.options.filter = filter;
+        self.filter_transition = Some(FilterTransition {
+            from,
+            to: filter,
+            start: Instant::now(),
+            duration,
+        });
+    }
+
+    pub fn is_filter_animating(&self) -> bool {
+        self.filter_transition
+            .as_ref()
+            .is_some_and(|transition| !transition.is_done())
+    }
+
+    /// Whether every tile visible in `viewport` has loaded, or failed, and
+    /// faded in, so drawing it needs no placeholders or fallback tiles
+    pub fn is_ready(&self, viewport: &Viewport) -> bool {
+        self.visible_native_tiles(viewport).iter().all(|coord| {
+            self.failed_tiles.contains_key(coord)
+                || self.missing_tiles.contains(coord)
+                || (self.tile_cache.contains(coord) && self.fade_opacity(coord) >= 1.0)
+        })
+    }
+
+    /// Load the tiles `viewport` shows without drawing them, so the layer
+    /// is ready when it is next drawn there
+    pub fn preload(&mut self, viewport: &Viewport, priority: TilePriority) -> Result<()> {
+        self.process_tile_results()?;
+
+        let to_load: Vec<TileCoord> = self
+            .visible_native_tiles(viewport)
+            .into_iter()
+            .filter(|coord| {
+                !self.tile_cache.contains(coord)
+                    && !self.failed_tiles.contains_key(coord)
+                    && !self.missing_tiles.contains(coord)
+            })
+            .collect();
+        if !to_load.is_empty() {
+            self.tile_loader.queue_tiles_batch(
+                self.request_source().as_ref(),
+                to_load,
+                priority,
+            )?;
+        }
         Ok(())
     }
 
+    /// Source tiles drawn for `viewport`
+    fn visible_native_tiles(&self, viewport: &Viewport) -> Vec<TileCoord> {
+        let zoom = self.data_zoom(viewport.zoom.floor() as u8);
+        if zoom < self.options.min_zoom || zoom > self.options.max_zoom {
+            return Vec::new();
+        }
+
+        let tile_center = self.get_tile_center(viewport);
+        let tiled_pixel_bounds = self.get_tiled_pixel_bounds(Some(tile_center), viewport, zoom);
+        let tile_range = self.pixel_bounds_to_tile_range(&tiled_pixel_bounds, zoom);
+        let visible_tiles: Vec<TileCoord> = self
+            .tile_range_to_coords(&tile_range, zoom)
+            .into_iter()
+            .filter(|coord| self.is_tile_within_boundary(coord))
+            .collect();
+        self.native_coords(&visible_tiles)
+    }
+
+    /// Draw the `uv` part of tile `coord` from `data`, using its decoded
+    /// pixels when the cache still has them
+    fn draw_tile(
+        &self,
+        ctx: &mut RenderContext,
+        coord: TileCoord,
+        data: &[u8],
+        uv: (Point, Point),
+        bounds: (Point, Point),
+        opacity: f32,
+    ) -> bool {
+        let image = self.tile_cache.get_decoded(&coord);
+
+        // An animated filter cross-fades from the tile filtered as the
+        // animation started to the tile filtered as it ends, so each tile is
+        // filtered and uploaded once per end rather than on every frame
+        if let Some((from, progress)) = self
+            .filter_transition
+            .as_ref()
+            .and_then(FilterTransition::crossfade)
+        {
+            let to = ctx.raster_filter;
+            ctx.set_raster_filter(Some(from).filter(|from| !from.is_identity()));
+            let drawn = ctx.render_decoded_tile_region(data, image.clone(), uv, bounds, opacity);
+            ctx.set_raster_filter(to);
+            return drawn.is_ok()
+                && ctx
+                    .render_decoded_tile_region(data, image, uv, bounds, opacity * progress)
+                    .is_ok();
+        }
+
+        ctx.render_decoded_tile_region(data, image, uv, bounds, opacity)
+            .is_ok()
+    }
+
+    /// Source tiles making up display tile `coord`, each with the part of the
+    /// source tile to draw and where it goes
+    ///
+    /// Past `max_native_zoom` this is part of an ancestor tile; below
+    /// `min_native_zoom` it is the descendant tiles, each drawn into its share
+    /// of `bounds`, and nothing once too far below.
+    fn native_pieces(&self, coord: TileCoord, bounds: (Point, Point)) -> Vec<NativePiece> {
+        let Some(native_zoom) = self.native_zoom(coord.z) else {
+            return Vec::new();
+        };
+        if native_zoom < coord.z {
+            let ancestor = coord.ancestor(native_zoom).unwrap_or(coord);
+            return vec![NativePiece {
+                coord: ancestor,
+                uv: region_in_ancestor(coord, ancestor),
+                bounds,
+            }];
+        }
+
+        coord
+            .descendants(native_zoom)
+            .into_iter()
+            .map(|tile| {
+                let region = region_in_ancestor(tile, coord);
+                let size = Point::new(bounds.1.x - bounds.0.x, bounds.1.y - bounds.0.y);
+                let at = |uv: Point| {
+                    Point::new(bounds.0.x + uv.x * size.x, bounds.0.y + uv.y * size.y)
+                };
+                NativePiece {
+                    coord: tile,
+                    uv: FULL_UV,
+                    bounds: (at(region.0), at(region.1)),
+                }
+            })
+            .collect()
+    }
+
+    /// Zoom the source has tiles for that is closest to `zoom`, or `None`
+    /// when that is too far below `min_native_zoom` to draw from
+    fn native_zoom(&self, zoom: u8) -> Option<u8> {
+        let clamped = self.options.max_native_zoom.map_or(zoom, |max| zoom.min(max));
+        let native = self
+            .options
+            .min_native_zoom
+            .map_or(clamped, |min| clamped.max(min));
+        (native <= zoom.saturating_add(MAX_DESCENDANT_DEPTH)).then_some(native)
+    }
+
+    /// Source tiles to request for display tiles `coords`
+    fn native_coords(&self, coords: &[TileCoord]) -> Vec<TileCoord> {
+        let mut seen = HashSet::default();
+        coords
+            .iter()
+            .flat_map(|coord| {
+                let Some(native_zoom) = self.native_zoom(coord.z) else {
+                    return Vec::new();
+                };
+                match coord.ancestor(native_zoom) {
+                    Some(ancestor) => vec![ancestor],
+                    None => coord.descendants(native_zoom),
+                }
+            })
+            .filter(|coord| seen.insert(*coord))
+            .collect()
+    }
+
     /// Calculate screen bounds for a tile coordinate using a specific center
     /// This allows consistent positioning during drag operations
     /// CRITICAL FIX: Use viewport's coordinate transformation methods to eliminate fish-eye distortion
@@ -264,6 +820,7 @@ impl TileLayer {
         center: crate::core::geo::LatLng,
     ) -> (Point, Point) {
         let tile_size = self.options.tile_size as f64;
+        let scale = self.tile_display_scale(coord.z, viewport);
 
         // Convert tile coordinate to world pixel coordinates
         let tile_world_x = coord.x as f64 * tile_size;
@@ -272,10 +829,12 @@ impl TileLayer {
         // Project specified center to same zoom level
         let center_world = viewport.project(&center, Some(coord.z as f64));
 
-        // Calculate layer point (relative to pixel origin)
-        let layer_x = tile_world_x - center_world.x;
-        let layer_y = tile_world_y - center_world.y;
+        // Calculate layer point (relative to pixel origin), shrunk for tiles
+        // drawn at less than full size
+        let layer_x = (tile_world_x - center_world.x) * scale;
+        let layer_y = (tile_world_y - center_world.y) * scale;
         let layer_point = Point::new(layer_x, layer_y);
+        let tile_size = tile_size * scale;
 
         // Use viewport's coordinate transformation to get container point
         // This properly handles map pane position during dragging
@@ -289,7 +848,7 @@ impl TileLayer {
     }
 
 
-
+    /// Create a layer of generated debug tiles that loads without a network
     pub fn for_testing(id: String, name: String) -> Self {
         println!(
             "🧪 [DEBUG] TileLayer::for_testing() - Creating test tile layer '{}' ({})",
@@ -297,7 +856,7 @@ impl TileLayer {
         );
         let mut layer = Self::new_with_config(
             id,
-            Box::new(OpenStreetMapSource::new()),
+            Box::new(super::DebugTileSource::new()),
             TileLayerOptions::default(),
             TileLoaderConfig::for_testing(),
         )
@@ -307,7 +866,10 @@ impl TileLayer {
     }
 
     pub fn openstreetmap(id: String, _name: String) -> Self {
-        let options = TileLayerOptions::default();
+        let options = TileLayerOptions {
+            basemap: true,
+            ..Default::default()
+        };
         Self::new(id, Box::new(OpenStreetMapSource::new()), options).unwrap_or_else(|e| {
             panic!("Failed to create OpenStreetMap tile layer: {:?}", e);
         })
@@ -317,9 +879,15 @@ impl TileLayer {
         let options = TileLayerOptions {
             attribution: Some("© Esri, Maxar, GeoEye, Earthstar Geographics, CNES/Airbus DS, USDA, USGS, AeroGRID, IGN, and the GIS User Community".to_string()),
             subdomains: vec![],
+            max_zoom: 19,
             ..Default::default()
         };
-        Self::new(id, Box::new(OpenStreetMapSource::new()), options).unwrap()
+        Self::from_template(
+            id,
+            "https://server.arcgisonline.com/ArcGIS/rest/services/World_Imagery/MapServer/tile/{z}/{y}/{x}",
+            options,
+        )
+        .unwrap()
     }
 
     /// Create a high-performance tile layer with background task manager
@@ -328,11 +896,13 @@ impl TileLayer {
         name: String,
         bg_task_manager: Arc<crate::background::BackgroundTaskManager>,
     ) -> Self {
-        let tile_source = Box::new(OpenStreetMapSource::new());
+        let tile_source = Arc::new(OpenStreetMapSource::new());
         let options = TileLayerOptions {
             tile_size: 256,
             min_zoom: 0,
             max_zoom: 18,
+            max_native_zoom: None,
+            min_native_zoom: None,
             attribution: Some("© OpenStreetMap contributors".to_string()),
             opacity: 1.0,
             z_index: 1,
@@ -347,6 +917,9 @@ impl TileLayer {
             update_when_idle: false,
             update_when_zooming: true,
             update_interval: 16, // Fixed: u32 instead of Duration
+            fade_duration_ms: 200,
+            filter: None,
+            basemap: true,
             target_zoom: None,
         };
 
@@ -362,10 +935,13 @@ impl TileLayer {
         };
 
         let tile_loader = TileLoader::with_adaptive_config(loader_config, adaptive_config)
-            .with_background_task_manager(bg_task_manager);
+            .with_background_task_manager(bg_task_manager)
+            .with_image_decoding(true);
 
         let tile_cache = TileCache::new(4096); // Larger cache for high performance
         let animation_manager = crate::layers::animation::AnimationManager::new();
+        let error_tile = ErrorTile::new(options.error_tile_url.clone(), options.tile_size)
+            .with_request_options(tile_source.request_options().cloned());
 
         Self {
             properties: LayerProperties {
@@ -393,6 +969,16 @@ impl TileLayer {
             tiles_loading_count: 0,
             loading_state_changed: false,
             is_dragging_last_frame: false,
+            pixels_per_point: 1.0,
+            retina_mode: RetinaMode::Off,
+            retina_source: None,
+            error_tile,
+            failed_tiles: HashMap::default(),
+            keep_range: None,
+            missing_tiles: HashSet::default(),
+            tile_errors: Vec::new(),
+            fading_tiles: HashMap::default(),
+            filter_transition: None,
         }
     }
 
@@ -401,11 +987,54 @@ impl TileLayer {
     pub(crate) fn process_tile_results(&mut self) -> Result<()> {
         let results = self.tile_loader.try_recv_results();
 
+        let fade = self.fade_duration();
+        self.fading_tiles
+            .retain(|_, arrived| arrived.elapsed() < fade);
+
         for result in results {
             match result.data {
-                Ok(data) => {
+                // Archive and directory sources have no data for tiles they
+                // don't cover; parents and overzoomed tiles show instead
+                Ok(None) => {
+                    self.tile_cache.remove(&result.coord);
+                    self.failed_tiles.remove(&result.coord);
+                    self.missing_tiles.insert(result.coord);
+                    if let Some(level) = self.levels.get_mut(&result.coord.z) {
+                        if let Some(tile) = level.tiles.get_mut(&result.coord) {
+                            tile.mark_missing();
+                        }
+                    }
+                }
+                // A revalidated tile that has not changed keeps its pixels
+                Ok(Some(data))
+                    if self
+                        .tile_cache
+                        .get(&result.coord)
+                        .is_some_and(|cached| *cached == data) =>
+                {
+                    if let Some(policy) = result.policy {
+                        self.tile_cache.revalidated(&result.coord, policy);
+                    }
+                }
+                Ok(Some(data)) => {
+                    // Tiles fade in when they first appear, not when a tile
+                    // already on screen is refreshed
+                    if !fade.is_zero() && !self.tile_cache.contains(&result.coord) {
+                        self.fading_tiles.insert(result.coord, Instant::now());
+                    }
                     let data_arc = Arc::new(data);
-                    self.tile_cache.put(result.coord, data_arc.clone());
+                    match result.policy {
+                        Some(policy) => {
+                            self.tile_cache
+                                .put_downloaded(result.coord, data_arc.clone(), policy)
+                        }
+                        None => self.tile_cache.put(result.coord, data_arc.clone()),
+                    }
+                    if let Some(image) = result.image {
+                        self.tile_cache.put_decoded(result.coord, image);
+                    }
+                    self.failed_tiles.remove(&result.coord);
+                    self.missing_tiles.remove(&result.coord);
 
                     if let Some(level) = self.levels.get_mut(&result.coord.z) {
                         if let Some(tile) = level.tiles.get_mut(&result.coord) {
@@ -420,6 +1049,16 @@ impl TileLayer {
                             tile.mark_error(e.to_string());
                         }
                     }
+
+                    // Report a tile once when it fails, not on every retry
+                    let failed = self
+                        .failed_tiles
+                        .entry(result.coord)
+                        .or_insert_with(|| TileState::new(result.coord));
+                    if failed.error.is_none() {
+                        self.tile_errors.push((result.coord, e.to_string()));
+                    }
+                    failed.mark_error(e.to_string());
                 }
             }
         }
@@ -443,6 +1082,46 @@ impl TileLayer {
         true
     }
 
+    /// Tiles whose last load failed
+    pub fn failed_tiles(&self) -> Vec<TileCoord> {
+        self.failed_tiles.keys().copied().collect()
+    }
+
+    /// Why `coord` failed to load, if it did
+    pub fn tile_error(&self, coord: &TileCoord) -> Option<&str> {
+        self.failed_tiles
+            .get(coord)
+            .and_then(|tile| tile.error.as_deref())
+    }
+
+    /// Request every failed tile again, returning how many were queued
+    ///
+    /// Failed tiles are retried automatically a few times; this starts over
+    /// once those retries are used up, e.g. when the host application knows
+    /// the connection is back.
+    pub fn retry_failed_tiles(&mut self) -> usize {
+        let coords = self.failed_tiles();
+        self.failed_tiles.clear();
+        let _ = self.tile_loader.queue_tiles_batch(
+            self.request_source().as_ref(),
+            coords.clone(),
+            TilePriority::Visible,
+        );
+        coords.len()
+    }
+
+    /// Tile failures since the last call, as [`MapEvent::TileError`] events
+    pub fn take_tile_errors(&mut self) -> Vec<MapEvent> {
+        self.tile_errors
+            .drain(..)
+            .map(|(coord, error)| MapEvent::TileError {
+                coord,
+                layer_id: self.properties.id.clone(),
+                error,
+            })
+            .collect()
+    }
+
     pub fn tile_source(&self) -> &dyn TileSource {
         self.tile_source.as_ref()
     }
@@ -453,10 +1132,13 @@ impl TileLayer {
 
     pub fn set_tile_options(&mut self, options: TileLayerOptions) {
         self.options = options;
+        self.filter_transition = None;
 
         self.levels.clear();
         self.tile_zoom = None;
         self.loading = false;
+        self.update_retina_mode();
+        self.reset_error_tile();
     }
 
     pub fn is_loading(&self) -> bool {
@@ -470,6 +1152,15 @@ impl TileLayer {
             return true;
         }
 
+        // Keep repainting until new tiles have faded in
+        if self.is_fading() {
+            return true;
+        }
+
+        if self.is_filter_animating() {
+            return true;
+        }
+
         // Only repaint when we have very few tiles loading (almost done)
         if self.tiles_loading_count > 0 && self.tiles_loading_count <= 1 {
             return true;
@@ -487,13 +1178,42 @@ impl TileLayer {
 
     pub(crate) fn handle_tile_retries(&mut self) -> Result<()> {
         let config = self.tile_loader.config().clone();
+        let source = self.request_source().clone();
+
+        let due: Vec<TileCoord> = self
+            .failed_tiles
+            .iter()
+            .filter(|(coord, tile)| {
+                !tile.loading
+                    && self.in_keep_range(coord)
+                    && tile.should_retry(
+                        config.max_retries as u32,
+                        config.retry_delay.as_millis() as u64,
+                        false,
+                    )
+            })
+            .map(|(coord, _)| *coord)
+            .collect();
+        if !due.is_empty() {
+            for coord in &due {
+                if let Some(tile) = self.failed_tiles.get_mut(coord) {
+                    tile.loading = true;
+                }
+            }
+            self.tile_loader
+                .queue_tiles_batch(source.as_ref(), due, TilePriority::Background)?;
+        }
 
+        let keep_range = self.keep_range;
         for level in self.levels.values_mut() {
             let mut tiles_to_retry = Vec::new();
 
             for (coord, tile_state) in level.tiles.iter() {
                 if tile_state.error.is_some()
                     && !tile_state.loading
+                    && keep_range
+                        .as_ref()
+                        .is_none_or(|keep_range| Self::overlaps_keep_range(keep_range, coord))
                     && tile_state.should_retry(
                         config.max_retries as u32,
                         config.retry_delay.as_millis() as u64,
@@ -513,7 +1233,7 @@ impl TileLayer {
 
                     if let Err(e) =
                         self.tile_loader
-                            .queue_tile(self.tile_source.as_ref(), coord, priority)
+                            .queue_tile(source.as_ref(), coord, priority)
                     {
                         #[cfg(feature = "debug")]
                         log::warn!("Failed to retry tile {:?}: {}", coord, e);
@@ -533,6 +1253,17 @@ impl TileLayer {
             .is_some_and(|level| !level.tiles.is_empty())
     }
 
+    /// Tiles held in memory, as downloaded and decoded
+    pub fn tile_cache(&self) -> &TileCache {
+        &self.tile_cache
+    }
+
+    /// Bound the memory of the tile cache, evicting tiles over the new
+    /// budget
+    pub fn set_memory_budget(&mut self, budget: &TileMemoryBudget) {
+        self.tile_cache.set_memory_budget(budget);
+    }
+
     pub fn tile_loader(&self) -> &TileLoader {
         &self.tile_loader
     }
@@ -562,7 +1293,12 @@ impl TileLayer {
         // Use provided center or default to viewport center (like Leaflet)
         let effective_center = center.unwrap_or(viewport.center);
         let viewport_center_px = viewport.project(&effective_center, Some(effective_zoom));
-        let half_size = Point::new(viewport.size.x / 2.0, viewport.size.y / 2.0);
+        // Half-size HiDPI tiles cover twice the viewport in their own pixels
+        let scale = self.tile_display_scale(zoom, viewport);
+        let half_size = Point::new(
+            viewport.size.x / 2.0 / scale,
+            viewport.size.y / 2.0 / scale,
+        );
 
         (
             Point::new(
@@ -694,7 +1430,7 @@ impl TileLayer {
     pub fn update_tiles(&mut self, viewport: &Viewport) -> Result<()> {
         // Trigger aggressive prefetching by updating the tile loader with viewport changes
         self.tile_loader.update_viewport(viewport);
-        let zoom = viewport.zoom.floor() as u8;
+        let zoom = self.data_zoom(viewport.zoom.floor() as u8);
 
         // Check if we're being called during an animation
         let is_animating = self
@@ -773,8 +1509,20 @@ impl TileLayer {
         // Update levels like Leaflet (manage zoom level containers)
         self.update_levels(zoom, self.options.max_zoom);
 
-        // Set zoom transforms for all levels during animations
-        self.set_zoom_transforms(viewport.center, viewport.zoom, viewport);
+        // Tiles of the levels on screen or kept for a zoom transition are
+        // the last to be evicted when the cache is over budget
+        let retained: Vec<u8> = self
+            .levels
+            .values()
+            .filter(|level| level.zoom == zoom || level.should_retain())
+            .filter_map(|level| self.native_zoom(level.zoom))
+            .collect();
+        self.tile_cache.set_retained_zooms(retained);
+
+        // Set zoom transforms for all levels during animations. Half-size HiDPI
+        // tiles are already scaled when placed, so measure from their zoom.
+        let retina_offset = (zoom as f64 - viewport.zoom.floor()).max(0.0);
+        self.set_zoom_transforms(viewport.center, viewport.zoom + retina_offset, viewport);
 
         // Skip if zoom is out of bounds
         if zoom < self.options.min_zoom || zoom > self.options.max_zoom {
@@ -809,6 +1557,10 @@ impl TileLayer {
 
         // Mark tiles for retention
         self.mark_tiles_for_retention(&tile_range, zoom);
+        self.keep_range = Some((
+            self.expand_tile_range(&tile_range, self.keep_buffer as i32),
+            zoom,
+        ));
 
         // Get visible tiles - always prioritize these
         let visible_tiles = self.tile_range_to_coords(&tile_range, zoom);
@@ -964,7 +1716,9 @@ impl TileLayer {
         // Only prune tiles when not animating to prevent blackout during zoom transitions
         // ENHANCED: More conservative pruning even when not animating
         if !is_animating {
+            self.cancel_requests_outside();
             self.prune_tiles(zoom);
+            self.prune_failed_tiles();
         } else {
             #[cfg(feature = "debug")]
             log::debug!("Skipping tile pruning during animation to maintain smooth transition");
@@ -1063,6 +1817,66 @@ impl TileLayer {
         )
     }
 
+    /// Cancel queued and in-flight requests for tiles that fall outside the
+    /// keep buffer of the last update
+    fn cancel_requests_outside(&self) -> usize {
+        let Some(keep_range) = self.keep_range else {
+            return 0;
+        };
+        self.tile_loader
+            .retain_tasks(|coord| Self::overlaps_keep_range(&keep_range, coord))
+    }
+
+    /// Whether `coord` is within the keep buffer of the last update; every
+    /// tile is before the first
+    fn in_keep_range(&self, coord: &TileCoord) -> bool {
+        self.keep_range
+            .as_ref()
+            .is_none_or(|keep_range| Self::overlaps_keep_range(keep_range, coord))
+    }
+
+    /// Whether `coord` overlaps `keep_range`, a tile range and its zoom
+    ///
+    /// Tiles from other zoom levels are kept while their footprint at `zoom`
+    /// overlaps the buffer, so parents and children of the view still load.
+    fn overlaps_keep_range(keep_range: &((Point, Point), u8), coord: &TileCoord) -> bool {
+        let ((min, max), zoom) = *keep_range;
+        let (x0, y0, x1, y1) = if coord.z <= zoom {
+            let scale = 1u64 << (zoom - coord.z).min(32);
+            let (x, y) = (coord.x as u64 * scale, coord.y as u64 * scale);
+            (x, y, x + scale - 1, y + scale - 1)
+        } else {
+            let shift = (coord.z - zoom).min(32);
+            let (x, y) = ((coord.x as u64) >> shift, (coord.y as u64) >> shift);
+            (x, y, x, y)
+        };
+        x1 as f64 >= min.x && x0 as f64 <= max.x && y1 as f64 >= min.y && y0 as f64 <= max.y
+    }
+
+    /// Forget failures and misses outside the keep buffer, like
+    /// [`Self::prune_tiles`] does for tiles, so they load afresh when back
+    /// in view
+    ///
+    /// Failures of tiles still retained as fallbacks are kept, but any retry
+    /// of theirs was cancelled with the other requests outside the buffer.
+    fn prune_failed_tiles(&mut self) {
+        let Some(keep_range) = self.keep_range else {
+            return;
+        };
+        self.missing_tiles
+            .retain(|coord| Self::overlaps_keep_range(&keep_range, coord));
+        let levels = &self.levels;
+        self.failed_tiles.retain(|coord, tile| {
+            if Self::overlaps_keep_range(&keep_range, coord) {
+                return true;
+            }
+            tile.loading = false;
+            levels
+                .get(&coord.z)
+                .is_some_and(|level| level.tiles.contains_key(coord))
+        });
+    }
+
     /// Mark tiles for retention within buffer area
     /// This prevents tiles from being pruned during panning
     fn mark_tiles_for_retention(&mut self, buffer_range: &(Point, Point), zoom: u8) {
@@ -1092,12 +1906,31 @@ impl TileLayer {
             return Ok(());
         }
 
-        // Queue tiles for loading
-        for &coord in coords {
-            if let Err(e) = self
-                .tile_loader
-                .queue_tile(self.tile_source.as_ref(), coord, priority)
-            {
+        // Failed tiles are only requested again by retries, and cached tiles
+        // only once their HTTP caching headers say they are stale
+        let source = self.request_source();
+        for coord in self.native_coords(coords) {
+            if self.failed_tiles.contains_key(&coord) || self.missing_tiles.contains(&coord) {
+                continue;
+            }
+            let queued = match self.tile_cache.get(&coord) {
+                Some(data) => match self.tile_cache.freshness(&coord) {
+                    Some(freshness) if freshness.is_due(REVALIDATE_INTERVAL) => {
+                        self.tile_cache.mark_checked(&coord);
+                        let cached = CachedTile {
+                            data: data.to_vec(),
+                            info: freshness.policy.info,
+                        };
+                        self.tile_loader
+                            .revalidate_tile(source.as_ref(), coord, cached, priority)
+                    }
+                    _ => continue,
+                },
+                None => self
+                    .tile_loader
+                    .queue_tile(source.as_ref(), coord, priority),
+            };
+            if let Err(e) = queued {
                 #[cfg(feature = "debug")]
                 log::warn!("Failed to queue tile {:?}: {}", coord, e);
             }
@@ -1569,3 +2402,49 @@ impl TileLayer {
         }
     }
 }
+
+/// Stale or uncacheable tiles in view are revalidated at most this often
+const REVALIDATE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Levels below `min_native_zoom` still drawn from the tiles at that zoom;
+/// each level further down draws four times as many of them
+const MAX_DESCENDANT_DEPTH: u8 = 2;
+
+/// Part of a source tile drawn for a display tile
+struct NativePiece {
+    coord: TileCoord,
+    /// Part of the source tile image, in texture coordinates
+    uv: (Point, Point),
+    /// Where it is drawn on screen
+    bounds: (Point, Point),
+}
+
+/// Texture coordinates of `coord` within the image of its `ancestor`
+fn region_in_ancestor(coord: TileCoord, ancestor: TileCoord) -> (Point, Point) {
+    let n = 2_f64.powi((coord.z - ancestor.z) as i32);
+    let x = coord.x as f64 - ancestor.x as f64 * n;
+    let y = coord.y as f64 - ancestor.y as f64 * n;
+    (
+        Point::new(x / n, y / n),
+        Point::new((x + 1.0) / n, (y + 1.0) / n),
+    )
+}
+
+/// The `inner` part of the `outer` part of an image, as texture coordinates
+fn sub_region(outer: (Point, Point), inner: (Point, Point)) -> (Point, Point) {
+    let size = Point::new(outer.1.x - outer.0.x, outer.1.y - outer.0.y);
+    let at = |uv: Point| Point::new(outer.0.x + uv.x * size.x, outer.0.y + uv.y * size.y);
+    (at(inner.0), at(inner.1))
+}
+
+/// Round screen bounds to whole physical pixels at `pixels_per_point`
+fn snap_to_pixels(bounds: (Point, Point), pixels_per_point: f64) -> (Point, Point) {
+    if pixels_per_point <= 0.0 {
+        return bounds;
+    }
+    let snap = |value: f64| (value * pixels_per_point).round() / pixels_per_point;
+    (
+        Point::new(snap(bounds.0.x), snap(bounds.0.y)),
+        Point::new(snap(bounds.1.x), snap(bounds.1.y)),
+    )
+}
@@ -1,12 +1,24 @@
 use crossbeam_channel::{unbounded, Receiver, Sender};
 
-use super::source::TileSource;
+use super::decode::DecodedTile;
+use super::disk_cache::{CachedTile, CachedTileInfo, DiskTileCache};
+use super::failover::FailoverTileSource;
+use super::http_cache::{conditional_headers, HttpCachePolicy};
+use super::offline::{OfflineDownload, OfflineDownloadTask, OfflineRegion};
+use super::rate_limit::{parse_retry_after, rate_limiter, DEFAULT_RETRY_AFTER};
+use super::request::{RequestOptions, DEFAULT_USER_AGENT};
+use super::source::{TileDataSource, TileSource};
+use crate::background::tasks::AsyncExecutor;
 use crate::core::geo::TileCoord;
 use crate::core::viewport::Viewport;
-use crate::prelude::{Arc, BinaryHeap, Duration, HashSet, Instant, Mutex, Ordering, VecDeque};
+use crate::prelude::{
+    Arc, BinaryHeap, Duration, HashMap, HashSet, Instant, Mutex, Ordering, VecDeque,
+};
+use crate::runtime::async_utils::Semaphore;
 use crate::traits::GeometryOps;
 use crate::Result;
 use once_cell::sync::Lazy;
+use std::time::SystemTime;
 
 #[cfg(feature = "debug")]
 use log;
@@ -14,7 +26,7 @@ use log;
 /// Shared async HTTP client optimized for tile fetching
 pub(crate) static HTTP_CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
     reqwest::Client::builder()
-        .user_agent("maplet/0.1.0")
+        .user_agent(DEFAULT_USER_AGENT)
         .timeout(std::time::Duration::from_secs(30))
         .connection_verbose(true)
         .tcp_keepalive(std::time::Duration::from_secs(30))
@@ -37,14 +49,98 @@ pub enum TilePriority {
     Visible = 100,
 }
 
+/// Cancellation handle shared between a tile task and its loader
+///
+/// Cancelling a task that is still queued drops it; cancelling a download in
+/// flight aborts the request and no result is delivered for it.
+#[derive(Debug, Clone, Default)]
+pub struct TileCancelHandle {
+    state: Arc<CancelState>,
+}
+
+#[derive(Debug, Default)]
+struct CancelState {
+    cancelled: std::sync::atomic::AtomicBool,
+    waker: futures::task::AtomicWaker,
+}
+
+impl TileCancelHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.state
+            .cancelled
+            .store(true, std::sync::atomic::Ordering::Release);
+        self.state.waker.wake();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.state
+            .cancelled
+            .load(std::sync::atomic::Ordering::Acquire)
+    }
+
+    /// Completes once the task has been cancelled
+    pub(crate) async fn cancelled(&self) {
+        futures::future::poll_fn(|cx| {
+            self.state.waker.register(cx.waker());
+            if self.is_cancelled() {
+                std::task::Poll::Ready(())
+            } else {
+                std::task::Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
 /// A tile loading task with priority
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct TileTask {
     pub coord: TileCoord,
     pub url: String,
     pub priority: TilePriority,
     /// Sequence number for tie-breaking (lower = earlier)
     pub sequence: u64,
+    /// Local byte provider; when set the tile is read from it instead of `url`
+    pub data_source: Option<Arc<dyn TileDataSource>>,
+    /// Identifier of the source, used as the disk cache key
+    pub source_id: Arc<str>,
+    /// Persistent cache consulted before downloading
+    pub disk_cache: Option<Arc<DiskTileCache>>,
+    /// Headers and credentials of the source, applied when the tile is fetched
+    pub request: Option<Arc<RequestOptions>>,
+    /// Aborts the task when its tile is no longer wanted
+    pub cancel: TileCancelHandle,
+    /// Sources tried in turn instead of `url` when the source is a chain
+    pub failover: Option<FailoverTileSource>,
+    /// Decode the tile image on a worker before delivering it
+    pub decode: bool,
+    /// Stale copy the layer is showing, revalidated with a conditional
+    /// request rather than downloaded again
+    pub cached: Option<CachedTile>,
+    /// Results channel of the loader that queued the task, which may share
+    /// its worker with other loaders
+    pub result_tx: Sender<TileResult>,
+}
+
+impl std::fmt::Debug for TileTask {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TileTask")
+            .field("coord", &self.coord)
+            .field("url", &self.url)
+            .field("priority", &self.priority)
+            .field("sequence", &self.sequence)
+            .field("data_source", &self.data_source.is_some())
+            .field("source_id", &self.source_id)
+            .field("disk_cache", &self.disk_cache.is_some())
+            .field("cancelled", &self.cancel.is_cancelled())
+            .field("failover", &self.failover.is_some())
+            .field("cached", &self.cached.is_some())
+            .finish()
+    }
 }
 
 impl PartialEq for TileTask {
@@ -75,7 +171,70 @@ impl Ord for TileTask {
 #[derive(Debug)]
 pub struct TileResult {
     pub coord: TileCoord,
-    pub data: Result<Vec<u8>>,
+    /// Payload of the tile, or `None` when the source has no tile at `coord`
+    pub data: Result<Option<Vec<u8>>>,
+    /// Pixels of `data`, when the loader decodes images and `data` is one
+    pub image: Option<Arc<DecodedTile>>,
+    /// Caching headers the tile was served with, when it was downloaded
+    pub policy: Option<HttpCachePolicy>,
+}
+
+impl TileResult {
+    /// Result for `data`, decoding it on a blocking worker when `decode` is set
+    ///
+    /// Data that is not an image, or fails to decode, is delivered as it is
+    /// and left to the renderer.
+    async fn decoded(
+        coord: TileCoord,
+        data: Result<Option<Vec<u8>>>,
+        policy: Option<HttpCachePolicy>,
+        decode: bool,
+    ) -> Self {
+        let (data, image) = match data {
+            Ok(Some(data)) if decode => {
+                let decoded = AsyncExecutor::execute_blocking(move || {
+                    let image = DecodedTile::decode(&data);
+                    Ok((data, image))
+                })
+                .await;
+                match decoded {
+                    Ok((data, Ok(image))) => (Ok(Some(data)), image.map(Arc::new)),
+                    Ok((data, Err(_e))) => {
+                        #[cfg(feature = "debug")]
+                        log::warn!("Tile {:?}: {}", coord, _e);
+                        (Ok(Some(data)), None)
+                    }
+                    Err(e) => (Err(e), None),
+                }
+            }
+            data => (data, None),
+        };
+        Self {
+            coord,
+            data,
+            image,
+            policy,
+        }
+    }
+}
+
+/// What loading a tile produced for the worker to deliver
+enum Loaded {
+    /// Payload of the tile, or `None` when the source has no tile there,
+    /// and its caching headers when it was downloaded
+    Tile {
+        data: Option<Vec<u8>>,
+        policy: Option<HttpCachePolicy>,
+    },
+    /// The copy the layer asked to revalidate is still current, for the
+    /// time given by the new caching headers
+    Unchanged {
+        data: Vec<u8>,
+        policy: HttpCachePolicy,
+    },
+    /// Nothing to deliver: the load was cancelled, or a tile already on
+    /// screen was revalidated without changes
+    Nothing,
 }
 
 /// Configuration for the tile loader - MUCH more aggressive defaults
@@ -353,6 +512,10 @@ pub struct NetworkMetrics {
     condition: NetworkCondition,
     /// Failed requests in recent window
     recent_failures: VecDeque<Instant>,
+    /// Requests cancelled because their tiles left the keep buffer
+    cancelled_requests: u64,
+    /// End of the latest back-off a server asked for with `Retry-After`
+    rate_limited_until: Option<Instant>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -369,6 +532,8 @@ impl Default for NetworkMetrics {
             average_download_time: Duration::from_millis(500),
             condition: NetworkCondition::Good,
             recent_failures: VecDeque::with_capacity(50),
+            cancelled_requests: 0,
+            rate_limited_until: None,
         }
     }
 }
@@ -411,6 +576,30 @@ impl NetworkMetrics {
         self.update_condition();
     }
 
+    /// Record requests cancelled before they completed
+    ///
+    /// Cancellations are not failures and leave the network condition as is.
+    pub fn record_cancellations(&mut self, count: usize) {
+        self.cancelled_requests += count as u64;
+    }
+
+    /// Total number of cancelled requests
+    pub fn cancelled_requests(&self) -> u64 {
+        self.cancelled_requests
+    }
+
+    /// Record a server asking for no requests during `retry_after`
+    pub fn record_rate_limited(&mut self, retry_after: Duration) {
+        let until = Instant::now() + retry_after;
+        self.rate_limited_until = Some(self.rate_limited_until.map_or(until, |u| u.max(until)));
+    }
+
+    /// Whether a server's `Retry-After` back-off is still running
+    pub fn is_rate_limited(&self) -> bool {
+        self.rate_limited_until
+            .is_some_and(|until| until > Instant::now())
+    }
+
     fn update_average(&mut self) {
         if self.recent_download_times.is_empty() {
             return;
@@ -439,7 +628,13 @@ impl NetworkMetrics {
     }
 
     /// Get recommended concurrency limit based on network condition
+    ///
+    /// While a server is rate limiting requests the limit drops to a quarter,
+    /// so prefetching and offline downloads back off with it.
     pub fn get_concurrency_limit(&self, base_limit: usize) -> usize {
+        if self.is_rate_limited() {
+            return (base_limit / 4).max(1);
+        }
         match self.condition {
             NetworkCondition::Good => base_limit,
             NetworkCondition::Fair => base_limit * 2 / 3,
@@ -482,7 +677,9 @@ impl Default for AdaptiveConfig {
 pub struct TileLoader {
     /// Channel for sending tile tasks
     task_tx: Sender<TileTask>,
-    /// Channel for receiving tile results
+    /// Channel for receiving tile results, and the sending end given to
+    /// each task
+    result_tx: Sender<TileResult>,
     result_rx: Receiver<TileResult>,
     /// Configuration
     config: TileLoaderConfig,
@@ -496,12 +693,19 @@ pub struct TileLoader {
     last_viewport: Arc<Mutex<Option<Viewport>>>,
     /// Currently prefetched tiles
     prefetch_tiles: Arc<Mutex<HashSet<TileCoord>>>,
-    /// Currently pending/downloading tiles to prevent duplicates
-    pending_tiles: Arc<Mutex<HashSet<TileCoord>>>,
+    /// Currently pending/downloading tiles to prevent duplicates, with the
+    /// handles that cancel them
+    pending_tiles: Arc<Mutex<HashMap<TileCoord, TileCancelHandle>>>,
     /// Network performance metrics
     network_metrics: Arc<Mutex<NetworkMetrics>>,
     /// Background task manager for aggressive prefetching
     bg_task_manager: Option<Arc<crate::background::BackgroundTaskManager>>,
+    /// Persistent tile cache checked before downloading
+    disk_cache: Option<Arc<DiskTileCache>>,
+    /// Download slots shared by the worker and offline downloads
+    download_permits: Semaphore,
+    /// Decode tile images before delivering them
+    decode_images: bool,
 }
 
 impl TileLoader {
@@ -509,17 +713,23 @@ impl TileLoader {
     pub fn new(config: TileLoaderConfig) -> Self {
         let (task_tx, task_rx) = unbounded();
         let (result_tx, result_rx) = unbounded();
+        let download_permits = Semaphore::new(config.max_concurrent);
+        let network_metrics = Arc::new(Mutex::new(NetworkMetrics::default()));
 
         // Start the background worker
         let worker_config = config.clone();
+        let worker_permits = download_permits.clone();
+        let worker_metrics = network_metrics.clone();
         crate::runtime::spawn(async move {
-            TileWorker::new(task_rx, result_tx, worker_config)
+            TileWorker::new(task_rx, worker_config, worker_permits)
+                .with_network_metrics(worker_metrics)
                 .run()
                 .await;
         });
 
         Self {
             task_tx,
+            result_tx,
             result_rx,
             config,
             sequence_counter: std::sync::atomic::AtomicU64::new(0),
@@ -527,9 +737,12 @@ impl TileLoader {
             movement_pattern: Arc::new(Mutex::new(MovementPattern::default())),
             last_viewport: Arc::new(Mutex::new(None)),
             prefetch_tiles: Arc::new(Mutex::new(HashSet::default())),
-            pending_tiles: Arc::new(Mutex::new(HashSet::default())),
-            network_metrics: Arc::new(Mutex::new(NetworkMetrics::default())),
+            pending_tiles: Arc::new(Mutex::new(HashMap::default())),
+            network_metrics,
             bg_task_manager: None,
+            disk_cache: None,
+            download_permits,
+            decode_images: false,
         }
     }
 
@@ -538,47 +751,120 @@ impl TileLoader {
         Self::new(TileLoaderConfig::default())
     }
 
+    /// Loader for another layer that queues its tiles on this loader's
+    /// worker, within the same download slots, disk cache and metrics
+    ///
+    /// Its results and pending tiles are its own, so layers drawing the
+    /// same tiles from different sources, such as the time steps of an
+    /// animation, don't take each other's tiles.
+    pub fn share(&self) -> Self {
+        let (result_tx, result_rx) = unbounded();
+        Self {
+            task_tx: self.task_tx.clone(),
+            result_tx,
+            result_rx,
+            config: self.config.clone(),
+            sequence_counter: std::sync::atomic::AtomicU64::new(0),
+            adaptive_config: self.adaptive_config.clone(),
+            movement_pattern: Arc::new(Mutex::new(MovementPattern::default())),
+            last_viewport: Arc::new(Mutex::new(None)),
+            prefetch_tiles: Arc::new(Mutex::new(HashSet::default())),
+            pending_tiles: Arc::new(Mutex::new(HashMap::default())),
+            network_metrics: self.network_metrics.clone(),
+            bg_task_manager: self.bg_task_manager.clone(),
+            disk_cache: self.disk_cache.clone(),
+            download_permits: self.download_permits.clone(),
+            decode_images: self.decode_images,
+        }
+    }
+
     /// Queue multiple tiles for loading with batch processing and deduplication
     pub fn queue_tiles_batch(
         &self,
         source: &dyn TileSource,
         coords: Vec<TileCoord>,
         priority: TilePriority,
+    ) -> Result<()> {
+        let coords = coords.into_iter().map(|coord| (coord, None)).collect();
+        self.queue_tasks(source, coords, priority)
+    }
+
+    /// Queue a stale tile the layer is showing, to be revalidated with a
+    /// conditional request against the validators of `cached`
+    ///
+    /// An unchanged tile is delivered again with the caching headers of the
+    /// answer, so the layer knows how long it stays fresh.
+    pub fn revalidate_tile(
+        &self,
+        source: &dyn TileSource,
+        coord: TileCoord,
+        cached: CachedTile,
+        priority: TilePriority,
+    ) -> Result<()> {
+        self.queue_tasks(source, vec![(coord, Some(cached))], priority)
+    }
+
+    fn queue_tasks(
+        &self,
+        source: &dyn TileSource,
+        coords: Vec<(TileCoord, Option<CachedTile>)>,
+        priority: TilePriority,
     ) -> Result<()> {
         let sequence = self
             .sequence_counter
             .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
 
+        // Drop tiles the source cannot serve (outside its zoom range or bounds)
+        let coords: Vec<(TileCoord, Option<CachedTile>)> = coords
+            .into_iter()
+            .filter(|(coord, _)| source.has_tile(*coord))
+            .collect();
+
         // Filter out tiles that are already pending to prevent duplicates
-        let filtered_coords: Vec<TileCoord> = if let Ok(mut pending) = self.pending_tiles.try_lock()
-        {
-            coords
-                .into_iter()
-                .filter(|coord| {
-                    if pending.contains(coord) {
-                        #[cfg(feature = "debug")]
-                        log::debug!("Skipping duplicate tile request: {:?}", coord);
-                        false
-                    } else {
-                        pending.insert(*coord);
-                        true
-                    }
-                })
-                .collect()
-        } else {
-            // If we can't lock, just proceed with all coords (fallback)
-            coords
-        };
+        let filtered_coords: Vec<(TileCoord, Option<CachedTile>, TileCancelHandle)> =
+            if let Ok(mut pending) = self.pending_tiles.try_lock() {
+                coords
+                    .into_iter()
+                    .filter_map(|(coord, cached)| match pending.entry(coord) {
+                        std::collections::hash_map::Entry::Occupied(_) => {
+                            #[cfg(feature = "debug")]
+                            log::debug!("Skipping duplicate tile request: {:?}", coord);
+                            None
+                        }
+                        std::collections::hash_map::Entry::Vacant(entry) => {
+                            let cancel = entry.insert(TileCancelHandle::new()).clone();
+                            Some((coord, cached, cancel))
+                        }
+                    })
+                    .collect()
+            } else {
+                // If we can't lock, just proceed with all coords (fallback)
+                coords
+                    .into_iter()
+                    .map(|(coord, cached)| (coord, cached, TileCancelHandle::new()))
+                    .collect()
+            };
 
         if filtered_coords.is_empty() {
             return Ok(());
         }
 
+        let data_source = source.data_source();
+        let source_id: Arc<str> = source.source_id().into();
+        let request = source.request_options().cloned().map(Arc::new);
+        let failover = source.failover();
+        // Local sources are already on disk, so only downloads go through the disk cache
+        let disk_cache = if data_source.is_none() {
+            self.disk_cache.clone()
+        } else {
+            None
+        };
+
         // Create tasks for filtered tiles
         let tasks: Result<Vec<_>> = filtered_coords
             .into_iter()
             .enumerate()
-            .map(|(i, coord)| {
+            .map(|(i, (coord, cached, cancel))| {
                 let url = source.url(coord);
                 Ok(TileTask {
                     coord,
@@ -590,6 +876,15 @@ impl TileLoader {
                     } else {
                         sequence + i as u64
                     },
+                    data_source: data_source.clone(),
+                    source_id: source_id.clone(),
+                    disk_cache: disk_cache.clone(),
+                    request: request.clone(),
+                    cancel,
+                    failover: failover.clone(),
+                    decode: self.decode_images,
+                    cached,
+                    result_tx: self.result_tx.clone(),
                 })
             })
             .collect();
@@ -671,6 +966,91 @@ impl TileLoader {
         self
     }
 
+    /// Keep downloaded tiles in a persistent disk cache and serve them from it
+    pub fn with_disk_cache(mut self, disk_cache: Arc<DiskTileCache>) -> Self {
+        self.disk_cache = Some(disk_cache);
+        self
+    }
+
+    /// Set or remove the persistent disk cache
+    pub fn set_disk_cache(&mut self, disk_cache: Option<Arc<DiskTileCache>>) {
+        self.disk_cache = disk_cache;
+    }
+
+    /// The persistent disk cache, if one is configured
+    pub fn disk_cache(&self) -> Option<&Arc<DiskTileCache>> {
+        self.disk_cache.as_ref()
+    }
+
+    /// Decode raster tile images on a worker, delivering their pixels in
+    /// [`TileResult::image`] so the UI thread only uploads them
+    pub fn with_image_decoding(mut self, decode: bool) -> Self {
+        self.decode_images = decode;
+        self
+    }
+
+    pub fn decodes_images(&self) -> bool {
+        self.decode_images
+    }
+
+    /// Download every tile of `region` from `source` into the disk cache so it
+    /// can be viewed offline
+    ///
+    /// The download runs on the background task manager at
+    /// [`TaskPriority::Background`](crate::background::TaskPriority::Background)
+    /// and uses at most half of this loader's download slots. Tiles already on
+    /// disk are skipped, so downloading a region again only fetches what is
+    /// missing. The region's tiles are pinned in the disk cache until
+    /// [`TileLoader::release_region`]. Fails when no disk cache or task manager
+    /// is configured, or when the region's estimated size exceeds the room
+    /// left by other pinned regions.
+    pub fn download_region(
+        &self,
+        source: Arc<dyn TileSource>,
+        region: OfflineRegion,
+    ) -> Result<OfflineDownload> {
+        let disk_cache = self
+            .disk_cache
+            .clone()
+            .ok_or("Offline downloads need a disk cache")?;
+        let bg_task_manager = self
+            .bg_task_manager
+            .as_ref()
+            .ok_or("Offline downloads need a background task manager")?;
+
+        // A failed start leaves an earlier download of the region pinned
+        let pin = region.pin_name(&source.source_id());
+        let pinned_before = disk_cache.has_pin(&pin);
+        let task = OfflineDownloadTask::new(
+            source,
+            region,
+            disk_cache.clone(),
+            self.download_permits.clone(),
+            self.network_metrics.clone(),
+            self.config.max_concurrent,
+        )?;
+        let download = task.handle();
+        if let Err(e) = bg_task_manager.submit_task(Arc::new(task)) {
+            if !pinned_before {
+                disk_cache.unpin(&pin);
+            }
+            return Err(e);
+        }
+        Ok(download)
+    }
+
+    /// Release the tiles of a region downloaded from `source` with
+    /// [`TileLoader::download_region`], leaving them to the disk cache's
+    /// usual eviction
+    pub fn release_region(&self, source: &dyn TileSource, region: &OfflineRegion) -> Result<()> {
+        let disk_cache = self
+            .disk_cache
+            .as_ref()
+            .ok_or("Offline downloads need a disk cache")?;
+        disk_cache.unpin(&region.pin_name(&source.source_id()));
+        Ok(())
+    }
+
     /// Create a tile loader with high-performance preset and background task manager
     pub fn with_high_performance_preset(
         bg_task_manager: Arc<crate::background::BackgroundTaskManager>,
@@ -931,11 +1311,47 @@ impl TileLoader {
         }
     }
 
-    /// Clear all pending tiles (useful for cleanup or reset)
+    /// Cancel all queued and in-flight tiles (useful for cleanup or reset)
     pub fn clear_pending(&self) {
-        if let Ok(mut pending) = self.pending_tiles.try_lock() {
-            pending.clear();
+        self.retain_tasks(|_| false);
+    }
+
+    /// Cancel queued and in-flight tiles for which `keep` returns false,
+    /// returning how many were cancelled
+    ///
+    /// Cancelled downloads are aborted and deliver no result; the count is
+    /// added to the loader's [`NetworkMetrics`].
+    pub fn retain_tasks(&self, mut keep: impl FnMut(&TileCoord) -> bool) -> usize {
+        let Ok(mut pending) = self.pending_tiles.lock() else {
+            return 0;
+        };
+        let mut cancelled = 0;
+        pending.retain(|coord, cancel| {
+            if keep(coord) {
+                return true;
+            }
+            cancel.cancel();
+            cancelled += 1;
+            false
+        });
+        drop(pending);
+
+        if cancelled > 0 {
+            #[cfg(feature = "debug")]
+            log::debug!("Cancelled {} tile requests", cancelled);
+            if let Ok(mut metrics) = self.network_metrics.lock() {
+                metrics.record_cancellations(cancelled);
+            }
         }
+        cancelled
+    }
+
+    /// Snapshot of the loader's network metrics
+    pub fn network_metrics(&self) -> NetworkMetrics {
+        self.network_metrics
+            .lock()
+            .map(|metrics| metrics.clone())
+            .unwrap_or_default()
     }
 
     /// Update the tile loader configuration (creates new loader with updated config)
@@ -953,7 +1369,20 @@ impl TileLoader {
     }
     
     /// Submit background super prefetch task using the existing background task system
-    pub fn submit_background_super_prefetch(&self, coords: Vec<TileCoord>, viewport: Viewport) {
+    ///
+    /// Nothing is submitted while a tile server is rate limiting requests,
+    /// and at most `max_prefetch_tiles` of `coords` are considered. Tiles
+    /// queued from the recommendation still go through the per-host
+    /// [`RateLimiter`](super::RateLimiter).
+    pub fn submit_background_super_prefetch(&self, mut coords: Vec<TileCoord>, viewport: Viewport) {
+        if self.network_metrics().is_rate_limited() {
+            #[cfg(feature = "debug")]
+            log::debug!("Skipping super prefetch while rate limited");
+            return;
+        }
+        if let Some(adaptive_config) = &self.adaptive_config {
+            coords.truncate(adaptive_config.max_prefetch_tiles);
+        }
         if let Some(ref bg_task_manager) = self.bg_task_manager {
             let task = TilePrefetchTask::new(
                 format!("super_prefetch_{}_{}", viewport.zoom, viewport.center.lat),
@@ -1024,11 +1453,16 @@ impl crate::background::BackgroundTask for TilePrefetchTask {
                     let mut prefetch_results = Vec::new();
 
                     // Get network condition to adjust strategy
-                    let network_condition = if let Ok(metrics) = network_metrics.lock() {
-                        metrics.condition.clone()
-                    } else {
-                        NetworkCondition::Good
-                    };
+                    let (network_condition, rate_limited) =
+                        if let Ok(metrics) = network_metrics.lock() {
+                            (metrics.condition.clone(), metrics.is_rate_limited())
+                        } else {
+                            (NetworkCondition::Good, false)
+                        };
+                    // Recommend nothing while a server asks us to back off
+                    if rate_limited {
+                        return Ok(prefetch_results);
+                    }
 
                     // Adjust prefetch strategy based on network condition
                     let coords_len = coords.len();
@@ -1102,28 +1536,49 @@ impl crate::background::BackgroundTask for TilePrefetchTask {
 /// Background worker that processes tile loading tasks
 struct TileWorker {
     task_rx: Receiver<TileTask>,
-    result_tx: Sender<TileResult>,
     config: TileLoaderConfig,
     /// Unified semaphore to limit concurrent downloads
-    semaphore: crate::runtime::async_utils::Semaphore,
+    semaphore: Semaphore,
     /// Priority queue of pending tasks
     task_queue: BinaryHeap<TileTask>,
+    /// Downloads held back by the rate limiter, with when to try them again
+    deferred: Vec<(Instant, TileTask)>,
+    network_metrics: Arc<Mutex<NetworkMetrics>>,
 }
 
 impl TileWorker {
-    fn new(
-        task_rx: Receiver<TileTask>,
-        result_tx: Sender<TileResult>,
-        config: TileLoaderConfig,
-    ) -> Self {
-        let semaphore = crate::runtime::async_utils::Semaphore::new(config.max_concurrent);
-
+    fn new(task_rx: Receiver<TileTask>, config: TileLoaderConfig, semaphore: Semaphore) -> Self {
         Self {
             task_rx,
-            result_tx,
             config,
             semaphore,
             task_queue: BinaryHeap::new(),
+            deferred: Vec::new(),
+            network_metrics: Arc::new(Mutex::new(NetworkMetrics::default())),
+        }
+    }
+
+    /// Report rate limiting to the loader's metrics
+    fn with_network_metrics(mut self, network_metrics: Arc<Mutex<NetworkMetrics>>) -> Self {
+        self.network_metrics = network_metrics;
+        self
+    }
+
+    /// Time to hold `task` back so its host stays within its rate limit,
+    /// taking a request slot when none is needed
+    fn rate_limit_delay(task: &TileTask) -> Option<Duration> {
+        if task.data_source.is_some() && task.failover.is_none() {
+            return None;
+        }
+        let limiter = rate_limiter();
+        if limiter.try_acquire(&task.url) {
+            None
+        } else {
+            Some(
+                limiter
+                    .delay(&task.url)
+                    .unwrap_or(Duration::from_millis(10)),
+            )
         }
     }
 
@@ -1139,28 +1594,83 @@ impl TileWorker {
                 self.task_queue.push(task);
             }
 
+            // Drop tasks cancelled while they were queued
+            self.task_queue.retain(|task| !task.cancel.is_cancelled());
+            self.deferred
+                .retain(|(_, task)| !task.cancel.is_cancelled());
+
+            // Requeue rate limited tasks whose wait is over
+            let now = Instant::now();
+            let (ready, waiting) = std::mem::take(&mut self.deferred)
+                .into_iter()
+                .partition(|(retry_at, _)| *retry_at <= now);
+            self.deferred = waiting;
+            self.task_queue
+                .extend(ready.into_iter().map(|(_, task): (Instant, TileTask)| task));
+
             // Process the highest priority task if we have capacity
             if let Some(task) = self.task_queue.pop() {
                 // Check semaphore availability using unified semaphore
                 let can_start = self.semaphore.try_acquire();
-
-                if can_start {
-                    let result_tx = self.result_tx.clone();
-                    let config = self.config.clone();
+                let rate_limited = can_start.then(|| Self::rate_limit_delay(&task)).flatten();
+
+                if let Some(delay) = rate_limited {
+                    self.semaphore.release();
+                    self.deferred.push((now + delay, task));
+                } else if can_start {
+                    let network_metrics = self.network_metrics.clone();
+                    let result_tx = task.result_tx.clone();
                     let semaphore = self.semaphore.clone();
 
                     #[cfg(feature = "debug")]
                     log::debug!("Starting download for tile {:?}", task.coord);
 
                     crate::runtime::spawn(async move {
-                        let result = Self::download_tile(task.clone(), config).await;
-                        let tile_result = TileResult {
-                            coord: task.coord,
-                            data: result,
+                        let load = async {
+                            match task.failover.clone() {
+                                Some(chain) => {
+                                    Self::load_with_failover(task.clone(), chain, &result_tx).await
+                                }
+                                None => Self::load_tile(task.clone(), &result_tx).await,
+                            }
+                        };
+                        let cancelled = task.cancel.cancelled();
+                        futures::pin_mut!(load, cancelled);
+                        // Dropping the load future aborts its request
+                        let result = match futures::future::select(load, cancelled).await {
+                            futures::future::Either::Left((result, _)) => result,
+                            futures::future::Either::Right(_) => {
+                                #[cfg(feature = "debug")]
+                                log::debug!("Cancelled download for tile {:?}", task.coord);
+                                Ok(Loaded::Nothing)
+                            }
                         };
 
-                        // Send result back
-                        let _ = result_tx.send(tile_result);
+                        if result.is_err() {
+                            if let Some(retry_after) = rate_limiter().retry_after(&task.url) {
+                                if let Ok(mut metrics) = network_metrics.lock() {
+                                    metrics.record_rate_limited(retry_after);
+                                }
+                            }
+                        }
+
+                        // Send result back; an unchanged tile keeps the pixels
+                        // the layer already has
+                        let delivered = match result {
+                            Ok(Loaded::Tile { data, policy }) => {
+                                Some((Ok(data), policy, task.decode))
+                            }
+                            Ok(Loaded::Unchanged { data, policy }) => {
+                                Some((Ok(Some(data)), Some(policy), false))
+                            }
+                            Ok(Loaded::Nothing) => None,
+                            Err(e) => Some((Err(e), None, task.decode)),
+                        };
+                        if let Some((data, policy, decode)) = delivered {
+                            let result =
+                                TileResult::decoded(task.coord, data, policy, decode).await;
+                            let _ = result_tx.send(result);
+                        }
 
                         // Release semaphore permit using unified semaphore
                         semaphore.release();
@@ -1187,7 +1697,7 @@ impl TileWorker {
                     }
                     Err(crossbeam_channel::RecvTimeoutError::Disconnected) => {
                         // Channel closed, finish processing remaining tasks
-                        if self.task_queue.is_empty() {
+                        if self.task_queue.is_empty() && self.deferred.is_empty() {
                             log::debug!("TileWorker exiting - channel disconnected");
                             break;
                         }
@@ -1197,23 +1707,265 @@ impl TileWorker {
         }
     }
 
-    async fn download_tile(task: TileTask, _config: TileLoaderConfig) -> Result<Vec<u8>> {
-        // Set up timeout for the request - use a reasonable timeout for network requests
-        let request_timeout = std::time::Duration::from_secs(10); // 10 seconds is reasonable for tile downloads
-        let client = &*HTTP_CLIENT;
-        let response = client
-            .get(&task.url)
-            .timeout(request_timeout)
-            .send()
-            .await
-            .map_err(|e| e.to_string())?;
+    async fn load_tile(task: TileTask, result_tx: &Sender<TileResult>) -> Result<Loaded> {
+        match task.data_source.clone() {
+            Some(data_source) => Ok(Loaded::Tile {
+                data: data_source.load_tile(task.coord).await?,
+                policy: None,
+            }),
+            None => Self::fetch_tile(task, result_tx).await,
+        }
+    }
+
+    /// Load a tile from the first source of a chain that delivers it, moving
+    /// on to the next source after an error or a timeout
+    async fn load_with_failover(
+        task: TileTask,
+        chain: FailoverTileSource,
+        result_tx: &Sender<TileResult>,
+    ) -> Result<Loaded> {
+        let mut last_error = None;
+        for index in chain.attempt_order() {
+            let source = &chain.sources()[index];
+            if !source.has_tile(task.coord) {
+                continue;
+            }
+            let attempt = TileTask {
+                url: chain.source_url(index, task.coord),
+                data_source: source.data_source(),
+                request: source.request_options().cloned().map(Arc::new),
+                ..task.clone()
+            };
+
+            // The worker already took a request slot for the task's own URL
+            if attempt.data_source.is_none() && attempt.url != task.url {
+                rate_limiter().acquire(&attempt.url).await;
+            }
+
+            let started = Instant::now();
+            let load = Self::load_tile(attempt, result_tx);
+            let timeout = crate::runtime::async_utils::async_delay(chain.attempt_timeout());
+            futures::pin_mut!(load, timeout);
+            let result = match futures::future::select(load, timeout).await {
+                futures::future::Either::Left((result, _)) => result,
+                futures::future::Either::Right(_) => Err(format!(
+                    "Timed out after {:?} loading tile {:?}",
+                    chain.attempt_timeout(),
+                    task.coord
+                )
+                .into()),
+            };
+
+            match result {
+                Ok(data) => {
+                    chain.record_success(index, started.elapsed());
+                    return Ok(data);
+                }
+                Err(e) => {
+                    #[cfg(feature = "debug")]
+                    log::debug!("Source {} failed for tile {:?}: {}", index, task.coord, e);
+                    chain.record_failure(index, &e.to_string());
+                    last_error = Some(e);
+                }
+            }
+        }
+        Err(last_error.unwrap_or_else(|| format!("No source serves tile {:?}", task.coord).into()))
+    }
+
+    /// Serve a tile from the disk cache when possible, otherwise download it
+    /// and store the result for next time
+    ///
+    /// A cached tile past its HTTP expiry is sent to the layer straight away
+    /// and then revalidated with a conditional request, so it stays on screen
+    /// meanwhile. The stale copy a task carries from the layer's memory is
+    /// revalidated the same way when there is no disk cache. Returns
+    /// `Loaded::Nothing` when a disk copy was confirmed, and
+    /// `Loaded::Unchanged` when the layer's own copy was.
+    async fn fetch_tile(task: TileTask, result_tx: &Sender<TileResult>) -> Result<Loaded> {
+        let coord = task.coord;
+        let stale = match task.disk_cache.clone() {
+            Some(disk_cache) => {
+                let source_id = task.source_id.clone();
+                let cached = AsyncExecutor::execute_blocking(move || {
+                    Ok(disk_cache.get_entry(&source_id, coord))
+                })
+                .await?;
+                match cached {
+                    Some(entry) if !entry.info.is_stale(SystemTime::now()) => {
+                        let policy = HttpCachePolicy {
+                            no_store: false,
+                            info: entry.info,
+                        };
+                        return Ok(Loaded::Tile {
+                            data: Some(entry.data),
+                            policy: Some(policy),
+                        });
+                    }
+                    // The layer is already showing its own copy
+                    Some(entry) if task.cached.is_some() => Some(entry),
+                    Some(entry) => {
+                        let policy = HttpCachePolicy {
+                            no_store: false,
+                            info: entry.info.clone(),
+                        };
+                        let data = Ok(Some(entry.data.clone()));
+                        let stale = TileResult::decoded(coord, data, Some(policy), task.decode);
+                        let _ = result_tx.send(stale.await);
+                        Some(entry)
+                    }
+                    None => None,
+                }
+            }
+            None => task.cached.clone(),
+        };
+
+        let validators = stale.as_ref().map(|e| &e.info);
+        let response =
+            match download_tile(&task.url, coord, validators, task.request.as_deref()).await {
+                Ok(response) => response,
+                Err(_e) if stale.is_some() => {
+                    // Keep showing the stale tile rather than an error
+                    #[cfg(feature = "debug")]
+                    log::debug!("Revalidating tile {:?} failed: {}", coord, _e);
+                    return Ok(Loaded::Nothing);
+                }
+                Err(e) => return Err(e),
+            };
+
+        let changed = response.data.is_some();
+        let (data, policy) = match (response.data, stale) {
+            (Some(data), _) => (data, response.policy),
+            (None, Some(stale)) => (stale.data, response.policy.merge_not_modified(&stale.info)),
+            (None, None) => {
+                return Err(format!("Unexpected 304 for uncached tile {:?}", coord).into());
+            }
+        };
+        let loaded = if changed {
+            Loaded::Tile {
+                data: Some(data.clone()),
+                policy: Some(policy.clone()),
+            }
+        } else if task.cached.is_some() {
+            Loaded::Unchanged {
+                data: data.clone(),
+                policy: policy.clone(),
+            }
+        } else {
+            Loaded::Nothing
+        };
+
+        if let Some(disk_cache) = task.disk_cache {
+            let source_id = task.source_id;
+            crate::runtime::spawn(async move {
+                let result = AsyncExecutor::execute_blocking(move || {
+                    // Tiles of offline regions stay whatever the server says
+                    if policy.no_store && !disk_cache.is_pinned(&source_id, coord) {
+                        disk_cache.remove(&source_id, coord);
+                        Ok(())
+                    } else {
+                        disk_cache.put_entry(&source_id, coord, &data, &policy.info)
+                    }
+                })
+                .await;
+                if let Err(_e) = result {
+                    #[cfg(feature = "debug")]
+                    log::warn!("Failed to write tile {:?} to disk cache: {}", coord, _e);
+                }
+            });
+        }
+
+        Ok(loaded)
+    }
+}
 
-        if !response.status().is_success() {
-            return Err(format!("HTTP {} for tile {:?}", response.status(), task.coord).into());
+/// Download a tile, revalidating against `validators` when given
+///
+/// A `401 Unauthorized` answer to a request carrying a refreshable token
+/// refreshes the token and retries once.
+pub(super) async fn download_tile(
+    url: &str,
+    coord: TileCoord,
+    validators: Option<&CachedTileInfo>,
+    options: Option<&RequestOptions>,
+) -> Result<TileDownload> {
+    // Set up timeout for the request - use a reasonable timeout for network requests
+    let request_timeout = std::time::Duration::from_secs(10); // 10 seconds is reasonable for tile downloads
+    let client = &*HTTP_CLIENT;
+    let refreshable = options
+        .and_then(|options| options.token())
+        .filter(|token| token.is_refreshable());
+
+    let mut retried = false;
+    let response = loop {
+        let mut request = match options {
+            Some(options) => options.get(client, url).await?,
+            None => client.get(url),
+        }
+        .timeout(request_timeout);
+        if let Some(validators) = validators {
+            request = request.headers(conditional_headers(validators));
         }
+        let response = request.send().await.map_err(|e| e.to_string())?;
+
+        let rejected = response.status() == reqwest::StatusCode::UNAUTHORIZED;
+        match refreshable {
+            // Revoked or expired early: fetch a new token and try again
+            Some(token) if rejected && !retried => {
+                token.invalidate();
+                retried = true;
+            }
+            _ => break response,
+        }
+    };
 
-        let data = response.bytes().await.map_err(|e| e.to_string())?.to_vec();
+    let policy = HttpCachePolicy::from_headers(response.headers(), SystemTime::now());
+    if response.status() == reqwest::StatusCode::NOT_MODIFIED && validators.is_some() {
+        return Ok(TileDownload {
+            data: None,
+            policy,
+        });
+    }
 
-        Ok(data)
+    let status = response.status();
+    if status == reqwest::StatusCode::TOO_MANY_REQUESTS
+        || status == reqwest::StatusCode::SERVICE_UNAVAILABLE
+    {
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| parse_retry_after(value, SystemTime::now()));
+        // A 503 without Retry-After is an outage rather than throttling
+        let wait = match (retry_after, status) {
+            (Some(wait), _) => Some(wait),
+            (None, reqwest::StatusCode::TOO_MANY_REQUESTS) => Some(DEFAULT_RETRY_AFTER),
+            (None, _) => None,
+        };
+        if let Some(wait) = wait {
+            rate_limiter().record_retry_after(url, wait);
+            return Err(format!(
+                "HTTP {} for tile {:?}, retrying after {:?}",
+                status, coord, wait
+            )
+            .into());
+        }
     }
+
+    if !status.is_success() {
+        return Err(format!("HTTP {} for tile {:?}", status, coord).into());
+    }
+
+    let data = response.bytes().await.map_err(|e| e.to_string())?.to_vec();
+
+    Ok(TileDownload {
+        data: Some(data),
+        policy,
+    })
+}
+
+/// Outcome of a tile request
+pub(super) struct TileDownload {
+    /// Body of the response; `None` for `304 Not Modified`
+    pub(super) data: Option<Vec<u8>>,
+    pub(super) policy: HttpCachePolicy,
 }
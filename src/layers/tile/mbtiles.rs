@@ -0,0 +1,316 @@
+//! MBTiles archive support
+//!
+//! Reads raster tiles straight out of a local [MBTiles](https://github.com/mapbox/mbtiles-spec)
+//! SQLite archive so maps can be shown without network access. The archive's
+//! `metadata` table is parsed into [`MBTilesMetadata`], which can be applied to
+//! [`TileLayerOptions`] to pick up the archive's zoom range, bounds and attribution.
+
+use super::source::{covers_tile, TileDataFuture, TileDataSource, TileSource};
+use super::types::TileLayerOptions;
+use crate::background::tasks::AsyncExecutor;
+use crate::core::geo::{LatLng, LatLngBounds, TileCoord};
+use crate::prelude::{Arc, HashMap, Mutex};
+use crate::Result;
+use rusqlite::{Connection, OpenFlags, OptionalExtension};
+use std::path::{Path, PathBuf};
+
+/// Contents of an MBTiles `metadata` table
+#[derive(Debug, Clone, Default)]
+pub struct MBTilesMetadata {
+    pub name: Option<String>,
+    /// Tile encoding, e.g. `png`, `jpg`, `webp` or `pbf`
+    pub format: Option<String>,
+    pub bounds: Option<LatLngBounds>,
+    /// Suggested initial view as centre and zoom
+    pub center: Option<(LatLng, u8)>,
+    pub min_zoom: Option<u8>,
+    pub max_zoom: Option<u8>,
+    pub attribution: Option<String>,
+    pub description: Option<String>,
+    /// Every metadata row, including the ones parsed above
+    pub raw: HashMap<String, String>,
+}
+
+impl MBTilesMetadata {
+    /// Build metadata from raw `name`/`value` rows
+    pub fn from_rows(rows: impl IntoIterator<Item = (String, String)>) -> Self {
+        let raw: HashMap<String, String> = rows.into_iter().collect();
+        let text = |key: &str| {
+            raw.get(key)
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+        let zoom = |key: &str| {
+            raw.get(key)
+                .and_then(|value| value.trim().parse::<u8>().ok())
+        };
+
+        Self {
+            name: text("name"),
+            format: text("format").map(|format| format.to_ascii_lowercase()),
+            bounds: raw.get("bounds").and_then(|value| parse_bounds(value)),
+            center: raw.get("center").and_then(|value| parse_center(value)),
+            min_zoom: zoom("minzoom"),
+            max_zoom: zoom("maxzoom"),
+            attribution: text("attribution"),
+            description: text("description"),
+            raw,
+        }
+    }
+
+    /// Whether the archive holds vector tiles rather than images
+    pub fn is_vector(&self) -> bool {
+        self.format.as_deref() == Some("pbf")
+    }
+
+    /// Copy the archive's zoom range, bounds and attribution into `options`
+    ///
+    /// Only values present in the archive are applied; everything else is left
+    /// as configured.
+    pub fn apply_to_options(&self, options: &mut TileLayerOptions) {
+        if let Some(min_zoom) = self.min_zoom {
+            options.min_zoom = min_zoom;
+        }
+        if let Some(max_zoom) = self.max_zoom {
+            options.max_zoom = max_zoom;
+        }
+        if let Some(bounds) = &self.bounds {
+            options.bounds = Some(bounds.clone());
+        }
+        if let Some(attribution) = &self.attribution {
+            options.attribution = Some(attribution.clone());
+        }
+    }
+}
+
+/// `bounds` is stored as `left,bottom,right,top` in WGS84 degrees
+fn parse_bounds(value: &str) -> Option<LatLngBounds> {
+    let parts = parse_numbers(value)?;
+    if parts.len() != 4 {
+        return None;
+    }
+    let (west, south, east, north) = (parts[0], parts[1], parts[2], parts[3]);
+    (south <= north && west <= east).then(|| LatLngBounds::from_coords(south, west, north, east))
+}
+
+/// `center` is stored as `longitude,latitude,zoom`
+fn parse_center(value: &str) -> Option<(LatLng, u8)> {
+    let parts = parse_numbers(value)?;
+    if parts.len() != 3 {
+        return None;
+    }
+    Some((LatLng::new(parts[1], parts[0]), parts[2].max(0.0) as u8))
+}
+
+fn parse_numbers(value: &str) -> Option<Vec<f64>> {
+    value
+        .split(',')
+        .map(|part| part.trim().parse::<f64>().ok())
+        .collect()
+}
+
+/// Shared handle on the open archive, handed to the tile loader
+struct MBTilesReader {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl MBTilesReader {
+    fn read_tile(connection: &Mutex<Connection>, coord: TileCoord) -> Result<Option<Vec<u8>>> {
+        // MBTiles rows are numbered bottom-up (TMS)
+        let row = (1u32 << coord.z).saturating_sub(1).saturating_sub(coord.y);
+        let connection = connection
+            .lock()
+            .map_err(|_| "MBTiles connection lock poisoned")?;
+        let mut statement = connection.prepare_cached(
+            "SELECT tile_data FROM tiles WHERE zoom_level = ?1 AND tile_column = ?2 AND tile_row = ?3",
+        )?;
+        let data = statement
+            .query_row((coord.z, coord.x, row), |row| row.get::<_, Vec<u8>>(0))
+            .optional()?;
+        Ok(data)
+    }
+}
+
+impl TileDataSource for MBTilesReader {
+    fn load_tile(&self, coord: TileCoord) -> TileDataFuture<'_> {
+        let connection = self.connection.clone();
+        Box::pin(async move {
+            AsyncExecutor::execute_blocking(move || Self::read_tile(&connection, coord)).await
+        })
+    }
+}
+
+/// Tile source backed by a local MBTiles archive
+pub struct MBTilesSource {
+    path: PathBuf,
+    metadata: MBTilesMetadata,
+    reader: Arc<MBTilesReader>,
+}
+
+impl MBTilesSource {
+    /// Open an archive read-only and load its metadata
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let connection = Connection::open_with_flags(
+            &path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )
+        .map_err(|e| format!("Failed to open MBTiles archive {}: {}", path.display(), e))?;
+
+        let rows = Self::read_metadata_rows(&connection)
+            .map_err(|e| format!("{} is not a valid MBTiles archive: {}", path.display(), e))?;
+
+        Ok(Self {
+            path,
+            metadata: MBTilesMetadata::from_rows(rows),
+            reader: Arc::new(MBTilesReader {
+                connection: Arc::new(Mutex::new(connection)),
+            }),
+        })
+    }
+
+    fn read_metadata_rows(connection: &Connection) -> rusqlite::Result<Vec<(String, String)>> {
+        let mut statement = connection.prepare("SELECT name, value FROM metadata")?;
+        let rows = statement.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect()
+    }
+
+    /// Parsed archive metadata
+    pub fn metadata(&self) -> &MBTilesMetadata {
+        &self.metadata
+    }
+
+    /// Path of the archive on disk
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Tile options with the archive's metadata applied on top of `options`
+    pub fn layer_options(&self, mut options: TileLayerOptions) -> TileLayerOptions {
+        self.metadata.apply_to_options(&mut options);
+        options
+    }
+
+    /// Read a tile synchronously; `Ok(None)` when the archive has no such tile
+    pub fn read_tile(&self, coord: TileCoord) -> Result<Option<Vec<u8>>> {
+        MBTilesReader::read_tile(&self.reader.connection, coord)
+    }
+}
+
+impl TileSource for MBTilesSource {
+    fn url(&self, coord: TileCoord) -> String {
+        format!(
+            "mbtiles://{}/{}/{}/{}",
+            self.path.display(),
+            coord.z,
+            coord.x,
+            coord.y
+        )
+    }
+
+    fn has_tile(&self, coord: TileCoord) -> bool {
+        covers_tile(
+            coord,
+            self.metadata.min_zoom,
+            self.metadata.max_zoom,
+            self.metadata.bounds.as_ref(),
+        )
+    }
+
+    fn data_source(&self) -> Option<Arc<dyn TileDataSource>> {
+        Some(self.reader.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_archive(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "maplet_mbtiles_{}_{}.mbtiles",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+
+        let connection = Connection::open(&path).unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE metadata (name TEXT, value TEXT);
+                 CREATE TABLE tiles (zoom_level INTEGER, tile_column INTEGER, tile_row INTEGER, tile_data BLOB);
+                 INSERT INTO metadata VALUES ('name', 'Test');
+                 INSERT INTO metadata VALUES ('format', 'PNG');
+                 INSERT INTO metadata VALUES ('bounds', '-10.0,-5.0,10.0,5.0');
+                 INSERT INTO metadata VALUES ('center', '1.5,2.5,4');
+                 INSERT INTO metadata VALUES ('minzoom', '2');
+                 INSERT INTO metadata VALUES ('maxzoom', '12');
+                 INSERT INTO metadata VALUES ('attribution', '(c) Test');",
+            )
+            .unwrap();
+        // Tile (x=1, y=0, z=1) is stored as TMS row 1
+        connection
+            .execute("INSERT INTO tiles VALUES (1, 1, 1, ?1)", [vec![1u8, 2, 3]])
+            .unwrap();
+        path
+    }
+
+    #[test]
+    fn test_metadata_parsing_and_options() {
+        let path = create_archive("metadata");
+        let source = MBTilesSource::open(&path).unwrap();
+        let metadata = source.metadata();
+
+        assert_eq!(metadata.name.as_deref(), Some("Test"));
+        assert_eq!(metadata.format.as_deref(), Some("png"));
+        assert!(!metadata.is_vector());
+        assert_eq!(metadata.min_zoom, Some(2));
+        assert_eq!(metadata.max_zoom, Some(12));
+        let (center, zoom) = metadata.center.unwrap();
+        assert_eq!((center.lat, center.lng, zoom), (2.5, 1.5, 4));
+
+        let bounds = metadata.bounds.clone().unwrap();
+        assert_eq!(bounds.south_west, LatLng::new(-5.0, -10.0));
+        assert_eq!(bounds.north_east, LatLng::new(5.0, 10.0));
+
+        let options = source.layer_options(TileLayerOptions::default());
+        assert_eq!(options.min_zoom, 2);
+        assert_eq!(options.max_zoom, 12);
+        assert_eq!(options.attribution.as_deref(), Some("(c) Test"));
+        assert!(options.bounds.is_some());
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_read_tile_flips_rows_and_reports_misses() {
+        let path = create_archive("read");
+        let source = MBTilesSource::open(&path).unwrap();
+
+        assert_eq!(
+            source.read_tile(TileCoord::new(1, 0, 1)).unwrap(),
+            Some(vec![1, 2, 3])
+        );
+        assert_eq!(source.read_tile(TileCoord::new(1, 1, 1)).unwrap(), None);
+
+        let data_source = source.data_source().unwrap();
+        let loaded = data_source
+            .load_tile(TileCoord::new(1, 0, 1))
+            .await
+            .unwrap();
+        assert_eq!(loaded, Some(vec![1, 2, 3]));
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_open_rejects_non_archive() {
+        let path = std::env::temp_dir().join(format!(
+            "maplet_mbtiles_invalid_{}.mbtiles",
+            std::process::id()
+        ));
+        std::fs::write(&path, b"not a database").unwrap();
+        assert!(MBTilesSource::open(&path).is_err());
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -6,15 +6,60 @@
 //! - Boundary-constrained rendering
 //! - Unified tile prefetching system
 
+pub mod addressing;
 pub mod cache;
+pub mod debug_tiles;
+pub mod decode;
+pub mod dem;
+pub mod directory;
+pub mod disk_cache;
+pub mod error_tile;
+pub mod failover;
+pub mod hillshade;
+pub mod http_cache;
 pub mod layer;
 pub mod loader;
+#[cfg(feature = "mbtiles")]
+pub mod mbtiles;
+pub mod mvt;
+pub mod offline;
+pub mod pmtiles;
+pub(crate) mod prepared;
+pub mod rate_limit;
+pub mod request;
 pub mod source;
+pub mod time_dimension;
 pub mod trait_impl;
 pub mod types;
+pub mod vector_tile;
+pub mod wms;
+pub mod wmts;
 
-pub use cache::TileCache;
+pub use addressing::{AddressedTileSource, TileAddressing, TileScheme};
+pub use cache::{TileCache, TileFreshness};
+pub use debug_tiles::DebugTileSource;
+pub use decode::{DecodedTile, TileImageFormat};
+pub use dem::{DemEncoding, DemTile, ElevationLookup};
+pub use directory::DirectoryTileSource;
+pub use disk_cache::{CachedTile, CachedTileInfo, DiskCacheConfig, DiskTileCache};
+pub use failover::{FailoverTileSource, SourceStats};
+pub use hillshade::{HillshadeLayer, HillshadeOptions, HypsometricTint};
 pub use layer::TileLayer;
 pub use loader::{TileLoader, TileLoaderConfig, TilePriority};
-pub use source::{OpenStreetMapSource, TileSource};
-pub use types::{TileLayerOptions, TileLevel, TileState};
+#[cfg(feature = "mbtiles")]
+pub use mbtiles::{MBTilesMetadata, MBTilesSource};
+pub use mvt::{MvtFeature, MvtGeometryType, MvtLayer, MvtTile};
+pub use offline::{
+    OfflineDownload, OfflineDownloadState, OfflineEstimate, OfflineProgress, OfflineRegion,
+};
+pub use pmtiles::{FileRangeReader, HttpRangeReader, PMTilesSource, RangeReader};
+pub use rate_limit::{rate_limiter, RateLimit, RateLimiter};
+pub use request::{AuthToken, RequestOptions, TokenGrant, TokenPlacement, DEFAULT_USER_AGENT};
+pub use source::{
+    OpenStreetMapSource, TemplateTileSource, TileDataFuture, TileDataSource, TileSource,
+};
+pub use time_dimension::TimeDimensionLayer;
+pub use types::{RetinaMode, TileLayerOptions, TileLevel, TileState};
+pub use vector_tile::{SourceLayerStyle, VectorTileLayer, VectorTileStyle};
+pub use wms::{WmsCrs, WmsTileSource, WmsVersion};
+pub use wmts::{TileMatrixSet, WmtsCapabilities, WmtsEncoding, WmtsLayer, WmtsTileSource};
@@ -0,0 +1,735 @@
+//! Mapbox Vector Tile (MVT) decoding
+//!
+//! Decodes the protobuf encoding described by the Mapbox Vector Tile
+//! specification (version 2, tolerant of version 1 tiles) into layers of
+//! features with integer tile-space geometry, and converts those features
+//! into [`VectorFeature`]s positioned on the map.
+
+use crate::core::geo::{LatLng, TileCoord};
+use crate::layers::vector::{VectorFeature, VectorFeatureData};
+use crate::prelude::HashMap;
+use crate::Result;
+
+use super::vector_tile::SourceLayerStyle;
+
+/// Extent used by layers that do not declare one
+pub const DEFAULT_EXTENT: u32 = 4096;
+
+const WIRE_VARINT: u8 = 0;
+const WIRE_FIXED64: u8 = 1;
+const WIRE_LENGTH_DELIMITED: u8 = 2;
+const WIRE_FIXED32: u8 = 5;
+
+const COMMAND_MOVE_TO: u32 = 1;
+const COMMAND_LINE_TO: u32 = 2;
+const COMMAND_CLOSE_PATH: u32 = 7;
+
+/// A decoded vector tile
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MvtTile {
+    pub layers: Vec<MvtLayer>,
+}
+
+/// A named source layer within a tile
+#[derive(Debug, Clone, PartialEq)]
+pub struct MvtLayer {
+    pub name: String,
+    pub version: u32,
+    /// Size of the tile in geometry units; coordinates may fall outside
+    /// `0..extent` when the tile carries a buffer
+    pub extent: u32,
+    pub features: Vec<MvtFeature>,
+}
+
+/// Geometry type declared by a feature
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MvtGeometryType {
+    Unknown,
+    Point,
+    LineString,
+    Polygon,
+}
+
+impl MvtGeometryType {
+    fn from_raw(value: u64) -> Self {
+        match value {
+            1 => MvtGeometryType::Point,
+            2 => MvtGeometryType::LineString,
+            3 => MvtGeometryType::Polygon,
+            _ => MvtGeometryType::Unknown,
+        }
+    }
+}
+
+/// A feature with its geometry in tile coordinates (x right, y down)
+#[derive(Debug, Clone, PartialEq)]
+pub struct MvtFeature {
+    pub id: Option<u64>,
+    pub geometry_type: MvtGeometryType,
+    pub properties: HashMap<String, serde_json::Value>,
+    /// Points: a single part holding every point. Lines: one part per line.
+    /// Polygons: one closed ring per part, in encoded order.
+    pub geometry: Vec<Vec<[i32; 2]>>,
+}
+
+fn parse_error(message: impl Into<String>) -> Box<dyn std::error::Error + Send + Sync> {
+    Box::new(crate::Error::ParseError(format!(
+        "Invalid vector tile: {}",
+        message.into()
+    )))
+}
+
+/// Cursor over a protobuf message
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn varint(&mut self) -> Result<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = *self
+                .data
+                .get(self.pos)
+                .ok_or_else(|| parse_error("truncated varint"))?;
+            self.pos += 1;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(parse_error("varint is too long"))
+    }
+
+    /// Field number and wire type of the next field
+    fn key(&mut self) -> Result<(u32, u8)> {
+        let key = self.varint()?;
+        Ok(((key >> 3) as u32, (key & 0x7) as u8))
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| parse_error("field runs past the end of its message"))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8]> {
+        let len = self.varint()? as usize;
+        self.take(len)
+    }
+
+    fn string(&mut self) -> Result<String> {
+        String::from_utf8(self.bytes()?.to_vec()).map_err(|_| parse_error("string is not UTF-8"))
+    }
+
+    fn fixed32(&mut self) -> Result<[u8; 4]> {
+        Ok(self.take(4)?.try_into().expect("four bytes"))
+    }
+
+    fn fixed64(&mut self) -> Result<[u8; 8]> {
+        Ok(self.take(8)?.try_into().expect("eight bytes"))
+    }
+
+    fn skip(&mut self, wire_type: u8) -> Result<()> {
+        match wire_type {
+            WIRE_VARINT => self.varint().map(drop),
+            WIRE_FIXED64 => self.take(8).map(drop),
+            WIRE_LENGTH_DELIMITED => self.bytes().map(drop),
+            WIRE_FIXED32 => self.take(4).map(drop),
+            other => Err(parse_error(format!("unsupported wire type {}", other))),
+        }
+    }
+
+    /// Repeated `uint32` values, accepting both packed and unpacked encodings
+    fn packed_u32(&mut self, wire_type: u8, out: &mut Vec<u32>) -> Result<()> {
+        if wire_type == WIRE_LENGTH_DELIMITED {
+            let mut packed = Reader::new(self.bytes()?);
+            while !packed.is_empty() {
+                out.push(packed.varint()? as u32);
+            }
+        } else {
+            out.push(self.varint()? as u32);
+        }
+        Ok(())
+    }
+}
+
+fn zigzag(value: u32) -> i32 {
+    ((value >> 1) as i32) ^ -((value & 1) as i32)
+}
+
+impl MvtTile {
+    /// Decode a tile, gunzipping it first if it is gzip-compressed
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        if data.starts_with(&[0x1f, 0x8b]) {
+            use std::io::Read;
+            let mut decoded = Vec::new();
+            flate2::read::GzDecoder::new(data).read_to_end(&mut decoded)?;
+            return Self::decode_uncompressed(&decoded);
+        }
+        Self::decode_uncompressed(data)
+    }
+
+    fn decode_uncompressed(data: &[u8]) -> Result<Self> {
+        let mut reader = Reader::new(data);
+        let mut layers = Vec::new();
+        while !reader.is_empty() {
+            match reader.key()? {
+                (3, WIRE_LENGTH_DELIMITED) => layers.push(MvtLayer::decode(reader.bytes()?)?),
+                (_, wire_type) => reader.skip(wire_type)?,
+            }
+        }
+        Ok(Self { layers })
+    }
+
+    pub fn layer(&self, name: &str) -> Option<&MvtLayer> {
+        self.layers.iter().find(|layer| layer.name == name)
+    }
+}
+
+impl MvtLayer {
+    fn decode(data: &[u8]) -> Result<Self> {
+        let mut reader = Reader::new(data);
+        let mut name = None;
+        let mut version = 1;
+        let mut extent = DEFAULT_EXTENT;
+        let mut keys = Vec::new();
+        let mut values = Vec::new();
+        let mut raw_features = Vec::new();
+
+        while !reader.is_empty() {
+            match reader.key()? {
+                (1, WIRE_LENGTH_DELIMITED) => name = Some(reader.string()?),
+                (2, WIRE_LENGTH_DELIMITED) => raw_features.push(reader.bytes()?),
+                (3, WIRE_LENGTH_DELIMITED) => keys.push(reader.string()?),
+                (4, WIRE_LENGTH_DELIMITED) => values.push(decode_value(reader.bytes()?)?),
+                (5, WIRE_VARINT) => extent = reader.varint()? as u32,
+                (15, WIRE_VARINT) => version = reader.varint()? as u32,
+                (_, wire_type) => reader.skip(wire_type)?,
+            }
+        }
+
+        let name = name.ok_or_else(|| parse_error("layer has no name"))?;
+        if extent == 0 {
+            return Err(parse_error(format!("layer {} has a zero extent", name)));
+        }
+        // Keys and values come after the features on the wire, so features
+        // are decoded once the whole layer has been read
+        let features = raw_features
+            .into_iter()
+            .map(|data| MvtFeature::decode(data, &keys, &values))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            name,
+            version,
+            extent,
+            features,
+        })
+    }
+}
+
+fn decode_value(data: &[u8]) -> Result<serde_json::Value> {
+    let mut reader = Reader::new(data);
+    let mut value = serde_json::Value::Null;
+    while !reader.is_empty() {
+        value = match reader.key()? {
+            (1, WIRE_LENGTH_DELIMITED) => serde_json::Value::String(reader.string()?),
+            (2, WIRE_FIXED32) => {
+                serde_json::Number::from_f64(f32::from_le_bytes(reader.fixed32()?) as f64)
+                    .map_or(serde_json::Value::Null, serde_json::Value::Number)
+            }
+            (3, WIRE_FIXED64) => {
+                serde_json::Number::from_f64(f64::from_le_bytes(reader.fixed64()?))
+                    .map_or(serde_json::Value::Null, serde_json::Value::Number)
+            }
+            (4, WIRE_VARINT) => serde_json::Value::from(reader.varint()? as i64),
+            (5, WIRE_VARINT) => serde_json::Value::from(reader.varint()?),
+            (6, WIRE_VARINT) => {
+                let raw = reader.varint()?;
+                serde_json::Value::from((raw >> 1) as i64 ^ -((raw & 1) as i64))
+            }
+            (7, WIRE_VARINT) => serde_json::Value::Bool(reader.varint()? != 0),
+            (_, wire_type) => {
+                reader.skip(wire_type)?;
+                continue;
+            }
+        };
+    }
+    Ok(value)
+}
+
+impl MvtFeature {
+    fn decode(data: &[u8], keys: &[String], values: &[serde_json::Value]) -> Result<Self> {
+        let mut reader = Reader::new(data);
+        let mut id = None;
+        let mut geometry_type = MvtGeometryType::Unknown;
+        let mut tags = Vec::new();
+        let mut commands = Vec::new();
+
+        while !reader.is_empty() {
+            match reader.key()? {
+                (1, WIRE_VARINT) => id = Some(reader.varint()?),
+                (2, wire_type) => reader.packed_u32(wire_type, &mut tags)?,
+                (3, WIRE_VARINT) => geometry_type = MvtGeometryType::from_raw(reader.varint()?),
+                (4, wire_type) => reader.packed_u32(wire_type, &mut commands)?,
+                (_, wire_type) => reader.skip(wire_type)?,
+            }
+        }
+
+        if tags.len() % 2 != 0 {
+            return Err(parse_error("feature has an odd number of tags"));
+        }
+        let mut properties = HashMap::default();
+        for pair in tags.chunks_exact(2) {
+            let key = keys
+                .get(pair[0] as usize)
+                .ok_or_else(|| parse_error(format!("tag key {} out of range", pair[0])))?;
+            let value = values
+                .get(pair[1] as usize)
+                .ok_or_else(|| parse_error(format!("tag value {} out of range", pair[1])))?;
+            properties.insert(key.clone(), value.clone());
+        }
+
+        Ok(Self {
+            id,
+            geometry_type,
+            properties,
+            geometry: decode_geometry(&commands, geometry_type)?,
+        })
+    }
+
+    /// Convert to a map feature for the tile at `coord`, with geometry
+    /// scaled by the layer `extent`
+    ///
+    /// Returns `None` for features without usable geometry. Polygon rings
+    /// are grouped by winding: each exterior ring (positive area in tile
+    /// coordinates) starts a polygon and the rings after it are its holes.
+    pub fn to_vector_feature(
+        &self,
+        coord: TileCoord,
+        extent: u32,
+        style: &SourceLayerStyle,
+    ) -> Option<VectorFeature> {
+        let project = |ring: &[[i32; 2]]| -> Vec<LatLng> {
+            ring.iter()
+                .map(|&point| tile_point_to_lat_lng(coord, extent, point))
+                .collect()
+        };
+
+        match self.geometry_type {
+            MvtGeometryType::Point => {
+                let mut points = project(self.geometry.first()?);
+                match points.len() {
+                    0 => None,
+                    1 => Some(VectorFeature::Point {
+                        position: points.remove(0),
+                        style: style.point.clone(),
+                    }),
+                    _ => Some(VectorFeature::MultiPoint {
+                        points,
+                        style: style.point.clone(),
+                    }),
+                }
+            }
+            MvtGeometryType::LineString => {
+                let mut lines: Vec<Vec<LatLng>> = self
+                    .geometry
+                    .iter()
+                    .filter(|line| line.len() >= 2)
+                    .map(|line| project(line))
+                    .collect();
+                match lines.len() {
+                    0 => None,
+                    1 => Some(VectorFeature::LineString {
+                        points: lines.remove(0),
+                        style: style.line.clone(),
+                    }),
+                    _ => Some(VectorFeature::MultiLineString {
+                        lines,
+                        style: style.line.clone(),
+                    }),
+                }
+            }
+            MvtGeometryType::Polygon => {
+                let mut polygons = group_rings(&self.geometry)
+                    .into_iter()
+                    .map(|(exterior, holes)| {
+                        (project(exterior), holes.into_iter().map(&project).collect())
+                    })
+                    .collect::<Vec<_>>();
+                match polygons.len() {
+                    0 => None,
+                    1 => {
+                        let (exterior, holes) = polygons.remove(0);
+                        Some(VectorFeature::Polygon {
+                            exterior,
+                            holes,
+                            style: style.polygon.clone(),
+                        })
+                    }
+                    _ => Some(VectorFeature::MultiPolygon {
+                        polygons,
+                        style: style.polygon.clone(),
+                    }),
+                }
+            }
+            MvtGeometryType::Unknown => None,
+        }
+    }
+
+    /// Convert to feature data carrying the feature's properties plus a
+    /// `layer` property naming its source layer
+    pub fn to_feature_data(
+        &self,
+        coord: TileCoord,
+        layer: &MvtLayer,
+        index: usize,
+        style: &SourceLayerStyle,
+    ) -> Option<VectorFeatureData> {
+        let feature = self.to_vector_feature(coord, layer.extent, style)?;
+        let id = match self.id {
+            Some(id) => id.to_string(),
+            None => format!("#{}", index),
+        };
+        let mut data = VectorFeatureData::new(
+            format!("{}/{}/{}/{}/{}", coord.z, coord.x, coord.y, layer.name, id),
+            feature,
+        );
+        data.properties = self.properties.clone();
+        data.properties
+            .entry("layer".to_string())
+            .or_insert_with(|| serde_json::Value::String(layer.name.clone()));
+        Some(data)
+    }
+}
+
+/// Run the geometry command stream into parts of absolute tile coordinates
+fn decode_geometry(commands: &[u32], geometry_type: MvtGeometryType) -> Result<Vec<Vec<[i32; 2]>>> {
+    let mut parts: Vec<Vec<[i32; 2]>> = Vec::new();
+    let mut cursor = [0i32, 0i32];
+    let mut i = 0;
+
+    while i < commands.len() {
+        let command = commands[i] & 0x7;
+        let count = (commands[i] >> 3) as usize;
+        i += 1;
+
+        match command {
+            COMMAND_MOVE_TO | COMMAND_LINE_TO => {
+                if commands.len() - i < count * 2 {
+                    return Err(parse_error("geometry command is missing parameters"));
+                }
+                if command == COMMAND_LINE_TO && parts.is_empty() {
+                    return Err(parse_error("LineTo before the first MoveTo"));
+                }
+                for _ in 0..count {
+                    cursor[0] = cursor[0].wrapping_add(zigzag(commands[i]));
+                    cursor[1] = cursor[1].wrapping_add(zigzag(commands[i + 1]));
+                    i += 2;
+
+                    let starts_part = command == COMMAND_MOVE_TO
+                        && (geometry_type != MvtGeometryType::Point || parts.is_empty());
+                    if starts_part {
+                        parts.push(Vec::new());
+                    }
+                    parts.last_mut().expect("part started").push(cursor);
+                }
+            }
+            COMMAND_CLOSE_PATH => {
+                if let Some(first) = parts.last().and_then(|part| part.first().copied()) {
+                    parts.last_mut().expect("part exists").push(first);
+                }
+            }
+            other => return Err(parse_error(format!("unknown geometry command {}", other))),
+        }
+    }
+
+    Ok(parts)
+}
+
+/// Twice the signed area of a ring by the surveyor's formula
+fn ring_area(ring: &[[i32; 2]]) -> i64 {
+    ring.windows(2)
+        .map(|pair| {
+            i64::from(pair[0][0]) * i64::from(pair[1][1])
+                - i64::from(pair[1][0]) * i64::from(pair[0][1])
+        })
+        .sum()
+}
+
+/// An exterior ring and its holes
+type RingGroup<'a> = (&'a [[i32; 2]], Vec<&'a [[i32; 2]]>);
+
+/// Group polygon rings into `(exterior, holes)`, skipping degenerate rings
+///
+/// The first ring's winding is taken as the exterior winding, which also
+/// handles version 1 tiles written with the opposite orientation.
+fn group_rings(rings: &[Vec<[i32; 2]>]) -> Vec<RingGroup<'_>> {
+    let mut polygons: Vec<RingGroup<'_>> = Vec::new();
+    let mut exterior_sign = 0;
+
+    for ring in rings {
+        let sign = ring_area(ring).signum();
+        if ring.len() < 4 || sign == 0 {
+            continue;
+        }
+        if exterior_sign == 0 {
+            exterior_sign = sign;
+        }
+        if sign == exterior_sign {
+            polygons.push((ring, Vec::new()));
+        } else if let Some((_, holes)) = polygons.last_mut() {
+            holes.push(ring);
+        }
+    }
+
+    polygons
+}
+
+/// Position of a tile-space point, honouring points in the tile buffer
+fn tile_point_to_lat_lng(coord: TileCoord, extent: u32, point: [i32; 2]) -> LatLng {
+    let n = 2_f64.powi(coord.z as i32);
+    let x = (coord.x as f64 + point[0] as f64 / extent as f64) / n;
+    let y = (coord.y as f64 + point[1] as f64 / extent as f64) / n;
+    let lat = (std::f64::consts::PI * (1.0 - 2.0 * y)).sinh().atan();
+    LatLng::new(lat.to_degrees(), x * 360.0 - 180.0)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    fn varint(mut value: u64, out: &mut Vec<u8>) {
+        while value >= 0x80 {
+            out.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        out.push(value as u8);
+    }
+
+    fn field(number: u32, wire_type: u8, out: &mut Vec<u8>) {
+        varint(((number as u64) << 3) | wire_type as u64, out);
+    }
+
+    fn message(number: u32, body: &[u8], out: &mut Vec<u8>) {
+        field(number, WIRE_LENGTH_DELIMITED, out);
+        varint(body.len() as u64, out);
+        out.extend_from_slice(body);
+    }
+
+    fn packed(number: u32, values: &[u32], out: &mut Vec<u8>) {
+        let mut body = Vec::new();
+        for value in values {
+            varint(*value as u64, &mut body);
+        }
+        message(number, &body, out);
+    }
+
+    fn command(id: u32, count: u32) -> u32 {
+        id | (count << 3)
+    }
+
+    fn zz(value: i32) -> u32 {
+        ((value << 1) ^ (value >> 31)) as u32
+    }
+
+    fn feature(id: Option<u64>, kind: u64, tags: &[u32], geometry: &[u32]) -> Vec<u8> {
+        let mut out = Vec::new();
+        if let Some(id) = id {
+            field(1, WIRE_VARINT, &mut out);
+            varint(id, &mut out);
+        }
+        packed(2, tags, &mut out);
+        field(3, WIRE_VARINT, &mut out);
+        varint(kind, &mut out);
+        packed(4, geometry, &mut out);
+        out
+    }
+
+    /// Tile with a `places` layer (one point with properties) and a
+    /// `shapes` layer of extent 256 holding a line and a polygon with a hole
+    pub(crate) fn sample_tile() -> Vec<u8> {
+        let mut places = Vec::new();
+        field(15, WIRE_VARINT, &mut places);
+        varint(2, &mut places);
+        message(1, b"places", &mut places);
+        message(
+            2,
+            &feature(
+                Some(7),
+                1,
+                &[0, 0, 1, 1, 2, 2],
+                &[command(COMMAND_MOVE_TO, 1), zz(2048), zz(2048)],
+            ),
+            &mut places,
+        );
+        message(3, b"name", &mut places);
+        message(3, b"population", &mut places);
+        message(3, b"capital", &mut places);
+        let mut value = Vec::new();
+        message(1, b"Null Island", &mut value);
+        message(4, &value, &mut places);
+        let mut value = Vec::new();
+        field(6, WIRE_VARINT, &mut value);
+        varint(zz(-12) as u64, &mut value);
+        message(4, &value, &mut places);
+        let mut value = Vec::new();
+        field(7, WIRE_VARINT, &mut value);
+        varint(1, &mut value);
+        message(4, &value, &mut places);
+
+        let mut shapes = Vec::new();
+        message(1, b"shapes", &mut shapes);
+        field(5, WIRE_VARINT, &mut shapes);
+        varint(256, &mut shapes);
+        message(
+            2,
+            &feature(
+                None,
+                2,
+                &[],
+                &[
+                    command(COMMAND_MOVE_TO, 1),
+                    zz(0),
+                    zz(0),
+                    command(COMMAND_LINE_TO, 2),
+                    zz(128),
+                    zz(0),
+                    zz(0),
+                    zz(128),
+                ],
+            ),
+            &mut shapes,
+        );
+        // Clockwise exterior square then a counter-clockwise hole
+        message(
+            2,
+            &feature(
+                Some(9),
+                3,
+                &[],
+                &[
+                    command(COMMAND_MOVE_TO, 1),
+                    zz(0),
+                    zz(0),
+                    command(COMMAND_LINE_TO, 3),
+                    zz(256),
+                    zz(0),
+                    zz(0),
+                    zz(256),
+                    zz(-256),
+                    zz(0),
+                    command(COMMAND_CLOSE_PATH, 1),
+                    command(COMMAND_MOVE_TO, 1),
+                    zz(64),
+                    zz(-192),
+                    command(COMMAND_LINE_TO, 3),
+                    zz(0),
+                    zz(128),
+                    zz(128),
+                    zz(0),
+                    zz(0),
+                    zz(-128),
+                    command(COMMAND_CLOSE_PATH, 1),
+                ],
+            ),
+            &mut shapes,
+        );
+
+        let mut tile = Vec::new();
+        message(3, &places, &mut tile);
+        message(3, &shapes, &mut tile);
+        tile
+    }
+
+    #[test]
+    fn test_decode_layers_properties_and_geometry() {
+        let tile = MvtTile::decode(&sample_tile()).unwrap();
+        assert_eq!(tile.layers.len(), 2);
+
+        let places = tile.layer("places").unwrap();
+        assert_eq!((places.version, places.extent), (2, DEFAULT_EXTENT));
+        let place = &places.features[0];
+        assert_eq!(place.id, Some(7));
+        assert_eq!(place.geometry_type, MvtGeometryType::Point);
+        assert_eq!(place.geometry, vec![vec![[2048, 2048]]]);
+        assert_eq!(place.properties["name"], "Null Island");
+        assert_eq!(place.properties["population"], -12);
+        assert_eq!(place.properties["capital"], true);
+
+        let shapes = tile.layer("shapes").unwrap();
+        assert_eq!(shapes.extent, 256);
+        assert_eq!(
+            shapes.features[0].geometry,
+            vec![vec![[0, 0], [128, 0], [128, 128]]]
+        );
+        let rings = &shapes.features[1].geometry;
+        assert_eq!(rings.len(), 2);
+        assert_eq!(rings[0].first(), rings[0].last());
+        assert_eq!(rings[1][0], [64, 64]);
+    }
+
+    #[test]
+    fn test_conversion_uses_layer_extent() {
+        let tile = MvtTile::decode(&sample_tile()).unwrap();
+        let style = SourceLayerStyle::default();
+        let coord = TileCoord::new(0, 0, 1);
+
+        // Centre of the north-west quarter of the world
+        let places = tile.layer("places").unwrap();
+        let data = places.features[0]
+            .to_feature_data(coord, places, 0, &style)
+            .unwrap();
+        assert_eq!(data.id, "1/0/0/places/7");
+        assert_eq!(data.properties["layer"], "places");
+        match data.feature {
+            VectorFeature::Point { position, .. } => {
+                assert!((position.lng + 90.0).abs() < 1e-9);
+                assert!((position.lat - 66.5132).abs() < 1e-3);
+            }
+            other => panic!("expected a point, got {:?}", other),
+        }
+
+        let shapes = tile.layer("shapes").unwrap();
+        match shapes.features[1].to_vector_feature(coord, shapes.extent, &style) {
+            Some(VectorFeature::Polygon {
+                exterior, holes, ..
+            }) => {
+                assert_eq!(exterior.len(), 5);
+                assert_eq!(holes.len(), 1);
+                // The square covers the whole tile
+                assert!((exterior[1].lng - 0.0).abs() < 1e-9);
+                assert!((exterior[2].lat - 0.0).abs() < 1e-9);
+            }
+            other => panic!("expected a polygon with a hole, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_gzip_and_malformed_input() {
+        use std::io::Write;
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&sample_tile()).unwrap();
+        let gzipped = encoder.finish().unwrap();
+        assert_eq!(MvtTile::decode(&gzipped).unwrap().layers.len(), 2);
+
+        let truncated = sample_tile();
+        assert!(MvtTile::decode(&truncated[..truncated.len() - 3]).is_err());
+        assert!(MvtTile::decode(&[]).unwrap().layers.is_empty());
+    }
+}
@@ -0,0 +1,557 @@
+//! Offline region downloads
+//!
+//! Pre-loads every tile of an area over a range of zoom levels into the
+//! persistent [`DiskTileCache`], so the area can be browsed later without a
+//! connection. The tiles are pinned there, out of reach of the cache's
+//! eviction and max age, until the region is released. Downloads run on the [`BackgroundTaskManager`] at
+//! [`TaskPriority::Background`] and share the tile loader's download slots,
+//! taking at most half of them so interactive loading keeps priority.
+//!
+//! [`BackgroundTaskManager`]: crate::background::BackgroundTaskManager
+
+use super::disk_cache::DiskTileCache;
+use super::loader::{download_tile, NetworkMetrics};
+use super::rate_limit::rate_limiter;
+use super::request::RequestOptions;
+use super::source::TileSource;
+use crate::background::tasks::AsyncExecutor;
+use crate::background::{BackgroundTask, TaskPriority};
+use crate::core::geo::{LatLng, LatLngBounds, TileCoord};
+use crate::prelude::{Arc, Duration, Future, Mutex, Pin};
+use crate::runtime::async_utils::{async_delay, Semaphore};
+use crate::Result;
+use futures::stream::{FuturesUnordered, StreamExt};
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+#[cfg(feature = "debug")]
+use log;
+
+/// Typical size of a compressed raster tile, used for download estimates
+pub const AVERAGE_TILE_BYTES: u64 = 15_000;
+
+/// An area and zoom range to make available offline
+#[derive(Debug, Clone, PartialEq)]
+pub struct OfflineRegion {
+    pub bounds: LatLngBounds,
+    pub min_zoom: u8,
+    pub max_zoom: u8,
+}
+
+/// Size of an offline region before downloading it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OfflineEstimate {
+    pub tile_count: u64,
+    pub estimated_bytes: u64,
+}
+
+impl OfflineRegion {
+    pub fn new(bounds: LatLngBounds, min_zoom: u8, max_zoom: u8) -> Self {
+        Self {
+            bounds,
+            min_zoom,
+            max_zoom,
+        }
+    }
+
+    /// Check that the bounds and zoom range describe a non-empty area.
+    /// Regions crossing the antimeridian must be split in two.
+    pub fn validate(&self) -> Result<()> {
+        let (sw, ne) = (self.bounds.south_west, self.bounds.north_east);
+        if sw.lat > ne.lat || sw.lng > ne.lng {
+            return Err(format!(
+                "Offline region bounds are inverted: south-west {:?}, north-east {:?}",
+                sw, ne
+            )
+            .into());
+        }
+        if self.min_zoom > self.max_zoom || self.max_zoom > 30 {
+            return Err(format!(
+                "Invalid offline zoom range {}..={}",
+                self.min_zoom, self.max_zoom
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Columns and rows of the tiles covering the region at `zoom`
+    pub fn tile_range(&self, zoom: u8) -> (RangeInclusive<u32>, RangeInclusive<u32>) {
+        let last = (1u32 << zoom) - 1;
+        let (sw, ne) = (self.bounds.south_west, self.bounds.north_east);
+        let north_west = TileCoord::from_lat_lng(&LatLng::new(ne.lat, sw.lng), zoom);
+        let south_east = TileCoord::from_lat_lng(&LatLng::new(sw.lat, ne.lng), zoom);
+
+        (
+            north_west.x.min(last)..=south_east.x.min(last),
+            north_west.y.min(last)..=south_east.y.min(last),
+        )
+    }
+
+    /// Number of tiles covering the region at `zoom`
+    pub fn tile_count_at_zoom(&self, zoom: u8) -> u64 {
+        let (columns, rows) = self.tile_range(zoom);
+        let span = |range: RangeInclusive<u32>| {
+            (*range.end() as u64 + 1).saturating_sub(*range.start() as u64)
+        };
+        span(columns) * span(rows)
+    }
+
+    /// Number of tiles in the whole region
+    pub fn tile_count(&self) -> u64 {
+        (self.min_zoom..=self.max_zoom)
+            .map(|zoom| self.tile_count_at_zoom(zoom))
+            .sum()
+    }
+
+    /// All tiles of the region, lowest zoom first
+    pub fn tiles(&self) -> impl Iterator<Item = TileCoord> + Send + 'static {
+        let region = self.clone();
+        (self.min_zoom..=self.max_zoom).flat_map(move |zoom| {
+            let (columns, rows) = region.tile_range(zoom);
+            columns.flat_map(move |x| rows.clone().map(move |y| TileCoord::new(x, y, zoom)))
+        })
+    }
+
+    /// Name of the disk cache pin holding the tiles of the region downloaded
+    /// from `source_id`
+    pub(crate) fn pin_name(&self, source_id: &str) -> String {
+        let (sw, ne) = (self.bounds.south_west, self.bounds.north_east);
+        format!(
+            "{} {},{},{},{} {}-{}",
+            source_id, sw.lat, sw.lng, ne.lat, ne.lng, self.min_zoom, self.max_zoom
+        )
+    }
+
+    /// Tile count and expected download size, assuming [`AVERAGE_TILE_BYTES`]
+    pub fn estimate(&self) -> OfflineEstimate {
+        self.estimate_with_tile_size(AVERAGE_TILE_BYTES)
+    }
+
+    /// Tile count and expected download size for a given average tile size
+    pub fn estimate_with_tile_size(&self, average_tile_bytes: u64) -> OfflineEstimate {
+        let tile_count = self.tile_count();
+        OfflineEstimate {
+            tile_count,
+            estimated_bytes: tile_count.saturating_mul(average_tile_bytes),
+        }
+    }
+}
+
+/// Lifecycle of an offline download
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OfflineDownloadState {
+    /// Waiting for the background task manager to start it
+    Queued,
+    Running,
+    /// Paused; tiles already requested still finish
+    Paused,
+    /// Every tile was attempted; see [`OfflineProgress::failed_tiles`]
+    Completed,
+    Cancelled,
+}
+
+/// Snapshot of an offline download's progress
+#[derive(Debug, Clone, PartialEq)]
+pub struct OfflineProgress {
+    pub state: OfflineDownloadState,
+    /// Tiles in the region that the source serves
+    pub total_tiles: u64,
+    /// Tiles fetched during this download
+    pub downloaded_tiles: u64,
+    /// Tiles that were already on disk
+    pub cached_tiles: u64,
+    /// Tiles that could not be fetched; downloading the region again retries them
+    pub failed_tiles: u64,
+    pub downloaded_bytes: u64,
+}
+
+impl OfflineProgress {
+    /// Tiles dealt with so far, successfully or not
+    pub fn completed_tiles(&self) -> u64 {
+        self.downloaded_tiles + self.cached_tiles + self.failed_tiles
+    }
+
+    /// Share of the region dealt with, from 0.0 to 1.0
+    pub fn fraction(&self) -> f64 {
+        if self.total_tiles == 0 {
+            return 1.0;
+        }
+        self.completed_tiles() as f64 / self.total_tiles as f64
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self.state,
+            OfflineDownloadState::Completed | OfflineDownloadState::Cancelled
+        )
+    }
+}
+
+struct DownloadShared {
+    id: String,
+    total_tiles: u64,
+    phase: Mutex<OfflineDownloadState>,
+    paused: AtomicBool,
+    cancelled: AtomicBool,
+    downloaded_tiles: AtomicU64,
+    cached_tiles: AtomicU64,
+    failed_tiles: AtomicU64,
+    downloaded_bytes: AtomicU64,
+}
+
+/// Handle for observing and controlling an offline download
+#[derive(Clone)]
+pub struct OfflineDownload {
+    shared: Arc<DownloadShared>,
+}
+
+impl std::fmt::Debug for OfflineDownload {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OfflineDownload")
+            .field("id", &self.shared.id)
+            .field("progress", &self.progress())
+            .finish()
+    }
+}
+
+impl OfflineDownload {
+    fn new(id: String, total_tiles: u64) -> Self {
+        Self {
+            shared: Arc::new(DownloadShared {
+                id,
+                total_tiles,
+                phase: Mutex::new(OfflineDownloadState::Queued),
+                paused: AtomicBool::new(false),
+                cancelled: AtomicBool::new(false),
+                downloaded_tiles: AtomicU64::new(0),
+                cached_tiles: AtomicU64::new(0),
+                failed_tiles: AtomicU64::new(0),
+                downloaded_bytes: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Identifier of the background task running the download
+    pub fn id(&self) -> &str {
+        &self.shared.id
+    }
+
+    pub fn state(&self) -> OfflineDownloadState {
+        let phase = *self.shared.phase.lock().unwrap_or_else(|e| e.into_inner());
+        match phase {
+            OfflineDownloadState::Queued | OfflineDownloadState::Running
+                if self.shared.cancelled.load(Ordering::Relaxed) =>
+            {
+                OfflineDownloadState::Cancelled
+            }
+            OfflineDownloadState::Queued | OfflineDownloadState::Running
+                if self.shared.paused.load(Ordering::Relaxed) =>
+            {
+                OfflineDownloadState::Paused
+            }
+            phase => phase,
+        }
+    }
+
+    pub fn progress(&self) -> OfflineProgress {
+        let shared = &self.shared;
+        OfflineProgress {
+            state: self.state(),
+            total_tiles: shared.total_tiles,
+            downloaded_tiles: shared.downloaded_tiles.load(Ordering::Relaxed),
+            cached_tiles: shared.cached_tiles.load(Ordering::Relaxed),
+            failed_tiles: shared.failed_tiles.load(Ordering::Relaxed),
+            downloaded_bytes: shared.downloaded_bytes.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Stop requesting new tiles until [`OfflineDownload::resume`] is called
+    pub fn pause(&self) {
+        self.shared.paused.store(true, Ordering::Relaxed);
+    }
+
+    pub fn resume(&self) {
+        self.shared.paused.store(false, Ordering::Relaxed);
+    }
+
+    /// Stop the download; tiles already stored stay on disk
+    pub fn cancel(&self) {
+        self.shared.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.progress().is_finished()
+    }
+
+    fn set_phase(&self, phase: OfflineDownloadState) {
+        *self.shared.phase.lock().unwrap_or_else(|e| e.into_inner()) = phase;
+    }
+}
+
+/// Returns a download slot to the shared semaphore when dropped, including
+/// when a cancelled download drops its in-flight requests
+struct DownloadPermit(Semaphore);
+
+impl Drop for DownloadPermit {
+    fn drop(&mut self) {
+        self.0.release();
+    }
+}
+
+/// Background task downloading an [`OfflineRegion`] into a disk cache
+pub(crate) struct OfflineDownloadTask {
+    task_id: String,
+    source: Arc<dyn TileSource>,
+    source_id: Arc<str>,
+    request: Option<Arc<RequestOptions>>,
+    region: OfflineRegion,
+    disk_cache: Arc<DiskTileCache>,
+    permits: Semaphore,
+    network_metrics: Arc<Mutex<NetworkMetrics>>,
+    max_concurrent: usize,
+    handle: OfflineDownload,
+}
+
+impl OfflineDownloadTask {
+    /// Prepare a download of `region` from `source`, limited to half of
+    /// `max_concurrent` (scaled down on poor networks) of `permits`
+    pub(crate) fn new(
+        source: Arc<dyn TileSource>,
+        region: OfflineRegion,
+        disk_cache: Arc<DiskTileCache>,
+        permits: Semaphore,
+        network_metrics: Arc<Mutex<NetworkMetrics>>,
+        max_concurrent: usize,
+    ) -> Result<Self> {
+        region.validate()?;
+        if source.data_source().is_some() {
+            return Err("Tile source is read from local data and cannot be downloaded".into());
+        }
+
+        // Other pinned regions are never evicted to make room
+        let source_id: Arc<str> = source.source_id().into();
+        let pin = region.pin_name(&source_id);
+        let estimate = region.estimate();
+        let capacity = disk_cache.config().max_size_bytes;
+        let pinned = disk_cache.pinned_bytes_except(&pin);
+        if estimate.estimated_bytes.saturating_add(pinned) > capacity {
+            return Err(format!(
+                "Offline region needs about {} bytes for {} tiles but the disk cache holds {} bytes, {} of them pinned by other regions",
+                estimate.estimated_bytes, estimate.tile_count, capacity, pinned
+            )
+            .into());
+        }
+
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        let task_id = format!(
+            "offline_download_{}",
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        );
+        let tiles: Vec<TileCoord> = region.tiles().filter(|c| source.has_tile(*c)).collect();
+        let total_tiles = tiles.len() as u64;
+        disk_cache.pin(&pin, &source_id, tiles)?;
+
+        Ok(Self {
+            handle: OfflineDownload::new(task_id.clone(), total_tiles),
+            task_id,
+            source_id,
+            request: source.request_options().cloned().map(Arc::new),
+            source,
+            region,
+            disk_cache,
+            permits,
+            network_metrics,
+            max_concurrent,
+        })
+    }
+
+    pub(crate) fn handle(&self) -> OfflineDownload {
+        self.handle.clone()
+    }
+
+    /// Download slots this task may hold at once
+    fn concurrency_limit(&self) -> usize {
+        let limit = self
+            .network_metrics
+            .lock()
+            .map(|metrics| metrics.get_concurrency_limit(self.max_concurrent))
+            .unwrap_or(self.max_concurrent);
+        (limit / 2).max(1)
+    }
+
+    /// Fetch one tile and store it on disk, returning its size
+    ///
+    /// Tiles are stored even when the server asks for them not to be, since
+    /// the region was downloaded to be kept.
+    async fn download(
+        url: String,
+        request: Option<Arc<RequestOptions>>,
+        coord: TileCoord,
+        source_id: Arc<str>,
+        disk_cache: Arc<DiskTileCache>,
+        _permit: DownloadPermit,
+    ) -> Result<u64> {
+        rate_limiter().acquire(&url).await;
+        let response = download_tile(&url, coord, None, request.as_deref()).await?;
+        let data = response.data.unwrap_or_default();
+        let size = data.len() as u64;
+        AsyncExecutor::execute_blocking(move || {
+            disk_cache.put_entry(&source_id, coord, &data, &response.policy.info)
+        })
+        .await?;
+        Ok(size)
+    }
+
+    fn record(&self, outcome: Result<u64>) {
+        let shared = &self.handle.shared;
+        match outcome {
+            Ok(size) => {
+                shared.downloaded_tiles.fetch_add(1, Ordering::Relaxed);
+                shared.downloaded_bytes.fetch_add(size, Ordering::Relaxed);
+            }
+            Err(_e) => {
+                #[cfg(feature = "debug")]
+                log::debug!("Offline tile download failed: {}", _e);
+                shared.failed_tiles.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    async fn run(&self) -> OfflineProgress {
+        let shared = &self.handle.shared;
+        self.handle.set_phase(OfflineDownloadState::Running);
+        let mut in_flight = FuturesUnordered::new();
+
+        'tiles: for coord in self.region.tiles().filter(|c| self.source.has_tile(*c)) {
+            if self.disk_cache.contains(&self.source_id, coord) {
+                shared.cached_tiles.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+
+            // Wait for a free slot, finishing earlier requests meanwhile
+            let permit = loop {
+                if shared.cancelled.load(Ordering::Relaxed) {
+                    break 'tiles;
+                }
+                if !shared.paused.load(Ordering::Relaxed)
+                    && in_flight.len() < self.concurrency_limit()
+                    && self.permits.try_acquire()
+                {
+                    break DownloadPermit(self.permits.clone());
+                }
+                if in_flight.is_empty() {
+                    async_delay(Duration::from_millis(10)).await;
+                } else if let Some(outcome) = in_flight.next().await {
+                    self.record(outcome);
+                }
+            };
+
+            in_flight.push(Self::download(
+                self.source.url(coord),
+                self.request.clone(),
+                coord,
+                self.source_id.clone(),
+                self.disk_cache.clone(),
+                permit,
+            ));
+        }
+
+        while !shared.cancelled.load(Ordering::Relaxed) {
+            match in_flight.next().await {
+                Some(outcome) => self.record(outcome),
+                None => break,
+            }
+        }
+
+        let finished = if shared.cancelled.load(Ordering::Relaxed) {
+            OfflineDownloadState::Cancelled
+        } else {
+            OfflineDownloadState::Completed
+        };
+        self.handle.set_phase(finished);
+        self.handle.progress()
+    }
+}
+
+impl BackgroundTask for OfflineDownloadTask {
+    fn execute(
+        &self,
+    ) -> Pin<Box<dyn Future<Output = Result<Box<dyn std::any::Any + Send>>> + Send + '_>> {
+        Box::pin(async move {
+            let progress = self.run().await;
+            Ok(Box::new(progress) as Box<dyn std::any::Any + Send>)
+        })
+    }
+
+    fn task_id(&self) -> &str {
+        &self.task_id
+    }
+
+    fn priority(&self) -> TaskPriority {
+        TaskPriority::Background
+    }
+
+    fn estimated_duration(&self) -> Duration {
+        Duration::from_millis(50) * self.handle.shared.total_tiles.min(u32::MAX as u64) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traits::GeometryOps;
+
+    #[test]
+    fn test_region_tile_count_and_estimate() {
+        let world = OfflineRegion::new(LatLngBounds::from_coords(-85.0, -180.0, 85.0, 180.0), 0, 2);
+        assert_eq!(world.tile_count_at_zoom(0), 1);
+        assert_eq!(world.tile_count_at_zoom(2), 16);
+        assert_eq!(world.tile_count(), 21);
+        assert_eq!(world.tiles().count(), 21);
+        assert!(world.tiles().all(|coord| coord.is_valid()));
+
+        let estimate = world.estimate_with_tile_size(1_000);
+        assert_eq!(estimate.tile_count, 21);
+        assert_eq!(estimate.estimated_bytes, 21_000);
+
+        // A small area covers one tile per low zoom and a few more higher up
+        let city = OfflineRegion::new(
+            LatLngBounds::from_coords(51.49, -0.14, 51.52, -0.10),
+            10,
+            14,
+        );
+        assert_eq!(city.tile_count_at_zoom(10), 1);
+        assert!(city.tile_count_at_zoom(14) > 1);
+        let first = city.tiles().next().unwrap();
+        assert_eq!(first.z, 10);
+        assert!(first.bounds().contains_point(&LatLng::new(51.5, -0.12)));
+    }
+
+    #[test]
+    fn test_region_validation() {
+        let bounds = LatLngBounds::from_coords(10.0, 10.0, 20.0, 20.0);
+        assert!(OfflineRegion::new(bounds.clone(), 3, 5).validate().is_ok());
+        assert!(OfflineRegion::new(bounds, 5, 3).validate().is_err());
+        let inverted = LatLngBounds::from_coords(20.0, 10.0, 10.0, 20.0);
+        assert!(OfflineRegion::new(inverted, 0, 1).validate().is_err());
+    }
+
+    #[test]
+    fn test_download_handle_state() {
+        let download = OfflineDownload::new("test".to_string(), 10);
+        assert_eq!(download.state(), OfflineDownloadState::Queued);
+        download.pause();
+        assert_eq!(download.state(), OfflineDownloadState::Paused);
+        download.resume();
+        download.set_phase(OfflineDownloadState::Running);
+        assert_eq!(download.state(), OfflineDownloadState::Running);
+        download.cancel();
+        assert_eq!(download.state(), OfflineDownloadState::Cancelled);
+
+        let progress = download.progress();
+        assert_eq!(progress.total_tiles, 10);
+        assert_eq!(progress.fraction(), 0.0);
+        assert!(progress.is_finished());
+    }
+}
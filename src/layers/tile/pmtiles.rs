@@ -0,0 +1,741 @@
+//! PMTiles v3 archive support
+//!
+//! [PMTiles](https://github.com/protomaps/PMTiles) packs a whole tile pyramid
+//! into one file that is read with byte-range requests, so a region can be
+//! shipped as a single static archive instead of being served by a tile server.
+//! Archives are read through a [`RangeReader`], which can be a local file, an
+//! HTTP server that honours `Range` headers, or an in-memory buffer.
+
+use super::source::{covers_tile, TileDataFuture, TileDataSource, TileSource};
+use super::types::TileLayerOptions;
+use crate::background::tasks::AsyncExecutor;
+use crate::core::geo::{LatLng, LatLngBounds, TileCoord};
+use crate::prelude::{Arc, Future, Mutex, Pin};
+use crate::Result;
+use lru::LruCache;
+use std::io::{Read, Seek, SeekFrom};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+
+/// Size of the fixed v3 header
+const HEADER_LEN: usize = 127;
+/// Leaf directories can nest, but never deeper than this
+const MAX_DIRECTORY_DEPTH: usize = 4;
+/// Number of decoded leaf directories kept in memory
+const LEAF_CACHE_SIZE: usize = 64;
+
+/// Future returned by [`RangeReader::read_range`]
+pub type RangeFuture<'a> = Pin<Box<dyn Future<Output = Result<Vec<u8>>> + Send + 'a>>;
+
+/// Anything that can return a byte range of an archive
+pub trait RangeReader: Send + Sync {
+    /// Read `length` bytes starting at `offset`. Ranges running past the end
+    /// of the archive return the bytes that exist.
+    fn read_range(&self, offset: u64, length: u64) -> RangeFuture<'_>;
+}
+
+/// Reads ranges from a local file on the blocking pool
+pub struct FileRangeReader {
+    path: PathBuf,
+    file: Arc<Mutex<std::fs::File>>,
+}
+
+impl FileRangeReader {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = std::fs::File::open(&path)
+            .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        Ok(Self {
+            path,
+            file: Arc::new(Mutex::new(file)),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Read a range on the calling thread
+    fn read_range_blocking(&self, offset: u64, length: u64) -> Result<Vec<u8>> {
+        read_file_range(&self.file, offset, length)
+    }
+}
+
+fn read_file_range(file: &Mutex<std::fs::File>, offset: u64, length: u64) -> Result<Vec<u8>> {
+    let mut file = file.lock().map_err(|_| "PMTiles file lock poisoned")?;
+    file.seek(SeekFrom::Start(offset))?;
+    let mut buffer = Vec::with_capacity(length as usize);
+    (&mut *file).take(length).read_to_end(&mut buffer)?;
+    Ok(buffer)
+}
+
+impl RangeReader for FileRangeReader {
+    fn read_range(&self, offset: u64, length: u64) -> RangeFuture<'_> {
+        let file = self.file.clone();
+        Box::pin(AsyncExecutor::execute_blocking(move || {
+            read_file_range(&file, offset, length)
+        }))
+    }
+}
+
+/// Reads ranges from a remote archive with HTTP `Range` requests
+pub struct HttpRangeReader {
+    url: String,
+    request: Option<super::RequestOptions>,
+}
+
+impl HttpRangeReader {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            request: None,
+        }
+    }
+
+    /// Send these headers, User-Agent and credentials with range requests
+    pub fn with_request_options(mut self, request: super::RequestOptions) -> Self {
+        self.request = Some(request);
+        self
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+}
+
+impl RangeReader for HttpRangeReader {
+    fn read_range(&self, offset: u64, length: u64) -> RangeFuture<'_> {
+        Box::pin(async move {
+            if length == 0 {
+                return Ok(Vec::new());
+            }
+            let request = match &self.request {
+                Some(request) => request.get(&super::loader::HTTP_CLIENT, &self.url).await?,
+                None => super::loader::HTTP_CLIENT.get(&self.url),
+            };
+            let response = request
+                .header(
+                    reqwest::header::RANGE,
+                    format!("bytes={}-{}", offset, offset + length - 1),
+                )
+                .send()
+                .await?;
+
+            let status = response.status();
+            if !status.is_success() {
+                return Err(format!("HTTP {} reading range of {}", status, self.url).into());
+            }
+            let bytes = response.bytes().await?;
+
+            // Servers that ignore `Range` send the whole archive
+            if status != reqwest::StatusCode::PARTIAL_CONTENT {
+                let start = (offset as usize).min(bytes.len());
+                let end = (offset + length).min(bytes.len() as u64) as usize;
+                return Ok(bytes[start..end].to_vec());
+            }
+            Ok(bytes.to_vec())
+        })
+    }
+}
+
+impl RangeReader for Vec<u8> {
+    fn read_range(&self, offset: u64, length: u64) -> RangeFuture<'_> {
+        let start = (offset as usize).min(self.len());
+        let end = (offset.saturating_add(length) as usize).min(self.len());
+        let bytes = self[start..end].to_vec();
+        Box::pin(async move { Ok(bytes) })
+    }
+}
+
+/// Compression applied to directories, metadata or tiles
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PMTilesCompression {
+    Unknown,
+    None,
+    Gzip,
+    Brotli,
+    Zstd,
+}
+
+impl PMTilesCompression {
+    fn from_byte(value: u8) -> Self {
+        match value {
+            1 => Self::None,
+            2 => Self::Gzip,
+            3 => Self::Brotli,
+            4 => Self::Zstd,
+            _ => Self::Unknown,
+        }
+    }
+
+    fn decompress(self, data: Vec<u8>) -> Result<Vec<u8>> {
+        match self {
+            Self::None => Ok(data),
+            Self::Gzip => {
+                let mut decoded = Vec::with_capacity(data.len() * 2);
+                flate2::read::GzDecoder::new(data.as_slice()).read_to_end(&mut decoded)?;
+                Ok(decoded)
+            }
+            // Some writers leave this unset for uncompressed tiles
+            Self::Unknown => Ok(data),
+            other => Err(format!("Unsupported PMTiles compression: {:?}", other).into()),
+        }
+    }
+}
+
+/// Encoding of the tiles stored in an archive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PMTilesTileType {
+    Unknown,
+    Mvt,
+    Png,
+    Jpeg,
+    Webp,
+    Avif,
+}
+
+impl PMTilesTileType {
+    fn from_byte(value: u8) -> Self {
+        match value {
+            1 => Self::Mvt,
+            2 => Self::Png,
+            3 => Self::Jpeg,
+            4 => Self::Webp,
+            5 => Self::Avif,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// The fixed-size header at the start of every v3 archive
+#[derive(Debug, Clone)]
+pub struct PMTilesHeader {
+    pub root_dir_offset: u64,
+    pub root_dir_length: u64,
+    pub metadata_offset: u64,
+    pub metadata_length: u64,
+    pub leaf_dirs_offset: u64,
+    pub leaf_dirs_length: u64,
+    pub tile_data_offset: u64,
+    pub tile_data_length: u64,
+    pub addressed_tiles: u64,
+    pub tile_entries: u64,
+    pub tile_contents: u64,
+    pub clustered: bool,
+    pub internal_compression: PMTilesCompression,
+    pub tile_compression: PMTilesCompression,
+    pub tile_type: PMTilesTileType,
+    pub min_zoom: u8,
+    pub max_zoom: u8,
+    pub bounds: LatLngBounds,
+    pub center_zoom: u8,
+    pub center: LatLng,
+}
+
+impl PMTilesHeader {
+    /// Parse the first 127 bytes of an archive
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < HEADER_LEN || &bytes[0..7] != b"PMTiles" {
+            return Err(Box::new(crate::Error::ParseError(
+                "Not a PMTiles archive".to_string(),
+            )));
+        }
+        if bytes[7] != 3 {
+            return Err(Box::new(crate::Error::ParseError(format!(
+                "Unsupported PMTiles version {}",
+                bytes[7]
+            ))));
+        }
+
+        let u64_at = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+        let degrees_at =
+            |at: usize| i32::from_le_bytes(bytes[at..at + 4].try_into().unwrap()) as f64 / 1e7;
+
+        Ok(Self {
+            root_dir_offset: u64_at(8),
+            root_dir_length: u64_at(16),
+            metadata_offset: u64_at(24),
+            metadata_length: u64_at(32),
+            leaf_dirs_offset: u64_at(40),
+            leaf_dirs_length: u64_at(48),
+            tile_data_offset: u64_at(56),
+            tile_data_length: u64_at(64),
+            addressed_tiles: u64_at(72),
+            tile_entries: u64_at(80),
+            tile_contents: u64_at(88),
+            clustered: bytes[96] == 1,
+            internal_compression: PMTilesCompression::from_byte(bytes[97]),
+            tile_compression: PMTilesCompression::from_byte(bytes[98]),
+            tile_type: PMTilesTileType::from_byte(bytes[99]),
+            min_zoom: bytes[100],
+            max_zoom: bytes[101],
+            bounds: LatLngBounds::from_coords(
+                degrees_at(106),
+                degrees_at(102),
+                degrees_at(114),
+                degrees_at(110),
+            ),
+            center_zoom: bytes[118],
+            center: LatLng::new(degrees_at(123), degrees_at(119)),
+        })
+    }
+}
+
+/// Map a tile coordinate to its PMTiles tile ID (position on the Hilbert curve
+/// of its zoom level, offset by the tile count of all lower levels)
+pub fn tile_id(coord: TileCoord) -> u64 {
+    let z = coord.z as u32;
+    let base = ((1u64 << (2 * z)) - 1) / 3;
+    let n = 1u64 << z;
+    let (mut x, mut y) = (coord.x as u64, coord.y as u64);
+    let mut d = 0u64;
+    let mut s = n / 2;
+    while s > 0 {
+        let rx = (x & s) > 0;
+        let ry = (y & s) > 0;
+        d += s * s * ((3 * rx as u64) ^ ry as u64);
+        if !ry {
+            if rx {
+                x = n - 1 - x;
+                y = n - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    base + d
+}
+
+/// One directory entry; `run_length == 0` marks a pointer to a leaf directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct DirEntry {
+    tile_id: u64,
+    offset: u64,
+    length: u64,
+    run_length: u64,
+}
+
+fn read_varint(bytes: &[u8], pos: &mut usize) -> Result<u64> {
+    let mut value = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = *bytes
+            .get(*pos)
+            .ok_or("Truncated varint in PMTiles directory")?;
+        *pos += 1;
+        if shift >= 64 {
+            return Err("Varint too long in PMTiles directory".into());
+        }
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+        shift += 7;
+    }
+}
+
+/// Decode a (decompressed) directory. Columns are stored one after another:
+/// delta-encoded tile IDs, run lengths, lengths, then offsets where `0` means
+/// "directly after the previous entry".
+fn parse_directory(bytes: &[u8]) -> Result<Vec<DirEntry>> {
+    let mut pos = 0;
+    let count = read_varint(bytes, &mut pos)? as usize;
+    // Every entry needs at least four bytes, so this guards bogus counts
+    if count > bytes.len() {
+        return Err("Invalid PMTiles directory entry count".into());
+    }
+
+    let mut entries = vec![
+        DirEntry {
+            tile_id: 0,
+            offset: 0,
+            length: 0,
+            run_length: 0,
+        };
+        count
+    ];
+
+    let mut last_id = 0u64;
+    for entry in entries.iter_mut() {
+        last_id += read_varint(bytes, &mut pos)?;
+        entry.tile_id = last_id;
+    }
+    for entry in entries.iter_mut() {
+        entry.run_length = read_varint(bytes, &mut pos)?;
+    }
+    for entry in entries.iter_mut() {
+        entry.length = read_varint(bytes, &mut pos)?;
+    }
+    for i in 0..count {
+        let value = read_varint(bytes, &mut pos)?;
+        entries[i].offset = if value == 0 && i > 0 {
+            entries[i - 1].offset + entries[i - 1].length
+        } else {
+            value.saturating_sub(1)
+        };
+    }
+    Ok(entries)
+}
+
+/// Find the entry covering `tile_id`: the last entry starting at or before it
+fn find_entry(entries: &[DirEntry], tile_id: u64) -> Option<DirEntry> {
+    let index = entries.partition_point(|entry| entry.tile_id <= tile_id);
+    let entry = *entries.get(index.checked_sub(1)?)?;
+    if entry.run_length == 0 || tile_id < entry.tile_id + entry.run_length {
+        Some(entry)
+    } else {
+        None
+    }
+}
+
+/// Open archive state shared between the tile source and the loader
+struct PMTilesArchive {
+    reader: Arc<dyn RangeReader>,
+    header: PMTilesHeader,
+    root: Vec<DirEntry>,
+    leaves: Mutex<LruCache<u64, Arc<Vec<DirEntry>>>>,
+}
+
+impl PMTilesArchive {
+    async fn read_directory(&self, offset: u64, length: u64) -> Result<Vec<DirEntry>> {
+        let data = self.reader.read_range(offset, length).await?;
+        parse_directory(&self.header.internal_compression.decompress(data)?)
+    }
+
+    async fn leaf_directory(&self, offset: u64, length: u64) -> Result<Arc<Vec<DirEntry>>> {
+        if let Ok(mut leaves) = self.leaves.lock() {
+            if let Some(entries) = leaves.get(&offset) {
+                return Ok(entries.clone());
+            }
+        }
+
+        let entries = Arc::new(
+            self.read_directory(self.header.leaf_dirs_offset + offset, length)
+                .await?,
+        );
+        if let Ok(mut leaves) = self.leaves.lock() {
+            leaves.put(offset, entries.clone());
+        }
+        Ok(entries)
+    }
+
+    async fn read_tile(&self, coord: TileCoord) -> Result<Option<Vec<u8>>> {
+        if coord.z < self.header.min_zoom || coord.z > self.header.max_zoom || !coord.is_valid() {
+            return Ok(None);
+        }
+
+        let id = tile_id(coord);
+        let mut entry = find_entry(&self.root, id);
+        for _ in 0..MAX_DIRECTORY_DEPTH {
+            match entry {
+                None => return Ok(None),
+                Some(found) if found.run_length > 0 => {
+                    let data = self
+                        .reader
+                        .read_range(self.header.tile_data_offset + found.offset, found.length)
+                        .await?;
+                    return Ok(Some(self.header.tile_compression.decompress(data)?));
+                }
+                Some(leaf) => {
+                    let entries = self.leaf_directory(leaf.offset, leaf.length).await?;
+                    entry = find_entry(&entries, id);
+                }
+            }
+        }
+        Err(format!("PMTiles directories nested too deeply for tile {:?}", coord).into())
+    }
+}
+
+impl TileDataSource for PMTilesArchive {
+    fn load_tile(&self, coord: TileCoord) -> TileDataFuture<'_> {
+        Box::pin(self.read_tile(coord))
+    }
+}
+
+/// Tile source backed by a PMTiles v3 archive
+pub struct PMTilesSource {
+    name: String,
+    archive: Arc<PMTilesArchive>,
+    metadata: serde_json::Value,
+}
+
+impl PMTilesSource {
+    /// Open an archive from any range reader, reading its header, root
+    /// directory and JSON metadata
+    pub async fn open(reader: Arc<dyn RangeReader>, name: impl Into<String>) -> Result<Self> {
+        let header = PMTilesHeader::parse(&reader.read_range(0, HEADER_LEN as u64).await?)?;
+
+        let root_data = reader
+            .read_range(header.root_dir_offset, header.root_dir_length)
+            .await?;
+        let metadata_data = if header.metadata_length > 0 {
+            reader
+                .read_range(header.metadata_offset, header.metadata_length)
+                .await?
+        } else {
+            Vec::new()
+        };
+        Self::from_sections(reader, name, header, root_data, metadata_data)
+    }
+
+    /// Open a local archive file, reading its header and root directory on
+    /// the calling thread
+    pub fn open_file(path: impl AsRef<Path>) -> Result<Self> {
+        let reader = FileRangeReader::open(path)?;
+        let name = reader.path().display().to_string();
+        let header = PMTilesHeader::parse(&reader.read_range_blocking(0, HEADER_LEN as u64)?)?;
+        let root_data =
+            reader.read_range_blocking(header.root_dir_offset, header.root_dir_length)?;
+        let metadata_data =
+            reader.read_range_blocking(header.metadata_offset, header.metadata_length)?;
+        Self::from_sections(Arc::new(reader), name, header, root_data, metadata_data)
+    }
+
+    /// Assemble a source from the raw root directory and metadata sections
+    fn from_sections(
+        reader: Arc<dyn RangeReader>,
+        name: impl Into<String>,
+        header: PMTilesHeader,
+        root_data: Vec<u8>,
+        metadata_data: Vec<u8>,
+    ) -> Result<Self> {
+        let root = parse_directory(&header.internal_compression.decompress(root_data)?)?;
+
+        let metadata = if metadata_data.is_empty() {
+            serde_json::Value::Null
+        } else {
+            let data = header.internal_compression.decompress(metadata_data)?;
+            serde_json::from_slice(&data).unwrap_or(serde_json::Value::Null)
+        };
+
+        Ok(Self {
+            name: name.into(),
+            archive: Arc::new(PMTilesArchive {
+                reader,
+                header,
+                root,
+                leaves: Mutex::new(LruCache::new(NonZeroUsize::new(LEAF_CACHE_SIZE).unwrap())),
+            }),
+            metadata,
+        })
+    }
+
+    pub fn header(&self) -> &PMTilesHeader {
+        &self.archive.header
+    }
+
+    /// The archive's JSON metadata (`Null` when it has none)
+    pub fn metadata(&self) -> &serde_json::Value {
+        &self.metadata
+    }
+
+    /// Whether the archive holds vector tiles rather than images
+    pub fn is_vector(&self) -> bool {
+        self.archive.header.tile_type == PMTilesTileType::Mvt
+    }
+
+    /// Copy the archive's zoom range, bounds and attribution into `options`
+    pub fn apply_to_options(&self, options: &mut TileLayerOptions) {
+        let header = &self.archive.header;
+        options.min_zoom = header.min_zoom;
+        options.max_zoom = header.max_zoom;
+        options.bounds = Some(header.bounds.clone());
+        if let Some(attribution) = self.metadata.get("attribution").and_then(|a| a.as_str()) {
+            options.attribution = Some(attribution.to_string());
+        }
+    }
+
+    /// Read a tile; `Ok(None)` when the archive has no tile at `coord`
+    pub async fn read_tile(&self, coord: TileCoord) -> Result<Option<Vec<u8>>> {
+        self.archive.read_tile(coord).await
+    }
+}
+
+impl TileSource for PMTilesSource {
+    fn url(&self, coord: TileCoord) -> String {
+        format!(
+            "pmtiles://{}/{}/{}/{}",
+            self.name, coord.z, coord.x, coord.y
+        )
+    }
+
+    fn has_tile(&self, coord: TileCoord) -> bool {
+        let header = &self.archive.header;
+        covers_tile(
+            coord,
+            Some(header.min_zoom),
+            Some(header.max_zoom),
+            Some(&header.bounds),
+        )
+    }
+
+    fn data_source(&self) -> Option<Arc<dyn TileDataSource>> {
+        Some(self.archive.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+        while value >= 0x80 {
+            out.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        out.push(value as u8);
+    }
+
+    fn encode_directory(entries: &[DirEntry]) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_varint(&mut out, entries.len() as u64);
+        let mut last_id = 0;
+        for entry in entries {
+            write_varint(&mut out, entry.tile_id - last_id);
+            last_id = entry.tile_id;
+        }
+        for entry in entries {
+            write_varint(&mut out, entry.run_length);
+        }
+        for entry in entries {
+            write_varint(&mut out, entry.length);
+        }
+        for entry in entries {
+            write_varint(&mut out, entry.offset + 1);
+        }
+        out
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn entry(tile_id: u64, offset: u64, length: u64, run_length: u64) -> DirEntry {
+        DirEntry {
+            tile_id,
+            offset,
+            length,
+            run_length,
+        }
+    }
+
+    /// Archive with a gzip-compressed root directory pointing at one tile,
+    /// one run of four identical tiles and a leaf directory
+    fn build_archive() -> Vec<u8> {
+        let tile_a = gzip(b"tile-a");
+        let tile_b = gzip(b"tile-b");
+        let tile_c = gzip(b"tile-c");
+        let tile_data = [tile_a.clone(), tile_b.clone(), tile_c.clone()].concat();
+
+        let leaf = gzip(&encode_directory(&[entry(
+            tile_id(TileCoord::new(0, 0, 2)),
+            (tile_a.len() + tile_b.len()) as u64,
+            tile_c.len() as u64,
+            1,
+        )]));
+        let root = gzip(&encode_directory(&[
+            entry(0, 0, tile_a.len() as u64, 1),
+            entry(1, tile_a.len() as u64, tile_b.len() as u64, 4),
+            entry(5, 0, leaf.len() as u64, 0),
+        ]));
+        let metadata = gzip(br#"{"attribution":"(c) Test"}"#);
+
+        let root_offset = HEADER_LEN as u64;
+        let metadata_offset = root_offset + root.len() as u64;
+        let leaf_offset = metadata_offset + metadata.len() as u64;
+        let data_offset = leaf_offset + leaf.len() as u64;
+
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        header.extend_from_slice(b"PMTiles");
+        header.push(3);
+        for value in [
+            root_offset,
+            root.len() as u64,
+            metadata_offset,
+            metadata.len() as u64,
+            leaf_offset,
+            leaf.len() as u64,
+            data_offset,
+            tile_data.len() as u64,
+            6,
+            3,
+            3,
+        ] {
+            header.extend_from_slice(&value.to_le_bytes());
+        }
+        header.extend_from_slice(&[1, 2, 2, 2, 0, 2]);
+        for degrees in [-180.0f64, -85.0, 180.0, 85.0] {
+            header.extend_from_slice(&((degrees * 1e7) as i32).to_le_bytes());
+        }
+        header.push(1);
+        header.extend_from_slice(&0i32.to_le_bytes());
+        header.extend_from_slice(&0i32.to_le_bytes());
+        assert_eq!(header.len(), HEADER_LEN);
+
+        [header, root, metadata, leaf, tile_data].concat()
+    }
+
+    #[test]
+    fn test_tile_id_follows_hilbert_order() {
+        assert_eq!(tile_id(TileCoord::new(0, 0, 0)), 0);
+        assert_eq!(tile_id(TileCoord::new(0, 0, 1)), 1);
+        assert_eq!(tile_id(TileCoord::new(0, 1, 1)), 2);
+        assert_eq!(tile_id(TileCoord::new(1, 1, 1)), 3);
+        assert_eq!(tile_id(TileCoord::new(1, 0, 1)), 4);
+        assert_eq!(tile_id(TileCoord::new(0, 0, 2)), 5);
+        assert_eq!(tile_id(TileCoord::new(3, 0, 2)), 20);
+    }
+
+    #[test]
+    fn test_directory_round_trip_and_lookup() {
+        let entries = vec![entry(0, 0, 10, 1), entry(1, 10, 5, 4), entry(9, 0, 20, 0)];
+        let parsed = parse_directory(&encode_directory(&entries)).unwrap();
+        assert_eq!(parsed, entries);
+
+        assert_eq!(find_entry(&parsed, 0), Some(entries[0]));
+        // Run-length entries cover consecutive IDs
+        assert_eq!(find_entry(&parsed, 4), Some(entries[1]));
+        assert_eq!(find_entry(&parsed, 6), None);
+        // Leaf pointers cover everything after them
+        assert_eq!(find_entry(&parsed, 100), Some(entries[2]));
+    }
+
+    #[test]
+    fn test_read_tiles_from_archive() {
+        let archive: Arc<dyn RangeReader> = Arc::new(build_archive());
+        let source = futures::executor::block_on(PMTilesSource::open(archive, "test")).unwrap();
+
+        let header = source.header();
+        assert_eq!(header.tile_type, PMTilesTileType::Png);
+        assert_eq!(header.tile_compression, PMTilesCompression::Gzip);
+        assert_eq!((header.min_zoom, header.max_zoom), (0, 2));
+
+        let read = |coord| futures::executor::block_on(source.read_tile(coord)).unwrap();
+        assert_eq!(read(TileCoord::new(0, 0, 0)), Some(b"tile-a".to_vec()));
+        assert_eq!(read(TileCoord::new(1, 0, 1)), Some(b"tile-b".to_vec()));
+        assert_eq!(read(TileCoord::new(0, 0, 2)), Some(b"tile-c".to_vec()));
+        assert_eq!(read(TileCoord::new(1, 0, 2)), None);
+        assert_eq!(read(TileCoord::new(0, 0, 3)), None);
+
+        let mut options = TileLayerOptions::default();
+        source.apply_to_options(&mut options);
+        assert_eq!(options.max_zoom, 2);
+        assert_eq!(options.attribution.as_deref(), Some("(c) Test"));
+    }
+
+    #[test]
+    fn test_open_file_rejects_other_formats() {
+        let path =
+            std::env::temp_dir().join(format!("maplet_pmtiles_{}.pmtiles", std::process::id()));
+        std::fs::write(&path, build_archive()).unwrap();
+        let source = PMTilesSource::open_file(&path).unwrap();
+        assert!(source.has_tile(TileCoord::new(0, 0, 1)));
+
+        std::fs::write(&path, b"definitely not an archive").unwrap();
+        assert!(PMTilesSource::open_file(&path).is_err());
+        let _ = std::fs::remove_file(&path);
+    }
+}
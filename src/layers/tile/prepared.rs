@@ -0,0 +1,237 @@
+//! Tile loading for layers that prepare tiles before drawing them
+//!
+//! Hillshade and vector tile layers turn each downloaded payload into their
+//! own form, heights or styled features, which takes too long to do on the
+//! render thread for a screenful of tiles. [`PreparedTiles`] loads tiles
+//! through the regular tile loader, prepares the payloads on blocking
+//! workers, and remembers tiles the source lacks or that failed to load so
+//! they are not requested again every frame.
+
+use super::{TileLoader, TileLoaderConfig, TilePriority, TileSource, TileState};
+use crate::{
+    background::tasks::AsyncExecutor,
+    core::geo::TileCoord,
+    prelude::{Arc, HashMap, HashSet},
+    traits::RetryLogic,
+    Result,
+};
+use crossbeam_channel::{unbounded, Receiver, Sender};
+
+/// Work on tiles run on blocking workers, collected on a later frame
+pub(crate) struct TileJobs<T> {
+    /// Bumped on cancel, so results of older jobs can be told apart
+    generation: u64,
+    running: HashSet<TileCoord>,
+    sender: Sender<(u64, TileCoord, Result<T>)>,
+    receiver: Receiver<(u64, TileCoord, Result<T>)>,
+}
+
+impl<T: Send + 'static> TileJobs<T> {
+    pub fn new() -> Self {
+        let (sender, receiver) = unbounded();
+        Self {
+            generation: 0,
+            running: HashSet::default(),
+            sender,
+            receiver,
+        }
+    }
+
+    /// Jobs spawned since the last [`cancel`](Self::cancel) share a
+    /// generation
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn is_running(&self, coord: &TileCoord) -> bool {
+        self.running.contains(coord)
+    }
+
+    /// Run `job` for `coord` on a blocking worker, unless one is already
+    /// running for it
+    pub fn spawn(&mut self, coord: TileCoord, job: impl FnOnce() -> Result<T> + Send + 'static) {
+        if !self.running.insert(coord) {
+            return;
+        }
+        let (sender, generation) = (self.sender.clone(), self.generation);
+        crate::runtime::spawn(async move {
+            let result = AsyncExecutor::execute_blocking(job).await;
+            let _ = sender.send((generation, coord, result));
+        });
+    }
+
+    /// Forget the jobs still running; their results are dropped when they
+    /// arrive
+    pub fn cancel(&mut self) {
+        self.generation += 1;
+        self.running.clear();
+    }
+
+    /// Results of the jobs finished since the last call
+    pub fn finished(&mut self) -> Vec<(TileCoord, Result<T>)> {
+        let mut finished = Vec::new();
+        for (generation, coord, result) in self.receiver.try_iter() {
+            if generation == self.generation {
+                self.running.remove(&coord);
+                finished.push((coord, result));
+            }
+        }
+        finished
+    }
+}
+
+/// Loading side of a layer drawing tiles it prepares itself
+///
+/// The layer keeps the prepared tiles; calls that need to know which tiles
+/// are loaded take an `is_loaded` check against its store.
+pub(crate) struct PreparedTiles<T> {
+    tile_source: Arc<dyn TileSource>,
+    tile_loader: TileLoader,
+    preparing: TileJobs<T>,
+    /// Tiles the source does not have or that failed to prepare
+    missing: HashSet<TileCoord>,
+    /// Tiles whose download failed, waiting for a retry
+    failed: HashMap<TileCoord, TileState>,
+}
+
+impl<T: Send + 'static> PreparedTiles<T> {
+    pub fn new(tile_source: Arc<dyn TileSource>) -> Self {
+        Self {
+            tile_source,
+            tile_loader: TileLoader::new(TileLoaderConfig::default()),
+            preparing: TileJobs::new(),
+            missing: HashSet::default(),
+            failed: HashMap::default(),
+        }
+    }
+
+    /// Tiles prepared since the last call
+    ///
+    /// Payloads downloaded since then are handed to `prepare` on a blocking
+    /// worker, to be returned by a later call. Tiles the source lacks, or
+    /// whose payload `prepare` rejects, are marked missing.
+    pub fn receive(
+        &mut self,
+        prepare: impl FnOnce(TileCoord, Vec<u8>) -> Result<T> + Clone + Send + 'static,
+    ) -> Vec<(TileCoord, T)> {
+        for result in self.tile_loader.try_recv_results() {
+            let coord = result.coord;
+            match result.data {
+                Ok(None) => self.mark_missing(coord),
+                Ok(Some(data)) => {
+                    self.failed.remove(&coord);
+                    let prepare = prepare.clone();
+                    self.preparing.spawn(coord, move || prepare(coord, data));
+                }
+                Err(e) => {
+                    #[cfg(feature = "debug")]
+                    log::warn!("Failed to load tile {:?}: {}", coord, e);
+                    self.failed
+                        .entry(coord)
+                        .or_insert_with(|| TileState::new(coord))
+                        .mark_error(e.to_string());
+                }
+            }
+        }
+
+        let mut prepared = Vec::new();
+        for (coord, result) in self.preparing.finished() {
+            match result {
+                Ok(tile) => {
+                    self.mark_loaded(coord);
+                    prepared.push((coord, tile));
+                }
+                Err(_e) => {
+                    #[cfg(feature = "debug")]
+                    log::warn!("Failed to prepare tile {:?}: {}", coord, _e);
+                    self.mark_missing(coord);
+                }
+            }
+        }
+        prepared
+    }
+
+    /// Record that there is nothing to draw for `coord`
+    pub fn mark_missing(&mut self, coord: TileCoord) {
+        self.failed.remove(&coord);
+        self.missing.insert(coord);
+    }
+
+    /// Record that the layer holds a prepared tile for `coord`
+    pub fn mark_loaded(&mut self, coord: TileCoord) {
+        self.failed.remove(&coord);
+        self.missing.remove(&coord);
+    }
+
+    /// Tiles whose download failed, whether or not they will be retried
+    pub fn failed_tiles(&self) -> Vec<TileCoord> {
+        self.failed.keys().copied().collect()
+    }
+
+    /// Queue the tiles of `visible` that are not loaded
+    ///
+    /// Tiles the source lacks and tiles being prepared are skipped, and
+    /// failed tiles wait for a retry as the loader's configuration allows.
+    pub fn request(
+        &mut self,
+        visible: &[TileCoord],
+        is_loaded: impl Fn(&TileCoord) -> bool,
+    ) -> Result<()> {
+        let config = self.tile_loader.config();
+        let (max_retries, retry_delay) = (
+            config.max_retries as u32,
+            config.retry_delay.as_millis() as u64,
+        );
+
+        let mut to_load = Vec::new();
+        for coord in visible {
+            if is_loaded(coord) || self.missing.contains(coord) || self.preparing.is_running(coord)
+            {
+                continue;
+            }
+            if let Some(failed) = self.failed.get_mut(coord) {
+                if failed.loading || !failed.should_retry(max_retries, retry_delay, false) {
+                    continue;
+                }
+                failed.loading = true;
+            }
+            to_load.push(*coord);
+        }
+
+        if !to_load.is_empty() {
+            self.tile_loader.queue_tiles_batch(
+                self.tile_source.as_ref(),
+                to_load,
+                TilePriority::Visible,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Loaded tiles to draw for `visible`: each tile or its nearest loaded
+/// ancestor down to `min_zoom`, once each, ancestors first so they go
+/// beneath exact tiles
+pub(crate) fn drawable_tiles(
+    visible: &[TileCoord],
+    min_zoom: u8,
+    is_loaded: impl Fn(&TileCoord) -> bool,
+) -> Vec<TileCoord> {
+    let mut seen = HashSet::default();
+    let mut tiles: Vec<TileCoord> = visible
+        .iter()
+        .filter_map(|coord| {
+            let mut current = Some(*coord);
+            while let Some(candidate) = current {
+                if is_loaded(&candidate) {
+                    return Some(candidate);
+                }
+                current = candidate.parent().filter(|parent| parent.z >= min_zoom);
+            }
+            None
+        })
+        .filter(|coord| seen.insert(*coord))
+        .collect();
+    tiles.sort_by_key(|coord| coord.z);
+    tiles
+}
@@ -0,0 +1,375 @@
+//! Per-host request rate limiting for tile downloads
+//!
+//! Public tile servers such as OpenStreetMap's block clients that request
+//! too many tiles, so every download from every loader goes through one
+//! shared [`RateLimiter`]. Each host gets a token bucket refilled at
+//! `requests_per_second` and holding up to `burst` requests, and a host that
+//! answers `429 Too Many Requests` or `503 Service Unavailable` with a
+//! `Retry-After` header gets no requests until that time has passed, for at
+//! most two minutes.
+
+use crate::prelude::{Duration, HashMap, Instant, Mutex};
+use once_cell::sync::Lazy;
+use std::time::SystemTime;
+
+/// Host of OpenStreetMap's public tile servers, limited by default
+pub const OPENSTREETMAP_HOST: &str = "tile.openstreetmap.org";
+
+/// Back-off after a `429 Too Many Requests` without a `Retry-After` header
+pub(crate) const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(5);
+
+/// Longest back-off honoured from a `Retry-After` header, so a misbehaving
+/// server cannot stall a host's tiles for hours
+pub(crate) const MAX_RETRY_AFTER: Duration = Duration::from_secs(120);
+
+static RATE_LIMITER: Lazy<RateLimiter> = Lazy::new(RateLimiter::default);
+
+/// The rate limiter shared by all tile downloads
+pub fn rate_limiter() -> &'static RateLimiter {
+    &RATE_LIMITER
+}
+
+/// Sustained request rate and burst size allowed for a host
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub requests_per_second: f64,
+    /// Requests that may be sent at once after a quiet period
+    pub burst: u32,
+}
+
+impl RateLimit {
+    pub fn new(requests_per_second: f64, burst: u32) -> Self {
+        Self {
+            requests_per_second: requests_per_second.max(f64::MIN_POSITIVE),
+            burst: burst.max(1),
+        }
+    }
+
+    /// A conservative limit for OpenStreetMap's tile usage policy, enough
+    /// for interactive browsing but not for bulk downloading
+    pub fn openstreetmap() -> Self {
+        Self::new(8.0, 24)
+    }
+}
+
+/// Token bucket of one host
+#[derive(Debug, Clone)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+    retry_at: Option<Instant>,
+}
+
+impl Bucket {
+    fn new(limit: Option<RateLimit>, now: Instant) -> Self {
+        Self {
+            tokens: limit.map_or(0.0, |limit| limit.burst as f64),
+            refilled_at: now,
+            retry_at: None,
+        }
+    }
+
+    fn refill(&mut self, limit: RateLimit, now: Instant) {
+        let elapsed = now
+            .saturating_duration_since(self.refilled_at)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.requests_per_second).min(limit.burst as f64);
+        self.refilled_at = now;
+    }
+
+    /// Time until a request may be sent, refilling first
+    fn delay(&mut self, limit: Option<RateLimit>, now: Instant) -> Option<Duration> {
+        if let Some(retry_at) = self.retry_at {
+            if retry_at > now {
+                return Some(retry_at - now);
+            }
+            self.retry_at = None;
+        }
+        let limit = limit?;
+        self.refill(limit, now);
+        (self.tokens < 1.0)
+            .then(|| Duration::from_secs_f64((1.0 - self.tokens) / limit.requests_per_second))
+    }
+}
+
+#[derive(Debug, Default)]
+struct LimiterState {
+    default_limit: Option<RateLimit>,
+    host_limits: HashMap<String, RateLimit>,
+    buckets: HashMap<String, Bucket>,
+}
+
+impl LimiterState {
+    /// Bucket key and limit for `host`
+    ///
+    /// A host limit also covers the host's subdomains, which share its
+    /// bucket: `a.tile.openstreetmap.org` and `b.tile.openstreetmap.org`
+    /// count against the same budget.
+    fn resolve(&self, host: &str) -> (String, Option<RateLimit>) {
+        let mut domain = host;
+        loop {
+            if let Some(limit) = self.host_limits.get(domain) {
+                return (domain.to_string(), Some(*limit));
+            }
+            match domain.split_once('.') {
+                Some((_, parent)) if parent.contains('.') => domain = parent,
+                _ => return (host.to_string(), self.default_limit),
+            }
+        }
+    }
+
+    fn bucket(&mut self, host: &str, now: Instant) -> (&mut Bucket, Option<RateLimit>) {
+        let (key, limit) = self.resolve(host);
+        let bucket = self
+            .buckets
+            .entry(key)
+            .or_insert_with(|| Bucket::new(limit, now));
+        (bucket, limit)
+    }
+}
+
+/// Token-bucket rate limits per host, with `Retry-After` back-off
+///
+/// Hosts without a limit of their own use the default limit, which is off
+/// unless set with [`RateLimiter::set_default_limit`]. OpenStreetMap's tile
+/// servers are limited to [`RateLimit::openstreetmap`] out of the box.
+#[derive(Debug)]
+pub struct RateLimiter {
+    state: Mutex<LimiterState>,
+}
+
+impl RateLimiter {
+    /// A limiter without any limits
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(LimiterState::default()),
+        }
+    }
+
+    /// Limit requests to `host` and its subdomains, or lift the limit with
+    /// `None`
+    pub fn set_host_limit(&self, host: impl Into<String>, limit: Option<RateLimit>) {
+        if let Ok(mut state) = self.state.lock() {
+            let host = host.into().to_ascii_lowercase();
+            match limit {
+                Some(limit) => state.host_limits.insert(host, limit),
+                None => state.host_limits.remove(&host),
+            };
+            // Buckets are rebuilt with the new limit on next use
+            state.buckets.retain(|_, bucket| bucket.retry_at.is_some());
+        }
+    }
+
+    /// Limit requests to hosts without a limit of their own
+    pub fn set_default_limit(&self, limit: Option<RateLimit>) {
+        if let Ok(mut state) = self.state.lock() {
+            state.default_limit = limit;
+            state.buckets.retain(|_, bucket| bucket.retry_at.is_some());
+        }
+    }
+
+    /// Limit that applies to `host`
+    pub fn host_limit(&self, host: &str) -> Option<RateLimit> {
+        let host = host.to_ascii_lowercase();
+        self.state
+            .lock()
+            .ok()
+            .and_then(|state| state.resolve(&host).1)
+    }
+
+    /// Time until a request to `url` may be sent; `None` when it may be sent
+    /// now
+    pub fn delay(&self, url: &str) -> Option<Duration> {
+        self.delay_at(url, Instant::now())
+    }
+
+    fn delay_at(&self, url: &str, now: Instant) -> Option<Duration> {
+        let host = host_of(url)?;
+        let mut state = self.state.lock().ok()?;
+        let (bucket, limit) = state.bucket(&host, now);
+        bucket.delay(limit, now)
+    }
+
+    /// Take a request slot for `url` if one is free
+    pub fn try_acquire(&self, url: &str) -> bool {
+        self.try_acquire_at(url, Instant::now())
+    }
+
+    fn try_acquire_at(&self, url: &str, now: Instant) -> bool {
+        let Some(host) = host_of(url) else {
+            return true;
+        };
+        let Ok(mut state) = self.state.lock() else {
+            return true;
+        };
+        let (bucket, limit) = state.bucket(&host, now);
+        if bucket.delay(limit, now).is_some() {
+            return false;
+        }
+        if limit.is_some() {
+            bucket.tokens -= 1.0;
+        }
+        true
+    }
+
+    /// Wait for a request slot for `url`
+    pub async fn acquire(&self, url: &str) {
+        while !self.try_acquire(url) {
+            let wait = self.delay(url).unwrap_or(Duration::from_millis(10));
+            crate::runtime::async_utils::async_delay(wait).await;
+        }
+    }
+
+    /// Hold back requests to the host of `url` for `wait`, capped at two
+    /// minutes
+    pub fn record_retry_after(&self, url: &str, wait: Duration) {
+        let Some(host) = host_of(url) else {
+            return;
+        };
+        let now = Instant::now();
+        if let Ok(mut state) = self.state.lock() {
+            let (bucket, _) = state.bucket(&host, now);
+            let retry_at = now + wait.min(MAX_RETRY_AFTER);
+            bucket.retry_at = Some(bucket.retry_at.map_or(retry_at, |at| at.max(retry_at)));
+        }
+    }
+
+    /// Time left before the host of `url` accepts requests again, after it
+    /// answered with `Retry-After`
+    pub fn retry_after(&self, url: &str) -> Option<Duration> {
+        let host = host_of(url)?;
+        let now = Instant::now();
+        let mut state = self.state.lock().ok()?;
+        let (bucket, _) = state.bucket(&host, now);
+        bucket
+            .retry_at
+            .filter(|retry_at| *retry_at > now)
+            .map(|retry_at| retry_at - now)
+    }
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        let limiter = Self::new();
+        limiter.set_host_limit(OPENSTREETMAP_HOST, Some(RateLimit::openstreetmap()));
+        limiter
+    }
+}
+
+/// Lower-case host of `url`; `None` for URLs without one
+fn host_of(url: &str) -> Option<String> {
+    let url = reqwest::Url::parse(url).ok()?;
+    url.host_str().map(str::to_ascii_lowercase)
+}
+
+/// Parse a `Retry-After` value, either seconds or an HTTP date
+pub(crate) fn parse_retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let at = httpdate::parse_http_date(value).ok()?;
+    Some(at.duration_since(now).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_burst_then_steady_rate_shared_by_subdomains() {
+        let limiter = RateLimiter::new();
+        limiter.set_host_limit("tiles.example.com", Some(RateLimit::new(10.0, 3)));
+        let urls = [
+            "https://a.tiles.example.com/1/0/0.png",
+            "https://b.tiles.example.com/1/0/1.png",
+            "https://tiles.example.com/1/1/0.png",
+        ];
+        for url in urls {
+            assert!(limiter.try_acquire(url));
+        }
+        // The burst is spent across all subdomains
+        assert!(!limiter.try_acquire(urls[0]));
+        let delay = limiter.delay(urls[1]).unwrap();
+        assert!(delay <= Duration::from_millis(100), "{:?}", delay);
+
+        // Other hosts are not limited
+        assert!(limiter
+            .delay("https://other.example.com/0/0/0.png")
+            .is_none());
+        assert!((0..100).all(|_| limiter.try_acquire("https://other.example.com/0/0/0.png")));
+
+        std::thread::sleep(Duration::from_millis(120));
+        assert!(limiter.try_acquire(urls[2]));
+
+        // The default limit covers hosts without their own
+        limiter.set_default_limit(Some(RateLimit::new(1.0, 1)));
+        assert!(limiter.try_acquire("https://other.example.com/0/0/0.png"));
+        assert!(!limiter.try_acquire("https://other.example.com/0/0/0.png"));
+        assert_eq!(
+            limiter.host_limit("x.tiles.example.com"),
+            Some(RateLimit::new(10.0, 3))
+        );
+        assert_eq!(
+            RateLimiter::default().host_limit("c.tile.openstreetmap.org"),
+            Some(RateLimit::openstreetmap())
+        );
+    }
+
+    #[test]
+    fn test_requests_after_the_burst_are_spaced_by_the_rate() {
+        let limiter = RateLimiter::new();
+        limiter.set_host_limit("tiles.example.com", Some(RateLimit::new(20.0, 4)));
+        let url = "https://tiles.example.com/1/0/0.png";
+        let start = Instant::now();
+        assert!((0..4).all(|_| limiter.try_acquire_at(url, start)));
+        assert!(!limiter.try_acquire_at(url, start));
+
+        // One request every 50ms once the burst is spent
+        for step in 1..=10 {
+            let due = start + Duration::from_millis(50 * step);
+            let early = due - Duration::from_millis(5);
+            assert!(!limiter.try_acquire_at(url, early), "step {}", step);
+            let delay = limiter.delay_at(url, early).unwrap();
+            assert!(delay <= Duration::from_millis(5), "{:?}", delay);
+            let due = due + Duration::from_micros(10);
+            assert!(limiter.try_acquire_at(url, due), "step {}", step);
+        }
+    }
+
+    #[test]
+    fn test_retry_after_blocks_the_host() {
+        let limiter = RateLimiter::new();
+        let url = "http://127.0.0.1:8080/3/1/2.png";
+        assert!(limiter.try_acquire(url));
+        limiter.record_retry_after(url, Duration::from_secs(30));
+        assert!(!limiter.try_acquire(url));
+        assert!(limiter.retry_after(url).unwrap() > Duration::from_secs(29));
+        assert!(limiter.delay("http://127.0.0.1:8080/other").is_some());
+        assert!(limiter.delay("http://127.0.0.2:8080/3/1/2.png").is_none());
+
+        // Excessive waits are capped
+        limiter.record_retry_after(url, Duration::from_secs(24 * 3600));
+        assert!(limiter.retry_after(url).unwrap() <= MAX_RETRY_AFTER);
+
+        let now = SystemTime::now();
+        assert_eq!(
+            parse_retry_after(" 120 ", now),
+            Some(Duration::from_secs(120))
+        );
+        let later = httpdate::fmt_http_date(now + Duration::from_secs(90));
+        let parsed = parse_retry_after(&later, now).unwrap();
+        assert!(parsed > Duration::from_secs(88) && parsed <= Duration::from_secs(90));
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+
+    #[test]
+    fn test_retry_after_lowers_the_concurrency_limit() {
+        let mut metrics = crate::layers::tile::loader::NetworkMetrics::default();
+        assert_eq!(metrics.get_concurrency_limit(128), 128);
+        metrics.record_rate_limited(Duration::from_secs(60));
+        assert!(metrics.is_rate_limited());
+        assert_eq!(metrics.get_concurrency_limit(128), 32);
+        assert_eq!(metrics.get_concurrency_limit(2), 1);
+    }
+}
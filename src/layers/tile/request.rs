@@ -0,0 +1,330 @@
+//! Per-source HTTP request settings
+//!
+//! A tile source describes the extra headers, User-Agent and credentials its
+//! requests need with [`RequestOptions`]. The loader applies them when each
+//! request is sent, so a token replaced or refreshed in the meantime is used
+//! by tiles that were already queued.
+
+use crate::prelude::{Arc, Duration, Future, Instant, Pin};
+use crate::Result;
+use std::sync::RwLock;
+
+/// User-Agent sent when a source does not set its own
+pub const DEFAULT_USER_AGENT: &str = concat!("maplet/", env!("CARGO_PKG_VERSION"));
+
+/// A token handed out by a refresh callback
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenGrant {
+    pub token: String,
+    /// How long the token stays valid; `None` if it does not expire
+    pub expires_in: Option<Duration>,
+}
+
+impl TokenGrant {
+    pub fn new(token: impl Into<String>) -> Self {
+        Self {
+            token: token.into(),
+            expires_in: None,
+        }
+    }
+
+    pub fn with_expires_in(mut self, expires_in: Duration) -> Self {
+        self.expires_in = Some(expires_in);
+        self
+    }
+}
+
+/// Future returned by a token refresh callback
+pub type TokenFuture = Pin<Box<dyn Future<Output = Result<TokenGrant>> + Send>>;
+
+type Refresher = Arc<dyn Fn() -> TokenFuture + Send + Sync>;
+
+struct TokenState {
+    current: RwLock<Option<(String, Option<Instant>)>>,
+    refresher: Option<Refresher>,
+    /// Held while refreshing so concurrent requests share one refresh
+    refreshing: futures::lock::Mutex<()>,
+}
+
+/// Shared, replaceable credential attached to a source's requests
+///
+/// Clones share the same token, so the application can keep a clone and
+/// call [`AuthToken::set`] when its credentials change. A token created with
+/// [`AuthToken::refreshable`] fetches a new value itself when it is missing,
+/// has expired, or the server answers `401 Unauthorized`.
+#[derive(Clone)]
+pub struct AuthToken {
+    state: Arc<TokenState>,
+}
+
+impl std::fmt::Debug for AuthToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AuthToken")
+            .field("refreshable", &self.state.refresher.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl AuthToken {
+    /// Token with a fixed value until replaced with [`AuthToken::set`]
+    pub fn new(token: impl Into<String>) -> Self {
+        let auth = Self::with_refresher(None);
+        auth.set(token);
+        auth
+    }
+
+    /// Token obtained from `refresh`, called on first use and whenever the
+    /// token expires or is rejected
+    pub fn refreshable<F, Fut>(refresh: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<TokenGrant>> + Send + 'static,
+    {
+        Self::with_refresher(Some(Arc::new(move || Box::pin(refresh()) as TokenFuture)))
+    }
+
+    fn with_refresher(refresher: Option<Refresher>) -> Self {
+        Self {
+            state: Arc::new(TokenState {
+                current: RwLock::new(None),
+                refresher,
+                refreshing: futures::lock::Mutex::new(()),
+            }),
+        }
+    }
+
+    /// Replace the token; it does not expire
+    pub fn set(&self, token: impl Into<String>) {
+        self.store(token.into(), None);
+    }
+
+    /// Replace the token, valid for `expires_in`
+    pub fn set_with_expiry(&self, token: impl Into<String>, expires_in: Duration) {
+        self.store(token.into(), Some(Instant::now() + expires_in));
+    }
+
+    /// Mark the current token as no longer valid
+    pub fn invalidate(&self) {
+        if let Ok(mut current) = self.state.current.write() {
+            *current = None;
+        }
+    }
+
+    /// Current token, if one is set and has not expired
+    pub fn current(&self) -> Option<String> {
+        let current = self.state.current.read().ok()?;
+        match &*current {
+            Some((token, expires_at)) if expires_at.is_none_or(|at| Instant::now() < at) => {
+                Some(token.clone())
+            }
+            _ => None,
+        }
+    }
+
+    pub fn is_refreshable(&self) -> bool {
+        self.state.refresher.is_some()
+    }
+
+    fn store(&self, token: String, expires_at: Option<Instant>) {
+        if let Ok(mut current) = self.state.current.write() {
+            *current = Some((token, expires_at));
+        }
+    }
+
+    /// The token to send, refreshing it first when needed
+    pub(crate) async fn resolve(&self) -> Result<String> {
+        if let Some(token) = self.current() {
+            return Ok(token);
+        }
+        let Some(refresher) = &self.state.refresher else {
+            return Err("Authentication token is missing or has expired".into());
+        };
+
+        let _guard = self.state.refreshing.lock().await;
+        // Another request may have refreshed while this one waited
+        if let Some(token) = self.current() {
+            return Ok(token);
+        }
+        let grant = refresher().await?;
+        self.store(
+            grant.token.clone(),
+            grant
+                .expires_in
+                .map(|expires_in| Instant::now() + expires_in),
+        );
+        Ok(grant.token)
+    }
+}
+
+/// Where a token goes in the request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenPlacement {
+    /// `Authorization: Bearer <token>`
+    Bearer,
+    /// A named header carrying the bare token, e.g. `X-Api-Key`
+    Header(String),
+    /// A URL query parameter, e.g. `access_token`
+    Query(String),
+}
+
+/// Headers, User-Agent and credentials sent with a source's requests
+#[derive(Debug, Clone, Default)]
+pub struct RequestOptions {
+    headers: Vec<(String, String)>,
+    user_agent: Option<String>,
+    token: Option<(AuthToken, TokenPlacement)>,
+}
+
+impl RequestOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Send `name: value` with every request, replacing an earlier value
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        let name = name.into();
+        self.headers
+            .retain(|(existing, _)| !existing.eq_ignore_ascii_case(&name));
+        self.headers.push((name, value.into()));
+        self
+    }
+
+    /// Identify the application to the tile server, as many usage policies
+    /// (including OpenStreetMap's) require
+    pub fn with_user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = Some(user_agent.into());
+        self
+    }
+
+    pub fn with_referer(self, referer: impl Into<String>) -> Self {
+        self.with_header("Referer", referer)
+    }
+
+    /// Attach `token` to every request at `placement`
+    pub fn with_token(mut self, token: AuthToken, placement: TokenPlacement) -> Self {
+        self.token = Some((token, placement));
+        self
+    }
+
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+
+    /// User-Agent sent with requests, falling back to [`DEFAULT_USER_AGENT`]
+    pub fn user_agent(&self) -> &str {
+        self.user_agent.as_deref().unwrap_or(DEFAULT_USER_AGENT)
+    }
+
+    pub fn token(&self) -> Option<&AuthToken> {
+        self.token.as_ref().map(|(token, _)| token)
+    }
+
+    /// Build a GET request for `url` carrying these settings
+    pub(crate) async fn get(
+        &self,
+        client: &reqwest::Client,
+        url: &str,
+    ) -> Result<reqwest::RequestBuilder> {
+        let mut url = url.to_string();
+        let mut auth_header = None;
+        if let Some((token, placement)) = &self.token {
+            let value = token.resolve().await?;
+            match placement {
+                TokenPlacement::Bearer => {
+                    auth_header = Some(("Authorization".to_string(), format!("Bearer {}", value)))
+                }
+                TokenPlacement::Header(name) => auth_header = Some((name.clone(), value)),
+                TokenPlacement::Query(param) => {
+                    url = super::wms::append_query(&url, &[(param, value)]);
+                }
+            }
+        }
+
+        let mut request = client
+            .get(url)
+            .header(reqwest::header::USER_AGENT, self.user_agent());
+        for (name, value) in self.headers.iter().chain(auth_header.as_ref()) {
+            request = request.header(name.as_str(), value.as_str());
+        }
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn url_of(request: reqwest::RequestBuilder) -> (String, reqwest::header::HeaderMap) {
+        let request = request.build().unwrap();
+        (request.url().to_string(), request.headers().clone())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_headers_user_agent_and_token_placement() {
+        let client = reqwest::Client::new();
+        let token = AuthToken::new("secret");
+        let options = RequestOptions::new()
+            .with_header("X-Client", "one")
+            .with_header("x-client", "two")
+            .with_referer("https://app.example.com/")
+            .with_token(token.clone(), TokenPlacement::Bearer);
+
+        let (url, headers) = url_of(
+            options
+                .get(&client, "https://t.example.com/1/2/3.png")
+                .await
+                .unwrap(),
+        );
+        assert_eq!(url, "https://t.example.com/1/2/3.png");
+        assert_eq!(headers["x-client"], "two");
+        assert_eq!(headers["referer"], "https://app.example.com/");
+        assert_eq!(headers["user-agent"], DEFAULT_USER_AGENT);
+        assert_eq!(headers["authorization"], "Bearer secret");
+
+        // Replacing the token affects later requests
+        token.set("rotated");
+        let options = options
+            .with_user_agent("TrailApp/2.1 (ops@example.com)")
+            .with_token(token, TokenPlacement::Query("access_token".to_string()));
+        let (url, headers) = url_of(
+            options
+                .get(&client, "https://t.example.com/a?fmt=png")
+                .await
+                .unwrap(),
+        );
+        assert_eq!(url, "https://t.example.com/a?fmt=png&access_token=rotated");
+        assert_eq!(headers["user-agent"], "TrailApp/2.1 (ops@example.com)");
+        assert!(headers.get("authorization").is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_refreshable_token_expires_and_refreshes_once() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let token = AuthToken::refreshable(move || {
+            let call = counter.fetch_add(1, Ordering::SeqCst) + 1;
+            async move {
+                Ok(TokenGrant::new(format!("token-{}", call))
+                    .with_expires_in(Duration::from_millis(200)))
+            }
+        });
+        assert_eq!(token.current(), None);
+
+        // Concurrent requests share a single refresh
+        let results = futures::future::join_all((0..8).map(|_| token.resolve())).await;
+        assert!(results.iter().all(|r| r.as_deref().ok() == Some("token-1")));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        assert_eq!(token.current(), None);
+        assert_eq!(token.resolve().await.unwrap(), "token-2");
+
+        token.invalidate();
+        assert_eq!(token.resolve().await.unwrap(), "token-3");
+
+        let fixed = AuthToken::new("fixed");
+        fixed.invalidate();
+        assert!(fixed.resolve().await.is_err());
+    }
+}
@@ -1,22 +1,193 @@
-use crate::core::geo::TileCoord;
+use super::failover::FailoverTileSource;
+use super::request::RequestOptions;
+use super::types::TileLayerOptions;
+use crate::core::geo::{LatLngBounds, TileCoord};
+use crate::prelude::{Arc, Future, HashMap, Pin};
+use crate::traits::GeometryOps;
+use crate::Result;
+
+/// Whether `coord` is a valid tile within `min_zoom..=max_zoom` that
+/// overlaps `bounds`, the usual [`TileSource::has_tile`] check; `None`
+/// leaves that limit open
+pub(crate) fn covers_tile(
+    coord: TileCoord,
+    min_zoom: Option<u8>,
+    max_zoom: Option<u8>,
+    bounds: Option<&LatLngBounds>,
+) -> bool {
+    coord.is_valid()
+        && min_zoom.is_none_or(|min| coord.z >= min)
+        && max_zoom.is_none_or(|max| coord.z <= max)
+        && bounds.is_none_or(|bounds| bounds.intersects_bounds(&coord.bounds()))
+}
+
+/// Query parameter and placeholder names that carry credentials
+const SECRET_PARAMS: &[&str] = &[
+    "key",
+    "apikey",
+    "api_key",
+    "access_token",
+    "token",
+    "secret",
+    "signature",
+    "sig",
+    "password",
+];
+
+fn is_secret_param(name: &str) -> bool {
+    SECRET_PARAMS
+        .iter()
+        .any(|secret| name.eq_ignore_ascii_case(secret))
+}
+
+/// `url` without the query parameters that carry credentials, so cache keys
+/// survive a key rotation and never end up holding the key
+pub(crate) fn strip_secret_params(url: &str) -> String {
+    let Some((base, query)) = url.split_once('?') else {
+        return url.to_string();
+    };
+    let kept: Vec<&str> = query
+        .split('&')
+        .filter(|pair| !is_secret_param(pair.split('=').next().unwrap_or(pair)))
+        .collect();
+    if kept.is_empty() {
+        base.to_string()
+    } else {
+        format!("{}?{}", base, kept.join("&"))
+    }
+}
 
 /// Trait representing anything that can produce tile URLs for a given coordinate.
 pub trait TileSource: Send + Sync {
     /// Build a URL for the requested `coord`.
     fn url(&self, coord: TileCoord) -> String;
+
+    /// Whether the source serves `coord` at all. Tiles outside a source's zoom
+    /// range or bounds are never queued for download.
+    fn has_tile(&self, _coord: TileCoord) -> bool {
+        true
+    }
+
+    /// Stable identifier for this source, used to key persistent caches.
+    /// Defaults to the URL of the root tile without credential query
+    /// parameters such as `key` or `access_token`.
+    fn source_id(&self) -> String {
+        strip_secret_params(&self.url(TileCoord::new(0, 0, 0)))
+    }
+
+    /// Byte provider used instead of an HTTP download, for sources that read
+    /// tiles from somewhere other than [`TileSource::url`] (local archives,
+    /// directories, generated tiles). `None` means tiles are downloaded.
+    fn data_source(&self) -> Option<Arc<dyn TileDataSource>> {
+        None
+    }
+
+    /// Headers, User-Agent and credentials sent with this source's tile
+    /// requests. `None` sends requests with the default User-Agent only.
+    fn request_options(&self) -> Option<&RequestOptions> {
+        None
+    }
+
+    /// URL of a double-resolution (`@2x`) version of `coord`, for sources
+    /// that offer one. Layers with `detect_retina` use it on HiDPI displays
+    /// and otherwise fall back to drawing the next zoom level at half size.
+    fn retina_url(&self, _coord: TileCoord) -> Option<String> {
+        None
+    }
+
+    /// Chain of sources the loader tries in turn for each tile. `None` for
+    /// ordinary sources, whose requests are not retried elsewhere.
+    fn failover(&self) -> Option<FailoverTileSource> {
+        None
+    }
+}
+
+/// Source requesting the double-resolution tiles of another source
+///
+/// Cached separately from the wrapped source, since its tiles differ.
+pub(crate) struct RetinaTileSource {
+    inner: Arc<dyn TileSource>,
+}
+
+impl RetinaTileSource {
+    pub(crate) fn new(inner: Arc<dyn TileSource>) -> Self {
+        Self { inner }
+    }
+}
+
+impl TileSource for RetinaTileSource {
+    fn url(&self, coord: TileCoord) -> String {
+        self.inner
+            .retina_url(coord)
+            .unwrap_or_else(|| self.inner.url(coord))
+    }
+
+    fn has_tile(&self, coord: TileCoord) -> bool {
+        self.inner.has_tile(coord)
+    }
+
+    fn source_id(&self) -> String {
+        format!("{}@2x", self.inner.source_id())
+    }
+
+    fn data_source(&self) -> Option<Arc<dyn TileDataSource>> {
+        self.inner.data_source()
+    }
+
+    fn request_options(&self) -> Option<&RequestOptions> {
+        self.inner.request_options()
+    }
+
+    fn retina_url(&self, coord: TileCoord) -> Option<String> {
+        self.inner.retina_url(coord)
+    }
+
+    fn failover(&self) -> Option<FailoverTileSource> {
+        self.inner.failover().map(FailoverTileSource::with_retina)
+    }
+}
+
+/// Future returned by [`TileDataSource::load_tile`]
+pub type TileDataFuture<'a> = Pin<Box<dyn Future<Output = Result<Option<Vec<u8>>>> + Send + 'a>>;
+
+/// Trait for sources that hand out raw tile bytes directly
+///
+/// The loader calls this from its worker instead of fetching the tile URL.
+/// Returning `Ok(None)` means the source has no tile at `coord`; this is a
+/// normal miss, not an error, and is delivered to the layer as an empty tile.
+pub trait TileDataSource: Send + Sync {
+    /// Load the encoded tile image for `coord`
+    fn load_tile(&self, coord: TileCoord) -> TileDataFuture<'_>;
 }
 
 /// Simple implementation that hits the default OpenStreetMap tile server.
+///
+/// The OpenStreetMap tile usage policy asks applications to identify
+/// themselves; set an application-specific User-Agent with
+/// [`OpenStreetMapSource::with_user_agent`].
 pub struct OpenStreetMapSource {
     subdomains: Vec<&'static str>,
+    request: RequestOptions,
 }
 
 impl OpenStreetMapSource {
     pub fn new() -> Self {
         Self {
             subdomains: vec!["a", "b", "c"],
+            request: RequestOptions::default(),
         }
     }
+
+    /// Identify the application in the User-Agent of tile requests
+    pub fn with_user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.request = self.request.with_user_agent(user_agent);
+        self
+    }
+
+    pub fn with_request_options(mut self, request: RequestOptions) -> Self {
+        self.request = request;
+        self
+    }
 }
 
 impl Default for OpenStreetMapSource {
@@ -41,4 +212,332 @@ impl TileSource for OpenStreetMapSource {
             sub, coord.z, coord.x, coord.y
         )
     }
+
+    fn request_options(&self) -> Option<&RequestOptions> {
+        Some(&self.request)
+    }
+}
+
+/// Generic tile source driven by a Leaflet-style URL template
+///
+/// Supported placeholders:
+/// - `{s}` or `{subdomain}`: subdomain, picked from `subdomains` by tile position
+/// - `{z}`, `{x}`, `{y}`: tile coordinates (`{y}` is flipped when `tms` is set);
+///   ArcGIS-style `{z}/{y}/{x}` ordering is just a matter of placement
+/// - `{-y}`: always the TMS-flipped row
+/// - `{q}` or `{quadkey}`: Bing-style quadkey, see [`TileCoord::to_quadkey`]
+/// - `{r}`: `@2x` when retina tiles are requested, empty otherwise
+/// - `{key}`: any custom parameter registered with [`TemplateTileSource::with_param`]
+#[derive(Debug, Clone)]
+pub struct TemplateTileSource {
+    template: String,
+    subdomains: Vec<String>,
+    tms: bool,
+    retina: bool,
+    min_zoom: u8,
+    max_zoom: u8,
+    bounds: Option<LatLngBounds>,
+    params: HashMap<String, String>,
+    request: Option<RequestOptions>,
+}
+
+impl TemplateTileSource {
+    /// Create a template source using the default tile layer options
+    pub fn new(template: impl Into<String>) -> Self {
+        Self::from_options(template, &TileLayerOptions::default())
+    }
+
+    /// Create a template source that honours the relevant tile layer options
+    /// (subdomains, TMS addressing, zoom range and bounds)
+    ///
+    /// `detect_retina` is left to the layer, which asks for
+    /// [`TileSource::retina_url`] only on HiDPI displays.
+    pub fn from_options(template: impl Into<String>, options: &TileLayerOptions) -> Self {
+        Self {
+            template: template.into(),
+            subdomains: options.subdomains.clone(),
+            tms: options.tms,
+            retina: false,
+            min_zoom: options.min_zoom,
+            max_zoom: options.max_zoom,
+            bounds: options.bounds.clone(),
+            params: HashMap::default(),
+            request: None,
+        }
+    }
+
+    /// Register a value for a custom `{key}` placeholder
+    pub fn with_param(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.params.insert(key.into(), value.into());
+        self
+    }
+
+    /// Override the subdomains used for `{s}`
+    pub fn with_subdomains(mut self, subdomains: Vec<String>) -> Self {
+        self.subdomains = subdomains;
+        self
+    }
+
+    /// Enable or disable TMS (bottom-up) row numbering for `{y}`
+    pub fn with_tms(mut self, tms: bool) -> Self {
+        self.tms = tms;
+        self
+    }
+
+    /// Always request `@2x` tiles through the `{r}` placeholder, whatever
+    /// the display
+    pub fn with_retina(mut self, retina: bool) -> Self {
+        self.retina = retina;
+        self
+    }
+
+    /// Restrict the zoom levels this source serves
+    pub fn with_zoom_range(mut self, min_zoom: u8, max_zoom: u8) -> Self {
+        self.min_zoom = min_zoom;
+        self.max_zoom = max_zoom;
+        self
+    }
+
+    /// Send these headers, User-Agent and credentials with tile requests
+    pub fn with_request_options(mut self, request: RequestOptions) -> Self {
+        self.request = Some(request);
+        self
+    }
+
+    /// Get the raw URL template
+    pub fn template(&self) -> &str {
+        &self.template
+    }
+
+    /// Update a custom parameter in place (e.g. a rotating API key)
+    pub fn set_param(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        self.params.insert(key.into(), value.into());
+    }
+
+    fn subdomain(&self, coord: TileCoord) -> &str {
+        if self.subdomains.is_empty() {
+            return "";
+        }
+        let idx = (coord.x as u64 + coord.y as u64) % self.subdomains.len() as u64;
+        &self.subdomains[idx as usize]
+    }
+
+    fn placeholder_value(&self, key: &str, coord: TileCoord, retina: bool) -> Option<String> {
+        let inverted_y = coord.flip_y().y;
+        match key {
+            "s" | "subdomain" => Some(self.subdomain(coord).to_string()),
+            "z" => Some(coord.z.to_string()),
+            "x" => Some(coord.x.to_string()),
+            "y" => Some(if self.tms { inverted_y } else { coord.y }.to_string()),
+            "-y" => Some(inverted_y.to_string()),
+            "q" | "quadkey" => Some(coord.to_quadkey()),
+            "r" => Some(if retina { "@2x" } else { "" }.to_string()),
+            _ => self.params.get(key).cloned(),
+        }
+    }
+
+    /// Expand the template for `coord`, with `{r}` set to `@2x` when `retina`
+    fn expand(&self, coord: TileCoord, retina: bool) -> String {
+        self.expand_with(coord, retina, &|_| None)
+    }
+
+    /// Expand the template, taking placeholder values from `extra` before
+    /// the built-in ones
+    pub(crate) fn expand_with(
+        &self,
+        coord: TileCoord,
+        retina: bool,
+        extra: &dyn Fn(&str) -> Option<String>,
+    ) -> String {
+        let mut url = String::with_capacity(self.template.len() + 16);
+        let mut rest = self.template.as_str();
+
+        while let Some(start) = rest.find('{') {
+            url.push_str(&rest[..start]);
+            let after = &rest[start + 1..];
+            match after.find('}') {
+                Some(end) => {
+                    let key = &after[..end];
+                    match extra(key).or_else(|| self.placeholder_value(key, coord, retina)) {
+                        Some(value) => url.push_str(&value),
+                        None => {
+                            // Leave unknown placeholders untouched so the problem is visible
+                            #[cfg(feature = "debug")]
+                            log::warn!("No value provided for tile URL placeholder {{{}}}", key);
+                            url.push('{');
+                            url.push_str(key);
+                            url.push('}');
+                        }
+                    }
+                    rest = &after[end + 1..];
+                }
+                None => {
+                    url.push_str(&rest[start..]);
+                    rest = "";
+                }
+            }
+        }
+        url.push_str(rest);
+        url
+    }
+}
+
+impl TileSource for TemplateTileSource {
+    fn url(&self, coord: TileCoord) -> String {
+        self.expand(coord, self.retina)
+    }
+
+    fn has_tile(&self, coord: TileCoord) -> bool {
+        covers_tile(
+            coord,
+            Some(self.min_zoom),
+            Some(self.max_zoom),
+            self.bounds.as_ref(),
+        )
+    }
+
+    /// The root tile URL with credential placeholders left unexpanded and
+    /// credential query parameters removed
+    fn source_id(&self) -> String {
+        let root = TileCoord::new(0, 0, 0);
+        let secret = |key: &str| is_secret_param(key).then(|| format!("{{{}}}", key));
+        strip_secret_params(&self.expand_with(root, self.retina, &secret))
+    }
+
+    fn request_options(&self) -> Option<&RequestOptions> {
+        self.request.as_ref()
+    }
+
+    /// Available when the template has an `{r}` placeholder
+    fn retina_url(&self, coord: TileCoord) -> Option<String> {
+        self.template
+            .contains("{r}")
+            .then(|| self.expand(coord, true))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_template_basic_placeholders() {
+        let source = TemplateTileSource::new("https://{s}.example.com/{z}/{x}/{y}{r}.png");
+        let url = source.url(TileCoord::new(1, 2, 3));
+        assert_eq!(url, "https://a.example.com/3/1/2.png");
+
+        // Subdomain rotates with tile position
+        let url = source.url(TileCoord::new(2, 2, 3));
+        assert_eq!(url, "https://b.example.com/3/2/2.png");
+    }
+
+    #[test]
+    fn test_template_tms_and_inverted_y() {
+        let options = TileLayerOptions {
+            tms: true,
+            ..Default::default()
+        };
+        let source = TemplateTileSource::from_options("/{z}/{x}/{y}|{-y}", &options);
+        // At zoom 3 there are 8 rows, so row 2 flips to 5
+        assert_eq!(source.url(TileCoord::new(1, 2, 3)), "/3/1/5|5");
+
+        let xyz = TemplateTileSource::new("/{z}/{x}/{y}|{-y}");
+        assert_eq!(xyz.url(TileCoord::new(1, 2, 3)), "/3/1/2|5");
+
+        let bing = TemplateTileSource::new("https://{subdomain}.example.com/a{q}.jpeg|{quadkey}")
+            .with_subdomains(vec!["t0".to_string()]);
+        assert_eq!(
+            bing.url(TileCoord::new(3, 5, 3)),
+            "https://t0.example.com/a213.jpeg|213"
+        );
+    }
+
+    #[test]
+    fn test_template_custom_params_and_retina() {
+        let source =
+            TemplateTileSource::new("https://tiles.example.com/{z}/{x}/{y}{r}.png?key={apikey}")
+                .with_param("apikey", "secret")
+                .with_retina(true)
+                .with_subdomains(Vec::new());
+        assert_eq!(
+            source.url(TileCoord::new(0, 0, 0)),
+            "https://tiles.example.com/0/0/0@2x.png?key=secret"
+        );
+
+        // Unknown placeholders are preserved verbatim
+        let source = TemplateTileSource::new("/{z}/{missing}");
+        assert_eq!(source.url(TileCoord::new(0, 0, 4)), "/4/{missing}");
+    }
+
+    #[test]
+    fn test_source_id_leaves_out_credentials() {
+        let mut source =
+            TemplateTileSource::new("https://tiles.example.com/{time}/{apikey}/{z}/{x}/{y}.png")
+                .with_param("apikey", "secret")
+                .with_param("time", "2024");
+        assert_eq!(
+            source.source_id(),
+            "https://tiles.example.com/2024/{apikey}/0/0/0.png"
+        );
+        // Rotating the key keeps the cache
+        let id = source.source_id();
+        source.set_param("apikey", "rotated");
+        assert_eq!(source.source_id(), id);
+
+        assert_eq!(
+            strip_secret_params("https://a.example.com/0.png?layer=x&access_token=t&Key=k"),
+            "https://a.example.com/0.png?layer=x"
+        );
+        assert_eq!(
+            strip_secret_params("https://a.example.com/0.png?token=t"),
+            "https://a.example.com/0.png"
+        );
+    }
+
+    #[test]
+    fn test_retina_url_and_retina_source() {
+        let options = TileLayerOptions {
+            detect_retina: true,
+            ..Default::default()
+        };
+        let source: Arc<dyn TileSource> = Arc::new(TemplateTileSource::from_options(
+            "https://tiles.example.com/{z}/{x}/{y}{r}.png",
+            &options,
+        ));
+        let coord = TileCoord::new(3, 1, 2);
+        // Detection is up to the layer, so plain URLs stay standard resolution
+        assert_eq!(source.url(coord), "https://tiles.example.com/2/3/1.png");
+        assert_eq!(
+            source.retina_url(coord).as_deref(),
+            Some("https://tiles.example.com/2/3/1@2x.png")
+        );
+
+        let retina = RetinaTileSource::new(source.clone());
+        assert_eq!(retina.url(coord), "https://tiles.example.com/2/3/1@2x.png");
+        assert_ne!(retina.source_id(), source.source_id());
+
+        // Without `{r}` there is no high-resolution variant
+        let plain = TemplateTileSource::new("https://tiles.example.com/{z}/{x}/{y}.png");
+        assert_eq!(plain.retina_url(coord), None);
+        assert_eq!(OpenStreetMapSource::new().retina_url(coord), None);
+    }
+
+    #[test]
+    fn test_template_zoom_range_and_bounds() {
+        let options = TileLayerOptions {
+            min_zoom: 2,
+            max_zoom: 10,
+            bounds: Some(LatLngBounds::from_coords(0.0, 0.0, 10.0, 10.0)),
+            ..Default::default()
+        };
+        let source = TemplateTileSource::from_options("/{z}/{x}/{y}", &options);
+
+        assert!(!source.has_tile(TileCoord::new(0, 0, 1)));
+        assert!(!source.has_tile(TileCoord::new(0, 0, 11)));
+
+        let inside = TileCoord::from_lat_lng(&crate::core::geo::LatLng::new(5.0, 5.0), 6);
+        let outside = TileCoord::from_lat_lng(&crate::core::geo::LatLng::new(-40.0, -70.0), 6);
+        assert!(source.has_tile(inside));
+        assert!(!source.has_tile(outside));
+    }
 }
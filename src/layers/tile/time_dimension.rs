@@ -0,0 +1,416 @@
+//! Time-dimension tile layer
+//!
+//! Weather radar, satellite and forecast services publish the same tiles
+//! for a series of time steps, usually selected by a `time` URL parameter.
+//! [`TimeDimensionLayer`] keeps one [`TileLayer`] per time step and plays
+//! them as an animation. The frames share one tile loader, so the animation
+//! downloads within a single set of download slots. The frames after the one
+//! on screen are loaded in the background, and the layer only moves to a
+//! frame once its visible tiles are in, so playback holds on the current
+//! frame rather than showing a half-loaded one.
+
+use super::{TemplateTileSource, TileLayer, TileLayerOptions, TilePriority, TileSource};
+use crate::{
+    core::{config::TileMemoryBudget, geo::LatLngBounds, viewport::Viewport},
+    input::MapEvent,
+    layers::base::{LayerProperties, LayerTrait, LayerType},
+    prelude::{Duration, Instant},
+    rendering::context::RenderContext,
+    Result,
+};
+
+/// Time each frame is shown for at speed 1.0
+pub const DEFAULT_FRAME_DURATION: Duration = Duration::from_millis(500);
+
+/// Frames loaded ahead of the one on screen
+pub const DEFAULT_PRELOAD: usize = 2;
+
+pub struct TimeDimensionLayer {
+    properties: LayerProperties,
+    times: Vec<String>,
+    frames: Vec<TileLayer>,
+    /// Frame on screen
+    current: usize,
+    /// Frame to show once its tiles are ready
+    pending: Option<usize>,
+    playing: bool,
+    looping: bool,
+    speed: f64,
+    frame_duration: Duration,
+    preload: usize,
+    /// When the current frame was first shown
+    shown_at: Instant,
+    /// Viewport of the last render, which frames must be ready for
+    viewport: Option<Viewport>,
+}
+
+impl TimeDimensionLayer {
+    /// Create a layer with one frame per entry of `times`, each drawing
+    /// tiles from the source `source` returns for that time
+    pub fn new<F>(
+        id: String,
+        times: Vec<String>,
+        options: TileLayerOptions,
+        source: F,
+    ) -> Result<Self>
+    where
+        F: Fn(&str) -> Box<dyn TileSource>,
+    {
+        if times.is_empty() {
+            return Err("A time-dimension layer needs at least one time step".into());
+        }
+
+        let frame_id = |time: &str| format!("{}@{}", id, time);
+        let first = TileLayer::new(frame_id(&times[0]), source(&times[0]), options.clone())?;
+        let mut frames = Vec::with_capacity(times.len());
+        for time in &times[1..] {
+            let loader = first.tile_loader().share();
+            frames.push(TileLayer::new_with_loader(
+                frame_id(time),
+                source(time),
+                options.clone(),
+                loader,
+            )?);
+        }
+        frames.insert(0, first);
+
+        let properties = LayerProperties {
+            id,
+            name: "Time Dimension Layer".to_string(),
+            layer_type: LayerType::Tile,
+            visible: true,
+            opacity: options.opacity,
+            z_index: options.z_index,
+            interactive: false,
+            options: serde_json::Value::Null,
+        };
+
+        Ok(Self {
+            properties,
+            times,
+            frames,
+            current: 0,
+            pending: None,
+            playing: false,
+            looping: true,
+            speed: 1.0,
+            frame_duration: DEFAULT_FRAME_DURATION,
+            preload: DEFAULT_PRELOAD,
+            shown_at: Instant::now(),
+            viewport: None,
+        })
+    }
+
+    /// Create a layer from a URL template with a `{time}` placeholder, such
+    /// as `https://radar.example.com/{time}/{z}/{x}/{y}.png`
+    pub fn from_template(
+        id: String,
+        template: impl Into<String>,
+        times: Vec<String>,
+        options: TileLayerOptions,
+    ) -> Result<Self> {
+        let template = template.into();
+        let source_options = options.clone();
+        Self::new(id, times, options, |time| {
+            Box::new(
+                TemplateTileSource::from_options(template.clone(), &source_options)
+                    .with_param("time", time),
+            )
+        })
+    }
+
+    /// Show each frame for `duration` at speed 1.0
+    pub fn with_frame_duration(mut self, duration: Duration) -> Self {
+        self.frame_duration = duration;
+        self
+    }
+
+    /// Load this many frames ahead of the one on screen
+    pub fn with_preload(mut self, frames: usize) -> Self {
+        self.preload = frames;
+        self
+    }
+
+    /// Whether playback starts over after the last frame rather than
+    /// stopping there
+    pub fn with_looping(mut self, looping: bool) -> Self {
+        self.looping = looping;
+        self
+    }
+
+    pub fn play(&mut self) {
+        if !self.playing {
+            self.playing = true;
+            self.shown_at = Instant::now();
+        }
+    }
+
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    /// Playback rate relative to the frame duration, e.g. 2.0 for twice as
+    /// fast
+    pub fn set_speed(&mut self, speed: f64) {
+        if speed.is_finite() && speed > 0.0 {
+            self.speed = speed;
+        }
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    /// Move to the next frame, once it is ready
+    pub fn step_forward(&mut self) {
+        let index = self.target_index();
+        self.set_time_index((index + 1) % self.frames.len());
+    }
+
+    /// Move to the previous frame, once it is ready
+    pub fn step_back(&mut self) {
+        let index = self.target_index();
+        self.set_time_index((index + self.frames.len() - 1) % self.frames.len());
+    }
+
+    /// Move to frame `index`, once its visible tiles are ready
+    pub fn set_time_index(&mut self, index: usize) {
+        if index >= self.frames.len() {
+            return;
+        }
+        self.pending = (index != self.current).then_some(index);
+        self.swap_if_ready();
+    }
+
+    /// Move to the frame for `time`, returning false if there is none
+    pub fn set_time(&mut self, time: &str) -> bool {
+        match self.times.iter().position(|t| t == time) {
+            Some(index) => {
+                self.set_time_index(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Index of the frame on screen
+    pub fn current_index(&self) -> usize {
+        self.current
+    }
+
+    /// Time step of the frame on screen
+    pub fn current_time(&self) -> &str {
+        &self.times[self.current]
+    }
+
+    /// Frame waiting for its tiles before it is shown
+    pub fn pending_index(&self) -> Option<usize> {
+        self.pending
+    }
+
+    pub fn times(&self) -> &[String] {
+        &self.times
+    }
+
+    /// Tile layer drawing frame `index`
+    pub fn frame(&self, index: usize) -> Option<&TileLayer> {
+        self.frames.get(index)
+    }
+
+    /// Whether frame `index` has its tiles for the last rendered viewport
+    pub fn is_frame_ready(&self, index: usize) -> bool {
+        match (self.frames.get(index), &self.viewport) {
+            (Some(frame), Some(viewport)) => frame.is_ready(viewport),
+            (Some(_), None) => true,
+            (None, _) => false,
+        }
+    }
+
+    pub fn needs_repaint(&self) -> bool {
+        self.playing || self.pending.is_some() || self.frames[self.current].needs_repaint()
+    }
+
+    /// Bound the memory of all frames together, each frame's tile cache
+    /// getting an equal share of `budget`
+    pub fn set_memory_budget(&mut self, budget: &TileMemoryBudget) {
+        let frames = self.frames.len();
+        let share = TileMemoryBudget {
+            compressed_bytes: budget.compressed_bytes / frames,
+            decoded_bytes: budget.decoded_bytes / frames,
+            texture_bytes: budget.texture_bytes,
+        };
+        for frame in &mut self.frames {
+            frame.set_memory_budget(&share);
+        }
+    }
+
+    /// Tile failures of every frame since the last call
+    pub fn take_tile_errors(&mut self) -> Vec<MapEvent> {
+        self.frames
+            .iter_mut()
+            .flat_map(|frame| frame.take_tile_errors())
+            .collect()
+    }
+
+    /// Frame the layer is moving to, or the current one
+    fn target_index(&self) -> usize {
+        self.pending.unwrap_or(self.current)
+    }
+
+    /// Frames to keep loading: the pending one and those after the target
+    fn preloaded_frames(&self) -> Vec<usize> {
+        let count = self.frames.len();
+        let target = self.target_index();
+        let mut frames: Vec<usize> = (1..=self.preload.min(count - 1))
+            .map(|ahead| (target + ahead) % count)
+            .filter(|index| self.looping || *index > target)
+            .collect();
+        if target != self.current {
+            frames.insert(0, target);
+        }
+        frames
+    }
+
+    /// Queue the next frame when playback is due to move on
+    fn advance(&mut self) {
+        if !self.playing || self.pending.is_some() {
+            return;
+        }
+        if self.shown_at.elapsed().as_secs_f64() * self.speed < self.frame_duration.as_secs_f64() {
+            return;
+        }
+
+        let next = self.current + 1;
+        if next < self.frames.len() {
+            self.pending = Some(next);
+        } else if self.looping {
+            self.pending = (self.current != 0).then_some(0);
+        } else {
+            self.playing = false;
+        }
+    }
+
+    /// Show the pending frame if its visible tiles are ready
+    fn swap_if_ready(&mut self) {
+        if let Some(index) = self.pending {
+            if self.is_frame_ready(index) {
+                self.current = index;
+                self.pending = None;
+                self.shown_at = Instant::now();
+            }
+        }
+    }
+}
+
+impl LayerTrait for TimeDimensionLayer {
+    crate::impl_layer_trait!(TimeDimensionLayer, properties);
+
+    fn bounds(&self) -> Option<LatLngBounds> {
+        self.frames[self.current].options().bounds.clone()
+    }
+
+    fn render(&mut self, context: &mut RenderContext, viewport: &Viewport) -> Result<()> {
+        if !self.is_visible() {
+            return Ok(());
+        }
+
+        self.viewport = Some(viewport.clone());
+        self.advance();
+        self.swap_if_ready();
+
+        // Frames off screen only load their tiles, the one to show next
+        // ahead of those further on
+        for index in self.preloaded_frames() {
+            let priority = if Some(index) == self.pending {
+                TilePriority::Visible
+            } else {
+                TilePriority::Background
+            };
+            let frame = &mut self.frames[index];
+            frame.set_pixels_per_point(context.pixels_per_point);
+            frame.preload(viewport, priority)?;
+        }
+
+        let opacity = self.opacity();
+        let frame = &mut self.frames[self.current];
+        frame.set_opacity(opacity);
+        frame.render(context, viewport)
+    }
+
+    fn update(&mut self, delta_time: f64) -> Result<()> {
+        self.frames[self.current].update(delta_time)?;
+        for index in self.preloaded_frames() {
+            self.frames[index].update(delta_time)?;
+        }
+
+        self.advance();
+        self.swap_if_ready();
+        Ok(())
+    }
+
+    fn options(&self) -> serde_json::Value {
+        serde_json::json!({
+            "time": self.current_time(),
+            "times": self.times,
+            "playing": self.playing,
+            "speed": self.speed
+        })
+    }
+
+    crate::impl_todo_options_setting!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::geo::TileCoord;
+
+    fn layer(times: &[&str]) -> TimeDimensionLayer {
+        TimeDimensionLayer::from_template(
+            "radar".to_string(),
+            "https://radar.example.com/{time}/{z}/{x}/{y}.png",
+            times.iter().map(|t| t.to_string()).collect(),
+            TileLayerOptions::default(),
+        )
+        .unwrap()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_frames_request_their_time_step() {
+        let layer = layer(&["0900", "0910"]);
+        let coord = TileCoord::new(1, 2, 3);
+        assert_eq!(
+            layer.frame(1).unwrap().tile_source().url(coord),
+            "https://radar.example.com/0910/3/1/2.png"
+        );
+        assert!(TimeDimensionLayer::from_template(
+            "radar".to_string(),
+            "{time}/{z}/{x}/{y}",
+            Vec::new(),
+            TileLayerOptions::default()
+        )
+        .is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_steps_wrap_around() {
+        // Without a rendered viewport there is nothing to wait for
+        let mut layer = layer(&["a", "b", "c"]);
+        layer.step_back();
+        assert_eq!(layer.current_time(), "c");
+        layer.step_forward();
+        layer.step_forward();
+        assert_eq!(layer.current_time(), "b");
+        assert!(layer.set_time("a"));
+        assert!(!layer.set_time("d"));
+        assert_eq!(layer.current_index(), 0);
+        assert_eq!(layer.preloaded_frames(), vec![1, 2]);
+
+        let layer = layer.with_looping(false).with_preload(5);
+        assert_eq!(layer.preloaded_frames(), vec![1, 2]);
+    }
+}
@@ -20,6 +20,8 @@ impl LayerTrait for TileLayer {
             return Ok(());
         }
 
+        self.set_pixels_per_point(context.pixels_per_point);
+
         self.process_tile_results()?;
 
         self.update_tiles(viewport)?;
@@ -57,10 +59,15 @@ impl LayerTrait for TileLayer {
             animation_manager.update();
         }
 
+        if !self.is_filter_animating() {
+            self.filter_transition = None;
+        }
+
+        let fade = self.fade_duration();
         for level in self.levels.values_mut() {
             for tile in level.tiles.values_mut() {
                 if tile.is_loaded() {
-                    tile.opacity = 1.0;
+                    tile.opacity = tile.fade_opacity(fade);
                 }
             }
         }
@@ -1,9 +1,13 @@
 //! Core data types for tile layer functionality
 
 use crate::{
-    core::geo::{LatLng, Point, TileCoord},
+    core::{
+        geo::{LatLng, LatLngBounds, Point, TileCoord},
+        viewport::Viewport,
+    },
     prelude::{Arc, HashMap},
-    traits::{should_retry_with_backoff, RetryLogic},
+    rendering::filter::RasterFilter,
+    traits::{should_retry_with_backoff, GeometryOps, RetryLogic},
 };
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -11,11 +15,20 @@ pub struct TileLayerOptions {
     pub tile_size: u32,
     pub min_zoom: u8,
     pub max_zoom: u8,
+    /// Highest zoom the source has tiles for. Deeper zoom levels show part
+    /// of the ancestor tile at this zoom, scaled up, instead of requesting
+    /// tiles that do not exist
+    pub max_native_zoom: Option<u8>,
+    /// Lowest zoom the source has tiles for. The two zoom levels below show
+    /// the tiles at this zoom scaled down; the layer is empty further out
+    pub min_native_zoom: Option<u8>,
     pub attribution: Option<String>,
     pub opacity: f32,
     pub z_index: i32,
     pub keep_buffer: u32,
     pub subdomains: Vec<String>,
+    /// Image shown in place of tiles that fail to load; a generated pattern
+    /// is shown when unset
     pub error_tile_url: Option<String>,
     pub cross_origin: bool,
     pub tms: bool,
@@ -31,6 +44,18 @@ pub struct TileLayerOptions {
     pub update_when_zooming: bool,
     /// Tiles will not update more than once every updateInterval milliseconds when panning
     pub update_interval: u32,
+    /// Time over which a newly loaded tile fades in while the parent or
+    /// child tiles drawn in its place fade out (like Leaflet's
+    /// fadeAnimation); `0` shows tiles at once
+    pub fade_duration_ms: u32,
+    /// Colour adjustments applied as tiles are drawn, without reloading
+    /// them. Unset, the map's theme filters basemap layers; set
+    /// `Some(RasterFilter::default())` to always keep the original colours
+    pub filter: Option<RasterFilter>,
+    /// Whether the layer is the map's basemap, drawn with the colour filter
+    /// of the map's theme when it has no filter of its own. Overlays such as
+    /// imagery or data layers keep their colours in every theme
+    pub basemap: bool,
     /// Target zoom level for animations (internal use)
     pub target_zoom: Option<f64>,
 }
@@ -41,6 +66,8 @@ impl Default for TileLayerOptions {
             tile_size: 256,
             min_zoom: 0,
             max_zoom: 18,
+            max_native_zoom: None,
+            min_native_zoom: None,
             attribution: None,
             opacity: 1.0,
             z_index: 1,
@@ -55,11 +82,73 @@ impl Default for TileLayerOptions {
             update_when_idle: false,   // Like Leaflet's default for desktop
             update_when_zooming: true, // CRITICAL: Always update during zoom for smooth transitions
             update_interval: 150,      // Slightly faster updates (was 200)
+            fade_duration_ms: 200,
+            filter: None,
+            basemap: false,
             target_zoom: None,
         }
     }
 }
 
+/// How a tile layer with `detect_retina` serves a HiDPI display
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RetinaMode {
+    /// Standard tiles, one tile pixel per screen point
+    #[default]
+    Off,
+    /// The source's double-resolution (`@2x`) tiles at the usual size
+    HighResolution,
+    /// Tiles from one zoom level higher, drawn at half size
+    ZoomOffset,
+}
+
+impl TileLayerOptions {
+    /// Zoom of the tiles to draw at view `zoom`: its floor, capped at the
+    /// maximum zoom so the deepest tiles are reused when zoomed past it, or
+    /// `None` below the minimum zoom
+    pub(crate) fn tile_zoom(&self, zoom: f64) -> Option<u8> {
+        let zoom = zoom.floor();
+        if zoom < self.min_zoom as f64 {
+            return None;
+        }
+        Some(zoom.min(self.max_zoom as f64) as u8)
+    }
+
+    /// Tiles at `zoom` covering the viewport, limited to the layer bounds
+    pub(crate) fn covering_tiles(&self, viewport: &Viewport, zoom: u8) -> Vec<TileCoord> {
+        let mut bounds = viewport.bounds();
+        if let Some(limit) = &self.bounds {
+            if !bounds.intersects_bounds(limit) {
+                return Vec::new();
+            }
+            bounds = LatLngBounds::from_coords(
+                bounds.south_west.lat.max(limit.south_west.lat),
+                bounds.south_west.lng.max(limit.south_west.lng),
+                bounds.north_east.lat.min(limit.north_east.lat),
+                bounds.north_east.lng.min(limit.north_east.lng),
+            );
+        }
+
+        // Corners past the edge of the world clamp to the outermost tiles
+        let last = 2_u32.pow(zoom as u32) - 1;
+        let north_west = TileCoord::from_lat_lng(
+            &LatLng::new(bounds.north_east.lat, bounds.south_west.lng),
+            zoom,
+        );
+        let south_east = TileCoord::from_lat_lng(
+            &LatLng::new(bounds.south_west.lat, bounds.north_east.lng),
+            zoom,
+        );
+
+        (north_west.y.min(last)..=south_east.y.min(last))
+            .flat_map(|y| {
+                (north_west.x.min(last)..=south_east.x.min(last))
+                    .map(move |x| TileCoord::new(x, y, zoom))
+            })
+            .collect()
+    }
+}
+
 /// Represents a collection of tiles at a specific zoom level
 /// Enhanced with CSS-style animation transform support (like Leaflet's level system)
 #[derive(Debug)]
@@ -396,10 +485,60 @@ impl TileState {
         self.last_retry_time = Some(std::time::Instant::now());
     }
 
+    /// The source has no data for this tile
+    pub fn mark_missing(&mut self) {
+        self.loading = false;
+        self.error = None;
+    }
+
     pub fn set_parent_data(&mut self, parent_data: Option<Arc<Vec<u8>>>) {
         self.show_parent = parent_data.is_some() && self.data.is_none();
         self.parent_data = parent_data;
     }
+
+    /// Opacity of the tile fading in over `fade` since it loaded
+    pub fn fade_opacity(&self, fade: std::time::Duration) -> f32 {
+        self.loaded_time
+            .map_or(0.0, |loaded| fade_progress(loaded, fade))
+    }
+}
+
+/// Share of a fade of length `fade` done since `start`, from 0.0 to 1.0
+pub(crate) fn fade_progress(start: std::time::Instant, fade: std::time::Duration) -> f32 {
+    if fade.is_zero() {
+        return 1.0;
+    }
+    (start.elapsed().as_secs_f32() / fade.as_secs_f32()).min(1.0)
+}
+
+/// Animation of a tile layer's colour filter
+#[derive(Debug, Clone)]
+pub(crate) struct FilterTransition {
+    pub from: RasterFilter,
+    pub to: Option<RasterFilter>,
+    pub start: std::time::Instant,
+    pub duration: std::time::Duration,
+}
+
+impl FilterTransition {
+    pub fn is_done(&self) -> bool {
+        self.start.elapsed() >= self.duration
+    }
+
+    /// Filter the transition starts from, and how far it has moved to the
+    /// target, while it runs
+    pub fn crossfade(&self) -> Option<(RasterFilter, f32)> {
+        (!self.is_done()).then(|| (self.from, fade_progress(self.start, self.duration)))
+    }
+
+    /// Filter reached so far; the target itself once done
+    pub fn current(&self) -> Option<RasterFilter> {
+        if self.is_done() {
+            return self.to;
+        }
+        let progress = fade_progress(self.start, self.duration);
+        Some(self.from.lerp(&self.to.unwrap_or_default(), progress))
+    }
 }
 
 impl RetryLogic for TileState {
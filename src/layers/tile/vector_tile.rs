@@ -0,0 +1,450 @@
+//! Vector tile layer
+//!
+//! Loads Mapbox Vector Tiles through the regular tile loader, decodes and
+//! styles them with [`MvtTile`] on blocking workers, and draws their features
+//! through the render context, styled per source layer.
+
+use super::mvt::MvtTile;
+use super::prepared::{drawable_tiles, PreparedTiles};
+use super::{TemplateTileSource, TileLayerOptions, TileSource};
+use crate::{
+    core::{
+        geo::{LatLngBounds, TileCoord},
+        viewport::Viewport,
+    },
+    layers::{
+        base::{LayerProperties, LayerTrait, LayerType},
+        vector::{render_vector_feature, LineStyle, PointStyle, PolygonStyle, VectorFeatureData},
+    },
+    prelude::Arc,
+    rendering::context::RenderContext,
+    Result,
+};
+use lru::LruCache;
+use std::num::NonZeroUsize;
+
+/// Decoded tiles kept in memory
+const TILE_CACHE_SIZE: usize = 256;
+
+/// Styles applied to the features of one source layer, by geometry kind
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SourceLayerStyle {
+    pub point: PointStyle,
+    pub line: LineStyle,
+    pub polygon: PolygonStyle,
+}
+
+impl SourceLayerStyle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_point(mut self, point: PointStyle) -> Self {
+        self.point = point;
+        self
+    }
+
+    pub fn with_line(mut self, line: LineStyle) -> Self {
+        self.line = line;
+        self
+    }
+
+    pub fn with_polygon(mut self, polygon: PolygonStyle) -> Self {
+        self.polygon = polygon;
+        self
+    }
+}
+
+/// Per-source-layer styling for a [`VectorTileLayer`]
+///
+/// Styled layers are painted in the order they were added, followed by any
+/// other layers in tile order using the default style. Without a default
+/// style, layers that have not been styled are hidden.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VectorTileStyle {
+    layers: Vec<(String, SourceLayerStyle)>,
+    default: Option<SourceLayerStyle>,
+}
+
+impl Default for VectorTileStyle {
+    fn default() -> Self {
+        Self {
+            layers: Vec::new(),
+            default: Some(SourceLayerStyle::default()),
+        }
+    }
+}
+
+impl VectorTileStyle {
+    /// Style showing every source layer with the default styles
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Style the source layer `name`, replacing any earlier style for it
+    pub fn with_layer(mut self, name: impl Into<String>, style: SourceLayerStyle) -> Self {
+        let name = name.into();
+        self.layers.retain(|(existing, _)| *existing != name);
+        self.layers.push((name, style));
+        self
+    }
+
+    /// Style used for source layers without their own; `None` hides them
+    pub fn with_default(mut self, default: Option<SourceLayerStyle>) -> Self {
+        self.default = default;
+        self
+    }
+
+    pub fn layer_style(&self, name: &str) -> Option<&SourceLayerStyle> {
+        self.layers
+            .iter()
+            .find(|(layer, _)| layer == name)
+            .map(|(_, style)| style)
+            .or(self.default.as_ref())
+    }
+
+    /// Convert the features of `tile` into drawable features, in paint order
+    fn style_tile(&self, coord: TileCoord, tile: &MvtTile) -> Vec<VectorFeatureData> {
+        let styled = self
+            .layers
+            .iter()
+            .filter_map(|(name, style)| tile.layer(name).map(|layer| (layer, style)));
+        let unstyled = self.default.iter().flat_map(|style| {
+            tile.layers
+                .iter()
+                .filter(|layer| !self.layers.iter().any(|(name, _)| *name == layer.name))
+                .map(move |layer| (layer, style))
+        });
+
+        styled
+            .chain(unstyled)
+            .flat_map(|(layer, style)| {
+                layer
+                    .features
+                    .iter()
+                    .enumerate()
+                    .filter_map(move |(index, feature)| {
+                        feature.to_feature_data(coord, layer, index, style)
+                    })
+            })
+            .collect()
+    }
+}
+
+/// A decoded tile with its features converted for a style
+struct StyledTile {
+    tile: MvtTile,
+    /// Style the features were converted with
+    style: Arc<VectorTileStyle>,
+    features: Vec<VectorFeatureData>,
+}
+
+impl StyledTile {
+    fn new(coord: TileCoord, tile: MvtTile, style: Arc<VectorTileStyle>) -> Self {
+        let features = style.style_tile(coord, &tile);
+        Self {
+            tile,
+            style,
+            features,
+        }
+    }
+
+    fn restyle(&mut self, coord: TileCoord, style: &Arc<VectorTileStyle>) {
+        if !Arc::ptr_eq(&self.style, style) {
+            self.style = style.clone();
+            self.features = style.style_tile(coord, &self.tile);
+        }
+    }
+}
+
+/// Layer drawing Mapbox Vector Tiles
+///
+/// Tiles load at the floor of the view zoom. Past the maximum zoom the
+/// deepest tiles are reused, since their geometry scales without loss, and
+/// while a tile loads its nearest loaded ancestor is drawn in its place.
+pub struct VectorTileLayer {
+    properties: LayerProperties,
+    options: TileLayerOptions,
+    loader: PreparedTiles<StyledTile>,
+    style: Arc<VectorTileStyle>,
+    tiles: LruCache<TileCoord, StyledTile>,
+}
+
+impl VectorTileLayer {
+    pub fn new(id: String, tile_source: Box<dyn TileSource>, options: TileLayerOptions) -> Self {
+        let properties = LayerProperties {
+            id,
+            name: "Vector Tile Layer".to_string(),
+            layer_type: LayerType::Vector,
+            visible: true,
+            opacity: options.opacity,
+            z_index: options.z_index,
+            interactive: false,
+            options: serde_json::Value::Null,
+        };
+
+        Self {
+            properties,
+            options,
+            loader: PreparedTiles::new(Arc::from(tile_source)),
+            style: Arc::new(VectorTileStyle::default()),
+            tiles: LruCache::new(NonZeroUsize::new(TILE_CACHE_SIZE).unwrap()),
+        }
+    }
+
+    /// Create a layer from a URL template such as
+    /// `https://tiles.example.com/{z}/{x}/{y}.mvt`
+    pub fn from_template(
+        id: String,
+        template: impl Into<String>,
+        options: TileLayerOptions,
+    ) -> Self {
+        let source = TemplateTileSource::from_options(template, &options);
+        Self::new(id, Box::new(source), options)
+    }
+
+    /// Create a layer reading vector tiles from a local PMTiles archive
+    pub fn from_pmtiles(
+        id: String,
+        path: impl AsRef<std::path::Path>,
+        mut options: TileLayerOptions,
+    ) -> Result<Self> {
+        let source = super::PMTilesSource::open_file(path)?;
+        if !source.is_vector() {
+            return Err(Box::new(crate::Error::Layer(
+                "PMTiles archive does not contain vector tiles".to_string(),
+            )));
+        }
+        source.apply_to_options(&mut options);
+        Ok(Self::new(id, Box::new(source), options))
+    }
+
+    /// Create a layer reading vector tiles from a local MBTiles archive
+    #[cfg(feature = "mbtiles")]
+    pub fn from_mbtiles(
+        id: String,
+        path: impl AsRef<std::path::Path>,
+        options: TileLayerOptions,
+    ) -> Result<Self> {
+        let source = super::MBTilesSource::open(path)?;
+        if !source.metadata().is_vector() {
+            return Err(Box::new(crate::Error::Layer(format!(
+                "{} does not contain vector tiles",
+                source.path().display()
+            ))));
+        }
+        let options = source.layer_options(options);
+        Ok(Self::new(id, Box::new(source), options))
+    }
+
+    pub fn with_style(mut self, style: VectorTileStyle) -> Self {
+        self.set_style(style);
+        self
+    }
+
+    /// Replace the style, restyling loaded tiles without reloading them
+    pub fn set_style(&mut self, style: VectorTileStyle) {
+        self.style = Arc::new(style);
+        for (coord, styled) in self.tiles.iter_mut() {
+            styled.restyle(*coord, &self.style);
+        }
+    }
+
+    pub fn style(&self) -> &VectorTileStyle {
+        &self.style
+    }
+
+    pub fn options(&self) -> &TileLayerOptions {
+        &self.options
+    }
+
+    /// Decode `data` as the tile at `coord` and keep it for drawing
+    ///
+    /// An empty payload records that the source has no tile there. Tiles
+    /// the layer downloads itself are decoded on a blocking worker instead.
+    pub fn insert_tile(&mut self, coord: TileCoord, data: &[u8]) -> Result<()> {
+        if data.is_empty() {
+            self.loader.mark_missing(coord);
+            return Ok(());
+        }
+        match MvtTile::decode(data) {
+            Ok(tile) => {
+                self.loader.mark_loaded(coord);
+                let styled = StyledTile::new(coord, tile, self.style.clone());
+                self.tiles.put(coord, styled);
+                Ok(())
+            }
+            Err(e) => {
+                self.loader.mark_missing(coord);
+                Err(e)
+            }
+        }
+    }
+
+    /// Decoded tile at `coord`, if loaded
+    pub fn tile(&self, coord: TileCoord) -> Option<&MvtTile> {
+        self.tiles.peek(&coord).map(|styled| &styled.tile)
+    }
+
+    /// Styled features of the tile at `coord` in paint order, if loaded
+    pub fn tile_features(&self, coord: TileCoord) -> Option<&[VectorFeatureData]> {
+        self.tiles
+            .peek(&coord)
+            .map(|styled| styled.features.as_slice())
+    }
+
+    pub fn loaded_tile_count(&self) -> usize {
+        self.tiles.len()
+    }
+
+    /// Tiles that failed to download, whether or not they will be retried
+    pub fn failed_tiles(&self) -> Vec<TileCoord> {
+        self.loader.failed_tiles()
+    }
+
+    fn process_tile_results(&mut self) {
+        let style = self.style.clone();
+        let styled = self
+            .loader
+            .receive(move |coord, data| Ok(StyledTile::new(coord, MvtTile::decode(&data)?, style)));
+        // Tiles styled before a style change are restyled on arrival
+        for (coord, mut tile) in styled {
+            tile.restyle(coord, &self.style);
+            self.tiles.put(coord, tile);
+        }
+    }
+}
+
+impl LayerTrait for VectorTileLayer {
+    crate::impl_layer_trait!(VectorTileLayer, properties);
+
+    fn bounds(&self) -> Option<LatLngBounds> {
+        self.options.bounds.clone()
+    }
+
+    fn render(&mut self, context: &mut RenderContext, viewport: &Viewport) -> Result<()> {
+        if !self.is_visible() {
+            return Ok(());
+        }
+
+        self.process_tile_results();
+
+        let Some(zoom) = self.options.tile_zoom(viewport.zoom) else {
+            return Ok(());
+        };
+        let visible = self.options.covering_tiles(viewport, zoom);
+
+        let is_loaded = |coord: &TileCoord| self.tiles.contains(coord);
+        self.loader.request(&visible, is_loaded)?;
+        let tiles = drawable_tiles(&visible, self.options.min_zoom, is_loaded);
+
+        let opacity = self.opacity();
+        for coord in tiles {
+            let Some(styled) = self.tiles.peek(&coord) else {
+                continue;
+            };
+            for feature in &styled.features {
+                render_vector_feature(
+                    context,
+                    viewport,
+                    &feature.feature,
+                    &feature.style,
+                    opacity,
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn options(&self) -> serde_json::Value {
+        serde_json::json!({
+            "min_zoom": self.options.min_zoom,
+            "max_zoom": self.options.max_zoom,
+            "loaded_tiles": self.tiles.len()
+        })
+    }
+
+    crate::impl_todo_options_setting!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::geo::{LatLng, Point};
+    use crate::layers::vector::VectorFeature;
+    use crate::rendering::context::DrawCommand;
+
+    fn layer() -> VectorTileLayer {
+        VectorTileLayer::from_template(
+            "mvt".to_string(),
+            "http://127.0.0.1:9/{z}/{x}/{y}.mvt",
+            TileLayerOptions::default(),
+        )
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_style_controls_paint_order_and_visibility() {
+        let mut layer = layer();
+        let coord = TileCoord::new(0, 0, 1);
+        layer
+            .insert_tile(coord, &crate::layers::tile::mvt::tests::sample_tile())
+            .unwrap();
+
+        // Default style: every layer in tile order
+        let ids: Vec<_> = layer
+            .tile_features(coord)
+            .unwrap()
+            .iter()
+            .map(|feature| feature.id.as_str())
+            .collect();
+        assert_eq!(ids, ["1/0/0/places/7", "1/0/0/shapes/#0", "1/0/0/shapes/9"]);
+
+        // Only `shapes`, restyled, without reloading the tile
+        let thick = LineStyle {
+            width: 6.0,
+            ..Default::default()
+        };
+        layer.set_style(
+            VectorTileStyle::new()
+                .with_layer("shapes", SourceLayerStyle::new().with_line(thick.clone()))
+                .with_default(None),
+        );
+        let features = layer.tile_features(coord).unwrap();
+        assert_eq!(features.len(), 2);
+        match &features[0].feature {
+            VectorFeature::LineString { style, .. } => assert_eq!(*style, thick),
+            other => panic!("expected a line, got {:?}", other),
+        }
+        assert!(layer.tile(coord).unwrap().layer("places").is_some());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_render_draws_loaded_ancestor() {
+        let mut layer = layer();
+        layer
+            .insert_tile(
+                TileCoord::new(0, 0, 1),
+                &crate::layers::tile::mvt::tests::sample_tile(),
+            )
+            .unwrap();
+        assert!(layer
+            .insert_tile(TileCoord::new(1, 1, 1), b"not a tile")
+            .is_err());
+
+        // Zoomed into the north-west quarter: the z1 tile stands in for the
+        // z2 tiles that are still loading
+        let viewport = Viewport::new(LatLng::new(40.0, -90.0), 2.5, Point::new(256.0, 256.0));
+        let mut context = RenderContext::new(256, 256).unwrap();
+        layer.render(&mut context, &viewport).unwrap();
+
+        let queue = context.get_drawing_queue();
+        assert_eq!(queue.len(), 3);
+        assert!(matches!(queue[0], DrawCommand::Point { .. }));
+        assert!(matches!(queue[1], DrawCommand::Line { .. }));
+        match &queue[2] {
+            DrawCommand::Polygon { holes, .. } => assert_eq!(holes.len(), 1),
+            other => panic!("expected a polygon, got {:?}", other),
+        }
+    }
+}
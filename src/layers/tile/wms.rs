@@ -0,0 +1,387 @@
+//! OGC Web Map Service (WMS) tile source
+//!
+//! Requests each tile as a `GetMap` image whose bounding box matches the tile,
+//! in either Web Mercator (EPSG:3857) or geographic (EPSG:4326) coordinates.
+
+use super::request::RequestOptions;
+use super::source::{covers_tile, TileSource};
+use super::types::TileLayerOptions;
+use crate::core::geo::{LatLngBounds, TileCoord};
+use crate::Result;
+
+/// Half the width of the Web Mercator world in metres
+pub(crate) const MERCATOR_EXTENT: f64 = 20_037_508.342_789_244;
+
+/// WMS protocol version
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WmsVersion {
+    V1_1_1,
+    V1_3_0,
+}
+
+impl WmsVersion {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WmsVersion::V1_1_1 => "1.1.1",
+            WmsVersion::V1_3_0 => "1.3.0",
+        }
+    }
+}
+
+/// Coordinate reference system of the requested bounding boxes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WmsCrs {
+    /// Spherical Web Mercator, in metres
+    Epsg3857,
+    /// WGS 84 latitude/longitude, in degrees
+    Epsg4326,
+}
+
+impl WmsCrs {
+    /// Map a `TileLayerOptions::reference_system` code to a supported CRS
+    pub fn from_reference_system(code: &str) -> Result<Self> {
+        match code.trim().to_ascii_uppercase().as_str() {
+            "EPSG:3857" | "EPSG:900913" | "EPSG:102100" => Ok(WmsCrs::Epsg3857),
+            "EPSG:4326" => Ok(WmsCrs::Epsg4326),
+            _ => Err(Box::new(crate::Error::Layer(format!(
+                "Unsupported WMS reference system {}; use EPSG:3857 or EPSG:4326",
+                code
+            )))),
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            WmsCrs::Epsg3857 => "EPSG:3857",
+            WmsCrs::Epsg4326 => "EPSG:4326",
+        }
+    }
+}
+
+/// Tile source issuing WMS `GetMap` requests
+///
+/// Version 1.1.1 sends the CRS as `SRS`; 1.3.0 sends it as `CRS` and, for
+/// EPSG:4326, orders the `BBOX` latitude first as that version requires.
+#[derive(Debug, Clone)]
+pub struct WmsTileSource {
+    base_url: String,
+    layers: Vec<String>,
+    styles: Vec<String>,
+    format: String,
+    transparent: bool,
+    version: WmsVersion,
+    crs: WmsCrs,
+    tile_size: u32,
+    min_zoom: u8,
+    max_zoom: u8,
+    bounds: Option<LatLngBounds>,
+    params: Vec<(String, String)>,
+    request: Option<RequestOptions>,
+}
+
+impl WmsTileSource {
+    /// Create a source for `layers` of the service at `base_url`, requesting
+    /// transparent PNG tiles in EPSG:3857 with WMS 1.1.1
+    pub fn new(base_url: impl Into<String>, layers: Vec<String>) -> Self {
+        let options = TileLayerOptions::default();
+        Self {
+            base_url: base_url.into(),
+            layers,
+            styles: Vec::new(),
+            format: "image/png".to_string(),
+            transparent: true,
+            version: WmsVersion::V1_1_1,
+            crs: WmsCrs::Epsg3857,
+            tile_size: options.tile_size,
+            min_zoom: options.min_zoom,
+            max_zoom: options.max_zoom,
+            bounds: options.bounds,
+            params: Vec::new(),
+            request: None,
+        }
+    }
+
+    /// Create a source that honours the relevant tile layer options (reference
+    /// system, tile size, zoom range and bounds)
+    pub fn from_options(
+        base_url: impl Into<String>,
+        layers: Vec<String>,
+        options: &TileLayerOptions,
+    ) -> Result<Self> {
+        let mut source = Self::new(base_url, layers);
+        source.crs = WmsCrs::from_reference_system(&options.reference_system)?;
+        source.tile_size = options.tile_size;
+        source.min_zoom = options.min_zoom;
+        source.max_zoom = options.max_zoom;
+        source.bounds = options.bounds.clone();
+        Ok(source)
+    }
+
+    /// Styles applied to the layers, in the same order; empty for defaults
+    pub fn with_styles(mut self, styles: Vec<String>) -> Self {
+        self.styles = styles;
+        self
+    }
+
+    /// Image MIME type, e.g. `image/png` or `image/jpeg`
+    pub fn with_format(mut self, format: impl Into<String>) -> Self {
+        self.format = format.into();
+        self
+    }
+
+    pub fn with_transparent(mut self, transparent: bool) -> Self {
+        self.transparent = transparent;
+        self
+    }
+
+    pub fn with_version(mut self, version: WmsVersion) -> Self {
+        self.version = version;
+        self
+    }
+
+    pub fn with_crs(mut self, crs: WmsCrs) -> Self {
+        self.crs = crs;
+        self
+    }
+
+    /// Add a vendor-specific query parameter sent with every request
+    pub fn with_param(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.params.push((key.into(), value.into()));
+        self
+    }
+
+    /// Send these headers, User-Agent and credentials with `GetMap` requests
+    pub fn with_request_options(mut self, request: RequestOptions) -> Self {
+        self.request = Some(request);
+        self
+    }
+
+    pub fn crs(&self) -> WmsCrs {
+        self.crs
+    }
+
+    pub fn version(&self) -> WmsVersion {
+        self.version
+    }
+
+    /// Bounding box of `coord` as sent in `BBOX`: min/max x then y, except
+    /// for EPSG:4326 under WMS 1.3.0 where latitude comes first
+    pub fn bbox(&self, coord: TileCoord) -> [f64; 4] {
+        match self.crs {
+            WmsCrs::Epsg3857 => {
+                let size = 2.0 * MERCATOR_EXTENT / 2_f64.powi(coord.z as i32);
+                let min_x = -MERCATOR_EXTENT + coord.x as f64 * size;
+                let max_y = MERCATOR_EXTENT - coord.y as f64 * size;
+                [min_x, max_y - size, min_x + size, max_y]
+            }
+            WmsCrs::Epsg4326 => {
+                let bounds = coord.bounds();
+                let (sw, ne) = (bounds.south_west, bounds.north_east);
+                if self.version == WmsVersion::V1_3_0 {
+                    [sw.lat, sw.lng, ne.lat, ne.lng]
+                } else {
+                    [sw.lng, sw.lat, ne.lng, ne.lat]
+                }
+            }
+        }
+    }
+}
+
+/// Percent-encode a query value, leaving the characters WMS values commonly
+/// contain (`,` and `:`) readable
+pub(super) fn encode_query_value(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b',' | b':' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// `url` with `query` appended as percent-encoded parameters, after any
+/// query the URL already has
+pub(crate) fn append_query<K, V>(url: &str, query: &[(K, V)]) -> String
+where
+    K: AsRef<str>,
+    V: AsRef<str>,
+{
+    let separator = match url.find('?') {
+        None => "?",
+        Some(_) if url.ends_with('?') || url.ends_with('&') => "",
+        Some(_) => "&",
+    };
+    let query = query
+        .iter()
+        .map(|(key, value)| {
+            format!(
+                "{}={}",
+                encode_query_value(key.as_ref()),
+                encode_query_value(value.as_ref())
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("&");
+    format!("{}{}{}", url, separator, query)
+}
+
+impl WmsTileSource {
+    /// `GetMap` URL for `coord` rendered at `size` pixels square
+    fn get_map_url(&self, coord: TileCoord, size: u32) -> String {
+        let crs_key = match self.version {
+            WmsVersion::V1_1_1 => "SRS",
+            WmsVersion::V1_3_0 => "CRS",
+        };
+        let bbox = self
+            .bbox(coord)
+            .iter()
+            .map(|value| value.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+
+        let mut query = vec![
+            ("SERVICE", "WMS".to_string()),
+            ("REQUEST", "GetMap".to_string()),
+            ("VERSION", self.version.as_str().to_string()),
+            ("LAYERS", self.layers.join(",")),
+            ("STYLES", self.styles.join(",")),
+            ("FORMAT", self.format.clone()),
+            (
+                "TRANSPARENT",
+                if self.transparent { "TRUE" } else { "FALSE" }.to_string(),
+            ),
+            (crs_key, self.crs.code().to_string()),
+            ("WIDTH", size.to_string()),
+            ("HEIGHT", size.to_string()),
+            ("BBOX", bbox),
+        ];
+        query.extend(self.params.iter().map(|(k, v)| (k.as_str(), v.clone())));
+        append_query(&self.base_url, &query)
+    }
+}
+
+impl TileSource for WmsTileSource {
+    fn url(&self, coord: TileCoord) -> String {
+        self.get_map_url(coord, self.tile_size)
+    }
+
+    fn has_tile(&self, coord: TileCoord) -> bool {
+        covers_tile(
+            coord,
+            Some(self.min_zoom),
+            Some(self.max_zoom),
+            self.bounds.as_ref(),
+        )
+    }
+
+    fn request_options(&self) -> Option<&RequestOptions> {
+        self.request.as_ref()
+    }
+
+    /// The same map area rendered at twice the pixel size
+    fn retina_url(&self, coord: TileCoord) -> Option<String> {
+        Some(self.get_map_url(coord, self.tile_size * 2))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query_value<'a>(url: &'a str, key: &str) -> Option<&'a str> {
+        let query = url.split_once('?')?.1;
+        query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix(&format!("{}=", key)))
+    }
+
+    #[test]
+    fn test_mercator_get_map_request() {
+        let source = WmsTileSource::new(
+            "https://maps.example.gov/wms?map=base",
+            vec!["roads".to_string(), "rivers".to_string()],
+        )
+        .with_styles(vec!["".to_string(), "blue".to_string()])
+        .with_param("DPI", "96");
+
+        let url = source.url(TileCoord::new(0, 0, 1));
+        assert!(url.starts_with("https://maps.example.gov/wms?map=base&SERVICE=WMS&"));
+        assert_eq!(query_value(&url, "REQUEST"), Some("GetMap"));
+        assert_eq!(query_value(&url, "VERSION"), Some("1.1.1"));
+        assert_eq!(query_value(&url, "LAYERS"), Some("roads,rivers"));
+        assert_eq!(query_value(&url, "STYLES"), Some(",blue"));
+        assert_eq!(query_value(&url, "FORMAT"), Some("image%2Fpng"));
+        assert_eq!(query_value(&url, "TRANSPARENT"), Some("TRUE"));
+        assert_eq!(query_value(&url, "SRS"), Some("EPSG:3857"));
+        assert_eq!(query_value(&url, "WIDTH"), Some("256"));
+        assert_eq!(query_value(&url, "DPI"), Some("96"));
+
+        // Top-left quarter of the world
+        let bbox = source.bbox(TileCoord::new(0, 0, 1));
+        assert_eq!(bbox, [-MERCATOR_EXTENT, 0.0, 0.0, MERCATOR_EXTENT]);
+    }
+
+    #[test]
+    fn test_geographic_axis_order_by_version() {
+        let options = TileLayerOptions {
+            reference_system: "EPSG:4326".to_string(),
+            detect_retina: true,
+            ..Default::default()
+        };
+        let source = WmsTileSource::from_options(
+            "https://wms.example.org/",
+            vec!["a".to_string()],
+            &options,
+        )
+        .unwrap();
+        let coord = TileCoord::new(1, 0, 1);
+        let [min_x, min_y, max_x, max_y] = source.bbox(coord);
+        assert_eq!((min_x, min_y, max_x), (0.0, 0.0, 180.0));
+        assert!((max_y - 85.0511).abs() < 1e-3);
+        assert_eq!(query_value(&source.url(coord), "WIDTH"), Some("256"));
+        let retina_url = source.retina_url(coord).unwrap();
+        assert_eq!(query_value(&retina_url, "WIDTH"), Some("512"));
+        assert_eq!(query_value(&retina_url, "HEIGHT"), Some("512"));
+
+        let source = source.with_version(WmsVersion::V1_3_0);
+        let url = source.url(coord);
+        assert_eq!(query_value(&url, "CRS"), Some("EPSG:4326"));
+        assert_eq!(query_value(&url, "SRS"), None);
+        let [min_lat, min_lng, max_lat, max_lng] = source.bbox(coord);
+        assert_eq!((min_lat, min_lng, max_lng), (0.0, 0.0, 180.0));
+        assert!((max_lat - 85.0511).abs() < 1e-3);
+
+        // Mercator keeps x/y order under 1.3.0
+        let source = source.with_crs(WmsCrs::Epsg3857);
+        assert_eq!(source.bbox(coord)[0], 0.0);
+
+        let options = TileLayerOptions {
+            reference_system: "EPSG:27700".to_string(),
+            ..Default::default()
+        };
+        assert!(
+            WmsTileSource::from_options("https://wms.example.org/", Vec::new(), &options).is_err()
+        );
+    }
+
+    #[test]
+    fn test_append_query_picks_the_separator() {
+        let query = [("LAYER", "a b"), ("TIME", "2024-01-01T00:00:00Z")];
+        let expected = "LAYER=a%20b&TIME=2024-01-01T00:00:00Z";
+        assert_eq!(
+            append_query("https://example.org/wms", &query),
+            format!("https://example.org/wms?{}", expected)
+        );
+        assert_eq!(
+            append_query("https://example.org/wms?map=x", &query),
+            format!("https://example.org/wms?map=x&{}", expected)
+        );
+        assert_eq!(
+            append_query("https://example.org/wms?", &query),
+            format!("https://example.org/wms?{}", expected)
+        );
+    }
+}
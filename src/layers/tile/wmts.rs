@@ -0,0 +1,772 @@
+//! OGC Web Map Tile Service (WMTS) client
+//!
+//! Parses a `GetCapabilities` document and builds tile sources for its
+//! layers. Only tile matrix sets that line up with the GoogleMapsCompatible
+//! Web Mercator pyramid can be shown, since tiles are addressed by
+//! [`TileCoord`]; other sets are rejected when the source is built.
+
+use super::loader::HTTP_CLIENT;
+use super::request::RequestOptions;
+use super::source::TileSource;
+use super::types::TileLayerOptions;
+use super::wms::{append_query, MERCATOR_EXTENT};
+use crate::core::geo::{LatLng, LatLngBounds, TileCoord};
+use crate::prelude::HashMap;
+use crate::traits::GeometryOps;
+use crate::Result;
+use roxmltree::Node;
+
+/// Scale denominator of GoogleMapsCompatible zoom 0 with 256 pixel tiles
+const GOOGLE_ZOOM_0_SCALE: f64 = 559_082_264.028_717_8;
+
+/// How requests for tiles are encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WmtsEncoding {
+    /// Query string parameters on the `GetTile` operation URL
+    Kvp,
+    /// Per-layer `ResourceURL` templates
+    Rest,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WmtsStyle {
+    pub identifier: String,
+    pub title: Option<String>,
+    pub is_default: bool,
+}
+
+/// A layer dimension such as `Time`, with its default value
+#[derive(Debug, Clone, PartialEq)]
+pub struct WmtsDimension {
+    pub identifier: String,
+    pub default: Option<String>,
+    pub values: Vec<String>,
+}
+
+/// A RESTful tile URL template for one image format
+#[derive(Debug, Clone, PartialEq)]
+pub struct WmtsResourceUrl {
+    pub format: String,
+    pub template: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WmtsLayer {
+    pub identifier: String,
+    pub title: Option<String>,
+    /// Extent from `WGS84BoundingBox`
+    pub bounds: Option<LatLngBounds>,
+    pub styles: Vec<WmtsStyle>,
+    pub formats: Vec<String>,
+    pub dimensions: Vec<WmtsDimension>,
+    /// Identifiers of the tile matrix sets the layer is published in
+    pub tile_matrix_sets: Vec<String>,
+    pub resource_urls: Vec<WmtsResourceUrl>,
+}
+
+impl WmtsLayer {
+    /// The style marked as default, or the first one
+    pub fn default_style(&self) -> Option<&WmtsStyle> {
+        self.styles
+            .iter()
+            .find(|style| style.is_default)
+            .or_else(|| self.styles.first())
+    }
+
+    fn resource_url(&self, format: &str) -> Option<&WmtsResourceUrl> {
+        self.resource_urls.iter().find(|url| url.format == format)
+    }
+}
+
+/// One zoom level of a tile matrix set
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileMatrix {
+    pub identifier: String,
+    pub scale_denominator: f64,
+    /// Top-left corner in the set's CRS, as written in the document
+    pub top_left_corner: (f64, f64),
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub matrix_width: u32,
+    pub matrix_height: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileMatrixSet {
+    pub identifier: String,
+    pub supported_crs: String,
+    pub well_known_scale_set: Option<String>,
+    pub matrices: Vec<TileMatrix>,
+}
+
+/// Position of a tile matrix within the GoogleMapsCompatible pyramid
+#[derive(Debug, Clone, PartialEq)]
+struct MatrixLevel {
+    identifier: String,
+    matrix_width: u32,
+    matrix_height: u32,
+}
+
+impl TileMatrixSet {
+    /// Whether the set is in a Web Mercator CRS
+    pub fn is_web_mercator(&self) -> bool {
+        let code = self.supported_crs.rsplit(':').next().unwrap_or_default();
+        matches!(code, "3857" | "900913" | "102100" | "102113" | "3785")
+    }
+
+    /// Map each matrix to the zoom level it stands for, failing when a matrix
+    /// does not coincide with a GoogleMapsCompatible level
+    fn google_levels(&self) -> Result<HashMap<u8, MatrixLevel>> {
+        let incompatible = |reason: String| -> Result<HashMap<u8, MatrixLevel>> {
+            Err(Box::new(crate::Error::Layer(format!(
+                "WMTS tile matrix set {} is not GoogleMapsCompatible: {}",
+                self.identifier, reason
+            ))))
+        };
+
+        if !self.is_web_mercator() {
+            return incompatible(format!("CRS {} is not EPSG:3857", self.supported_crs));
+        }
+
+        let mut levels = HashMap::default();
+        for matrix in &self.matrices {
+            if matrix.tile_width != matrix.tile_height {
+                return incompatible(format!("matrix {} has non-square tiles", matrix.identifier));
+            }
+            let zoom_0_scale = GOOGLE_ZOOM_0_SCALE * 256.0 / matrix.tile_width as f64;
+            let zoom = (zoom_0_scale / matrix.scale_denominator).log2();
+            if (zoom.round() - zoom).abs() > 0.01 || !(0.0..=30.0).contains(&zoom.round()) {
+                return incompatible(format!(
+                    "matrix {} has scale 1:{} which is not a Web Mercator zoom level",
+                    matrix.identifier, matrix.scale_denominator
+                ));
+            }
+            let zoom = zoom.round() as u8;
+
+            let (x, y) = matrix.top_left_corner;
+            if (x + MERCATOR_EXTENT).abs() > 1.0 || (y - MERCATOR_EXTENT).abs() > 1.0 {
+                return incompatible(format!(
+                    "matrix {} starts at ({}, {}) instead of the world's top-left corner",
+                    matrix.identifier, x, y
+                ));
+            }
+            let tiles = 1u64 << zoom;
+            if matrix.matrix_width as u64 > tiles || matrix.matrix_height as u64 > tiles {
+                return incompatible(format!(
+                    "matrix {} is larger than zoom level {}",
+                    matrix.identifier, zoom
+                ));
+            }
+
+            let level = MatrixLevel {
+                identifier: matrix.identifier.clone(),
+                matrix_width: matrix.matrix_width,
+                matrix_height: matrix.matrix_height,
+            };
+            if levels.insert(zoom, level).is_some() {
+                return incompatible(format!("zoom level {} appears twice", zoom));
+            }
+        }
+
+        if levels.is_empty() {
+            return incompatible("it has no tile matrices".to_string());
+        }
+        Ok(levels)
+    }
+}
+
+/// Contents of a WMTS `GetCapabilities` response
+#[derive(Debug, Clone, PartialEq)]
+pub struct WmtsCapabilities {
+    pub title: Option<String>,
+    pub layers: Vec<WmtsLayer>,
+    pub tile_matrix_sets: Vec<TileMatrixSet>,
+    /// `GetTile` URL accepting key-value-pair requests
+    pub get_tile_kvp_url: Option<String>,
+}
+
+fn parse_error(message: String) -> Box<dyn std::error::Error + Send + Sync> {
+    Box::new(crate::Error::ParseError(message))
+}
+
+fn children<'a, 'input: 'a>(
+    node: Node<'a, 'input>,
+    name: &'static str,
+) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children()
+        .filter(move |child| child.is_element() && child.tag_name().name() == name)
+}
+
+fn child<'a, 'input: 'a>(node: Node<'a, 'input>, name: &'static str) -> Option<Node<'a, 'input>> {
+    children(node, name).next()
+}
+
+fn child_text(node: Node, name: &'static str) -> Option<String> {
+    child(node, name)
+        .and_then(|child| child.text())
+        .map(|text| text.trim().to_string())
+        .filter(|text| !text.is_empty())
+}
+
+fn required_text(node: Node, name: &'static str, context: &str) -> Result<String> {
+    child_text(node, name).ok_or_else(|| parse_error(format!("{} is missing {}", context, name)))
+}
+
+fn required_number<T: std::str::FromStr>(
+    node: Node,
+    name: &'static str,
+    context: &str,
+) -> Result<T> {
+    let text = required_text(node, name, context)?;
+    text.parse()
+        .map_err(|_| parse_error(format!("{} has an invalid {}: {}", context, name, text)))
+}
+
+fn parse_corner(text: &str) -> Option<(f64, f64)> {
+    let mut parts = text.split_whitespace().map(str::parse::<f64>);
+    match (parts.next(), parts.next()) {
+        (Some(Ok(a)), Some(Ok(b))) => Some((a, b)),
+        _ => None,
+    }
+}
+
+impl WmtsCapabilities {
+    /// Parse a capabilities XML document
+    pub fn parse(xml: &str) -> Result<Self> {
+        let document = roxmltree::Document::parse(xml)
+            .map_err(|e| parse_error(format!("Invalid WMTS capabilities XML: {}", e)))?;
+        let root = document.root_element();
+        if root.tag_name().name() != "Capabilities" {
+            return Err(parse_error(format!(
+                "Expected a WMTS Capabilities document, found <{}>",
+                root.tag_name().name()
+            )));
+        }
+
+        let title = child(root, "ServiceIdentification").and_then(|id| child_text(id, "Title"));
+        let contents = child(root, "Contents")
+            .ok_or_else(|| parse_error("WMTS capabilities have no Contents".to_string()))?;
+
+        Ok(Self {
+            title,
+            layers: children(contents, "Layer")
+                .map(Self::parse_layer)
+                .collect::<Result<_>>()?,
+            tile_matrix_sets: children(contents, "TileMatrixSet")
+                .map(Self::parse_tile_matrix_set)
+                .collect::<Result<_>>()?,
+            get_tile_kvp_url: Self::parse_kvp_url(root),
+        })
+    }
+
+    /// Download and parse the capabilities document at `url`
+    pub async fn fetch(url: &str) -> Result<Self> {
+        Self::fetch_with(url, &RequestOptions::default()).await
+    }
+
+    /// Download and parse the capabilities document at `url`, sending the
+    /// service's headers and credentials
+    pub async fn fetch_with(url: &str, request: &RequestOptions) -> Result<Self> {
+        let response = request.get(&HTTP_CLIENT, url).await?.send().await?;
+        if !response.status().is_success() {
+            return Err(format!("HTTP {} for WMTS capabilities {}", response.status(), url).into());
+        }
+        Self::parse(&response.text().await?)
+    }
+
+    fn parse_layer(node: Node) -> Result<WmtsLayer> {
+        let identifier = required_text(node, "Identifier", "WMTS layer")?;
+        let bounds = child(node, "WGS84BoundingBox").and_then(|bbox| {
+            let lower = parse_corner(&child_text(bbox, "LowerCorner")?)?;
+            let upper = parse_corner(&child_text(bbox, "UpperCorner")?)?;
+            Some(LatLngBounds::new(
+                LatLng::new(lower.1, lower.0),
+                LatLng::new(upper.1, upper.0),
+            ))
+        });
+
+        let styles = children(node, "Style")
+            .filter_map(|style| {
+                Some(WmtsStyle {
+                    identifier: child_text(style, "Identifier")?,
+                    title: child_text(style, "Title"),
+                    is_default: style.attribute("isDefault") == Some("true"),
+                })
+            })
+            .collect();
+
+        let dimensions = children(node, "Dimension")
+            .filter_map(|dimension| {
+                Some(WmtsDimension {
+                    identifier: child_text(dimension, "Identifier")?,
+                    default: child_text(dimension, "Default"),
+                    values: children(dimension, "Value")
+                        .filter_map(|value| value.text())
+                        .map(|value| value.trim().to_string())
+                        .collect(),
+                })
+            })
+            .collect();
+
+        let resource_urls = children(node, "ResourceURL")
+            .filter(|url| url.attribute("resourceType") == Some("tile"))
+            .filter_map(|url| {
+                Some(WmtsResourceUrl {
+                    format: url.attribute("format")?.to_string(),
+                    template: url.attribute("template")?.to_string(),
+                })
+            })
+            .collect();
+
+        Ok(WmtsLayer {
+            title: child_text(node, "Title"),
+            bounds,
+            styles,
+            formats: children(node, "Format")
+                .filter_map(|format| format.text())
+                .map(|format| format.trim().to_string())
+                .collect(),
+            dimensions,
+            tile_matrix_sets: children(node, "TileMatrixSetLink")
+                .filter_map(|link| child_text(link, "TileMatrixSet"))
+                .collect(),
+            resource_urls,
+            identifier,
+        })
+    }
+
+    fn parse_tile_matrix_set(node: Node) -> Result<TileMatrixSet> {
+        let identifier = required_text(node, "Identifier", "WMTS TileMatrixSet")?;
+        let context = format!("WMTS TileMatrixSet {}", identifier);
+
+        let matrices = children(node, "TileMatrix")
+            .map(|matrix| {
+                let id = required_text(matrix, "Identifier", &context)?;
+                let context = format!("TileMatrix {} of {}", id, identifier);
+                let corner = required_text(matrix, "TopLeftCorner", &context)?;
+                Ok(TileMatrix {
+                    scale_denominator: required_number(matrix, "ScaleDenominator", &context)?,
+                    top_left_corner: parse_corner(&corner).ok_or_else(|| {
+                        parse_error(format!(
+                            "{} has an invalid TopLeftCorner: {}",
+                            context, corner
+                        ))
+                    })?,
+                    tile_width: required_number(matrix, "TileWidth", &context)?,
+                    tile_height: required_number(matrix, "TileHeight", &context)?,
+                    matrix_width: required_number(matrix, "MatrixWidth", &context)?,
+                    matrix_height: required_number(matrix, "MatrixHeight", &context)?,
+                    identifier: id,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(TileMatrixSet {
+            supported_crs: required_text(node, "SupportedCRS", &context)?,
+            well_known_scale_set: child_text(node, "WellKnownScaleSet"),
+            matrices,
+            identifier,
+        })
+    }
+
+    /// The `GetTile` GET endpoint allowing KVP encoding (or not restricting it)
+    fn parse_kvp_url(root: Node) -> Option<String> {
+        let operation = children(child(root, "OperationsMetadata")?, "Operation")
+            .find(|operation| operation.attribute("name") == Some("GetTile"))?;
+
+        children(child(child(operation, "DCP")?, "HTTP")?, "Get")
+            .find(|get| {
+                let encodings: Vec<_> = children(*get, "Constraint")
+                    .filter(|constraint| constraint.attribute("name") == Some("GetEncoding"))
+                    .flat_map(|constraint| constraint.descendants())
+                    .filter(|value| value.is_element() && value.tag_name().name() == "Value")
+                    .filter_map(|value| value.text())
+                    .collect();
+                encodings.is_empty() || encodings.iter().any(|value| value.trim() == "KVP")
+            })
+            .and_then(|get| {
+                get.attributes()
+                    .find(|attribute| attribute.name() == "href")
+                    .map(|attribute| attribute.value().to_string())
+            })
+    }
+
+    pub fn layer(&self, identifier: &str) -> Option<&WmtsLayer> {
+        self.layers
+            .iter()
+            .find(|layer| layer.identifier == identifier)
+    }
+
+    pub fn tile_matrix_set(&self, identifier: &str) -> Option<&TileMatrixSet> {
+        self.tile_matrix_sets
+            .iter()
+            .find(|set| set.identifier == identifier)
+    }
+}
+
+/// Tile source for one layer and tile matrix set of a WMTS service
+///
+/// Built with [`WmtsTileSource::from_capabilities`], which picks the layer's
+/// default style, its first format and RESTful encoding when the layer
+/// publishes a template for that format, KVP otherwise.
+#[derive(Debug, Clone)]
+pub struct WmtsTileSource {
+    layer: WmtsLayer,
+    matrix_set: String,
+    levels: HashMap<u8, MatrixLevel>,
+    kvp_url: Option<String>,
+    style: String,
+    format: String,
+    encoding: WmtsEncoding,
+    dimensions: Vec<(String, String)>,
+    tile_size: u32,
+    request: Option<RequestOptions>,
+}
+
+impl WmtsTileSource {
+    pub fn from_capabilities(
+        capabilities: &WmtsCapabilities,
+        layer: &str,
+        matrix_set: &str,
+    ) -> Result<Self> {
+        let layer = capabilities.layer(layer).ok_or_else(|| {
+            Box::new(crate::Error::Layer(format!(
+                "WMTS layer {} not found",
+                layer
+            )))
+        })?;
+        if !layer.tile_matrix_sets.iter().any(|set| set == matrix_set) {
+            return Err(Box::new(crate::Error::Layer(format!(
+                "WMTS layer {} is not published in tile matrix set {}",
+                layer.identifier, matrix_set
+            ))));
+        }
+        let set = capabilities.tile_matrix_set(matrix_set).ok_or_else(|| {
+            Box::new(crate::Error::Layer(format!(
+                "WMTS tile matrix set {} not found",
+                matrix_set
+            )))
+        })?;
+        let levels = set.google_levels()?;
+
+        let format = layer
+            .formats
+            .first()
+            .or_else(|| layer.resource_urls.first().map(|url| &url.format))
+            .cloned()
+            .unwrap_or_else(|| "image/png".to_string());
+        let encoding = if layer.resource_url(&format).is_some() {
+            WmtsEncoding::Rest
+        } else {
+            WmtsEncoding::Kvp
+        };
+        let source = Self {
+            matrix_set: set.identifier.clone(),
+            tile_size: set.matrices[0].tile_width,
+            levels,
+            kvp_url: capabilities.get_tile_kvp_url.clone(),
+            style: layer
+                .default_style()
+                .map(|style| style.identifier.clone())
+                .unwrap_or_else(|| "default".to_string()),
+            dimensions: layer
+                .dimensions
+                .iter()
+                .filter_map(|d| Some((d.identifier.clone(), d.default.clone()?)))
+                .collect(),
+            format,
+            encoding,
+            layer: layer.clone(),
+            request: None,
+        };
+        source.check_encoding()?;
+        Ok(source)
+    }
+
+    /// Use another of the layer's styles
+    pub fn with_style(mut self, style: &str) -> Result<Self> {
+        if !self.layer.styles.iter().any(|s| s.identifier == style) {
+            return Err(Box::new(crate::Error::Layer(format!(
+                "WMTS layer {} has no style {}",
+                self.layer.identifier, style
+            ))));
+        }
+        self.style = style.to_string();
+        Ok(self)
+    }
+
+    /// Request another of the layer's image formats
+    pub fn with_format(mut self, format: &str) -> Result<Self> {
+        let listed = self.layer.formats.iter().any(|f| f == format);
+        if !listed && self.layer.resource_url(format).is_none() {
+            return Err(Box::new(crate::Error::Layer(format!(
+                "WMTS layer {} is not available as {}",
+                self.layer.identifier, format
+            ))));
+        }
+        self.format = format.to_string();
+        self.check_encoding()?;
+        Ok(self)
+    }
+
+    pub fn with_encoding(mut self, encoding: WmtsEncoding) -> Result<Self> {
+        self.encoding = encoding;
+        self.check_encoding()?;
+        Ok(self)
+    }
+
+    /// Set a dimension value such as `Time`, overriding its default
+    pub fn with_dimension(mut self, identifier: &str, value: impl Into<String>) -> Self {
+        self.dimensions.retain(|(id, _)| id != identifier);
+        self.dimensions.push((identifier.to_string(), value.into()));
+        self
+    }
+
+    /// Send these headers, User-Agent and credentials with tile requests
+    pub fn with_request_options(mut self, request: RequestOptions) -> Self {
+        self.request = Some(request);
+        self
+    }
+
+    pub fn encoding(&self) -> WmtsEncoding {
+        self.encoding
+    }
+
+    fn check_encoding(&self) -> Result<()> {
+        let available = match self.encoding {
+            WmtsEncoding::Rest => self.layer.resource_url(&self.format).is_some(),
+            WmtsEncoding::Kvp => self.kvp_url.is_some(),
+        };
+        if available {
+            return Ok(());
+        }
+        Err(Box::new(crate::Error::Layer(format!(
+            "WMTS layer {} offers no {:?} encoding for {}",
+            self.layer.identifier, self.encoding, self.format
+        ))))
+    }
+
+    /// Zoom range, tile size and extent of the layer for a [`TileLayerOptions`]
+    pub fn apply_to_options(&self, options: &mut TileLayerOptions) {
+        if let (Some(min), Some(max)) = (self.levels.keys().min(), self.levels.keys().max()) {
+            options.min_zoom = *min;
+            options.max_zoom = *max;
+        }
+        options.tile_size = self.tile_size;
+        options.reference_system = "EPSG:3857".to_string();
+        if let Some(bounds) = &self.layer.bounds {
+            options.bounds = Some(bounds.clone());
+        }
+    }
+
+    fn matrix_identifier(&self, zoom: u8) -> String {
+        self.levels
+            .get(&zoom)
+            .map(|level| level.identifier.clone())
+            .unwrap_or_else(|| zoom.to_string())
+    }
+
+    fn placeholder_value(&self, key: &str, coord: TileCoord) -> Option<String> {
+        match key.to_ascii_lowercase().as_str() {
+            "tilematrixset" => Some(self.matrix_set.clone()),
+            "tilematrix" => Some(self.matrix_identifier(coord.z)),
+            "tilerow" => Some(coord.y.to_string()),
+            "tilecol" => Some(coord.x.to_string()),
+            "style" => Some(self.style.clone()),
+            "layer" => Some(self.layer.identifier.clone()),
+            _ => self
+                .dimensions
+                .iter()
+                .find(|(id, _)| id.eq_ignore_ascii_case(key))
+                .map(|(_, value)| value.clone()),
+        }
+    }
+
+    fn rest_url(&self, template: &str, coord: TileCoord) -> String {
+        let mut url = String::with_capacity(template.len() + 32);
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            url.push_str(&rest[..start]);
+            let after = &rest[start + 1..];
+            let Some(end) = after.find('}') else {
+                url.push_str(&rest[start..]);
+                rest = "";
+                break;
+            };
+            match self.placeholder_value(&after[..end], coord) {
+                Some(value) => url.push_str(&value),
+                None => url.push_str(&rest[start..start + end + 2]),
+            }
+            rest = &after[end + 1..];
+        }
+        url.push_str(rest);
+        url
+    }
+
+    fn kvp_url(&self, base: &str, coord: TileCoord) -> String {
+        let mut query = vec![
+            ("SERVICE".to_string(), "WMTS".to_string()),
+            ("REQUEST".to_string(), "GetTile".to_string()),
+            ("VERSION".to_string(), "1.0.0".to_string()),
+            ("LAYER".to_string(), self.layer.identifier.clone()),
+            ("STYLE".to_string(), self.style.clone()),
+            ("FORMAT".to_string(), self.format.clone()),
+            ("TILEMATRIXSET".to_string(), self.matrix_set.clone()),
+            ("TILEMATRIX".to_string(), self.matrix_identifier(coord.z)),
+            ("TILEROW".to_string(), coord.y.to_string()),
+            ("TILECOL".to_string(), coord.x.to_string()),
+        ];
+        query.extend(self.dimensions.iter().cloned());
+        append_query(base, &query)
+    }
+}
+
+impl TileSource for WmtsTileSource {
+    fn url(&self, coord: TileCoord) -> String {
+        match self.encoding {
+            WmtsEncoding::Rest => match self.layer.resource_url(&self.format) {
+                Some(resource) => self.rest_url(&resource.template, coord),
+                None => String::new(),
+            },
+            WmtsEncoding::Kvp => self.kvp_url(self.kvp_url.as_deref().unwrap_or_default(), coord),
+        }
+    }
+
+    fn has_tile(&self, coord: TileCoord) -> bool {
+        let Some(level) = self.levels.get(&coord.z) else {
+            return false;
+        };
+        if coord.x >= level.matrix_width || coord.y >= level.matrix_height {
+            return false;
+        }
+        match &self.layer.bounds {
+            Some(bounds) => bounds.intersects_bounds(&coord.bounds()),
+            None => true,
+        }
+    }
+
+    fn request_options(&self) -> Option<&RequestOptions> {
+        self.request.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAPABILITIES: &str = include_str!("../../../tests/fixtures/wmts/capabilities.xml");
+    const KVP_CAPABILITIES: &str =
+        include_str!("../../../tests/fixtures/wmts/capabilities_kvp.xml");
+
+    #[test]
+    fn test_parse_capabilities() {
+        let capabilities = WmtsCapabilities::parse(CAPABILITIES).unwrap();
+        assert_eq!(
+            capabilities.title.as_deref(),
+            Some("Example Topographic Service")
+        );
+        assert_eq!(
+            capabilities.get_tile_kvp_url.as_deref(),
+            Some("https://tiles.example.org/wmts?")
+        );
+
+        let topo = capabilities.layer("topo").unwrap();
+        assert_eq!(topo.formats, vec!["image/png", "image/jpeg"]);
+        assert_eq!(topo.default_style().unwrap().identifier, "default");
+        assert_eq!(topo.styles.len(), 2);
+        assert_eq!(topo.dimensions[0].default.as_deref(), Some("2024"));
+        assert_eq!(topo.tile_matrix_sets.len(), 3);
+        let bounds = topo.bounds.as_ref().unwrap();
+        assert_eq!(bounds.south_west, LatLng::new(35.0, -10.5));
+        assert_eq!(bounds.north_east, LatLng::new(60.0, 30.25));
+
+        let google = capabilities
+            .tile_matrix_set("GoogleMapsCompatible")
+            .unwrap();
+        assert!(google.is_web_mercator());
+        assert_eq!(google.matrices.len(), 4);
+        assert_eq!(google.matrices[3].matrix_width, 8);
+        assert!(!capabilities
+            .tile_matrix_set("WorldCRS84Quad")
+            .unwrap()
+            .is_web_mercator());
+
+        assert!(WmtsCapabilities::parse("<WMS_Capabilities/>").is_err());
+        assert!(WmtsCapabilities::parse("not xml").is_err());
+    }
+
+    #[test]
+    fn test_rest_and_kvp_urls() {
+        let capabilities = WmtsCapabilities::parse(CAPABILITIES).unwrap();
+        let source =
+            WmtsTileSource::from_capabilities(&capabilities, "topo", "GoogleMapsCompatible")
+                .unwrap();
+        assert_eq!(source.encoding(), WmtsEncoding::Rest);
+        assert_eq!(
+            source.url(TileCoord::new(3, 2, 3)),
+            "https://tiles.example.org/wmts/rest/topo/default/2024/GoogleMapsCompatible/3/2/3.png"
+        );
+
+        let source = source
+            .with_style("grey")
+            .unwrap()
+            .with_format("image/jpeg")
+            .unwrap()
+            .with_dimension("Time", "2023")
+            .with_encoding(WmtsEncoding::Kvp)
+            .unwrap();
+        assert_eq!(
+            source.url(TileCoord::new(3, 2, 3)),
+            "https://tiles.example.org/wmts?SERVICE=WMTS&REQUEST=GetTile&VERSION=1.0.0\
+             &LAYER=topo&STYLE=grey&FORMAT=image%2Fjpeg&TILEMATRIXSET=GoogleMapsCompatible\
+             &TILEMATRIX=3&TILEROW=2&TILECOL=3&Time=2023"
+        );
+        assert!(source.clone().with_style("sepia").is_err());
+        assert!(source.with_format("image/webp").is_err());
+
+        // Matrix identifiers need not be zoom numbers
+        let source =
+            WmtsTileSource::from_capabilities(&capabilities, "topo", "WebMercatorFrom2").unwrap();
+        assert!(source
+            .url(TileCoord::new(0, 0, 2))
+            .contains("/EPSG:3857:2/0/0.png"));
+        assert!(!source.has_tile(TileCoord::new(0, 0, 1)));
+
+        let mut options = TileLayerOptions::default();
+        source.apply_to_options(&mut options);
+        assert_eq!((options.min_zoom, options.max_zoom), (2, 3));
+        assert!(options.bounds.is_some());
+    }
+
+    #[test]
+    fn test_kvp_only_service_and_incompatible_sets() {
+        let capabilities = WmtsCapabilities::parse(KVP_CAPABILITIES).unwrap();
+        let source =
+            WmtsTileSource::from_capabilities(&capabilities, "parcels", "EPSG:3857").unwrap();
+        assert_eq!(source.encoding(), WmtsEncoding::Kvp);
+        let url = source.url(TileCoord::new(1, 0, 1));
+        assert!(url.starts_with("https://geo.example.gov/service/wmts?token=abc&SERVICE=WMTS"));
+        assert!(url.contains("&TILEMATRIX=01&TILEROW=0&TILECOL=1"));
+        assert!(source.clone().with_encoding(WmtsEncoding::Rest).is_err());
+        assert!(source.has_tile(TileCoord::new(1, 1, 1)));
+        assert!(!source.has_tile(TileCoord::new(0, 0, 2)));
+
+        let error = WmtsTileSource::from_capabilities(&capabilities, "parcels", "NationalGrid")
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("not GoogleMapsCompatible"), "{}", error);
+
+        let capabilities = WmtsCapabilities::parse(CAPABILITIES).unwrap();
+        let error = WmtsTileSource::from_capabilities(&capabilities, "topo", "WorldCRS84Quad")
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("is not EPSG:3857"), "{}", error);
+        assert!(
+            WmtsTileSource::from_capabilities(&capabilities, "ortho", "WorldCRS84Quad").is_err()
+        );
+        assert!(
+            WmtsTileSource::from_capabilities(&capabilities, "roads", "GoogleMapsCompatible")
+                .is_err()
+        );
+    }
+}
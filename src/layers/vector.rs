@@ -535,84 +535,101 @@ impl VectorLayer {
         viewport: &Viewport,
         feature_data: &VectorFeatureData,
     ) -> Result<()> {
-        use crate::rendering::context::StyleConversion;
-
-        let opacity_multiplier = self.opacity();
-        let effective_style = self.get_effective_style(feature_data);
+        render_vector_feature(
+            context,
+            viewport,
+            &feature_data.feature,
+            self.get_effective_style(feature_data),
+            self.opacity(),
+        )
+    }
+}
 
-        match &feature_data.feature {
-            VectorFeature::Point { position, .. } => {
-                let screen_pos = viewport.lat_lng_to_pixel(position);
-                if let VectorFeatureStyle::Point(style) = effective_style {
-                    let render_style = style.to_render_style(opacity_multiplier);
-                    context.render_point(&screen_pos, &render_style)?;
-                }
+/// Project `feature` to screen space and queue it on `context`
+///
+/// Geometry is drawn only when `style` matches its kind (point, line or
+/// polygon), so a feature with a mismatched style is skipped.
+pub(crate) fn render_vector_feature(
+    context: &mut RenderContext,
+    viewport: &Viewport,
+    feature: &VectorFeature,
+    style: &VectorFeatureStyle,
+    opacity_multiplier: f32,
+) -> Result<()> {
+    use crate::rendering::context::StyleConversion;
+
+    match feature {
+        VectorFeature::Point { position, .. } => {
+            let screen_pos = viewport.lat_lng_to_pixel(position);
+            if let VectorFeatureStyle::Point(style) = style {
+                let render_style = style.to_render_style(opacity_multiplier);
+                context.render_point(&screen_pos, &render_style)?;
             }
-            VectorFeature::LineString { points, .. } => {
-                let screen_points: Vec<Point> = points
-                    .iter()
-                    .map(|p| viewport.lat_lng_to_pixel(p))
-                    .collect();
-                if let VectorFeatureStyle::Line(style) = effective_style {
-                    let render_style = style.to_render_style(opacity_multiplier);
-                    context.render_line(&screen_points, &render_style)?;
-                }
+        }
+        VectorFeature::LineString { points, .. } => {
+            let screen_points: Vec<Point> = points
+                .iter()
+                .map(|p| viewport.lat_lng_to_pixel(p))
+                .collect();
+            if let VectorFeatureStyle::Line(style) = style {
+                let render_style = style.to_render_style(opacity_multiplier);
+                context.render_line(&screen_points, &render_style)?;
             }
-            VectorFeature::Polygon {
-                exterior, holes, ..
-            } => {
-                let screen_exterior: Vec<Point> = exterior
-                    .iter()
-                    .map(|p| viewport.lat_lng_to_pixel(p))
-                    .collect();
-                let screen_holes: Vec<Vec<Point>> = holes
-                    .iter()
-                    .map(|hole| hole.iter().map(|p| viewport.lat_lng_to_pixel(p)).collect())
-                    .collect();
-                if let VectorFeatureStyle::Polygon(style) = effective_style {
-                    let render_style = style.to_render_style(opacity_multiplier);
-                    context.render_polygon(&screen_exterior, &screen_holes, &render_style)?;
-                }
+        }
+        VectorFeature::Polygon {
+            exterior, holes, ..
+        } => {
+            let screen_exterior: Vec<Point> = exterior
+                .iter()
+                .map(|p| viewport.lat_lng_to_pixel(p))
+                .collect();
+            let screen_holes: Vec<Vec<Point>> = holes
+                .iter()
+                .map(|hole| hole.iter().map(|p| viewport.lat_lng_to_pixel(p)).collect())
+                .collect();
+            if let VectorFeatureStyle::Polygon(style) = style {
+                let render_style = style.to_render_style(opacity_multiplier);
+                context.render_polygon(&screen_exterior, &screen_holes, &render_style)?;
             }
-            VectorFeature::MultiPoint { points, .. } => {
-                if let VectorFeatureStyle::Point(style) = effective_style {
-                    let render_style = style.to_render_style(opacity_multiplier);
-                    for position in points {
-                        let screen_pos = viewport.lat_lng_to_pixel(position);
-                        context.render_point(&screen_pos, &render_style)?;
-                    }
+        }
+        VectorFeature::MultiPoint { points, .. } => {
+            if let VectorFeatureStyle::Point(style) = style {
+                let render_style = style.to_render_style(opacity_multiplier);
+                for position in points {
+                    let screen_pos = viewport.lat_lng_to_pixel(position);
+                    context.render_point(&screen_pos, &render_style)?;
                 }
             }
-            VectorFeature::MultiLineString { lines, .. } => {
-                if let VectorFeatureStyle::Line(style) = effective_style {
-                    let render_style = style.to_render_style(opacity_multiplier);
-                    for line in lines {
-                        let screen_points: Vec<Point> =
-                            line.iter().map(|p| viewport.lat_lng_to_pixel(p)).collect();
-                        context.render_line(&screen_points, &render_style)?;
-                    }
+        }
+        VectorFeature::MultiLineString { lines, .. } => {
+            if let VectorFeatureStyle::Line(style) = style {
+                let render_style = style.to_render_style(opacity_multiplier);
+                for line in lines {
+                    let screen_points: Vec<Point> =
+                        line.iter().map(|p| viewport.lat_lng_to_pixel(p)).collect();
+                    context.render_line(&screen_points, &render_style)?;
                 }
             }
-            VectorFeature::MultiPolygon { polygons, .. } => {
-                if let VectorFeatureStyle::Polygon(style) = effective_style {
-                    let render_style = style.to_render_style(opacity_multiplier);
-                    for (exterior, holes) in polygons {
-                        let screen_exterior: Vec<Point> = exterior
-                            .iter()
-                            .map(|p| viewport.lat_lng_to_pixel(p))
-                            .collect();
-                        let screen_holes: Vec<Vec<Point>> = holes
-                            .iter()
-                            .map(|hole| hole.iter().map(|p| viewport.lat_lng_to_pixel(p)).collect())
-                            .collect();
-                        context.render_polygon(&screen_exterior, &screen_holes, &render_style)?;
-                    }
+        }
+        VectorFeature::MultiPolygon { polygons, .. } => {
+            if let VectorFeatureStyle::Polygon(style) = style {
+                let render_style = style.to_render_style(opacity_multiplier);
+                for (exterior, holes) in polygons {
+                    let screen_exterior: Vec<Point> = exterior
+                        .iter()
+                        .map(|p| viewport.lat_lng_to_pixel(p))
+                        .collect();
+                    let screen_holes: Vec<Vec<Point>> = holes
+                        .iter()
+                        .map(|hole| hole.iter().map(|p| viewport.lat_lng_to_pixel(p)).collect())
+                        .collect();
+                    context.render_polygon(&screen_exterior, &screen_holes, &render_style)?;
                 }
             }
         }
-
-        Ok(())
     }
+
+    Ok(())
 }
 
 impl LayerTrait for VectorLayer {
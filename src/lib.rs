@@ -73,7 +73,7 @@ pub use core::{
     builder::MapBuilder,
     config::{
         FrameTimingConfig, GpuRenderingConfig, MapPerformanceOptions, MapPerformanceProfile,
-        TextureFilterMode, TileLoadingConfig,
+        TextureFilterMode, TileLoadingConfig, TileMemoryBudget,
     },
     geo::{LatLng, LatLngBounds, Point, TileCoord},
     map::{Map as CoreMap, MapOptions},
@@ -8,7 +8,7 @@ pub use crate::core::{
     builder::MapBuilder,
     config::{
         FrameTimingConfig, GpuRenderingConfig, MapPerformanceOptions, MapPerformanceProfile,
-        TextureFilterMode, TileLoadingConfig, UnifiedMapConfig,
+        TextureFilterMode, TileLoadingConfig, TileMemoryBudget, UnifiedMapConfig,
     },
     geo::{LatLng, LatLngBounds, Point, TileCoord},
     map::{Map as CoreMap, MapOptions, UpdateOrchestrator, UpdatePerformanceMetrics},
@@ -47,8 +47,10 @@ pub use crate::runtime::{
 
 pub use crate::layers::tile::{
     cache::TileCache,
+    disk_cache::{DiskCacheConfig, DiskTileCache},
     loader::{TileLoader, TileLoaderConfig},
-    source::TileSource,
+    offline::{OfflineDownload, OfflineRegion},
+    source::{TemplateTileSource, TileSource},
 };
 
 pub use crate::rendering::{context::RenderContext, pipeline::RenderPipeline};
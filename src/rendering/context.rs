@@ -1,5 +1,7 @@
-use crate::{core::geo::Point, Result};
+use super::filter::RasterFilter;
+use crate::{core::geo::Point, layers::tile::DecodedTile, Result};
 use egui::Color32;
+use std::sync::Arc;
 
 /// Unified style conversion trait to eliminate duplicate conversion patterns
 pub trait StyleConversion<T> {
@@ -79,8 +81,18 @@ pub struct RenderContext {
     pub clip_bounds: Option<(Point, Point)>,
     /// Whether clipping is enabled
     pub clipping_enabled: bool,
+    /// Physical pixels per screen coordinate (egui's pixels per point)
+    pub pixels_per_point: f32,
+    /// Colour filter given to the tiles drawn next
+    pub raster_filter: Option<RasterFilter>,
+    /// Colour filter of the map's theme, for basemap layers without a
+    /// filter of their own
+    pub basemap_filter: Option<RasterFilter>,
 }
 
+/// Texture coordinates covering a whole image
+pub const FULL_UV: (Point, Point) = (Point { x: 0.0, y: 0.0 }, Point { x: 1.0, y: 1.0 });
+
 /// Commands that can be issued to the render context
 #[derive(Debug, Clone)]
 pub enum DrawCommand {
@@ -100,7 +112,14 @@ pub enum DrawCommand {
     Tile {
         data: Vec<u8>,
         bounds: (Point, Point), // min, max screen coordinates
+        /// Part of the image drawn into `bounds`, in 0..1 texture coordinates
+        uv: (Point, Point),
         opacity: f32,
+        /// Pixels of `data` decoded ahead of time, drawn instead of decoding
+        /// `data` on the UI thread
+        image: Option<Arc<DecodedTile>>,
+        /// Colour filter of the layer drawing the tile, if it has one
+        filter: Option<RasterFilter>,
     },
     /// Tile that already lives in an egui texture atlas
     TileTextured {
@@ -119,9 +138,18 @@ impl RenderContext {
             drawing_queue: Vec::new(),
             clip_bounds: None,
             clipping_enabled: false,
+            pixels_per_point: 1.0,
+            raster_filter: None,
+            basemap_filter: None,
         })
     }
 
+    /// Set the display scale, e.g. `2.0` on a retina display
+    pub fn with_pixels_per_point(mut self, pixels_per_point: f32) -> Self {
+        self.pixels_per_point = pixels_per_point;
+        self
+    }
+
     /// Begin a frame
     pub fn begin_frame(&mut self) -> Result<()> {
         self.drawing_queue.clear();
@@ -168,6 +196,34 @@ impl RenderContext {
 
     /// Render a tile to the screen with proper error handling and validation
     pub fn render_tile(&mut self, data: &[u8], bounds: (Point, Point), opacity: f32) -> Result<()> {
+        self.render_tile_region(data, FULL_UV, bounds, opacity)
+    }
+
+    /// Render the `uv` part of a tile image, in 0..1 texture coordinates,
+    /// stretched over `bounds`
+    ///
+    /// Used to draw a quarter of a parent tile in place of a missing child,
+    /// or an ancestor tile beyond a source's native zoom.
+    pub fn render_tile_region(
+        &mut self,
+        data: &[u8],
+        uv: (Point, Point),
+        bounds: (Point, Point),
+        opacity: f32,
+    ) -> Result<()> {
+        self.render_decoded_tile_region(data, None, uv, bounds, opacity)
+    }
+
+    /// Render the `uv` part of a tile image whose pixels may already be
+    /// decoded, sparing the renderer from decoding `data`
+    pub fn render_decoded_tile_region(
+        &mut self,
+        data: &[u8],
+        image: Option<Arc<DecodedTile>>,
+        uv: (Point, Point),
+        bounds: (Point, Point),
+        opacity: f32,
+    ) -> Result<()> {
         // Validate bounds
         if bounds.0.x >= bounds.1.x || bounds.0.y >= bounds.1.y {
             return Err("Invalid tile bounds".into());
@@ -178,7 +234,7 @@ impl RenderContext {
             return Err("Opacity must be between 0.0 and 1.0".into());
         }
 
-        // Apply clipping if enabled
+        // Apply clipping if enabled, cropping the image along with the bounds
         let final_bounds = if self.clipping_enabled {
             self.clip_bounds_to_viewport(bounds)
         } else {
@@ -186,11 +242,21 @@ impl RenderContext {
         };
 
         if let Some(clipped_bounds) = final_bounds {
-            // For now, just queue the tile for rendering
+            let width = bounds.1.x - bounds.0.x;
+            let height = bounds.1.y - bounds.0.y;
+            let uv_at = |point: Point| {
+                Point::new(
+                    uv.0.x + (point.x - bounds.0.x) / width * (uv.1.x - uv.0.x),
+                    uv.0.y + (point.y - bounds.0.y) / height * (uv.1.y - uv.0.y),
+                )
+            };
             self.drawing_queue.push(DrawCommand::Tile {
                 data: data.to_vec(),
                 bounds: clipped_bounds,
+                uv: (uv_at(clipped_bounds.0), uv_at(clipped_bounds.1)),
                 opacity,
+                image,
+                filter: self.raster_filter,
             });
         }
         // If clipped_bounds is None, the tile is completely outside viewport and shouldn't be rendered
@@ -222,6 +288,18 @@ impl RenderContext {
         Ok(())
     }
 
+    /// Set the colour filter of the map's theme, applied to basemap layers
+    /// that do not filter their tiles themselves
+    pub fn with_basemap_filter(mut self, filter: Option<RasterFilter>) -> Self {
+        self.basemap_filter = filter;
+        self
+    }
+
+    /// Filter the colours of the tiles drawn from now on, until changed
+    pub fn set_raster_filter(&mut self, filter: Option<RasterFilter>) {
+        self.raster_filter = filter;
+    }
+
     /// Set viewport clipping bounds (like Leaflet's clip rectangle)
     pub fn set_clip_bounds(&mut self, min: Point, max: Point) {
         self.clip_bounds = Some((min, max));
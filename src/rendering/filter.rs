@@ -0,0 +1,317 @@
+//! Colour filters for raster tiles
+//!
+//! A [`RasterFilter`] adjusts tile pixels as they are drawn, after the tile
+//! has been downloaded and decoded, so changing or animating it costs
+//! neither. The adjustments follow their CSS `filter` namesakes and are
+//! combined into a single colour matrix applied in one pass.
+
+use crate::layers::vector::SerializableColor;
+
+/// Luminance weights shared by the grayscale, saturate and hue-rotate
+/// matrices of the CSS filter effects spec
+const LUMA: [f32; 3] = [0.213, 0.715, 0.072];
+
+/// Colour adjustments applied to raster tiles when drawn
+///
+/// The default leaves tiles unchanged. Adjustments apply in field order.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct RasterFilter {
+    /// 0.0 keeps colours, 1.0 is fully gray
+    pub grayscale: f32,
+    /// 1.0 keeps colours, 0.0 is gray, above 1.0 oversaturates
+    pub saturation: f32,
+    /// Rotation of hues, in degrees
+    pub hue_rotate: f32,
+    /// 0.0 keeps colours, 1.0 fully inverts them
+    pub invert: f32,
+    /// Multiplier of each channel; 1.0 keeps colours
+    pub brightness: f32,
+    /// 1.0 keeps colours, 0.0 is mid gray
+    pub contrast: f32,
+    /// Colour multiplied into the tile, blended in by its alpha
+    pub tint: Option<SerializableColor>,
+}
+
+impl Default for RasterFilter {
+    fn default() -> Self {
+        Self {
+            grayscale: 0.0,
+            saturation: 1.0,
+            hue_rotate: 0.0,
+            invert: 0.0,
+            brightness: 1.0,
+            contrast: 1.0,
+            tint: None,
+        }
+    }
+}
+
+impl RasterFilter {
+    /// Dark version of a light basemap: inverted with hues turned back, so
+    /// water stays blue, and toned down
+    pub fn dark() -> Self {
+        Self {
+            invert: 1.0,
+            hue_rotate: 180.0,
+            brightness: 0.95,
+            contrast: 0.9,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_grayscale(mut self, amount: f32) -> Self {
+        self.grayscale = amount;
+        self
+    }
+
+    pub fn with_saturation(mut self, saturation: f32) -> Self {
+        self.saturation = saturation;
+        self
+    }
+
+    pub fn with_hue_rotate(mut self, degrees: f32) -> Self {
+        self.hue_rotate = degrees;
+        self
+    }
+
+    pub fn with_invert(mut self, amount: f32) -> Self {
+        self.invert = amount;
+        self
+    }
+
+    pub fn with_brightness(mut self, brightness: f32) -> Self {
+        self.brightness = brightness;
+        self
+    }
+
+    pub fn with_contrast(mut self, contrast: f32) -> Self {
+        self.contrast = contrast;
+        self
+    }
+
+    pub fn with_tint(mut self, tint: impl Into<SerializableColor>) -> Self {
+        self.tint = Some(tint.into());
+        self
+    }
+
+    /// Whether the filter leaves every pixel unchanged
+    pub fn is_identity(&self) -> bool {
+        let tint_is_identity = self
+            .tint
+            .is_none_or(|tint| tint.a == 0 || (tint.r, tint.g, tint.b) == (255, 255, 255));
+        self.grayscale == 0.0
+            && self.saturation == 1.0
+            && self.hue_rotate % 360.0 == 0.0
+            && self.invert == 0.0
+            && self.brightness == 1.0
+            && self.contrast == 1.0
+            && tint_is_identity
+    }
+
+    /// The filter `t` of the way from `self` to `to`, for animating between
+    /// filters
+    pub fn lerp(&self, to: &RasterFilter, t: f32) -> RasterFilter {
+        let t = t.clamp(0.0, 1.0);
+        let mix = |a: f32, b: f32| a + (b - a) * t;
+        let tint = match (self.tint, to.tint) {
+            (None, None) => None,
+            (from, to) => {
+                let clear = SerializableColor {
+                    r: 255,
+                    g: 255,
+                    b: 255,
+                    a: 0,
+                };
+                let (from, to) = (from.unwrap_or(clear), to.unwrap_or(clear));
+                let channel = |a: u8, b: u8| mix(a as f32, b as f32).round() as u8;
+                Some(SerializableColor {
+                    r: channel(from.r, to.r),
+                    g: channel(from.g, to.g),
+                    b: channel(from.b, to.b),
+                    a: channel(from.a, to.a),
+                })
+            }
+        };
+        RasterFilter {
+            grayscale: mix(self.grayscale, to.grayscale),
+            saturation: mix(self.saturation, to.saturation),
+            hue_rotate: mix(self.hue_rotate, to.hue_rotate),
+            invert: mix(self.invert, to.invert),
+            brightness: mix(self.brightness, to.brightness),
+            contrast: mix(self.contrast, to.contrast),
+            tint,
+        }
+    }
+
+    /// Filter unpremultiplied RGBA pixels in place; alpha is kept
+    pub fn apply(&self, rgba: &mut [u8]) {
+        if self.is_identity() {
+            return;
+        }
+        let matrix = self.matrix();
+        for pixel in rgba.chunks_exact_mut(4) {
+            let color = [
+                pixel[0] as f32 / 255.0,
+                pixel[1] as f32 / 255.0,
+                pixel[2] as f32 / 255.0,
+            ];
+            for (channel, row) in pixel.iter_mut().zip(matrix.iter()) {
+                let value = row[0] * color[0] + row[1] * color[1] + row[2] * color[2] + row[3];
+                *channel = (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+            }
+        }
+    }
+
+    /// All adjustments as one affine colour matrix, each row giving a
+    /// channel from red, green, blue and a constant
+    fn matrix(&self) -> ColorMatrix {
+        let saturation = self.saturation * (1.0 - self.grayscale.clamp(0.0, 1.0));
+        let mut matrix = saturate_matrix(saturation.max(0.0));
+        matrix = compose(&hue_rotate_matrix(self.hue_rotate), &matrix);
+
+        let invert = self.invert.clamp(0.0, 1.0);
+        matrix = compose(&scale_matrix([1.0 - 2.0 * invert; 3], invert), &matrix);
+        matrix = compose(&scale_matrix([self.brightness.max(0.0); 3], 0.0), &matrix);
+        let contrast = self.contrast.max(0.0);
+        matrix = compose(
+            &scale_matrix([contrast; 3], 0.5 * (1.0 - contrast)),
+            &matrix,
+        );
+
+        if let Some(tint) = self.tint {
+            let strength = tint.a as f32 / 255.0;
+            let factor = |channel: u8| 1.0 + (channel as f32 / 255.0 - 1.0) * strength;
+            matrix = compose(
+                &scale_matrix([factor(tint.r), factor(tint.g), factor(tint.b)], 0.0),
+                &matrix,
+            );
+        }
+        matrix
+    }
+}
+
+type ColorMatrix = [[f32; 4]; 3];
+
+/// `second` applied after `first`
+fn compose(second: &ColorMatrix, first: &ColorMatrix) -> ColorMatrix {
+    let mut out = [[0.0; 4]; 3];
+    for (row, out_row) in out.iter_mut().enumerate() {
+        for column in 0..4 {
+            out_row[column] = (0..3)
+                .map(|k| second[row][k] * first[k][column])
+                .sum::<f32>();
+        }
+        out_row[3] += second[row][3];
+    }
+    out
+}
+
+fn scale_matrix(scale: [f32; 3], offset: f32) -> ColorMatrix {
+    let mut matrix = [[0.0; 4]; 3];
+    for (channel, row) in matrix.iter_mut().enumerate() {
+        row[channel] = scale[channel];
+        row[3] = offset;
+    }
+    matrix
+}
+
+fn saturate_matrix(s: f32) -> ColorMatrix {
+    let mut matrix = [[0.0; 4]; 3];
+    for (channel, row) in matrix.iter_mut().enumerate() {
+        for (column, luma) in LUMA.iter().enumerate() {
+            let identity = if channel == column { 1.0 } else { 0.0 };
+            row[column] = luma + (identity - luma) * s;
+        }
+    }
+    matrix
+}
+
+fn hue_rotate_matrix(degrees: f32) -> ColorMatrix {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    [
+        [
+            0.213 + cos * 0.787 - sin * 0.213,
+            0.715 - cos * 0.715 - sin * 0.715,
+            0.072 - cos * 0.072 + sin * 0.928,
+            0.0,
+        ],
+        [
+            0.213 - cos * 0.213 + sin * 0.143,
+            0.715 + cos * 0.285 + sin * 0.140,
+            0.072 - cos * 0.072 - sin * 0.283,
+            0.0,
+        ],
+        [
+            0.213 - cos * 0.213 - sin * 0.787,
+            0.715 - cos * 0.715 + sin * 0.715,
+            0.072 + cos * 0.928 + sin * 0.072,
+            0.0,
+        ],
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filtered(filter: RasterFilter, rgba: [u8; 4]) -> [u8; 4] {
+        let mut pixel = rgba;
+        filter.apply(&mut pixel);
+        pixel
+    }
+
+    #[test]
+    fn test_filters_match_their_css_counterparts() {
+        let orange = [255, 128, 0, 200];
+        assert!(RasterFilter::default().is_identity());
+        assert_eq!(filtered(RasterFilter::default(), orange), orange);
+
+        let invert = RasterFilter::default().with_invert(1.0);
+        assert_eq!(filtered(invert, orange), [0, 127, 255, 200]);
+
+        let gray = filtered(RasterFilter::default().with_grayscale(1.0), orange);
+        assert!(gray[0] == gray[1] && gray[1] == gray[2] && gray[3] == 200);
+
+        let half = RasterFilter::default().with_brightness(0.5);
+        assert_eq!(filtered(half, [200, 100, 0, 255]), [100, 50, 0, 255]);
+
+        let flat = RasterFilter::default().with_contrast(0.0);
+        assert_eq!(filtered(flat, orange), [128, 128, 128, 200]);
+
+        // A full turn of hue and an opaque white tint change nothing
+        let unchanged = RasterFilter::default()
+            .with_hue_rotate(360.0)
+            .with_tint(egui::Color32::WHITE);
+        assert!(unchanged.is_identity());
+
+        let red = RasterFilter::default().with_tint(egui::Color32::from_rgb(255, 0, 0));
+        assert_eq!(filtered(red, [200, 200, 200, 255]), [200, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_dark_filter_keeps_water_blue_and_darkens_land() {
+        let dark = RasterFilter::dark();
+        let water = filtered(dark, [170, 211, 223, 255]);
+        assert!(water[2] > water[0], "{:?}", water);
+        let land = filtered(dark, [242, 239, 233, 255]);
+        assert!(
+            land.iter().take(3).all(|channel| *channel < 64),
+            "{:?}",
+            land
+        );
+    }
+
+    #[test]
+    fn test_lerp_between_filters() {
+        let from = RasterFilter::default();
+        let to = RasterFilter::dark().with_tint(egui::Color32::from_rgb(0, 0, 255));
+        assert_eq!(from.lerp(&to, 0.0).invert, 0.0);
+        assert_eq!(from.lerp(&to, 1.0), to);
+
+        let halfway = from.lerp(&to, 0.5);
+        assert_eq!(halfway.invert, 0.5);
+        assert_eq!(halfway.hue_rotate, 90.0);
+        assert_eq!(halfway.tint.map(|tint| tint.a), Some(128));
+    }
+}
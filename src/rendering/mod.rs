@@ -1,11 +1,13 @@
 pub mod camera;
 pub mod context;
+pub mod filter;
 pub mod pipeline;
 pub mod resources;
 
 // Re-export main types
 pub use camera::Camera;
 pub use context::RenderContext;
+pub use filter::RasterFilter;
 pub use pipeline::{PipelineConfig, RenderPassType, RenderPipeline};
 pub use resources::{ResourceStats, Resources};
 
@@ -283,11 +283,11 @@ pub mod async_utils {
         }
 
         pub fn release(&self) {
-            // OPTIMIZATION: Use try_lock with fallback
-            if let Ok(mut permits) = self.permits.try_lock() {
-                if *permits < self.max_permits {
-                    *permits += 1;
-                }
+            // Block briefly rather than lose the permit when another holder
+            // is contending for the lock
+            let mut permits = self.permits.lock().unwrap_or_else(|e| e.into_inner());
+            if *permits < self.max_permits {
+                *permits += 1;
             }
         }
 
@@ -1,6 +1,7 @@
 pub mod elements;
 pub mod traits;
 
+mod tile_textures;
 pub mod widget;
 
 pub mod style;
@@ -0,0 +1,164 @@
+//! GPU textures of drawn tiles
+//!
+//! A tile is uploaded once and drawn from its texture for as long as it
+//! stays in use, rather than uploaded again every frame. Textures are
+//! keyed by the tile's data and colour filter, and the least recently used
+//! ones are released once they exceed the texture budget of the map's
+//! [`TileMemoryBudget`](crate::core::config::TileMemoryBudget).
+
+use crate::prelude::{Arc, FxHasher, Mutex};
+use crate::rendering::filter::RasterFilter;
+use egui::{ColorImage, TextureHandle, TextureId};
+use lru::LruCache;
+use std::hash::{Hash, Hasher};
+
+/// Textures of recently drawn tiles, limited by their total size
+pub(crate) struct TileTextures {
+    textures: LruCache<u64, TextureHandle>,
+    bytes: usize,
+    budget: usize,
+}
+
+impl TileTextures {
+    pub fn new(budget: usize) -> Self {
+        Self {
+            textures: LruCache::unbounded(),
+            bytes: 0,
+            budget,
+        }
+    }
+
+    /// Change the memory for textures, releasing textures over the new
+    /// budget
+    pub fn set_budget(&mut self, bytes: usize) {
+        self.budget = bytes;
+        self.shrink();
+    }
+
+    /// Texture of the tile with `data` drawn with `filter`, uploading the
+    /// pixels `image` gives when there is none yet
+    pub fn load(
+        &mut self,
+        ctx: &egui::Context,
+        data: &[u8],
+        filter: Option<&RasterFilter>,
+        image: impl FnOnce() -> image::ImageResult<ColorImage>,
+    ) -> image::ImageResult<(TextureId, [usize; 2])> {
+        let key = texture_key(data, filter);
+        if let Some(texture) = self.textures.get(&key) {
+            return Ok((texture.id(), texture.size()));
+        }
+
+        let image = image()?;
+        let size = image.size;
+        let texture = ctx.load_texture(
+            format!("tile_{:016x}", key),
+            image,
+            egui::TextureOptions::LINEAR,
+        );
+        let id = texture.id();
+        self.bytes += texture.byte_size();
+        self.textures.put(key, texture);
+        self.shrink();
+        Ok((id, size))
+    }
+
+    /// Release the least recently drawn textures until the rest fit the
+    /// budget; egui frees a texture once the frame drawing it is done
+    fn shrink(&mut self) {
+        while self.bytes > self.budget {
+            match self.textures.pop_lru() {
+                Some((_, texture)) => self.bytes -= texture.byte_size(),
+                None => break,
+            }
+        }
+    }
+}
+
+/// Texture cache of the egui context, shared by the maps drawn in it
+pub(crate) fn tile_textures(ctx: &egui::Context) -> Arc<Mutex<TileTextures>> {
+    ctx.memory_mut(|mem| {
+        mem.data
+            .get_temp_mut_or_insert_with(egui::Id::new("maplet_tile_textures"), || {
+                let budget = crate::core::config::TileMemoryBudget::default().texture_bytes;
+                Arc::new(Mutex::new(TileTextures::new(budget)))
+            })
+            .clone()
+    })
+}
+
+fn texture_key(data: &[u8], filter: Option<&RasterFilter>) -> u64 {
+    let mut hasher = FxHasher::default();
+    data.hash(&mut hasher);
+    if let Some(filter) = filter.filter(|filter| !filter.is_identity()) {
+        for value in [
+            filter.grayscale,
+            filter.saturation,
+            filter.hue_rotate,
+            filter.invert,
+            filter.brightness,
+            filter.contrast,
+        ] {
+            value.to_bits().hash(&mut hasher);
+        }
+        filter
+            .tint
+            .map(|tint| [tint.r, tint.g, tint.b, tint.a])
+            .hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(size: usize) -> image::ImageResult<ColorImage> {
+        Ok(ColorImage::new([size, size], egui::Color32::WHITE))
+    }
+
+    #[test]
+    fn test_textures_are_reused_and_stay_within_budget() {
+        let ctx = egui::Context::default();
+        let mut textures = TileTextures::new(3 * 16 * 16 * 4);
+
+        let (first, size) = textures.load(&ctx, b"a", None, || image(16)).unwrap();
+        assert_eq!(size, [16, 16]);
+        let (again, _) = textures
+            .load(&ctx, b"a", None, || panic!("uploaded twice"))
+            .unwrap();
+        assert_eq!(first, again);
+
+        // Filtered pixels are a different texture
+        let dark = RasterFilter::dark();
+        let (filtered, _) = textures
+            .load(&ctx, b"a", Some(&dark), || image(16))
+            .unwrap();
+        assert_ne!(filtered, first);
+        let identity = RasterFilter::default();
+        let (unfiltered, _) = textures
+            .load(&ctx, b"a", Some(&identity), || panic!("uploaded twice"))
+            .unwrap();
+        assert_eq!(unfiltered, first);
+
+        textures.load(&ctx, b"b", None, || image(16)).unwrap();
+        textures.load(&ctx, b"a", None, || image(16)).unwrap();
+        textures.load(&ctx, b"c", None, || image(16)).unwrap();
+        assert_eq!(
+            (textures.textures.len(), textures.bytes),
+            (3, 3 * 16 * 16 * 4)
+        );
+        // The filtered texture was the least recently drawn
+        let mut uploaded = false;
+        textures
+            .load(&ctx, b"a", Some(&dark), || {
+                uploaded = true;
+                image(16)
+            })
+            .unwrap();
+        assert!(uploaded);
+
+        textures.set_budget(16 * 16 * 4);
+        assert_eq!(textures.textures.len(), 1);
+    }
+}
@@ -1,11 +1,15 @@
+use super::tile_textures::tile_textures;
 use crate::prelude::{Arc, Mutex};
 use crate::{
     core::{
         geo::{LatLng, Point},
         map::Map as CoreMap,
     },
-    layers::tile::TileLayer,
-    rendering::context::{DrawCommand, RenderContext},
+    layers::tile::{DecodedTile, TileLayer},
+    rendering::{
+        context::{DrawCommand, RenderContext},
+        filter::RasterFilter,
+    },
 };
 use egui::{Color32, ColorImage, Rect, Response, Sense, Ui, Vec2, Widget};
 
@@ -31,6 +35,17 @@ pub enum MapTheme {
     Satellite,
 }
 
+impl MapTheme {
+    /// Colour filter for basemap tile layers that do not set their own, so
+    /// a light basemap does not glare in the dark theme
+    pub fn tile_filter(&self) -> Option<RasterFilter> {
+        match self {
+            MapTheme::Dark => Some(RasterFilter::dark()),
+            MapTheme::Light | MapTheme::Satellite => None,
+        }
+    }
+}
+
 impl Default for Map {
     fn default() -> Self {
         Self::new()
@@ -143,7 +158,7 @@ impl Widget for Map {
             handle_map_input(ui, &mut response, &core_map, &self, rect);
         }
 
-        render_map(ui, rect, &core_map);
+        render_map(ui, rect, &core_map, self.theme);
 
         if self.show_controls {
             render_zoom_controls(ui, rect, &core_map, &self, &mut response);
@@ -281,13 +296,24 @@ fn handle_map_input(
     }
 }
 
-fn render_map(ui: &mut Ui, rect: Rect, core_map: &Arc<Mutex<CoreMap>>) {
+fn render_map(ui: &mut Ui, rect: Rect, core_map: &Arc<Mutex<CoreMap>>, theme: MapTheme) {
     // Use a more robust locking mechanism to prevent rendering conflicts
     match core_map.try_lock() {
         Ok(mut map_guard) => {
             let width = rect.width().max(1.0) as u32;
             let height = rect.height().max(1.0) as u32;
 
+            // Tile textures stay within the budget of the map's performance
+            // options
+            let texture_budget = map_guard
+                .performance()
+                .tile_loader
+                .memory_budget
+                .texture_bytes;
+            if let Ok(mut textures) = tile_textures(ui.ctx()).lock() {
+                textures.set_budget(texture_budget);
+            }
+
             // Get the current viewport transform for animations
             let viewport_transform = *map_guard.viewport().get_transform();
             let has_active_transform = map_guard.viewport().has_active_transform();
@@ -308,7 +334,11 @@ fn render_map(ui: &mut Ui, rect: Rect, core_map: &Arc<Mutex<CoreMap>>) {
             }
 
             // Always try to render - the orchestrator was too restrictive
-            if let Ok(mut render_ctx) = RenderContext::new(width, height) {
+            let pixels_per_point = ui.ctx().pixels_per_point();
+            if let Ok(mut render_ctx) = RenderContext::new(width, height).map(|ctx| {
+                ctx.with_pixels_per_point(pixels_per_point)
+                    .with_basemap_filter(theme.tile_filter())
+            }) {
                 // Perform the update and render
                 match map_guard.update_and_render(&mut render_ctx) {
                     Ok(rendered) => {
@@ -319,13 +349,27 @@ fn render_map(ui: &mut Ui, rect: Rect, core_map: &Arc<Mutex<CoreMap>>) {
                             // Apply transforms during zoom animations (like Leaflet)
                             for cmd in drawing_queue.iter() {
                                 match cmd {
-                                    DrawCommand::Tile { data, bounds, .. } => {
+                                    DrawCommand::Tile {
+                                        data,
+                                        bounds,
+                                        uv,
+                                        opacity,
+                                        image,
+                                        filter,
+                                    } => {
+                                        let tile = TileImage {
+                                            data,
+                                            decoded: image.as_deref(),
+                                            filter: *filter,
+                                        };
                                         if has_active_transform {
                                             render_tile_with_transform(
                                                 ui,
                                                 rect,
-                                                data,
+                                                tile,
                                                 bounds,
+                                                uv,
+                                                *opacity,
                                                 &viewport_transform,
                                             );
                                         } else if is_dragging {
@@ -340,12 +384,14 @@ fn render_map(ui: &mut Ui, rect: Rect, core_map: &Arc<Mutex<CoreMap>>) {
                                             render_tile_with_transform(
                                                 ui,
                                                 rect,
-                                                data,
+                                                tile,
                                                 bounds,
+                                                uv,
+                                                *opacity,
                                                 &drag_transform,
                                             );
                                         } else {
-                                            render_tile(ui, rect, data, bounds);
+                                            render_tile(ui, rect, tile, bounds, uv, *opacity);
                                         }
                                     }
                                     DrawCommand::TileTextured {
@@ -384,6 +430,11 @@ fn render_map(ui: &mut Ui, rect: Rect, core_map: &Arc<Mutex<CoreMap>>) {
                                     }
                                 }
                             }
+
+                            // Keep frames coming while tiles fade in
+                            if map_guard.needs_repaint() {
+                                ui.ctx().request_repaint();
+                            }
                         } else {
                             // If no rendering occurred, show a simple background
                             ui.painter()
@@ -419,7 +470,15 @@ fn render_fallback_map(ui: &mut Ui, rect: Rect, message: &str) {
     );
 }
 
-fn render_tile(ui: &mut Ui, rect: Rect, data: &[u8], bounds: &(Point, Point)) {
+fn render_tile(
+    ui: &mut Ui,
+    rect: Rect,
+    tile: TileImage,
+    bounds: &(Point, Point),
+    uv: &(Point, Point),
+    opacity: f32,
+) {
+    let data = tile.data;
     if data.is_empty() {
         // Render a placeholder for empty tiles
         let (min_point, max_point) = *bounds;
@@ -438,27 +497,16 @@ fn render_tile(ui: &mut Ui, rect: Rect, data: &[u8], bounds: &(Point, Point)) {
         return;
     }
 
-    // Create a simple but stable texture key
+    // Name of the tile in error messages
     let texture_key = format!("tile_{}_{}", (bounds.0.x as i32), (bounds.0.y as i32));
 
-    // Try to load the image
-    match image::load_from_memory(data) {
-        Ok(img) => {
-            let rgba_img = img.to_rgba8();
-            let (width, height) = rgba_img.dimensions();
-
+    // Use the texture uploaded when the tile was last drawn, or the
+    // pixels decoded by the tile loader, decoding here only for tiles that
+    // arrived without them
+    match tile.texture(ui.ctx()) {
+        Ok((texture_id, [width, height])) => {
             // Only proceed if we have valid dimensions
             if width > 0 && height > 0 {
-                let color_image = ColorImage::from_rgba_unmultiplied(
-                    [width as usize, height as usize],
-                    &rgba_img.into_raw(),
-                );
-
-                let texture =
-                    ui.ctx()
-                        .load_texture(texture_key, color_image, egui::TextureOptions::LINEAR);
-
-                let texture_id = texture.id();
 
                 // Render the tile using the texture
                 let (min_point, max_point) = *bounds;
@@ -473,12 +521,12 @@ fn render_tile(ui: &mut Ui, rect: Rect, data: &[u8], bounds: &(Point, Point)) {
                     ),
                 );
 
-                // Render the tile
+                // Render the tile, faded while it fades in
                 ui.painter().image(
                     texture_id,
                     tile_rect,
-                    egui::Rect::from_min_size(egui::Pos2::ZERO, egui::Vec2::splat(1.0)),
-                    Color32::WHITE,
+                    uv_rect(uv),
+                    Color32::WHITE.gamma_multiply(opacity),
                 );
             } else {
                 println!("❌ [RENDER] Invalid tile dimensions: {}x{}", width, height);
@@ -543,10 +591,13 @@ fn render_error_tile(ui: &mut Ui, rect: Rect, bounds: &(Point, Point), error_msg
 fn render_tile_with_transform(
     ui: &mut Ui,
     rect: Rect,
-    data: &[u8],
+    tile: TileImage,
     bounds: &(Point, Point),
+    uv: &(Point, Point),
+    opacity: f32,
     transform: &crate::core::viewport::Transform,
 ) {
+    let data = tile.data;
     if data.is_empty() {
         // Render a placeholder for empty tiles with transform applied
         let (min_point, max_point) = *bounds;
@@ -556,27 +607,16 @@ fn render_tile_with_transform(
         return;
     }
 
-    // Create a simple but stable texture key
+    // Name of the tile in error messages
     let texture_key = format!("tile_{}_{}", (bounds.0.x as i32), (bounds.0.y as i32));
 
-    // Try to load the image
-    match image::load_from_memory(data) {
-        Ok(img) => {
-            let rgba_img = img.to_rgba8();
-            let (width, height) = rgba_img.dimensions();
-
+    // Use the texture uploaded when the tile was last drawn, or the
+    // pixels decoded by the tile loader, decoding here only for tiles that
+    // arrived without them
+    match tile.texture(ui.ctx()) {
+        Ok((texture_id, [width, height])) => {
             // Only proceed if we have valid dimensions
             if width > 0 && height > 0 {
-                let color_image = ColorImage::from_rgba_unmultiplied(
-                    [width as usize, height as usize],
-                    &rgba_img.into_raw(),
-                );
-
-                let texture =
-                    ui.ctx()
-                        .load_texture(texture_key, color_image, egui::TextureOptions::LINEAR);
-
-                let texture_id = texture.id();
 
                 // Apply transform to tile positioning (like Leaflet's CSS transforms)
                 let (min_point, max_point) = *bounds;
@@ -586,8 +626,8 @@ fn render_tile_with_transform(
                 ui.painter().image(
                     texture_id,
                     tile_rect,
-                    egui::Rect::from_min_size(egui::Pos2::ZERO, egui::Vec2::splat(1.0)),
-                    Color32::WHITE,
+                    uv_rect(uv),
+                    Color32::WHITE.gamma_multiply(opacity),
                 );
 
                 // Debug: Log successful tile rendering with transform
@@ -618,6 +658,56 @@ fn render_tile_with_transform(
     }
 }
 
+/// Image data of a tile, with its pixels when the tile loader decoded them
+/// and the colour filter to draw it with
+#[derive(Clone, Copy)]
+struct TileImage<'a> {
+    data: &'a [u8],
+    decoded: Option<&'a DecodedTile>,
+    filter: Option<RasterFilter>,
+}
+
+impl TileImage<'_> {
+    /// Texture of the tile, uploaded the first time it is drawn
+    fn texture(&self, ctx: &egui::Context) -> image::ImageResult<(egui::TextureId, [usize; 2])> {
+        let textures = tile_textures(ctx);
+        let mut textures = textures.lock().unwrap_or_else(|e| e.into_inner());
+        textures.load(ctx, self.data, self.filter.as_ref(), || self.color_image())
+    }
+
+    /// Filtered pixels of the tile, decoding `data` only when they are
+    /// missing
+    fn color_image(&self) -> image::ImageResult<ColorImage> {
+        let filter = self.filter.filter(|filter| !filter.is_identity());
+        if let (Some(decoded), None) = (self.decoded, filter) {
+            return Ok(ColorImage::from_rgba_unmultiplied(
+                decoded.size(),
+                &decoded.rgba,
+            ));
+        }
+        let (size, mut rgba) = match self.decoded {
+            Some(decoded) => (decoded.size(), decoded.rgba.clone()),
+            None => {
+                let rgba_img = image::load_from_memory(self.data)?.to_rgba8();
+                let size = [rgba_img.width() as usize, rgba_img.height() as usize];
+                (size, rgba_img.into_raw())
+            }
+        };
+        if let Some(filter) = filter {
+            filter.apply(&mut rgba);
+        }
+        Ok(ColorImage::from_rgba_unmultiplied(size, &rgba))
+    }
+}
+
+/// Texture coordinates of the part of a tile image to draw
+fn uv_rect(uv: &(Point, Point)) -> Rect {
+    Rect::from_min_max(
+        egui::Pos2::new(uv.0.x as f32, uv.0.y as f32),
+        egui::Pos2::new(uv.1.x as f32, uv.1.y as f32),
+    )
+}
+
 /// Apply Leaflet-style transform to a rectangle (translate + scale from origin)
 fn apply_transform_to_rect(
     container_rect: Rect,
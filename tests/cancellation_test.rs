@@ -0,0 +1,74 @@
+//! Cancelling tile requests against a local server that answers slowly, so
+//! downloads are still in flight when they are cancelled.
+
+mod common;
+
+use common::{ok, TestServer};
+use maplet::core::geo::TileCoord;
+use maplet::layers::tile::loader::TileResult;
+use maplet::layers::tile::{TemplateTileSource, TileLoader, TileLoaderConfig, TilePriority};
+use std::time::Duration;
+
+const RESPONSE_DELAY: Duration = Duration::from_millis(800);
+
+/// Serves every request after `RESPONSE_DELAY`
+fn start_slow_server() -> TestServer {
+    TestServer::start(|_| {
+        std::thread::sleep(RESPONSE_DELAY);
+        ok("tile")
+    })
+}
+
+/// Every result delivered within `wait`
+async fn collect_results(loader: &TileLoader, wait: Duration) -> Vec<TileResult> {
+    common::collect_results(loader, usize::MAX, wait).await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_cancelled_tiles_deliver_no_results() {
+    let server = start_slow_server();
+    let source = TemplateTileSource::new(server.template());
+    // Four download slots: four tiles start, the other two stay queued
+    let loader = TileLoader::new(TileLoaderConfig::for_testing());
+    let coords: Vec<TileCoord> = (0..6).map(|x| TileCoord::new(x, 0, 10)).collect();
+    loader
+        .queue_tiles_batch(&source, coords, TilePriority::Visible)
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    let started = server.paths();
+    assert_eq!(started.len(), 4);
+
+    let cancelled = loader.retain_tasks(|coord| coord.x < 2);
+    assert_eq!(cancelled, 4);
+    assert_eq!(loader.get_pending_count(), 2);
+    assert_eq!(loader.network_metrics().cancelled_requests(), 4);
+
+    let results = collect_results(&loader, RESPONSE_DELAY * 2).await;
+    let mut delivered: Vec<u32> = results.iter().map(|result| result.coord.x).collect();
+    delivered.sort_unstable();
+    assert_eq!(delivered, vec![0, 1]);
+    assert!(results.iter().all(|result| result.data.is_ok()));
+    // Cancelled tiles that were still queued were never requested
+    let requested = server.paths();
+    for path in &requested[started.len()..] {
+        assert!(path == "/10/0/0.png" || path == "/10/1/0.png", "{}", path);
+    }
+
+    // A cancelled tile can be requested again
+    loader
+        .queue_tile(&source, TileCoord::new(5, 0, 10), TilePriority::Visible)
+        .unwrap();
+    let results = collect_results(&loader, RESPONSE_DELAY * 2).await;
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].coord, TileCoord::new(5, 0, 10));
+
+    // Clearing cancels everything still pending
+    loader
+        .queue_tile(&source, TileCoord::new(9, 0, 10), TilePriority::Visible)
+        .unwrap();
+    loader.clear_pending();
+    assert_eq!(loader.network_metrics().cancelled_requests(), 5);
+    assert!(collect_results(&loader, RESPONSE_DELAY * 2)
+        .await
+        .is_empty());
+}
@@ -0,0 +1,224 @@
+//! Helpers shared by the integration tests: a local HTTP stand-in for tile
+//! servers, and loops that render or poll until tiles have arrived.
+
+// Each test binary uses only some of the helpers
+#![allow(dead_code)]
+
+use maplet::core::viewport::Viewport;
+use maplet::layers::base::LayerTrait;
+use maplet::layers::tile::loader::TileResult;
+use maplet::layers::tile::TileLoader;
+use maplet::rendering::context::{DrawCommand, RenderContext};
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A request received by a [`TestServer`]
+#[derive(Debug, Clone)]
+pub struct Request {
+    /// Path and query of the request line
+    pub path: String,
+    /// Request line and headers, lower-cased
+    pub head: String,
+    /// When the request arrived
+    pub at: Instant,
+}
+
+/// Minimal HTTP/1.1 server answering every request with what `handler`
+/// returns for it, each connection on its own thread so slow answers don't
+/// hold up the others
+///
+/// Connections closed before sending a request line are not recorded.
+pub struct TestServer {
+    host: String,
+    port: u16,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl TestServer {
+    pub fn start(handler: impl Fn(&Request) -> Vec<u8> + Send + Sync + 'static) -> Self {
+        Self::start_on("127.0.0.1", handler)
+    }
+
+    /// Listen on `host`, e.g. another loopback address to keep per-host
+    /// state apart from other tests
+    pub fn start_on(
+        host: &str,
+        handler: impl Fn(&Request) -> Vec<u8> + Send + Sync + 'static,
+    ) -> Self {
+        let listener = TcpListener::bind(format!("{}:0", host)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let (handler, log) = (Arc::new(handler), requests.clone());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { continue };
+                let (handler, log) = (handler.clone(), log.clone());
+                std::thread::spawn(move || {
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    let mut head = String::new();
+                    loop {
+                        let mut line = String::new();
+                        if reader.read_line(&mut line).unwrap_or(0) == 0 || line == "\r\n" {
+                            break;
+                        }
+                        head.push_str(&line);
+                    }
+                    let Some(path) = head.split(' ').nth(1) else {
+                        return;
+                    };
+                    let request = Request {
+                        path: path.to_string(),
+                        head: head.to_ascii_lowercase(),
+                        at: Instant::now(),
+                    };
+                    log.lock().unwrap().push(request.clone());
+                    let _ = stream.write_all(&handler(&request));
+                });
+            }
+        });
+
+        Self {
+            host: host.to_string(),
+            port,
+            requests,
+        }
+    }
+
+    /// Absolute URL of `path` on this server
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}:{}{}", self.host, self.port, path)
+    }
+
+    /// URL template for `.png` tiles served from the root
+    pub fn template(&self) -> String {
+        self.url("/{z}/{x}/{y}.png")
+    }
+
+    /// Requests received so far, in order of arrival
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Paths of the requests received so far
+    pub fn paths(&self) -> Vec<String> {
+        self.requests()
+            .into_iter()
+            .map(|request| request.path)
+            .collect()
+    }
+}
+
+/// Server answering each request with the next scripted response, and with
+/// `500 Internal Server Error` once the script has run out
+pub struct ScriptedServer {
+    server: TestServer,
+    script: Arc<Mutex<VecDeque<Vec<u8>>>>,
+}
+
+impl ScriptedServer {
+    pub fn start() -> Self {
+        let script = Arc::new(Mutex::new(VecDeque::<Vec<u8>>::new()));
+        let responses = script.clone();
+        let server = TestServer::start(move |_| {
+            responses
+                .lock()
+                .unwrap()
+                .pop_front()
+                .unwrap_or_else(|| response("500 Internal Server Error", &[], ""))
+        });
+        Self { server, script }
+    }
+
+    pub fn script(&self, response: Vec<u8>) {
+        self.script.lock().unwrap().push_back(response);
+    }
+
+    /// Request line and headers of each request received, lower-cased
+    pub fn requests(&self) -> Vec<String> {
+        self.server
+            .requests()
+            .into_iter()
+            .map(|request| request.head)
+            .collect()
+    }
+
+    pub fn template(&self) -> String {
+        self.server.template()
+    }
+}
+
+/// An HTTP/1.1 response closing the connection after `body`
+pub fn response(status: &str, headers: &[(&str, &str)], body: impl AsRef<[u8]>) -> Vec<u8> {
+    let body = body.as_ref();
+    let mut out = format!(
+        "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        status,
+        body.len()
+    );
+    for (name, value) in headers {
+        out.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    out.push_str("\r\n");
+    let mut out = out.into_bytes();
+    out.extend_from_slice(body);
+    out
+}
+
+/// A `200 OK` response carrying `body`
+pub fn ok(body: impl AsRef<[u8]>) -> Vec<u8> {
+    response("200 OK", &[], body)
+}
+
+/// `tile` applied to each draw command of one frame of `layer`, keeping the
+/// values it returns
+pub fn render_tiles<T>(
+    layer: &mut dyn LayerTrait,
+    viewport: &Viewport,
+    tile: impl FnMut(&DrawCommand) -> Option<T>,
+) -> Vec<T> {
+    let (width, height) = (viewport.size.x as u32, viewport.size.y as u32);
+    let mut context = RenderContext::new(width, height).unwrap();
+    layer.render(&mut context, viewport).unwrap();
+    context
+        .get_drawing_queue()
+        .iter()
+        .filter_map(tile)
+        .collect()
+}
+
+/// Run `frame` every 10ms until `done` accepts what it returns or five
+/// seconds pass, returning the last frame
+pub async fn render_until<S: ?Sized, T>(
+    state: &mut S,
+    mut frame: impl FnMut(&mut S) -> T,
+    mut done: impl FnMut(&T, &mut S) -> bool,
+) -> T {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let result = frame(state);
+        if done(&result, state) || Instant::now() > deadline {
+            return result;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+/// Results delivered by `loader` until `count` have arrived or `timeout`
+/// passes
+pub async fn collect_results(
+    loader: &TileLoader,
+    count: usize,
+    timeout: Duration,
+) -> Vec<TileResult> {
+    let deadline = Instant::now() + timeout;
+    let mut results = Vec::new();
+    while results.len() < count && Instant::now() < deadline {
+        results.extend(loader.try_recv_results());
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    results
+}
@@ -0,0 +1,123 @@
+//! The full load, render and error path of a tile layer, offline, using
+//! generated debug tiles.
+
+mod common;
+
+use maplet::core::geo::{LatLng, Point, TileCoord};
+use maplet::core::map::Map;
+use maplet::input::MapEvent;
+use maplet::layers::tile::debug_tiles::debug_tile;
+use maplet::layers::tile::error_tile::error_pattern;
+use maplet::layers::tile::{DebugTileSource, TileLayer, TileLayerOptions};
+use maplet::rendering::context::{DrawCommand, RenderContext};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const LAYER_ID: &str = "debug";
+
+fn map_with_source(source: DebugTileSource) -> Map {
+    let mut map = Map::for_testing(LatLng::new(0.0, 0.0), 1.0, Point::new(512.0, 512.0));
+    let layer = TileLayer::new(
+        LAYER_ID.to_string(),
+        Box::new(source),
+        TileLayerOptions::default(),
+    )
+    .unwrap();
+    map.add_layer(Box::new(layer)).unwrap();
+    map
+}
+
+fn with_tile_layer<R>(map: &mut Map, f: impl FnOnce(&mut TileLayer) -> R) -> R {
+    map.with_layer_mut(LAYER_ID, |layer| {
+        f(layer.as_any_mut().downcast_mut::<TileLayer>().unwrap())
+    })
+    .unwrap()
+}
+
+/// Render one frame, returning the image and screen bounds of each tile
+fn render(map: &mut Map) -> Vec<(Vec<u8>, (Point, Point))> {
+    let mut context = RenderContext::new(512, 512).unwrap();
+    map.update_and_render(&mut context).unwrap();
+    context
+        .get_drawing_queue()
+        .iter()
+        .filter_map(|command| match command {
+            DrawCommand::Tile { data, bounds, .. } => Some((data.clone(), *bounds)),
+            _ => None,
+        })
+        .collect()
+}
+
+async fn render_until(
+    map: &mut Map,
+    mut done: impl FnMut(&[(Vec<u8>, (Point, Point))]) -> bool,
+) -> Vec<(Vec<u8>, (Point, Point))> {
+    common::render_until(map, render, |tiles, _| done(tiles)).await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_debug_tiles_load_and_meet_without_seams() {
+    let source = DebugTileSource::new().with_latency(Duration::from_millis(100));
+    let mut map = map_with_source(source.clone());
+    let zoom_one: Vec<Vec<u8>> = TileCoord::new(0, 0, 0)
+        .descendants(1)
+        .into_iter()
+        .map(|coord| debug_tile(coord, 256))
+        .collect();
+
+    // Nothing has arrived during the injected latency
+    let first = render(&mut map);
+    assert!(first.iter().all(|(data, _)| !zoom_one.contains(data)));
+
+    let tiles = render_until(&mut map, |tiles| {
+        !tiles.is_empty() && tiles.iter().all(|(data, _)| zoom_one.contains(data))
+    })
+    .await;
+    assert!(tiles.iter().all(|(data, _)| zoom_one.contains(data)));
+    assert!(source.generated_tiles() >= 4);
+
+    // Neighbouring tiles share their edges exactly
+    for (_, (min, max)) in &tiles {
+        let right = tiles
+            .iter()
+            .find(|(_, (other, _))| other.y == min.y && other.x > min.x && other.x <= max.x);
+        if let Some((_, (other_min, _))) = right {
+            assert_eq!(other_min.x, max.x);
+        }
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_injected_failures_show_error_tiles_until_recovery() {
+    let failing = TileCoord::new(1, 0, 1);
+    let source = DebugTileSource::new().with_failing_tiles([failing]);
+    let mut map = map_with_source(source.clone());
+    let reported = Arc::new(Mutex::new(Vec::new()));
+    let listener = reported.clone();
+    map.on("tileerror", move |event| {
+        listener.lock().unwrap().push(event.clone());
+    });
+
+    let pattern = error_pattern(256);
+    let tiles = render_until(&mut map, |tiles| {
+        tiles.iter().filter(|(data, _)| *data == pattern).count() == 1
+            && tiles.iter().all(|(data, _)| !data.is_empty())
+    })
+    .await;
+    assert_eq!(tiles.iter().filter(|(data, _)| *data == pattern).count(), 1);
+    map.process_events();
+    assert!(reported.lock().unwrap().iter().any(|event| matches!(
+        event,
+        MapEvent::TileError { coord, error, .. }
+            if *coord == failing && error.contains("Simulated failure")
+    )));
+
+    source.set_failing_tiles([]);
+    with_tile_layer(&mut map, |layer| layer.retry_failed_tiles());
+    let expected = debug_tile(failing, 256);
+    let tiles = render_until(&mut map, |tiles| {
+        tiles.iter().any(|(data, _)| *data == expected)
+    })
+    .await;
+    assert!(tiles.iter().all(|(data, _)| *data != pattern));
+}
@@ -0,0 +1,116 @@
+//! Tile source failover against local servers: a primary that can be
+//! switched between failing and serving, and a mirror that always serves.
+
+mod common;
+
+use common::{ok, render_tiles, render_until, response, TestServer};
+use maplet::core::geo::{LatLng, Point};
+use maplet::core::viewport::Viewport;
+use maplet::layers::tile::{
+    FailoverTileSource, TemplateTileSource, TileLayer, TileLayerOptions, TileSource,
+};
+use maplet::rendering::context::DrawCommand;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Answers every tile with `body`, or `503 Service Unavailable` while
+/// `healthy` is false, returning the server's URL template
+fn start_server(healthy: Arc<AtomicBool>, body: &'static str) -> String {
+    TestServer::start(move |_| {
+        if healthy.load(Ordering::SeqCst) {
+            ok(body)
+        } else {
+            response("503 Service Unavailable", &[], "")
+        }
+    })
+    .template()
+}
+
+/// Render at `zoom` until every drawn tile has loaded, returning their bodies
+async fn render_loaded(layer: &mut TileLayer, zoom: f64) -> Vec<String> {
+    let viewport = Viewport::new(LatLng::new(0.0, 0.0), zoom, Point::new(512.0, 512.0));
+    let render = |layer: &mut TileLayer| {
+        render_tiles(layer, &viewport, |command| match command {
+            DrawCommand::Tile { data, .. } => Some(String::from_utf8_lossy(data).into_owned()),
+            _ => None,
+        })
+    };
+    render_until(layer, render, |tiles: &Vec<String>, _| {
+        !tiles.is_empty()
+            && tiles
+                .iter()
+                .all(|tile| tile == "primary" || tile == "mirror")
+    })
+    .await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_failing_primary_falls_back_to_mirror_until_it_recovers() {
+    let primary_up = Arc::new(AtomicBool::new(false));
+    let primary = start_server(primary_up.clone(), "primary");
+    let mirror = start_server(Arc::new(AtomicBool::new(true)), "mirror");
+
+    let options = TileLayerOptions::default();
+    let chain = FailoverTileSource::new(vec![
+        Box::new(TemplateTileSource::from_options(primary, &options)) as Box<dyn TileSource>,
+        Box::new(TemplateTileSource::from_options(mirror, &options)),
+    ])
+    .unwrap()
+    .with_failure_threshold(2)
+    .with_cooldown(Duration::from_millis(300));
+    let mut layer = TileLayer::new("tiles".to_string(), Box::new(chain.clone()), options).unwrap();
+
+    let tiles = render_loaded(&mut layer, 2.0).await;
+    assert!(!tiles.is_empty());
+    assert!(tiles.iter().all(|tile| tile == "mirror"), "{:?}", tiles);
+
+    // The layer reports the same health as the chain it was given
+    let stats = layer.source_stats();
+    assert!(!chain.is_healthy(0));
+    assert!(!stats[0].healthy);
+    assert!(stats[0].failures >= 2);
+    assert!(stats[0].last_error.as_deref().unwrap().contains("503"));
+    assert!(stats[1].healthy);
+    assert!(stats[1].successes >= tiles.len() as u64);
+    assert!(stats[1].average_response_time.is_some());
+
+    // After its cooldown the recovered primary serves new tiles again
+    primary_up.store(true, Ordering::SeqCst);
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    let tiles = render_loaded(&mut layer, 9.0).await;
+    assert!(tiles.iter().all(|tile| tile == "primary"), "{:?}", tiles);
+    let stats = layer.source_stats();
+    assert!(stats[0].healthy);
+    assert_eq!(stats[0].consecutive_failures, 0);
+    assert!(stats[0].successes > 0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_mirror_templates_and_plain_sources() {
+    let dead = "http://127.0.0.1:9/{z}/{x}/{y}.png".to_string();
+    let mirror = start_server(Arc::new(AtomicBool::new(true)), "mirror");
+    let mut layer = TileLayer::from_mirrors(
+        "tiles".to_string(),
+        vec![dead, mirror],
+        TileLayerOptions::default(),
+    )
+    .unwrap();
+    let tiles = render_loaded(&mut layer, 1.0).await;
+    assert!(!tiles.is_empty());
+    assert!(tiles.iter().all(|tile| tile == "mirror"), "{:?}", tiles);
+    assert!(layer.source_stats()[0].failures > 0);
+
+    // Layers with a single source have no chain to report on
+    let plain = TileLayer::from_template(
+        "plain".to_string(),
+        "http://127.0.0.1:9/{z}/{x}/{y}.png",
+        TileLayerOptions::default(),
+    )
+    .unwrap();
+    assert!(plain.source_stats().is_empty());
+    assert!(
+        TileLayer::from_mirrors("empty".to_string(), Vec::new(), TileLayerOptions::default())
+            .is_err()
+    );
+}
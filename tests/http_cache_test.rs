@@ -0,0 +1,235 @@
+//! Tile downloader HTTP caching against a local HTTP stand-in that returns
+//! scripted responses and records the requests it receives.
+
+mod common;
+
+use common::{collect_results, render_tiles, render_until, response, ScriptedServer, TestServer};
+use maplet::core::geo::{LatLng, Point, TileCoord};
+use maplet::core::viewport::Viewport;
+use maplet::layers::tile::loader::TileResult;
+use maplet::layers::tile::{
+    CachedTile, CachedTileInfo, DiskCacheConfig, DiskTileCache, TemplateTileSource, TileLayer,
+    TileLayerOptions, TileLoader, TileLoaderConfig, TilePriority, TileSource,
+};
+use maplet::rendering::context::DrawCommand;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+fn temp_cache(name: &str) -> (Arc<DiskTileCache>, DiskCacheConfig) {
+    let config = DiskCacheConfig::new(std::env::temp_dir().join(format!(
+        "maplet_http_cache_{}_{}",
+        name,
+        std::process::id()
+    )));
+    let _ = std::fs::remove_dir_all(&config.directory);
+    (
+        Arc::new(DiskTileCache::open(config.clone()).unwrap()),
+        config,
+    )
+}
+
+fn data(result: &TileResult) -> Vec<u8> {
+    result.data.as_ref().unwrap().clone().unwrap()
+}
+
+/// Wait for the background disk write following a download
+async fn wait_until(mut condition: impl FnMut() -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !condition() {
+        assert!(Instant::now() < deadline, "condition not reached");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_stale_tile_is_shown_then_revalidated_with_304() {
+    let server = ScriptedServer::start();
+    let (cache, config) = temp_cache("revalidate");
+    let source = TemplateTileSource::new(server.template());
+    let source_id = source.source_id();
+    let coord = TileCoord::new(1, 2, 3);
+    let loader = TileLoader::new(TileLoaderConfig::for_testing()).with_disk_cache(cache.clone());
+
+    // First load: stored with its validators but already stale
+    server.script(response(
+        "200 OK",
+        &[("ETag", "\"v1\""), ("Cache-Control", "max-age=0")],
+        "one",
+    ));
+    loader
+        .queue_tile(&source, coord, TilePriority::Visible)
+        .unwrap();
+    let results = collect_results(&loader, 1, Duration::from_secs(5)).await;
+    assert_eq!(data(&results[0]), b"one".to_vec());
+    wait_until(|| cache.contains(&source_id, coord)).await;
+
+    // Second load: stale copy first, then a conditional request answered with 304
+    server.script(response(
+        "304 Not Modified",
+        &[("Cache-Control", "max-age=3600")],
+        "",
+    ));
+    loader
+        .queue_tile(&source, coord, TilePriority::Visible)
+        .unwrap();
+    let results = collect_results(&loader, 2, Duration::from_millis(500)).await;
+    assert_eq!(results.len(), 1, "a 304 must not produce a second result");
+    assert_eq!(data(&results[0]), b"one".to_vec());
+    assert!(server.requests()[1].contains("if-none-match: \"v1\""));
+
+    wait_until(|| {
+        cache
+            .get_entry(&source_id, coord)
+            .is_some_and(|entry| !entry.info.is_stale(SystemTime::now()))
+    })
+    .await;
+    let entry = cache.get_entry(&source_id, coord).unwrap();
+    assert_eq!(entry.info.etag.as_deref(), Some("\"v1\""));
+
+    // Third load: fresh on disk, so the server is not asked at all
+    loader
+        .queue_tile(&source, coord, TilePriority::Visible)
+        .unwrap();
+    let results = collect_results(&loader, 1, Duration::from_secs(5)).await;
+    assert_eq!(data(&results[0]), b"one".to_vec());
+    assert_eq!(server.requests().len(), 2);
+
+    let _ = std::fs::remove_dir_all(&config.directory);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_changed_tile_replaces_stale_copy() {
+    let server = ScriptedServer::start();
+    let (cache, config) = temp_cache("changed");
+    let source = TemplateTileSource::new(server.template());
+    let source_id = source.source_id();
+    let coord = TileCoord::new(0, 0, 1);
+    let loader = TileLoader::new(TileLoaderConfig::for_testing()).with_disk_cache(cache.clone());
+
+    server.script(response(
+        "200 OK",
+        &[
+            ("Last-Modified", "Wed, 21 Oct 2015 07:28:00 GMT"),
+            ("Expires", "Thu, 01 Jan 1970 00:00:00 GMT"),
+        ],
+        "old",
+    ));
+    loader
+        .queue_tile(&source, coord, TilePriority::Visible)
+        .unwrap();
+    collect_results(&loader, 1, Duration::from_secs(5)).await;
+    wait_until(|| cache.contains(&source_id, coord)).await;
+
+    server.script(response(
+        "200 OK",
+        &[("Cache-Control", "max-age=60")],
+        "new",
+    ));
+    loader
+        .queue_tile(&source, coord, TilePriority::Visible)
+        .unwrap();
+    let results = collect_results(&loader, 2, Duration::from_secs(5)).await;
+    let payloads: Vec<_> = results.iter().map(data).collect();
+    assert_eq!(payloads, vec![b"old".to_vec(), b"new".to_vec()]);
+    assert!(server.requests()[1].contains("if-modified-since: wed, 21 oct 2015 07:28:00 gmt"));
+
+    wait_until(|| cache.get(&source_id, coord) == Some(b"new".to_vec())).await;
+
+    let _ = std::fs::remove_dir_all(&config.directory);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_no_store_responses_are_not_written_to_disk() {
+    let server = ScriptedServer::start();
+    let (cache, config) = temp_cache("no_store");
+    let source = TemplateTileSource::new(server.template());
+    let coord = TileCoord::new(0, 0, 0);
+    let loader = TileLoader::new(TileLoaderConfig::for_testing()).with_disk_cache(cache.clone());
+
+    server.script(response(
+        "200 OK",
+        &[("Cache-Control", "no-store")],
+        "secret",
+    ));
+    loader
+        .queue_tile(&source, coord, TilePriority::Visible)
+        .unwrap();
+    let results = collect_results(&loader, 1, Duration::from_secs(5)).await;
+    assert_eq!(data(&results[0]), b"secret".to_vec());
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(!cache.contains(&source.source_id(), coord));
+
+    let _ = std::fs::remove_dir_all(&config.directory);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_memory_copy_is_revalidated_without_disk_cache() {
+    let server = ScriptedServer::start();
+    let source = TemplateTileSource::new(server.template());
+    let coord = TileCoord::new(1, 0, 2);
+    let loader = TileLoader::new(TileLoaderConfig::for_testing()).with_image_decoding(true);
+
+    server.script(response(
+        "304 Not Modified",
+        &[("Cache-Control", "max-age=3600")],
+        "",
+    ));
+    let cached = CachedTile {
+        data: b"shown".to_vec(),
+        info: CachedTileInfo {
+            etag: Some("\"v1\"".to_string()),
+            last_modified: None,
+            expires_at: Some(SystemTime::now()),
+        },
+    };
+    loader
+        .revalidate_tile(&source, coord, cached, TilePriority::Visible)
+        .unwrap();
+
+    // The unchanged copy comes back with the new expiry and its validators
+    let results = collect_results(&loader, 1, Duration::from_secs(5)).await;
+    assert_eq!(data(&results[0]), b"shown".to_vec());
+    assert!(results[0].image.is_none());
+    let policy = results[0].policy.as_ref().unwrap();
+    assert!(!policy.info.is_stale(SystemTime::now()));
+    assert_eq!(policy.info.etag.as_deref(), Some("\"v1\""));
+    assert!(server.requests()[0].contains("if-none-match: \"v1\""));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_layer_keeps_fresh_tiles_without_disk_cache() {
+    let server = TestServer::start(|request| {
+        response(
+            "200 OK",
+            &[("Cache-Control", "max-age=3600")],
+            &request.path,
+        )
+    });
+    let options = TileLayerOptions {
+        fade_duration_ms: 0,
+        ..Default::default()
+    };
+    let mut layer =
+        TileLayer::from_template("tiles".to_string(), server.template(), options).unwrap();
+    let viewport = Viewport::new(LatLng::new(0.0, 0.0), 2.0, Point::new(512.0, 512.0));
+    let render = |layer: &mut TileLayer| {
+        render_tiles(layer, &viewport, |command| match command {
+            DrawCommand::Tile { data, .. } if !data.is_empty() => Some(data.clone()),
+            _ => None,
+        })
+    };
+
+    let drawn = render_until(&mut layer, render, |tiles, _| tiles.len() >= 4).await;
+    assert!(drawn.len() >= 4, "{:?}", drawn);
+    for _ in 0..20 {
+        render(&mut layer);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    // Tiles within their max-age are drawn from memory, not downloaded again
+    let paths = server.paths();
+    let unique: HashSet<&String> = paths.iter().collect();
+    assert_eq!(paths.len(), unique.len(), "{:?}", paths);
+}
@@ -0,0 +1,205 @@
+//! Offline region downloads against a local HTTP stand-in that serves every
+//! tile and counts the requests it receives.
+
+mod common;
+
+use common::{ok, response, TestServer};
+use maplet::background::tasks::TaskManagerConfig;
+use maplet::background::BackgroundTaskManager;
+use maplet::core::geo::LatLngBounds;
+use maplet::layers::tile::{
+    DiskCacheConfig, DiskTileCache, OfflineDownload, OfflineDownloadState, OfflineRegion,
+    TemplateTileSource, TileLoader, TileLoaderConfig, TileSource,
+};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const TILE: &str = "tile-bytes";
+
+/// Template of a port nothing listens on
+const UNREACHABLE: &str = "http://127.0.0.1:1/{z}/{x}/{y}.png";
+
+/// Serve `TILE` for every request after `delay`
+fn start_server(delay: Duration) -> TestServer {
+    TestServer::start(move |_| {
+        std::thread::sleep(delay);
+        ok(TILE)
+    })
+}
+
+fn source(template: &str) -> Arc<dyn TileSource> {
+    Arc::new(TemplateTileSource::new(template))
+}
+
+fn loader(name: &str, max_size_bytes: u64) -> (TileLoader, Arc<DiskTileCache>, DiskCacheConfig) {
+    let config = DiskCacheConfig::new(std::env::temp_dir().join(format!(
+        "maplet_offline_{}_{}",
+        name,
+        std::process::id()
+    )))
+    .with_max_size(max_size_bytes);
+    let _ = std::fs::remove_dir_all(&config.directory);
+    let cache = Arc::new(DiskTileCache::open(config.clone()).unwrap());
+    let loader = TileLoader::new(TileLoaderConfig::for_testing())
+        .with_disk_cache(cache.clone())
+        .with_background_task_manager(Arc::new(BackgroundTaskManager::new(
+            TaskManagerConfig::default(),
+        )));
+    (loader, cache, config)
+}
+
+fn world(max_zoom: u8) -> OfflineRegion {
+    OfflineRegion::new(
+        LatLngBounds::from_coords(-85.0, -180.0, 85.0, 180.0),
+        0,
+        max_zoom,
+    )
+}
+
+async fn wait_for(download: &OfflineDownload, state: OfflineDownloadState) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while download.state() != state {
+        assert!(
+            Instant::now() < deadline,
+            "stuck at {:?}",
+            download.progress()
+        );
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_region_is_stored_and_not_downloaded_twice() {
+    let server = start_server(Duration::ZERO);
+    let (loader, cache, config) = loader("complete", 64 * 1024 * 1024);
+    let source = source(&server.template());
+    let region = world(1);
+
+    let download = loader
+        .download_region(source.clone(), region.clone())
+        .unwrap();
+    wait_for(&download, OfflineDownloadState::Completed).await;
+
+    let progress = download.progress();
+    assert_eq!(progress.total_tiles, 5);
+    assert_eq!(progress.downloaded_tiles, 5);
+    assert_eq!(progress.downloaded_bytes, 5 * TILE.len() as u64);
+    assert_eq!(progress.fraction(), 1.0);
+    assert_eq!(server.requests().len(), 5);
+    let source_id = source.source_id();
+    assert!(region
+        .tiles()
+        .all(|coord| cache.contains(&source_id, coord)));
+
+    // Everything is on disk already, so a second run makes no requests
+    let download = loader.download_region(source, region).unwrap();
+    wait_for(&download, OfflineDownloadState::Completed).await;
+    assert_eq!(download.progress().cached_tiles, 5);
+    assert_eq!(server.requests().len(), 5);
+
+    let _ = std::fs::remove_dir_all(&config.directory);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_pause_resume_and_cancel() {
+    let server = start_server(Duration::from_millis(30));
+    let (loader, _cache, config) = loader("pause", 64 * 1024 * 1024);
+
+    let download = loader
+        .download_region(source(&server.template()), world(3))
+        .unwrap();
+    download.pause();
+    assert_eq!(download.state(), OfflineDownloadState::Paused);
+
+    // Nothing new is requested while paused
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    let paused_at = server.requests().len();
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(server.requests().len(), paused_at);
+
+    download.resume();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while download.progress().downloaded_tiles < 3 {
+        assert!(Instant::now() < deadline);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    download.cancel();
+    wait_for(&download, OfflineDownloadState::Cancelled).await;
+    let progress = download.progress();
+    assert!(progress.completed_tiles() < progress.total_tiles);
+    assert!(progress.is_finished());
+
+    let _ = std::fs::remove_dir_all(&config.directory);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_download_requires_room_and_task_manager() {
+    let (loader, _cache, config) = loader("too_large", 100_000);
+    let error = loader
+        .download_region(source(UNREACHABLE), world(4))
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("disk cache holds 100000 bytes"), "{}", error);
+
+    let without_manager = TileLoader::new(TileLoaderConfig::for_testing());
+    assert!(without_manager
+        .download_region(source(UNREACHABLE), world(0))
+        .is_err());
+
+    let _ = std::fs::remove_dir_all(&config.directory);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_region_tiles_are_pinned_even_when_not_to_be_stored() {
+    let server = TestServer::start(|_| response("200 OK", &[("Cache-Control", "no-store")], TILE));
+    let (loader, cache, config) = loader("pinned", 64 * 1024 * 1024);
+    let source = source(&server.template());
+    let region = world(1);
+
+    let download = loader
+        .download_region(source.clone(), region.clone())
+        .unwrap();
+    wait_for(&download, OfflineDownloadState::Completed).await;
+    assert_eq!(download.progress().downloaded_tiles, 5);
+
+    let source_id = source.source_id();
+    assert!(region
+        .tiles()
+        .all(|coord| cache.contains(&source_id, coord) && cache.is_pinned(&source_id, coord)));
+
+    loader.release_region(source.as_ref(), &region).unwrap();
+    assert!(region
+        .tiles()
+        .all(|coord| !cache.is_pinned(&source_id, coord)));
+    assert_eq!(cache.len(), 5);
+
+    let _ = std::fs::remove_dir_all(&config.directory);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_room_for_a_region_excludes_other_pinned_regions() {
+    let server = TestServer::start(|_| ok(vec![0; 20_000]));
+    // Room for two regions by estimate, but only one of the actual tiles
+    let (loader, _cache, config) = loader("room", 30_000);
+    let first = source(&server.url("/first/{z}/{x}/{y}.png"));
+    let second = source(&server.url("/second/{z}/{x}/{y}.png"));
+
+    let download = loader.download_region(first.clone(), world(0)).unwrap();
+    wait_for(&download, OfflineDownloadState::Completed).await;
+
+    let error = loader
+        .download_region(second.clone(), world(0))
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("pinned by other regions"), "{}", error);
+
+    // The region's own tiles leave room for downloading it again
+    let download = loader.download_region(first.clone(), world(0)).unwrap();
+    wait_for(&download, OfflineDownloadState::Completed).await;
+
+    loader.release_region(first.as_ref(), &world(0)).unwrap();
+    assert!(loader.download_region(second, world(0)).is_ok());
+
+    let _ = std::fs::remove_dir_all(&config.directory);
+}
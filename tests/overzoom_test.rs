@@ -0,0 +1,125 @@
+//! Zoom levels outside a source's native range, against a local server that
+//! answers every tile with its own request path.
+
+mod common;
+
+use common::{ok, render_tiles, render_until, TestServer};
+use maplet::core::geo::{LatLng, Point};
+use maplet::core::viewport::Viewport;
+use maplet::layers::tile::{TileLayer, TileLayerOptions};
+use maplet::rendering::context::DrawCommand;
+use std::time::Duration;
+
+/// Serves each tile with its path as the body, returning the server and
+/// its URL template
+fn start_server() -> (String, TestServer) {
+    let server = TestServer::start(|request| ok(&request.path));
+    (server.url("/{z}/{x}/{y}"), server)
+}
+
+struct DrawnTile {
+    path: String,
+    bounds: (Point, Point),
+    uv: (Point, Point),
+}
+
+/// Render until every drawn tile has loaded, returning the drawn tiles
+async fn render_loaded(layer: &mut TileLayer, zoom: f64) -> Vec<DrawnTile> {
+    let mut viewport = Viewport::new(LatLng::new(51.5, -0.12), 0.0, Point::new(512.0, 512.0));
+    viewport.set_zoom_limits(0.0, 20.0);
+    viewport.set_zoom(zoom);
+    let render = |layer: &mut TileLayer| {
+        render_tiles(layer, &viewport, |command| match command {
+            DrawCommand::Tile {
+                data, bounds, uv, ..
+            } => Some(DrawnTile {
+                path: String::from_utf8_lossy(data).into_owned(),
+                bounds: *bounds,
+                uv: *uv,
+            }),
+            _ => None,
+        })
+    };
+    render_until(layer, render, |tiles: &Vec<DrawnTile>, _| {
+        !tiles.is_empty() && tiles.iter().all(|tile| tile.path.starts_with('/'))
+    })
+    .await
+}
+
+fn zoom_of(path: &str) -> u8 {
+    path.split('/').nth(1).unwrap().parse().unwrap()
+}
+
+fn layer(template: &str, options: TileLayerOptions) -> TileLayer {
+    TileLayer::from_template("imagery".to_string(), template, options).unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_zooms_past_max_native_zoom_scale_up_ancestor_tiles() {
+    let (template, server) = start_server();
+    let options = TileLayerOptions {
+        max_zoom: 20,
+        max_native_zoom: Some(17),
+        ..Default::default()
+    };
+    let mut imagery = layer(&template, options);
+
+    let tiles = render_loaded(&mut imagery, 19.0).await;
+    assert!(!tiles.is_empty());
+    for tile in &tiles {
+        assert_eq!(zoom_of(&tile.path), 17, "{}", tile.path);
+        // A z19 tile is a quarter of the width of its z17 ancestor
+        let uv_width = tile.uv.1.x - tile.uv.0.x;
+        let screen_width = tile.bounds.1.x - tile.bounds.0.x;
+        assert!((uv_width * 1024.0 - screen_width).abs() < 1e-6);
+    }
+
+    // Tiles that do not exist are never requested
+    let requested = server.paths();
+    assert!(requested.iter().all(|path| zoom_of(path) <= 17));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_zooms_below_min_native_zoom_draw_descendant_tiles() {
+    let (template, server) = start_server();
+    let options = TileLayerOptions {
+        min_zoom: 2,
+        min_native_zoom: Some(3),
+        ..Default::default()
+    };
+    let mut imagery = layer(&template, options);
+
+    let tiles = render_loaded(&mut imagery, 2.0).await;
+    assert!(!tiles.is_empty());
+    for tile in &tiles {
+        assert_eq!(zoom_of(&tile.path), 3, "{}", tile.path);
+        assert!(tile.bounds.1.x - tile.bounds.0.x <= 128.0);
+    }
+    let requested = server.paths();
+    assert!(requested.iter().all(|path| zoom_of(path) >= 3));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_zooms_far_below_min_native_zoom_draw_nothing() {
+    let (template, server) = start_server();
+    let options = TileLayerOptions {
+        min_native_zoom: Some(10),
+        ..Default::default()
+    };
+    let mut imagery = layer(&template, options);
+
+    let mut viewport = Viewport::new(LatLng::new(51.5, -0.12), 0.0, Point::new(512.0, 512.0));
+    viewport.set_zoom_limits(0.0, 20.0);
+    for zoom in [0.0, 2.0, 6.0] {
+        viewport.set_zoom(zoom);
+        for _ in 0..3 {
+            let drawn = render_tiles(&mut imagery, &viewport, |command| {
+                matches!(command, DrawCommand::Tile { .. }).then_some(())
+            });
+            assert!(drawn.is_empty(), "tiles drawn at zoom {}", zoom);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }
+    let requested = server.paths();
+    assert!(requested.is_empty(), "{:?}", requested);
+}
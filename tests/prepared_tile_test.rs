@@ -0,0 +1,138 @@
+//! Hillshade and vector tile layers against a local server: tiles prepared
+//! on blocking workers, and failed tiles left alone between retries.
+
+mod common;
+
+use common::{ok, render_tiles, render_until, response, TestServer};
+use maplet::core::geo::{LatLng, Point};
+use maplet::core::viewport::Viewport;
+use maplet::layers::base::LayerTrait;
+use maplet::layers::tile::{DemEncoding, HillshadeLayer, TileLayerOptions, VectorTileLayer};
+use maplet::rendering::context::DrawCommand;
+use std::time::Duration;
+
+/// Terrarium tile 4 pixels square, flat at 100 metres
+fn flat_terrarium_png() -> Vec<u8> {
+    let image = image::RgbImage::from_pixel(4, 4, image::Rgb([128, 100, 0]));
+    let mut png = Vec::new();
+    image::DynamicImage::ImageRgb8(image)
+        .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+        .unwrap();
+    png
+}
+
+/// Vector tile with a `places` layer holding one point near the north-west
+/// corner
+fn point_tile() -> Vec<u8> {
+    let layer = [
+        0x78, 0x02, // version 2
+        0x0a, 0x01, b'p', // name
+        0x12, 0x07, // feature
+        0x18, 0x01, // point
+        0x22, 0x03, 0x09, 0x14, 0x14, // move to (10, 10)
+        0x28, 0x80, 0x20, // extent 4096
+    ];
+    let mut tile = vec![0x1a, layer.len() as u8];
+    tile.extend_from_slice(&layer);
+    tile
+}
+
+fn viewport() -> Viewport {
+    Viewport::new(LatLng::new(0.0, 0.0), 1.0, Point::new(512.0, 512.0))
+}
+
+fn hillshade(server: &TestServer) -> HillshadeLayer {
+    let options = TileLayerOptions {
+        max_zoom: 1,
+        ..Default::default()
+    };
+    HillshadeLayer::from_template(
+        "relief".to_string(),
+        server.template(),
+        DemEncoding::Terrarium,
+        options,
+    )
+}
+
+fn vector(server: &TestServer) -> VectorTileLayer {
+    let options = TileLayerOptions {
+        max_zoom: 1,
+        ..Default::default()
+    };
+    VectorTileLayer::from_template("mvt".to_string(), server.template(), options)
+}
+
+/// Render frames for `duration`
+async fn render_for(layer: &mut dyn LayerTrait, duration: Duration) {
+    let deadline = std::time::Instant::now() + duration;
+    while std::time::Instant::now() < deadline {
+        render_tiles(layer, &viewport(), |_| Some(()));
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_hillshade_draws_tiles_shaded_on_workers() {
+    let png = flat_terrarium_png();
+    let server = TestServer::start(move |_| ok(&png));
+    let mut layer = hillshade(&server);
+
+    let shaded = |layer: &mut HillshadeLayer| {
+        render_tiles(layer, &viewport(), |command| match command {
+            DrawCommand::Tile {
+                image: Some(image), ..
+            } => Some((image.width, image.height)),
+            _ => None,
+        })
+    };
+    let tiles = render_until(&mut layer, shaded, |tiles, _| tiles.len() == 4).await;
+    assert_eq!(tiles, vec![(4, 4); 4]);
+    assert_eq!(layer.elevation_at(&LatLng::new(30.0, 30.0)), Some(100.0));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_hillshade_does_not_request_failed_tiles_every_frame() {
+    let server = TestServer::start(|_| response("404 Not Found", &[], ""));
+    let mut layer = hillshade(&server);
+
+    // Let the automatic retries run out
+    render_for(&mut layer, Duration::from_millis(300)).await;
+    assert_eq!(layer.failed_tiles().len(), 4);
+    let requests = server.requests().len();
+
+    render_for(&mut layer, Duration::from_millis(300)).await;
+    assert_eq!(server.requests().len(), requests);
+    assert_eq!(layer.loaded_tile_count(), 0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_vector_tiles_draw_features_styled_on_workers() {
+    let server = TestServer::start(|_| ok(point_tile()));
+    let mut layer = vector(&server);
+
+    let points = |layer: &mut VectorTileLayer| {
+        render_tiles(layer, &viewport(), |command| match command {
+            DrawCommand::Point { .. } => Some(()),
+            _ => None,
+        })
+        .len()
+    };
+    let drawn = render_until(&mut layer, points, |drawn, _| *drawn == 4).await;
+    assert_eq!(drawn, 4);
+    assert_eq!(layer.loaded_tile_count(), 4);
+    assert!(layer.failed_tiles().is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_vector_tiles_do_not_request_failed_tiles_every_frame() {
+    let server = TestServer::start(|_| response("404 Not Found", &[], ""));
+    let mut layer = vector(&server);
+
+    render_for(&mut layer, Duration::from_millis(300)).await;
+    assert_eq!(layer.failed_tiles().len(), 4);
+    let requests = server.requests().len();
+
+    render_for(&mut layer, Duration::from_millis(300)).await;
+    assert_eq!(server.requests().len(), requests);
+    assert_eq!(layer.loaded_tile_count(), 0);
+}
@@ -0,0 +1,101 @@
+//! Per-host rate limiting of tile downloads against a local server that
+//! records when each request arrives.
+
+mod common;
+
+use common::{ok, render_tiles, render_until, response, TestServer};
+use maplet::core::geo::{LatLng, Point};
+use maplet::core::viewport::Viewport;
+use maplet::layers::base::LayerTrait;
+use maplet::layers::tile::{rate_limiter, RateLimit, TileLayer, TileLayerOptions};
+use maplet::rendering::context::DrawCommand;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// Serves tiles on `host`, answering the first `throttled` requests with
+/// `429 Too Many Requests` and `Retry-After: 1`
+fn start_server(host: &str, throttled: usize) -> TestServer {
+    let answered = AtomicUsize::new(0);
+    TestServer::start_on(host, move |_| {
+        if answered.fetch_add(1, Ordering::SeqCst) < throttled {
+            response("429 Too Many Requests", &[("Retry-After", "1")], "")
+        } else {
+            ok("tile")
+        }
+    })
+}
+
+/// Arrival times of the requests `server` received, in order
+fn arrivals(server: &TestServer) -> Vec<Instant> {
+    let mut arrivals: Vec<Instant> = server.requests().iter().map(|request| request.at).collect();
+    arrivals.sort();
+    arrivals
+}
+
+/// Render at `zoom` until every drawn tile has loaded, or give up
+async fn render_loaded(layer: &mut TileLayer, zoom: f64) -> bool {
+    let viewport = Viewport::new(LatLng::new(0.0, 0.0), zoom, Point::new(512.0, 512.0));
+    let render = |layer: &mut TileLayer| {
+        // Updating runs the layer's retries of failed tiles
+        layer.update(0.016).unwrap();
+        render_tiles(layer, &viewport, |command| match command {
+            DrawCommand::Tile { data, .. } => Some(data.as_slice() == b"tile"),
+            _ => None,
+        })
+    };
+    let loaded = |tiles: &Vec<bool>| !tiles.is_empty() && tiles.iter().all(|loaded| *loaded);
+    let tiles = render_until(layer, render, |tiles, _| loaded(tiles)).await;
+    loaded(&tiles)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_requests_stay_within_the_host_limit() {
+    // A loopback address of its own keeps the shared limiter's host limit
+    // to this test
+    let server = start_server("127.0.0.3", 0);
+    let start = Instant::now();
+    rate_limiter().set_host_limit("127.0.0.3", Some(RateLimit::new(20.0, 4)));
+    let mut layer = TileLayer::from_template(
+        "tiles".to_string(),
+        server.template(),
+        TileLayerOptions::default(),
+    )
+    .unwrap();
+
+    assert!(render_loaded(&mut layer, 2.0).await);
+    rate_limiter().set_host_limit("127.0.0.3", None);
+    let arrivals = arrivals(&server);
+    assert!(arrivals.len() > 8, "{}", arrivals.len());
+    // Once the burst of 4 is spent, requests are sent no sooner than one per
+    // 50ms. A busy machine only delays them further, so this holds under
+    // load; the exact spacing is checked in the limiter's unit tests.
+    for (i, arrival) in arrivals.iter().enumerate().skip(4) {
+        let earliest = Duration::from_millis(50 * (i as u64 - 3));
+        let elapsed = arrival.duration_since(start);
+        assert!(
+            elapsed + Duration::from_millis(1) >= earliest,
+            "request {} after {:?}",
+            i,
+            elapsed
+        );
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_retry_after_pauses_requests_to_the_host() {
+    // A separate loopback address keeps this host's back-off to this test
+    let server = start_server("127.0.0.2", 1);
+    let options = TileLayerOptions {
+        min_zoom: 0,
+        max_zoom: 0,
+        ..Default::default()
+    };
+    let mut layer =
+        TileLayer::from_template("tiles".to_string(), server.template(), options).unwrap();
+
+    assert!(render_loaded(&mut layer, 0.0).await);
+    let arrivals = arrivals(&server);
+    assert!(arrivals.len() >= 2);
+    // Nothing was sent until the second the server asked for had passed
+    assert!(arrivals[1].duration_since(arrivals[0]) >= Duration::from_millis(950));
+}
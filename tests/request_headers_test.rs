@@ -0,0 +1,104 @@
+//! Per-source request headers and credentials against a local HTTP stand-in
+//! that returns scripted responses and records the requests it receives.
+
+mod common;
+
+use common::{collect_results, ok, response, ScriptedServer};
+use maplet::core::geo::TileCoord;
+use maplet::layers::tile::{
+    AuthToken, RequestOptions, TemplateTileSource, TileLoader, TileLoaderConfig, TilePriority,
+    TokenGrant, TokenPlacement, DEFAULT_USER_AGENT,
+};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_source_headers_user_agent_and_token_are_sent() {
+    let server = ScriptedServer::start();
+    let loader = TileLoader::new(TileLoaderConfig::for_testing());
+    let token = AuthToken::new("key-1");
+    let source = TemplateTileSource::new(server.template()).with_request_options(
+        RequestOptions::new()
+            .with_user_agent("TrailApp/2.1 (+https://trail.example.com)")
+            .with_referer("https://trail.example.com/")
+            .with_token(
+                token.clone(),
+                TokenPlacement::Header("X-Api-Key".to_string()),
+            ),
+    );
+
+    server.script(ok("a"));
+    loader
+        .queue_tile(&source, TileCoord::new(0, 0, 1), TilePriority::Visible)
+        .unwrap();
+    assert_eq!(
+        collect_results(&loader, 1, Duration::from_secs(5))
+            .await
+            .len(),
+        1
+    );
+
+    // A replaced token is used by the next request
+    token.set("key-2");
+    server.script(ok("b"));
+    loader
+        .queue_tile(&source, TileCoord::new(1, 0, 1), TilePriority::Visible)
+        .unwrap();
+    assert_eq!(
+        collect_results(&loader, 1, Duration::from_secs(5))
+            .await
+            .len(),
+        1
+    );
+
+    let requests = server.requests();
+    assert!(requests[0].contains("user-agent: trailapp/2.1 (+https://trail.example.com)"));
+    assert!(requests[0].contains("referer: https://trail.example.com/"));
+    assert!(requests[0].contains("x-api-key: key-1"));
+    assert!(requests[1].contains("x-api-key: key-2"));
+
+    // Sources without options identify the library
+    server.script(ok("c"));
+    loader
+        .queue_tile(
+            &TemplateTileSource::new(server.template()),
+            TileCoord::new(0, 1, 1),
+            TilePriority::Visible,
+        )
+        .unwrap();
+    collect_results(&loader, 1, Duration::from_secs(5)).await;
+    let expected = format!("user-agent: {}", DEFAULT_USER_AGENT.to_ascii_lowercase());
+    assert!(server.requests()[2].contains(&expected));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_rejected_token_is_refreshed_and_retried() {
+    let server = ScriptedServer::start();
+    let loader = TileLoader::new(TileLoaderConfig::for_testing());
+    let refreshes = Arc::new(AtomicUsize::new(0));
+    let counter = refreshes.clone();
+    let token = AuthToken::refreshable(move || {
+        let n = counter.fetch_add(1, Ordering::SeqCst) + 1;
+        async move { Ok(TokenGrant::new(format!("session-{}", n))) }
+    });
+    let source = TemplateTileSource::new(server.template())
+        .with_request_options(RequestOptions::new().with_token(token, TokenPlacement::Bearer));
+
+    server.script(response("401 Unauthorized", &[], ""));
+    server.script(ok("tile"));
+    loader
+        .queue_tile(&source, TileCoord::new(0, 0, 0), TilePriority::Visible)
+        .unwrap();
+    let results = collect_results(&loader, 1, Duration::from_secs(5)).await;
+    assert_eq!(
+        results[0].data.as_ref().unwrap().as_deref(),
+        Some(&b"tile"[..])
+    );
+    assert_eq!(refreshes.load(Ordering::SeqCst), 2);
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert!(requests[0].contains("authorization: bearer session-1"));
+    assert!(requests[1].contains("authorization: bearer session-2"));
+}
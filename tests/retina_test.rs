@@ -0,0 +1,92 @@
+//! HiDPI tile selection and placement for tile layers with `detect_retina`
+
+use maplet::core::geo::{LatLng, Point};
+use maplet::core::viewport::Viewport;
+use maplet::layers::base::LayerTrait;
+use maplet::layers::tile::{RetinaMode, TileLayer, TileLayerOptions};
+use maplet::rendering::context::{DrawCommand, RenderContext};
+
+/// Nothing listens here, so queued downloads fail fast
+const PLAIN_TEMPLATE: &str = "http://127.0.0.1:9/{z}/{x}/{y}.png";
+const RETINA_TEMPLATE: &str = "http://127.0.0.1:9/{z}/{x}/{y}{r}.png";
+
+fn layer(template: &str, detect_retina: bool) -> TileLayer {
+    let options = TileLayerOptions {
+        detect_retina,
+        ..Default::default()
+    };
+    TileLayer::from_template("tiles".to_string(), template, options).unwrap()
+}
+
+/// Render one frame and return the screen bounds of every drawn tile
+fn render(layer: &mut TileLayer, pixels_per_point: f32) -> Vec<(Point, Point)> {
+    let viewport = Viewport::new(LatLng::new(0.0, 0.0), 3.0, Point::new(512.0, 512.0));
+    let mut context = RenderContext::new(512, 512)
+        .unwrap()
+        .with_pixels_per_point(pixels_per_point);
+    layer.render(&mut context, &viewport).unwrap();
+    context
+        .get_drawing_queue()
+        .iter()
+        .filter_map(|command| match command {
+            DrawCommand::Tile { bounds, .. } => Some(*bounds),
+            _ => None,
+        })
+        .collect()
+}
+
+fn width(bounds: &(Point, Point)) -> f64 {
+    bounds.1.x - bounds.0.x
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_sources_without_retina_tiles_use_the_next_zoom_at_half_size() {
+    let mut standard = layer(PLAIN_TEMPLATE, true);
+    let tiles = render(&mut standard, 1.0);
+    assert_eq!(standard.retina_mode(), RetinaMode::Off);
+    assert!(tiles.iter().all(|bounds| width(bounds) == 256.0));
+
+    let mut hidpi = layer(PLAIN_TEMPLATE, true);
+    let half_size = render(&mut hidpi, 2.0);
+    assert_eq!(hidpi.retina_mode(), RetinaMode::ZoomOffset);
+    assert!(half_size.iter().all(|bounds| width(bounds) == 128.0));
+    // The smaller tiles still cover the whole viewport
+    assert!(half_size.len() > tiles.len());
+    assert!(half_size
+        .iter()
+        .any(|(min, _)| min.x <= 0.0 && min.y <= 0.0));
+    assert!(half_size
+        .iter()
+        .any(|(_, max)| max.x >= 512.0 && max.y >= 512.0));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_sources_with_retina_tiles_keep_the_zoom() {
+    let mut hidpi = layer(RETINA_TEMPLATE, true);
+    let tiles = render(&mut hidpi, 2.0);
+    assert_eq!(hidpi.retina_mode(), RetinaMode::HighResolution);
+    assert!(!tiles.is_empty());
+    assert!(tiles.iter().all(|bounds| width(bounds) == 256.0));
+
+    // Back on a standard display the layer returns to ordinary tiles
+    render(&mut hidpi, 1.0);
+    assert_eq!(hidpi.retina_mode(), RetinaMode::Off);
+
+    // Without detect_retina the display scale is ignored
+    let mut disabled = layer(RETINA_TEMPLATE, false);
+    render(&mut disabled, 2.0);
+    assert_eq!(disabled.retina_mode(), RetinaMode::Off);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_tile_edges_are_snapped_to_physical_pixels() {
+    let mut hidpi = layer(PLAIN_TEMPLATE, true);
+    let tiles = render(&mut hidpi, 1.5);
+    assert!(!tiles.is_empty());
+    for (min, max) in tiles {
+        for value in [min.x, min.y, max.x, max.y] {
+            let pixels = value * 1.5;
+            assert!((pixels - pixels.round()).abs() < 1e-9, "{}", value);
+        }
+    }
+}
@@ -0,0 +1,93 @@
+//! Tile images decoded by the loader before they reach the renderer
+
+mod common;
+
+use common::{render_tiles, render_until, response, TestServer};
+use maplet::core::geo::{LatLng, Point};
+use maplet::core::viewport::Viewport;
+use maplet::layers::tile::{
+    DebugTileSource, DecodedTile, TemplateTileSource, TileImageFormat, TileLayer, TileLayerOptions,
+};
+use maplet::rendering::context::DrawCommand;
+use std::sync::Arc;
+
+/// 1x1 lossless WebP
+const WEBP: [u8; 34] = [
+    0x52, 0x49, 0x46, 0x46, 0x1a, 0x00, 0x00, 0x00, 0x57, 0x45, 0x42, 0x50, 0x56, 0x50, 0x38, 0x4c,
+    0x0d, 0x00, 0x00, 0x00, 0x2f, 0x00, 0x00, 0x00, 0x10, 0x07, 0x10, 0x11, 0x11, 0x88, 0x88, 0xfe,
+    0x07, 0x00,
+];
+
+/// Answers every tile with `body`, returning the server's URL template
+fn start_server(body: &'static [u8]) -> String {
+    let content_type = [("Content-Type", "application/octet-stream")];
+    TestServer::start(move |_| response("200 OK", &content_type, body)).url("/{z}/{x}/{y}.webp")
+}
+
+type DrawnTile = (Vec<u8>, Option<Arc<DecodedTile>>);
+
+/// Render until `done` accepts the drawn tiles, returning their data and
+/// decoded pixels
+async fn render_loaded(
+    layer: &mut TileLayer,
+    done: impl Fn(&[DrawnTile]) -> bool,
+) -> Vec<DrawnTile> {
+    let viewport = Viewport::new(LatLng::new(0.0, 0.0), 2.0, Point::new(512.0, 512.0));
+    let render = |layer: &mut TileLayer| {
+        render_tiles(layer, &viewport, |command| match command {
+            DrawCommand::Tile { data, image, .. } => Some((data.clone(), image.clone())),
+            _ => None,
+        })
+    };
+    render_until(layer, render, |tiles: &Vec<DrawnTile>, _| {
+        !tiles.is_empty() && done(tiles)
+    })
+    .await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_webp_tiles_arrive_decoded() {
+    let template = start_server(&WEBP);
+    let mut layer = TileLayer::new(
+        "webp".to_string(),
+        Box::new(TemplateTileSource::new(template)),
+        TileLayerOptions::default(),
+    )
+    .unwrap();
+
+    let tiles = render_loaded(&mut layer, |tiles| {
+        tiles.iter().all(|(_, image)| image.is_some())
+    })
+    .await;
+    for (data, image) in &tiles {
+        assert_eq!(data.as_slice(), WEBP.as_slice());
+        let image = image.as_ref().expect("tile was not decoded by the loader");
+        assert_eq!(image.format, TileImageFormat::WebP);
+        assert_eq!(image.size(), [1, 1]);
+        assert_eq!(image.rgba.len(), 4);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_decoded_pixels_match_the_tile_data() {
+    let source = DebugTileSource::new().with_tile_size(128);
+    let mut layer = TileLayer::new(
+        "debug".to_string(),
+        Box::new(source),
+        TileLayerOptions::default(),
+    )
+    .unwrap();
+
+    let tiles = render_loaded(&mut layer, |tiles| {
+        tiles.iter().all(|(_, image)| image.is_some())
+    })
+    .await;
+    assert!(!tiles.is_empty());
+    for (data, image) in &tiles {
+        let image = image.as_ref().expect("tile was not decoded by the loader");
+        let expected = DecodedTile::decode(data).unwrap().unwrap();
+        assert_eq!((image.width, image.height), (128, 128));
+        assert_eq!(image.format, TileImageFormat::Png);
+        assert!(image.rgba == expected.rgba);
+    }
+}
@@ -0,0 +1,225 @@
+//! Failed tiles against a local server that can be switched between refusing
+//! tiles and serving them: placeholders, `TileError` events and retries.
+
+mod common;
+
+use common::{ok, response, TestServer};
+use maplet::core::geo::{LatLng, Point, TileCoord};
+use maplet::core::map::Map;
+use maplet::input::MapEvent;
+use maplet::layers::tile::error_tile::error_pattern;
+use maplet::layers::tile::{TileLayer, TileLayerOptions};
+use maplet::rendering::context::{DrawCommand, RenderContext};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const LAYER_ID: &str = "tiles";
+
+/// Answers `/error.png` with `error_image` and tiles with `tile_image`, or
+/// `404 Not Found` while `healthy` is false
+fn start_server(healthy: Arc<AtomicBool>, tile_image: Vec<u8>, error_image: Vec<u8>) -> TestServer {
+    TestServer::start(move |request| {
+        if request.path == "/error.png" {
+            ok(&error_image)
+        } else if healthy.load(Ordering::SeqCst) {
+            ok(&tile_image)
+        } else {
+            response("404 Not Found", &[], "")
+        }
+    })
+}
+
+fn map_with_layer(server: &TestServer, error_tile_url: Option<String>) -> Map {
+    let mut map = Map::for_testing(LatLng::new(0.0, 0.0), 1.0, Point::new(512.0, 512.0));
+    let options = TileLayerOptions {
+        error_tile_url,
+        ..Default::default()
+    };
+    let layer = TileLayer::from_template(LAYER_ID.to_string(), server.template(), options).unwrap();
+    map.add_layer(Box::new(layer)).unwrap();
+    map
+}
+
+fn with_tile_layer<R>(map: &mut Map, f: impl FnOnce(&mut TileLayer) -> R) -> R {
+    map.with_layer_mut(LAYER_ID, |layer| {
+        f(layer.as_any_mut().downcast_mut::<TileLayer>().unwrap())
+    })
+    .unwrap()
+}
+
+fn zoom_one_failures(map: &mut Map) -> usize {
+    with_tile_layer(map, |layer| {
+        layer
+            .failed_tiles()
+            .iter()
+            .filter(|coord| coord.z == 1)
+            .count()
+    })
+}
+
+/// Tile images of one frame
+fn render(map: &mut Map) -> Vec<Vec<u8>> {
+    let mut context = RenderContext::new(512, 512).unwrap();
+    map.update_and_render(&mut context).unwrap();
+    context
+        .get_drawing_queue()
+        .iter()
+        .filter_map(|command| match command {
+            DrawCommand::Tile { data, .. } => Some(data.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Render frames until `done` holds, returning the last frame's tile images
+async fn render_until(
+    map: &mut Map,
+    mut done: impl FnMut(&mut Map, &[Vec<u8>]) -> bool,
+) -> Vec<Vec<u8>> {
+    common::render_until(map, render, |tiles, map| done(map, tiles)).await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_failed_tiles_are_reported_once_and_can_be_retried() {
+    let healthy = Arc::new(AtomicBool::new(false));
+    let server = start_server(healthy.clone(), b"tile".to_vec(), Vec::new());
+    let mut map = map_with_layer(&server, None);
+    let reported = Arc::new(Mutex::new(Vec::new()));
+    let listener = reported.clone();
+    map.on("tileerror", move |event| {
+        listener.lock().unwrap().push(event.clone());
+    });
+
+    // The four tiles of zoom 1 fail, as do the neighbouring zooms prefetched
+    // around them
+    let tiles = render_until(&mut map, |map, tiles| {
+        zoom_one_failures(map) == 4 && tiles.iter().all(|data| !data.is_empty())
+    })
+    .await;
+    assert!(!tiles.is_empty());
+    assert!(tiles.iter().all(|data| *data == error_pattern(256)));
+
+    // Let the automatic retries run out before counting events
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    let mut frames = 0;
+    render_until(&mut map, |_, _| {
+        frames += 1;
+        frames > 10
+    })
+    .await;
+    map.process_events();
+    let events = reported.lock().unwrap().clone();
+    let failed = with_tile_layer(&mut map, |layer| layer.failed_tiles());
+    let mut coords: Vec<TileCoord> = events
+        .iter()
+        .map(|event| match event {
+            MapEvent::TileError {
+                coord,
+                layer_id,
+                error,
+            } => {
+                assert_eq!(layer_id, LAYER_ID);
+                assert!(error.contains("404"), "{}", error);
+                *coord
+            }
+            other => panic!("unexpected event {:?}", other),
+        })
+        .collect();
+    // One event per failed tile, however often it was retried
+    assert_eq!(coords.len(), failed.len());
+    coords.sort_by_key(|coord| (coord.z, coord.x, coord.y));
+    coords.dedup();
+    assert_eq!(coords.len(), failed.len());
+    assert!(failed.iter().all(|coord| coords.contains(coord)));
+    assert!(with_tile_layer(&mut map, |layer| layer
+        .tile_error(&TileCoord::new(1, 1, 1))
+        .is_some_and(|error| error.contains("404"))));
+
+    // Once the server recovers, retried tiles load and leave the failed list
+    healthy.store(true, Ordering::SeqCst);
+    assert_eq!(
+        with_tile_layer(&mut map, |layer| layer.retry_failed_tiles()),
+        failed.len()
+    );
+    let tiles = render_until(&mut map, |map, tiles| {
+        zoom_one_failures(map) == 0 && tiles.iter().all(|data| data == b"tile")
+    })
+    .await;
+    assert!(tiles.iter().all(|data| data == b"tile"));
+    assert!(with_tile_layer(&mut map, |layer| layer
+        .failed_tiles()
+        .is_empty()));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_error_tile_url_replaces_the_generated_pattern() {
+    let error_image = error_pattern(8);
+    let server = start_server(
+        Arc::new(AtomicBool::new(false)),
+        Vec::new(),
+        error_image.clone(),
+    );
+    let mut map = map_with_layer(&server, Some(server.url("/error.png")));
+
+    let tiles = render_until(&mut map, |map, tiles| {
+        zoom_one_failures(map) == 4 && tiles.iter().all(|data| *data == error_image)
+    })
+    .await;
+    assert!(!tiles.is_empty());
+    assert!(tiles.iter().all(|data| *data == error_image));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_failures_are_forgotten_outside_the_keep_buffer() {
+    let healthy = Arc::new(AtomicBool::new(false));
+    let server = start_server(healthy.clone(), b"tile".to_vec(), Vec::new());
+    let mut map = map_with_layer(&server, None);
+    let home = LatLng::new(0.0, 0.0);
+    map.set_view(home, 6.0).unwrap();
+
+    let failed_at_six = |map: &mut Map| {
+        with_tile_layer(map, |layer| {
+            layer
+                .failed_tiles()
+                .into_iter()
+                .filter(|coord| coord.z == 6)
+                .collect::<Vec<_>>()
+        })
+    };
+    render_until(&mut map, |map, tiles| {
+        !failed_at_six(map).is_empty() && tiles.iter().all(|data| *data == error_pattern(256))
+    })
+    .await;
+    // Let the automatic retries run out
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    let mut frames = 0;
+    render_until(&mut map, |_, _| {
+        frames += 1;
+        frames > 10
+    })
+    .await;
+    let failed_at_home = failed_at_six(&mut map);
+
+    // Far away, the failures around home are dropped rather than kept for
+    // the rest of the session
+    map.set_view(LatLng::new(0.0, 170.0), 6.0).unwrap();
+    render_until(&mut map, |map, _| {
+        let failed = failed_at_six(map);
+        !failed_at_home.iter().any(|coord| failed.contains(coord))
+    })
+    .await;
+    let failed = failed_at_six(&mut map);
+    assert!(!failed_at_home.iter().any(|coord| failed.contains(coord)));
+
+    // Back home with the server recovered, the tiles load without a manual
+    // retry
+    healthy.store(true, Ordering::SeqCst);
+    map.set_view(home, 6.0).unwrap();
+    let tiles = render_until(&mut map, |_, tiles| {
+        !tiles.is_empty() && tiles.iter().all(|data| data == b"tile")
+    })
+    .await;
+    assert!(!tiles.is_empty());
+    assert!(tiles.iter().all(|data| data == b"tile"));
+}
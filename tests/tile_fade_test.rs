@@ -0,0 +1,90 @@
+//! Tiles fading in over their fallback, against a local server that answers
+//! every tile with its own request path.
+
+mod common;
+
+use common::{ok, render_tiles, TestServer};
+use maplet::core::geo::{LatLng, Point};
+use maplet::core::viewport::Viewport;
+use maplet::layers::tile::{TileLayer, TileLayerOptions};
+use maplet::rendering::context::DrawCommand;
+
+/// Serves each tile with its path as the body
+fn start_server() -> String {
+    TestServer::start(|request| ok(&request.path)).url("/{z}/{x}/{y}")
+}
+
+fn layer(fade_duration_ms: u32) -> TileLayer {
+    let options = TileLayerOptions {
+        fade_duration_ms,
+        ..Default::default()
+    };
+    TileLayer::from_template("tiles".to_string(), start_server(), options).unwrap()
+}
+
+/// Zoom of each drawn tile with its opacity, in drawing order; placeholders
+/// are left out
+fn render(layer: &mut TileLayer, zoom: f64) -> Vec<(u8, f32)> {
+    let viewport = Viewport::new(LatLng::new(0.0, 0.0), zoom, Point::new(512.0, 512.0));
+    render_tiles(layer, &viewport, |command| match command {
+        DrawCommand::Tile { data, opacity, .. } if !data.is_empty() => {
+            let path = String::from_utf8_lossy(data).into_owned();
+            Some((path.split('/').nth(1)?.parse().ok()?, *opacity))
+        }
+        _ => None,
+    })
+}
+
+async fn render_until(
+    layer: &mut TileLayer,
+    zoom: f64,
+    mut done: impl FnMut(&[(u8, f32)], &TileLayer) -> bool,
+) -> Vec<(u8, f32)> {
+    let render = |layer: &mut TileLayer| render(layer, zoom);
+    common::render_until(layer, render, |tiles, layer| done(tiles, layer)).await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_new_tiles_cross_fade_with_their_parents() {
+    let mut layer = layer(800);
+    render_until(&mut layer, 2.0, |tiles, _| {
+        !tiles.is_empty() && tiles.iter().all(|(z, _)| *z == 2)
+    })
+    .await;
+
+    // Zoomed in, each new tile is drawn over the part of its parent it
+    // replaces, the parent fading out as the tile fades in
+    let mut repainting = false;
+    let fading = render_until(&mut layer, 3.0, |tiles, layer| {
+        repainting = layer.needs_repaint();
+        tiles.windows(2).any(|pair| {
+            let ((parent_zoom, parent), (zoom, tile)) = (pair[0], pair[1]);
+            parent_zoom == 2 && zoom == 3 && tile > 0.0 && tile < 1.0 && parent > 0.0
+        })
+    })
+    .await;
+    assert!(repainting, "fading tiles must keep the repaint loop alive");
+    let (parent, tile) = fading
+        .windows(2)
+        .find(|pair| pair[0].0 == 2 && pair[1].0 == 3 && pair[1].1 < 1.0)
+        .map(|pair| (pair[0].1, pair[1].1))
+        .expect("no tile was fading in over its parent");
+    assert!(parent < 0.8 && tile < 1.0);
+
+    // Once faded in, only the new tiles are drawn, fully opaque
+    let done = render_until(&mut layer, 3.0, |tiles, layer| {
+        !tiles.is_empty() && tiles.iter().all(|tile| *tile == (3, 1.0)) && !layer.is_fading()
+    })
+    .await;
+    assert!(done.iter().all(|tile| *tile == (3, 1.0)), "{:?}", done);
+    assert!(!layer.is_fading());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_tiles_appear_at_once_without_a_fade_duration() {
+    let mut layer = layer(0);
+    let tiles = render_until(&mut layer, 2.0, |tiles, _| !tiles.is_empty()).await;
+    assert!(!tiles.is_empty());
+    assert!(tiles.iter().all(|(_, opacity)| *opacity == 1.0));
+    assert!(!layer.is_fading());
+}
@@ -0,0 +1,153 @@
+//! Colour filters carried by tile layers to the renderer
+
+mod common;
+
+use common::render_until;
+use maplet::core::geo::{LatLng, Point};
+use maplet::core::viewport::Viewport;
+use maplet::layers::base::LayerTrait;
+use maplet::layers::tile::{DebugTileSource, TileLayer, TileLayerOptions};
+use maplet::rendering::context::{DrawCommand, RenderContext};
+use maplet::rendering::RasterFilter;
+use maplet::ui::widget::MapTheme;
+use std::time::Duration;
+
+fn layer(source: DebugTileSource, filter: Option<RasterFilter>) -> TileLayer {
+    let options = TileLayerOptions {
+        filter,
+        fade_duration_ms: 0,
+        ..Default::default()
+    };
+    TileLayer::new("debug".to_string(), Box::new(source), options).unwrap()
+}
+
+/// Filters and opacities of the loaded tiles drawn, once every visible tile
+/// is drawn, with `theme` as the filter of the map's theme
+async fn render_themed(
+    layer: &mut TileLayer,
+    theme: Option<RasterFilter>,
+) -> Vec<(Option<RasterFilter>, f32)> {
+    let viewport = Viewport::new(LatLng::new(0.0, 0.0), 2.0, Point::new(512.0, 512.0));
+    let render = |layer: &mut TileLayer| {
+        let mut context = RenderContext::new(512, 512)
+            .unwrap()
+            .with_basemap_filter(theme);
+        layer.render(&mut context, &viewport).unwrap();
+        assert_eq!(context.raster_filter, None, "filter leaked to later layers");
+        context
+            .get_drawing_queue()
+            .iter()
+            .filter_map(|command| match command {
+                DrawCommand::Tile {
+                    data,
+                    filter,
+                    opacity,
+                    ..
+                } => Some((data.is_empty(), *filter, *opacity)),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+    let tiles = render_until(layer, render, |tiles, _| {
+        !tiles.is_empty() && tiles.iter().all(|(empty, _, _)| !empty)
+    })
+    .await;
+    tiles
+        .into_iter()
+        .map(|(_, filter, opacity)| (filter, opacity))
+        .collect()
+}
+
+/// Filters of the loaded tiles drawn, once every visible tile is drawn
+async fn render_loaded(layer: &mut TileLayer) -> Vec<Option<RasterFilter>> {
+    let tiles = render_themed(layer, None).await;
+    tiles.into_iter().map(|(filter, _)| filter).collect()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_tiles_carry_the_layer_filter() {
+    let grayscale = RasterFilter::default().with_grayscale(1.0);
+    let source = DebugTileSource::new().with_tile_size(64);
+    let mut layer = layer(source.clone(), Some(grayscale));
+    let filters = render_loaded(&mut layer).await;
+    assert!(!filters.is_empty());
+    assert!(filters.iter().all(|filter| *filter == Some(grayscale)));
+
+    // Changing the filter redraws the loaded tiles without fetching them,
+    // so nothing fails with the source gone
+    source.set_offline(true);
+    layer.set_filter(Some(RasterFilter::dark()));
+    let filters = render_loaded(&mut layer).await;
+    assert!(filters
+        .iter()
+        .all(|filter| *filter == Some(RasterFilter::dark())));
+    assert!(layer.failed_tiles().is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_filter_animates_to_its_target() {
+    let mut layer = layer(DebugTileSource::new().with_tile_size(64), None);
+    assert!(render_loaded(&mut layer).await.iter().all(Option::is_none));
+
+    let target = RasterFilter::default().with_invert(1.0);
+    layer.animate_filter(Some(target), Duration::from_millis(300));
+    assert!(layer.is_filter_animating());
+    assert!(layer.needs_repaint());
+
+    // Part way, each tile is drawn as it was with the target filter faded
+    // in over it, rather than filtered anew every frame
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let tiles = render_themed(&mut layer, None).await;
+    assert!(!tiles.is_empty() && tiles.len() % 2 == 0, "{:?}", tiles);
+    for pair in tiles.chunks(2) {
+        assert_eq!(pair[0], (None, 1.0));
+        let (filter, progress) = pair[1];
+        assert_eq!(filter, Some(target));
+        assert!(progress > 0.0 && progress < 1.0, "{}", progress);
+    }
+
+    tokio::time::sleep(Duration::from_millis(250)).await;
+    layer.update(0.016).unwrap();
+    assert!(!layer.is_filter_animating());
+    assert_eq!(layer.filter(), Some(target));
+    assert!(render_loaded(&mut layer)
+        .await
+        .iter()
+        .all(|filter| *filter == Some(target)));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_theme_filters_only_basemap_layers() {
+    let dark = Some(RasterFilter::dark());
+    let source = DebugTileSource::new().with_tile_size(64);
+    let mut overlay = layer(source.clone(), None);
+    assert!(render_themed(&mut overlay, dark)
+        .await
+        .iter()
+        .all(|(filter, _)| filter.is_none()));
+
+    let options = TileLayerOptions {
+        basemap: true,
+        fade_duration_ms: 0,
+        ..Default::default()
+    };
+    let mut basemap = TileLayer::new("base".to_string(), Box::new(source), options).unwrap();
+    assert!(render_themed(&mut basemap, dark)
+        .await
+        .iter()
+        .all(|(filter, _)| *filter == dark));
+
+    // A basemap's own filter wins over the theme's
+    let grayscale = RasterFilter::default().with_grayscale(1.0);
+    basemap.set_filter(Some(grayscale));
+    assert!(render_themed(&mut basemap, dark)
+        .await
+        .iter()
+        .all(|(filter, _)| *filter == Some(grayscale)));
+}
+
+#[test]
+fn test_dark_theme_filters_the_basemap() {
+    assert_eq!(MapTheme::Dark.tile_filter(), Some(RasterFilter::dark()));
+    assert_eq!(MapTheme::Light.tile_filter(), None);
+}
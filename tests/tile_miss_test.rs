@@ -0,0 +1,65 @@
+//! Tiles a local source has no data for, which show their parent tile
+//! rather than being drawn as empty images
+
+mod common;
+
+use common::{render_tiles, render_until};
+use maplet::core::geo::{LatLng, Point, TileCoord};
+use maplet::core::viewport::Viewport;
+use maplet::layers::base::LayerTrait;
+use maplet::layers::tile::{DirectoryTileSource, TileLayer, TileLayerOptions};
+use maplet::rendering::context::DrawCommand;
+use std::path::PathBuf;
+
+/// Directory with the zoom 0 tile and the western half of zoom 1, each file
+/// holding its own path
+fn create_tree() -> PathBuf {
+    let root = std::env::temp_dir().join(format!("maplet_tile_miss_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    for (z, x, y) in [(0, 0, 0), (1, 0, 0), (1, 0, 1)] {
+        let dir = root.join(z.to_string()).join(x.to_string());
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(format!("{}.png", y)), format!("{}/{}/{}", z, x, y)).unwrap();
+    }
+    root
+}
+
+fn render(layer: &mut TileLayer) -> Vec<Vec<u8>> {
+    layer.update(0.016).unwrap();
+    let viewport = Viewport::new(LatLng::new(0.0, 0.0), 1.0, Point::new(512.0, 512.0));
+    render_tiles(layer, &viewport, |command| match command {
+        DrawCommand::Tile { data, .. } => Some(data.clone()),
+        _ => None,
+    })
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_missing_tiles_show_their_parent() {
+    let root = create_tree();
+    let source = DirectoryTileSource::open(&root).unwrap();
+    let mut options = TileLayerOptions {
+        fade_duration_ms: 0,
+        ..Default::default()
+    };
+    source.apply_to_options(&mut options);
+    let mut layer = TileLayer::new("dir".to_string(), Box::new(source), options).unwrap();
+
+    let wanted = |tiles: &[Vec<u8>]| {
+        let has = |path: &[u8]| tiles.iter().any(|data| data == path);
+        has(b"1/0/0") && has(b"1/0/1") && has(b"0/0/0")
+    };
+    let tiles = render_until(&mut layer, render, |tiles, _| {
+        wanted(tiles) && tiles.iter().all(|data| !data.is_empty())
+    })
+    .await;
+
+    // The eastern half is drawn from the zoom 0 tile, not as empty tiles
+    assert!(wanted(&tiles), "{:?}", tiles);
+    assert!(tiles.iter().all(|data| !data.is_empty()), "{:?}", tiles);
+    let missing = TileCoord::new(1, 0, 1);
+    assert!(!layer.tile_cache().contains(&missing));
+    assert!(layer.failed_tiles().is_empty());
+    let viewport = Viewport::new(LatLng::new(0.0, 0.0), 1.0, Point::new(512.0, 512.0));
+    assert!(layer.is_ready(&viewport));
+    let _ = std::fs::remove_dir_all(&root);
+}
@@ -0,0 +1,178 @@
+//! Time-dimension layers against a local server that answers every tile
+//! with its own request path, holding back tiles for the `slow` time step
+
+mod common;
+
+use common::{collect_results, ok, render_tiles, TestServer};
+use maplet::core::geo::{LatLng, Point, TileCoord};
+use maplet::core::viewport::Viewport;
+use maplet::layers::tile::{
+    TemplateTileSource, TileLayerOptions, TileLoader, TileLoaderConfig, TilePriority,
+    TimeDimensionLayer,
+};
+use maplet::rendering::context::DrawCommand;
+use std::time::Duration;
+
+fn start_server() -> String {
+    TestServer::start(|request| {
+        if request.path.ends_with("time=slow") {
+            std::thread::sleep(Duration::from_millis(800));
+        }
+        ok(&request.path)
+    })
+    .url("/{z}/{x}/{y}?time={time}")
+}
+
+fn layer(times: &[&str]) -> TimeDimensionLayer {
+    let options = TileLayerOptions {
+        fade_duration_ms: 0,
+        ..Default::default()
+    };
+    TimeDimensionLayer::from_template(
+        "radar".to_string(),
+        start_server(),
+        times.iter().map(|time| time.to_string()).collect(),
+        options,
+    )
+    .unwrap()
+}
+
+/// Time step of each drawn tile, `None` for placeholders
+fn render(layer: &mut TimeDimensionLayer) -> Vec<Option<String>> {
+    let viewport = Viewport::new(LatLng::new(0.0, 0.0), 2.0, Point::new(512.0, 512.0));
+    render_tiles(layer, &viewport, |command| match command {
+        DrawCommand::Tile { data, .. } => Some(
+            String::from_utf8_lossy(data)
+                .split_once("time=")
+                .map(|(_, time)| time.to_string()),
+        ),
+        _ => None,
+    })
+}
+
+async fn render_until(
+    layer: &mut TimeDimensionLayer,
+    mut done: impl FnMut(&[Option<String>], &TimeDimensionLayer) -> bool,
+) -> Vec<Option<String>> {
+    common::render_until(layer, render, |tiles, layer| done(tiles, layer)).await
+}
+
+fn all_at(tiles: &[Option<String>], time: &str) -> bool {
+    !tiles.is_empty() && tiles.iter().all(|tile| tile.as_deref() == Some(time))
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_frames_swap_only_once_loaded() {
+    let mut layer = layer(&["fast", "slow"]).with_preload(0);
+    let tiles = render_until(&mut layer, |tiles, _| all_at(tiles, "fast")).await;
+    assert!(all_at(&tiles, "fast"), "{:?}", tiles);
+
+    // The slow frame loads behind the fast one, which stays on screen whole
+    layer.step_forward();
+    assert_eq!(layer.pending_index(), Some(1));
+    let mut held = 0;
+    let tiles = render_until(&mut layer, |tiles, layer| {
+        if layer.current_time() == "fast" {
+            assert!(all_at(tiles, "fast"), "{:?}", tiles);
+            held += 1;
+        }
+        layer.current_time() == "slow"
+    })
+    .await;
+    assert!(held > 0);
+    assert_eq!(layer.pending_index(), None);
+    assert!(all_at(&tiles, "slow"), "{:?}", tiles);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_play_pause_and_step() {
+    let mut layer = layer(&["a", "b", "c"]).with_frame_duration(Duration::from_millis(40));
+    render_until(&mut layer, |tiles, _| all_at(tiles, "a")).await;
+
+    // Playback shows every frame in turn and loops
+    layer.play();
+    assert!(layer.needs_repaint());
+    let mut shown = vec!["a".to_string()];
+    render_until(&mut layer, |tiles, layer| {
+        let time = layer.current_time();
+        assert!(all_at(tiles, time), "{:?} while showing {}", tiles, time);
+        if shown.last().map(String::as_str) != Some(time) {
+            shown.push(time.to_string());
+        }
+        shown.len() == 4
+    })
+    .await;
+    assert_eq!(shown, ["a", "b", "c", "a"]);
+
+    // Paused, the frame stays put until stepped
+    layer.pause();
+    let paused = layer.current_index();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    render(&mut layer);
+    assert_eq!(layer.current_index(), paused);
+
+    layer.step_back();
+    let tiles = render_until(&mut layer, |_, layer| layer.pending_index().is_none()).await;
+    let time = layer.times()[(paused + 2) % 3].clone();
+    assert_eq!(layer.current_time(), time);
+    assert!(all_at(&tiles, &time), "{:?}", tiles);
+
+    layer.set_speed(4.0);
+    assert_eq!(layer.speed(), 4.0);
+    layer.set_speed(0.0);
+    assert_eq!(layer.speed(), 4.0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_preloaded_frames_load_without_being_drawn() {
+    let mut layer = layer(&["a", "b", "c"]);
+    let tiles = render_until(&mut layer, |tiles, layer| {
+        assert!(
+            tiles.iter().flatten().all(|time| time == "a"),
+            "{:?}",
+            tiles
+        );
+        all_at(tiles, "a") && layer.is_frame_ready(1) && layer.is_frame_ready(2)
+    })
+    .await;
+    assert!(all_at(&tiles, "a"), "{:?}", tiles);
+    assert!(layer.is_frame_ready(1) && layer.is_frame_ready(2));
+
+    // A preloaded frame is shown on the next frame drawn
+    layer.step_forward();
+    assert!(all_at(&render(&mut layer), "b"));
+    assert_eq!(layer.pending_index(), None);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_shared_loaders_share_download_slots_but_not_results() {
+    let server = TestServer::start(|request| {
+        std::thread::sleep(Duration::from_millis(400));
+        ok(&request.path)
+    });
+    let template = server.url("/{z}/{x}/{y}?time={time}");
+    let source = |time: &str| TemplateTileSource::new(template.clone()).with_param("time", time);
+
+    // Four download slots between the two loaders
+    let first = TileLoader::new(TileLoaderConfig::for_testing());
+    let second = first.share();
+    let coords: Vec<TileCoord> = (0..4).map(|x| TileCoord::new(x, 0, 10)).collect();
+    first
+        .queue_tiles_batch(&source("a"), coords.clone(), TilePriority::Visible)
+        .unwrap();
+    second
+        .queue_tiles_batch(&source("b"), coords, TilePriority::Visible)
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(server.paths().len(), 4);
+
+    // The same tiles of each time step reach the loader that asked for them
+    for (loader, time) in [(&first, "time=a"), (&second, "time=b")] {
+        let results = collect_results(loader, 4, Duration::from_secs(5)).await;
+        assert_eq!(results.len(), 4);
+        for result in results {
+            let data = result.data.unwrap().unwrap();
+            assert!(String::from_utf8(data).unwrap().ends_with(time));
+        }
+    }
+}